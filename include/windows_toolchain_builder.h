@@ -0,0 +1,70 @@
+/* C interface of windows-toolchain-builder, built with the `capi` feature.
+ *
+ * Strings are UTF-8. Setters return 0 or WTB_INVALID_ARGUMENT. Operations take ownership of the
+ * builder and return 0, WTB_INVALID_ARGUMENT, WTB_PANIC or the exit code of the command line
+ * program. On failure, wtb_last_error() describes the error. */
+#ifndef WINDOWS_TOOLCHAIN_BUILDER_H
+#define WINDOWS_TOOLCHAIN_BUILDER_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define WTB_INVALID_ARGUMENT -1
+#define WTB_PANIC -2
+
+#define WTB_EVENT_MESSAGE 0
+#define WTB_EVENT_WARNING 1
+#define WTB_EVENT_REPOSITORY_LOAD 2
+#define WTB_EVENT_RESOLVING 3
+#define WTB_EVENT_RESOLVED 4
+#define WTB_EVENT_STARTED 5
+#define WTB_EVENT_DOWNLOAD 6
+#define WTB_EVENT_RECEIVED 7
+#define WTB_EVENT_EXTRACT 8
+#define WTB_EVENT_INSTALLED 9
+
+typedef struct WtbBuilder WtbBuilder;
+
+/* Strings are valid only during the callback */
+typedef struct WtbEvent {
+    int kind;
+    const char *package;
+    const char *text;
+    uint64_t current;
+    uint64_t total;
+} WtbEvent;
+
+/* Called from worker threads */
+typedef void (*WtbEventCallback)(const WtbEvent *event, void *user_data);
+typedef void (*WtbPackageCallback)(const char *name, const char *version, void *user_data);
+
+const char *wtb_last_error(void);
+
+WtbBuilder *wtb_builder_new(void);
+void wtb_builder_free(WtbBuilder *builder);
+
+int wtb_builder_set_package(WtbBuilder *builder, const char *name);
+int wtb_builder_set_repository(WtbBuilder *builder, const char *url);
+int wtb_builder_set_repository_name(WtbBuilder *builder, const char *name);
+int wtb_builder_set_architecture(WtbBuilder *builder, const char *architecture);
+/* "pacman", "rpm", "cygwin" or "conda" */
+int wtb_builder_set_source(WtbBuilder *builder, const char *kind);
+int wtb_builder_set_output(WtbBuilder *builder, const char *path);
+int wtb_builder_set_cache_dir(WtbBuilder *builder, const char *path);
+int wtb_builder_add_include(WtbBuilder *builder, const char *pattern);
+int wtb_builder_add_exclude(WtbBuilder *builder, const char *pattern);
+int wtb_builder_set_parallelism(WtbBuilder *builder, uint32_t tasks);
+int wtb_builder_on_event(WtbBuilder *builder, WtbEventCallback callback, void *user_data);
+
+int wtb_build(WtbBuilder *builder);
+int wtb_resolve(WtbBuilder *builder, WtbPackageCallback callback, void *user_data);
+int wtb_download(WtbBuilder *builder, WtbPackageCallback callback, void *user_data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
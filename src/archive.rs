@@ -0,0 +1,257 @@
+//! Output archive writers
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use flate2::write::GzEncoder;
+use zip::ZipWriter;
+use zip::write::FileOptions;
+use tempfile::TempDir;
+use crate::paths;
+
+/// Supported output archive formats
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Guess archive format from file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Archive writing settings
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ArchiveOptions {
+    /// Compression level, format default is used if not set
+    pub level: Option<u32>,
+    /// Write entries sorted by path with fixed modification time, so archives of the same files are
+    /// bit-identical. Time is taken from `SOURCE_DATE_EPOCH` and defaults to zero
+    pub reproducible: bool,
+}
+
+impl ArchiveOptions {
+    /// Modification time of archive entries, current time if not reproducible
+    fn mtime(&self) -> u64 {
+        if self.reproducible {
+            std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|epoch| epoch.parse().ok()).unwrap_or(0)
+        } else {
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+        }
+    }
+}
+
+/// Compression layer under tar stream
+pub enum Compressor {
+    Plain(Box<dyn Write + Send>),
+    Gzip(GzEncoder<Box<dyn Write + Send>>),
+    Zstd(zstd::stream::write::Encoder<Box<dyn Write + Send>>),
+}
+
+impl Compressor {
+    /// Create compressor with `level`, or with default level of the format
+    fn new(format: ArchiveFormat, writer: Box<dyn Write + Send>, level: Option<u32>) -> io::Result<Self> {
+        Ok(match format {
+            ArchiveFormat::TarGz => {
+                let level = level.map(flate2::Compression::new).unwrap_or_default();
+                Compressor::Gzip(GzEncoder::new(writer, level))
+            }
+            ArchiveFormat::TarZst => Compressor::Zstd(zstd::stream::write::Encoder::new(writer, level.unwrap_or(0) as i32)?),
+            _ => Compressor::Plain(writer),
+        })
+    }
+
+    fn finish(self) -> io::Result<Box<dyn Write + Send>> {
+        match self {
+            Compressor::Plain(writer) => Ok(writer),
+            Compressor::Gzip(encoder) => encoder.finish(),
+            Compressor::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl Write for Compressor {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Compressor::Plain(writer) => writer.write(buf),
+            Compressor::Gzip(encoder) => encoder.write(buf),
+            Compressor::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Compressor::Plain(writer) => writer.flush(),
+            Compressor::Gzip(encoder) => encoder.flush(),
+            Compressor::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Blocking archive writer
+pub enum ArchiveWriter {
+    Tar(tar::Builder<Compressor>, ArchiveOptions),
+    Zip(ZipWriter<File>, ArchiveOptions),
+}
+
+impl ArchiveWriter {
+    /// Create tar archive writer over any stream
+    pub fn tar(format: ArchiveFormat, writer: Box<dyn Write + Send>, options: ArchiveOptions) -> io::Result<Self> {
+        Ok(ArchiveWriter::Tar(tar::Builder::new(Compressor::new(format, writer, options.level)?), options))
+    }
+
+    /// Create archive writer for `file`. Compression level is used by compressed tar formats only
+    pub fn file(format: ArchiveFormat, file: File, options: ArchiveOptions) -> io::Result<Self> {
+        match format {
+            ArchiveFormat::Zip => Ok(ArchiveWriter::Zip(ZipWriter::new(file), options)),
+            _ => ArchiveWriter::tar(format, Box::new(file), options),
+        }
+    }
+
+    /// Append regular file to the archive
+    pub fn append(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        match self {
+            ArchiveWriter::Tar(builder, options) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(options.mtime());
+                builder.append_data(&mut header, path, data)
+            }
+            ArchiveWriter::Zip(writer, options) => {
+                let mut file_options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+                // DOS time can't represent the epoch, so reproducible archives use the earliest possible time
+                if options.reproducible {
+                    file_options = file_options.last_modified_time(zip::DateTime::default());
+                }
+                writer.start_file(path, file_options).map_err(zip_error)?;
+                writer.write_all(data)
+            }
+        }
+    }
+
+    /// Write archive trailer and flush underlying stream
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            ArchiveWriter::Tar(builder, _) => builder.into_inner()?.finish()?.flush(),
+            ArchiveWriter::Zip(mut writer, _) => writer.finish().map_err(zip_error)?.flush(),
+        }
+    }
+}
+
+fn zip_error(e: zip::result::ZipError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+/// Archive which receives extracted files instead of the output folder.
+/// Archive files are written to `<path>.part` and renamed on commit. Reproducible archives collect
+/// files in a temporary folder and write them in path order on commit.
+pub struct ArchiveSink {
+    file: Option<PartFile>,
+    writer: Arc<Mutex<ArchiveWriter>>,
+    buffer: Option<Buffer>,
+}
+
+struct Buffer {
+    folder: TempDir,
+    paths: Mutex<BTreeSet<String>>,
+}
+
+impl Buffer {
+    fn new(options: ArchiveOptions) -> io::Result<Option<Self>> {
+        if !options.reproducible {
+            return Ok(None);
+        }
+        Ok(Some(Buffer { folder: tempfile::tempdir()?, paths: Mutex::new(BTreeSet::new()) }))
+    }
+}
+
+struct PartFile {
+    path: PathBuf,
+    part: PathBuf,
+}
+
+impl ArchiveSink {
+    pub async fn new(path: &Path, format: ArchiveFormat, options: ArchiveOptions) -> io::Result<Self> {
+        let mut part = path.as_os_str().to_owned();
+        part.push(".part");
+        let part = PathBuf::from(part);
+        let file = tokio::fs::File::create(&part).await?.into_std().await;
+        Ok(ArchiveSink {
+            file: Some(PartFile { path: path.to_owned(), part }),
+            writer: Arc::new(Mutex::new(ArchiveWriter::file(format, file, options)?)),
+            buffer: Buffer::new(options)?,
+        })
+    }
+
+    /// Write uncompressed tar stream to stdout
+    pub fn stdout(options: ArchiveOptions) -> io::Result<Self> {
+        Ok(ArchiveSink {
+            file: None,
+            writer: Arc::new(Mutex::new(ArchiveWriter::tar(ArchiveFormat::Tar, Box::new(io::stdout()), options)?)),
+            buffer: Buffer::new(options)?,
+        })
+    }
+
+    pub async fn write(&self, path: &str, data: Vec<u8>) -> io::Result<()> {
+        if let Some(buffer) = self.buffer.as_ref() {
+            let file = paths::join(buffer.folder.path(), path);
+            tokio::fs::create_dir_all(file.parent().unwrap()).await?;
+            tokio::fs::write(&file, data).await?;
+            buffer.paths.lock().unwrap().insert(path.to_owned());
+            return Ok(());
+        }
+        let writer = self.writer.clone();
+        let path = path.to_owned();
+        tokio::task::spawn_blocking(move || writer.lock().unwrap().append(&path, &data)).await?
+    }
+
+    /// Finish the archive and move it into place
+    pub async fn commit(self) -> io::Result<()> {
+        let writer = Arc::try_unwrap(self.writer)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "archive is still in use"))?
+            .into_inner()
+            .unwrap();
+        let buffer = self.buffer;
+        tokio::task::spawn_blocking(move || {
+            let mut writer = writer;
+            if let Some(buffer) = buffer {
+                for path in buffer.paths.into_inner().unwrap() {
+                    writer.append(&path, &std::fs::read(paths::join(buffer.folder.path(), &path))?)?;
+                }
+            }
+            writer.finish()
+        }).await??;
+        match self.file {
+            Some(file) => tokio::fs::rename(&file.part, &file.path).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Drop the archive. Stream written to stdout is left without trailer, so consumers will see it as truncated
+    pub async fn discard(self) -> io::Result<()> {
+        drop(self.writer);
+        match self.file {
+            Some(file) => tokio::fs::remove_file(&file.part).await,
+            None => Ok(()),
+        }
+    }
+}
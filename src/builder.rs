@@ -0,0 +1,432 @@
+//! Installation set up in code instead of command line arguments
+use crate::archive::ArchiveFormat;
+use crate::config::{Command, Config};
+use crate::classify::ContentFilter;
+use crate::download::Downloader;
+use crate::error::{ConfigError, Error, Phase, Result};
+use crate::event::{self, Event, EventHandler, EventReporter, EventStream};
+use crate::hooks::Hook;
+use crate::license::LicensePolicy;
+use crate::pick::PackageChoice;
+use crate::progress::Progress;
+use crate::script::ScriptFilter;
+use crate::source::PackageSource;
+use crate::tool::CommandTemplate;
+use crate::urls::{UrlResolver, UrlRewrite};
+use crate::wasm::WasmPlugin;
+use crate::{command_output, fetch_archive, find_package, journal, lock_folders, resolve_tree, Program};
+use archlinux_repo::Package;
+use futures::StreamExt;
+use regex::Regex;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Builds a toolchain from a package and its dependencies. Options mirror the command line ones and
+/// have the same defaults, except that package name must match a package exactly, so the host process
+/// isn't asked to choose one in its terminal. Settings without a method are changed with `configure`
+pub struct ToolchainBuilder {
+    config: Config,
+    handlers: Vec<EventHandler>,
+    source: Option<Box<dyn PackageSource>>,
+}
+
+impl Default for ToolchainBuilder {
+    fn default() -> Self {
+        ToolchainBuilder::new()
+    }
+}
+
+impl ToolchainBuilder {
+    pub fn new() -> Self {
+        let mut config = Config::default();
+        config.package_choice = PackageChoice::Exact;
+        ToolchainBuilder::from_config(config)
+    }
+
+    /// Builder which starts from `config`, e.g. one parsed from the command line
+    pub fn from_config(config: Config) -> Self {
+        ToolchainBuilder { config, handlers: Vec::new(), source: None }
+    }
+
+    /// Root package to install
+    pub fn package(mut self, name: &str) -> Self {
+        self.config.package = name.to_owned();
+        self
+    }
+
+    /// What to do when the package name matches several packages, but none exactly
+    pub fn package_choice(mut self, choice: PackageChoice) -> Self {
+        self.config.package_choice = choice;
+        self
+    }
+
+    /// Repository base URL, without architecture
+    pub fn repository(mut self, url: &str) -> Self {
+        self.config.repository = url.to_owned();
+        self
+    }
+
+    pub fn repository_name(mut self, name: &str) -> Self {
+        self.config.repository_name = name.to_owned();
+        self
+    }
+
+    pub fn architecture(mut self, architecture: &str) -> Self {
+        self.config.architecture = architecture.to_owned();
+        self
+    }
+
+    /// Install packages from `source` instead of the repository
+    pub fn source(mut self, source: impl PackageSource + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    /// Output folder
+    pub fn output(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.output_folder = path.into();
+        self
+    }
+
+    /// Write files into archive at `path` instead of the output folder. Its extension picks the format:
+    /// `.zip`, `.tar`, `.tar.gz` or `.tar.zst`
+    pub fn archive_output(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.archive_output = Some(path.into());
+        self
+    }
+
+    /// Package archive cache folder
+    pub fn cache_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.cache_dir = path.into();
+        self
+    }
+
+    /// Download archives of packages matching `rewrite` from its URL instead of the repository
+    pub fn rewrite_url(mut self, rewrite: UrlRewrite) -> Self {
+        self.config.url_rewrites.push(rewrite);
+        self
+    }
+
+    /// Download archives from URLs `resolve` returns for package name and repository URL of its
+    /// archive. Archives it returns `None` for are downloaded from the repository, or the URL of a rewrite
+    pub fn resolve_urls(mut self, resolve: impl Fn(&str, &str) -> Option<String> + Send + Sync + 'static) -> Self {
+        self.config.url_resolver = Some(UrlResolver::new(resolve));
+        self
+    }
+
+    /// Extract only files which path matches `pattern`, or any other include pattern
+    pub fn include(mut self, pattern: Regex) -> Self {
+        self.config.include.push(pattern);
+        self
+    }
+
+    /// Don't extract files which path matches `pattern`
+    pub fn exclude(mut self, pattern: Regex) -> Self {
+        self.config.exclude.push(pattern);
+        self
+    }
+
+    /// Extract only files which `filter` keeps
+    pub fn filter(mut self, filter: ContentFilter) -> Self {
+        self.config.content_filters.push(filter);
+        self
+    }
+
+    /// Keep, drop or move files which passed other filters with `script`
+    pub fn filter_script(mut self, script: ScriptFilter) -> Self {
+        self.config.filter_script = Some(script);
+        self
+    }
+
+    pub fn license_policy(mut self, policy: LicensePolicy) -> Self {
+        self.config.license_policy = policy;
+        self
+    }
+
+    /// Start plugin `command`, which takes part in the installation over the plugin protocol
+    pub fn plugin(mut self, command: CommandTemplate) -> Self {
+        self.config.plugin = Some(command);
+        self
+    }
+
+    /// Time the plugin has to reply to a request
+    pub fn plugin_timeout(mut self, timeout: Duration) -> Self {
+        self.config.plugin_timeout = timeout;
+        self
+    }
+
+    /// Drop packages and filter files with sandboxed WebAssembly `plugin`
+    pub fn wasm_plugin(mut self, plugin: WasmPlugin) -> Self {
+        self.config.wasm_plugins.push(plugin);
+        self
+    }
+
+    /// Run `hook` after packages are extracted
+    pub fn hook(mut self, hook: Hook) -> Self {
+        self.config.hooks.push(hook);
+        self
+    }
+
+    /// Download and extract task count
+    pub fn parallelism(mut self, tasks: u32) -> Self {
+        self.config.parallelism = tasks;
+        self
+    }
+
+    pub fn strip(mut self, strip: bool) -> Self {
+        self.config.strip = strip;
+        self
+    }
+
+    /// Continue an interrupted run
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.config.resume = resume;
+        self
+    }
+
+    /// Install even if the output is up to date
+    pub fn force(mut self, force: bool) -> Self {
+        self.config.force = force;
+        self
+    }
+
+    /// Install packages pinned by the lockfile at `path`
+    pub fn locked(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.locked = Some(path.into());
+        self
+    }
+
+    /// Write lockfile of installed packages to `path`
+    pub fn write_lockfile(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.write_lockfile = Some(path.into());
+        self
+    }
+
+    /// Change any other setting
+    pub fn configure(mut self, configure: impl FnOnce(&mut Config)) -> Self {
+        configure(&mut self.config);
+        self
+    }
+
+    /// Call `handler` on every event of the installation. Progress isn't printed when there are handlers
+    pub fn on_event(mut self, handler: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    /// Stream of events of the installation, which ends when `build` finishes
+    pub fn events(&mut self) -> EventStream {
+        let (handler, events) = event::channel();
+        self.handlers.push(handler);
+        events
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Progress of the run, turned into events if there are handlers
+    fn progress(config: &Config, handlers: Vec<EventHandler>) -> Progress {
+        if handlers.is_empty() {
+            Progress::new(config.progress_mode, &config.theme)
+        } else {
+            Progress::with_reporter(Arc::new(EventReporter::new(handlers)))
+        }
+    }
+
+    /// The package with its dependencies, nothing is installed
+    pub async fn resolve(self) -> Result<Vec<Package>> {
+        let progress = ToolchainBuilder::progress(&self.config, self.handlers);
+        let (config, source) = (self.config, self.source);
+        let source = match source {
+            Some(source) => source,
+            None => crate::source::open(&config, &progress).await.map_err(|e| Error::in_phase(Phase::Repository, e))?,
+        };
+        let package = find_package(source.as_ref(), &config, &config.package, &progress)
+            .map_err(|e| Error::in_phase(Phase::Resolve, e))?;
+        resolve_tree(source.as_ref(), &config, &progress, package)
+            .map_err(|e| Error::in_phase(Phase::Resolve, e))
+    }
+
+    /// Download archives of the package and its dependencies into the cache folder without installing
+    /// them. Returns the downloaded packages
+    pub async fn download(self) -> Result<Vec<Package>> {
+        let progress = ToolchainBuilder::progress(&self.config, self.handlers);
+        let (config, source) = (self.config, self.source);
+        let source = match source {
+            Some(source) => source,
+            None => crate::source::open(&config, &progress).await.map_err(|e| Error::in_phase(Phase::Repository, e))?,
+        };
+        let package = find_package(source.as_ref(), &config, &config.package, &progress)
+            .map_err(|e| Error::in_phase(Phase::Resolve, e))?;
+        let tree = resolve_tree(source.as_ref(), &config, &progress, package)
+            .map_err(|e| Error::in_phase(Phase::Resolve, e))?;
+        tokio::fs::create_dir_all(&config.cache_dir).await?;
+        let downloader = Downloader::new(&config.repository_url(), &config.cache_dir, config.resume);
+        let (config, source, downloader, progress) = (&config, source.as_ref(), &downloader, &progress);
+        let mut downloads = futures::stream::iter(tree.iter().map(|package| async move {
+            let package_progress = progress.package_download(package);
+            fetch_archive(config, source, downloader, package, &package_progress).await
+                .map_err(|e| Error::in_package(Phase::Download, &package.name, e))?;
+            package_progress.complete();
+            Ok::<(), Error>(())
+        })).buffer_unordered(config.parallelism.max(1) as usize);
+        while let Some(result) = downloads.next().await {
+            result?;
+        }
+        drop(downloads);
+        Ok(tree)
+    }
+
+    /// Install the package with its dependencies
+    pub async fn build(self) -> Result<()> {
+        let mut config = self.config;
+        config.command = Command::Build;
+        // Checked before any folder is locked or created, like the command line validator does
+        if let Some(path) = config.archive_output.as_ref() {
+            if ArchiveFormat::from_path(path).is_none() {
+                return Err(ConfigError::UnsupportedArchive(path.clone()).into());
+            }
+        }
+        let progress = ToolchainBuilder::progress(&config, self.handlers);
+        let _locks = lock_folders(&config, &progress).await?;
+        if let Some(output) = command_output(&config) {
+            journal::recover(output, &progress).await?;
+        }
+        let package = config.package.clone();
+        let program = match self.source {
+            Some(source) => Program::with_source(config, progress, source).await?,
+            None => Program::with_progress(config, progress).await?,
+        };
+        program.run(&package).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::PackageInfo;
+
+    struct TestSource {
+        packages: Vec<Package>,
+    }
+
+    impl PackageSource for TestSource {
+        fn package(&self, name: &str) -> Option<&Package> {
+            self.packages.iter().find(|package| package.name == name)
+        }
+
+        fn packages(&self) -> Box<dyn Iterator<Item = &Package> + '_> {
+            Box::new(self.packages.iter())
+        }
+
+        fn archive_url(&self, file_name: &str) -> String {
+            format!("http://localhost/{}", file_name)
+        }
+    }
+
+    fn package(name: &str, depends: &[&str]) -> Package {
+        PackageInfo {
+            name: name.to_owned(),
+            version: String::from("1.0-1"),
+            file_name: format!("{}-1.0-1-any.pkg.tar.zst", name),
+            architecture: String::from("any"),
+            depends: depends.iter().map(|name| name.to_string()).collect(),
+            ..PackageInfo::default()
+        }.to_package().unwrap()
+    }
+
+    fn source() -> TestSource {
+        TestSource {
+            packages: vec![
+                package("mingw-w64-x86_64-gcc", &["mingw-w64-x86_64-binutils", "mingw-w64-x86_64-crt"]),
+                package("mingw-w64-x86_64-binutils", &["mingw-w64-x86_64-zlib"]),
+                package("mingw-w64-x86_64-crt", &[]),
+                package("mingw-w64-x86_64-zlib", &[]),
+            ],
+        }
+    }
+
+    fn names(packages: &[Package]) -> Vec<&str> {
+        let mut names: Vec<&str> = packages.iter().map(|package| package.name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn new_builder_requires_exact_package_name() {
+        assert_eq!(ToolchainBuilder::new().config().package_choice, PackageChoice::Exact);
+        assert_eq!(ToolchainBuilder::default().config().package_choice, PackageChoice::Exact);
+    }
+
+    #[test]
+    fn methods_change_config() {
+        let builder = ToolchainBuilder::new()
+            .package("mingw-w64-x86_64-gcc")
+            .repository_name("mingw64")
+            .output("toolchain")
+            .parallelism(3)
+            .strip(true)
+            .configure(|config| config.extract_threads = 2);
+        let config = builder.config();
+        assert_eq!(config.package, "mingw-w64-x86_64-gcc");
+        assert_eq!(config.repository_name, "mingw64");
+        assert_eq!(config.output_folder, PathBuf::from("toolchain"));
+        assert_eq!(config.parallelism, 3);
+        assert!(config.strip);
+        assert_eq!(config.extract_threads, 2);
+    }
+
+    #[tokio::test]
+    async fn resolve_returns_package_with_dependencies() {
+        let packages = ToolchainBuilder::new()
+            .package("mingw-w64-x86_64-gcc")
+            .source(source())
+            .on_event(|_| {})
+            .resolve().await
+            .unwrap();
+        assert_eq!(names(&packages), ["mingw-w64-x86_64-binutils", "mingw-w64-x86_64-crt", "mingw-w64-x86_64-gcc", "mingw-w64-x86_64-zlib"]);
+    }
+
+    #[tokio::test]
+    async fn resolve_fails_on_inexact_name() {
+        let error = ToolchainBuilder::new()
+            .package("gcc")
+            .source(source())
+            .on_event(|_| {})
+            .resolve().await
+            .unwrap_err();
+        assert_eq!(error.code(), "package_not_found");
+    }
+
+    #[tokio::test]
+    async fn resolve_takes_best_match_with_first() {
+        let packages = ToolchainBuilder::new()
+            .package("binutils")
+            .package_choice(PackageChoice::First)
+            .source(source())
+            .on_event(|_| {})
+            .resolve().await
+            .unwrap();
+        assert_eq!(names(&packages), ["mingw-w64-x86_64-binutils", "mingw-w64-x86_64-zlib"]);
+    }
+
+    #[tokio::test]
+    async fn build_rejects_unknown_archive_format() {
+        let output = tempfile::tempdir().unwrap();
+        let archive = output.path().join("toolchain.7z");
+        let error = ToolchainBuilder::new()
+            .package("mingw-w64-x86_64-gcc")
+            .source(source())
+            .output(output.path().join("unused"))
+            .archive_output(&archive)
+            .cache_dir(output.path().join("cache"))
+            .on_event(|_| {})
+            .build().await
+            .unwrap_err();
+        assert_eq!(error.code(), "unsupported_archive");
+        assert!(!archive.exists());
+        assert!(!output.path().join("cache").exists());
+    }
+}
@@ -0,0 +1,150 @@
+//! State bundles, tar archives with the manifest, the state database and a lockfile of an output.
+//! They let another machine rebuild the output or take over management of its copy
+use crate::download::Downloader;
+use crate::lock::{LockedPackage, Lockfile};
+use crate::manifest::{sha256_hex, Manifest, MANIFEST_PATH};
+use crate::owners::{self, OWNERS_PATH};
+use crate::paths;
+use crate::progress::Progress;
+use crate::state::{State, STATE_PATH};
+use crate::error::Error;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Bundle entry names
+const MANIFEST_ENTRY: &str = "manifest.json";
+const STATE_ENTRY: &str = "state.json";
+const LOCKFILE_ENTRY: &str = "toolchain.lock";
+
+/// Action of `state` command
+#[derive(Clone, Debug)]
+pub enum StateAction {
+    /// Write bundle of the output
+    Export { bundle: PathBuf },
+    /// Rebuild the output from bundle or adopt existing files of the output
+    Import { bundle: PathBuf },
+}
+
+/// Options of `state` command
+#[derive(Clone, Debug)]
+pub struct StateOptions {
+    /// Output folder with state database
+    pub output: PathBuf,
+    pub action: StateAction,
+}
+
+/// Contents of a state bundle
+#[derive(Clone, Debug)]
+pub struct Bundle {
+    pub manifest: Manifest,
+    pub state: State,
+    pub lockfile: Lockfile,
+}
+
+impl Bundle {
+    /// Bundle of `output`. Archives which aren't in `cache` are downloaded to hash them for the lockfile
+    pub async fn new(output: &Path, cache: &Path) -> Result<Self, Error> {
+        let manifest = Manifest::load(output).await?;
+        let state = State::load(output).await?;
+        let repository = state.repository.clone() + "/" + &state.architecture;
+        let downloader = Downloader::new(&repository, cache, false);
+        let mut packages = Vec::with_capacity(state.packages.len());
+        for package in state.packages.iter() {
+            packages.push(LockedPackage {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                file_name: package.file_name.clone(),
+                sha256: downloader.archive_sha256(&package.file_name, &downloader.url(&package.file_name)).await?,
+                url: downloader.url(&package.file_name),
+            });
+        }
+        let lockfile = Lockfile::new(&repository, &state.repository_name, &state.explicit(), packages);
+        Ok(Bundle { manifest, state, lockfile })
+    }
+
+    pub fn to_tar(&self) -> io::Result<Vec<u8>> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let entries = [
+            (MANIFEST_ENTRY, self.manifest.to_json()),
+            (STATE_ENTRY, self.state.to_json()),
+            (LOCKFILE_ENTRY, self.lockfile.to_json()),
+        ];
+        for (name, data) in entries.iter() {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(self.state.installed);
+            header.set_entry_type(tar::EntryType::Regular);
+            builder.append_data(&mut header, name, &data[..])?;
+        }
+        builder.into_inner()
+    }
+
+    pub fn from_tar(data: &[u8]) -> io::Result<Self> {
+        let (mut manifest, mut state, mut lockfile) = (None, None, None);
+        let mut archive = tar::Archive::new(data);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            match name.as_str() {
+                MANIFEST_ENTRY => manifest = Some(Manifest::from_json(&content)?),
+                STATE_ENTRY => state = Some(State::from_json(&content)?),
+                LOCKFILE_ENTRY => lockfile = Some(serde_json::from_slice(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?),
+                _ => {}
+            }
+        }
+        match (manifest, state, lockfile) {
+            (Some(manifest), Some(state), Some(lockfile)) => Ok(Bundle { manifest, state, lockfile }),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Bundle must have manifest.json, state.json and toolchain.lock")),
+        }
+    }
+
+    pub async fn load(path: &Path) -> io::Result<Self> {
+        Bundle::from_tar(&tokio::fs::read(path).await?)
+    }
+}
+
+/// Write bundle of `output` into `path`
+pub async fn export(output: &Path, cache: &Path, path: &Path, progress: &Progress) -> Result<(), Error> {
+    let bundle = Bundle::new(output, cache).await?;
+    tokio::fs::write(path, bundle.to_tar()?).await?;
+    progress.info(&format!("Exported {} packages and {} files", bundle.state.packages.len(), bundle.manifest.files.len()));
+    Ok(())
+}
+
+/// Check if `output` has any files besides tool data
+pub async fn is_empty(output: &Path) -> io::Result<bool> {
+    if tokio::fs::metadata(output).await.is_err() {
+        return Ok(true);
+    }
+    let output = output.to_owned();
+    Ok(tokio::task::spawn_blocking(move || paths::list_files(&output)).await??.is_empty())
+}
+
+/// Take over management of `output`, which already has files of the bundle. Fails if any of them
+/// differs from the manifest
+pub async fn adopt(output: &Path, bundle: &Bundle, progress: &Progress) -> Result<(), Error> {
+    let mut mismatched = 0;
+    for entry in bundle.manifest.files.iter() {
+        let matches = match tokio::fs::read(paths::join(output, &entry.path)).await {
+            Ok(data) => data.len() as u64 == entry.size && sha256_hex(&data) == entry.sha256,
+            Err(_) => false,
+        };
+        if !matches {
+            progress.warn(&format!("{} doesn't match the bundle", &entry.path));
+            mismatched += 1;
+        }
+    }
+    if mismatched > 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("{} files of the output don't match the bundle, import it into an empty folder to rebuild the output", mismatched)).into());
+    }
+    tokio::fs::create_dir_all(paths::join(output, MANIFEST_PATH).parent().unwrap()).await?;
+    bundle.manifest.save(output).await?;
+    tokio::fs::write(paths::join(output, OWNERS_PATH), owners::encode(&bundle.manifest)).await?;
+    tokio::fs::write(paths::join(output, STATE_PATH), bundle.state.to_json()).await?;
+    progress.info(&format!("Adopted {} files of {} packages", bundle.manifest.files.len(), bundle.state.packages.len()));
+    Ok(())
+}
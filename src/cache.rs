@@ -0,0 +1,227 @@
+//! Management of the package archive cache
+use crate::lock::Lockfile;
+use crate::paths;
+use crate::progress::Progress;
+use crate::state::State;
+use compress_tools::list_archive_files;
+use indicatif::HumanBytes;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use crate::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Action of `cache` command
+#[derive(Clone, Debug)]
+pub enum CacheAction {
+    /// Print size of the cache
+    Info,
+    /// Remove partial downloads, archives unused for `keep_days` and least recently used archives
+    /// above `max_size`
+    Prune { keep_days: Option<u64>, max_size: Option<u64> },
+    /// Check that cached archives can be read, removing damaged ones with `delete`
+    Verify { delete: bool },
+    /// Remove archives which no registered output or lockfile references
+    Gc { dry_run: bool },
+}
+
+/// Options of `cache` command
+#[derive(Clone, Debug)]
+pub struct CacheOptions {
+    pub action: CacheAction,
+}
+
+/// List of outputs and lockfiles built with the cache, relative to the cache folder
+const REGISTRY_FILE: &str = ".registry.json";
+
+/// Outputs and lockfiles which reference cached archives
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Registry {
+    /// Output folders with state database
+    outputs: BTreeSet<PathBuf>,
+    lockfiles: BTreeSet<PathBuf>,
+}
+
+impl Registry {
+    async fn load(cache: &Path) -> io::Result<Self> {
+        match tokio::fs::read(cache.join(REGISTRY_FILE)).await {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Registry::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn save(&self, cache: &Path) -> io::Result<()> {
+        tokio::fs::write(cache.join(REGISTRY_FILE), serde_json::to_vec_pretty(self).unwrap()).await
+    }
+}
+
+/// Remember that archives of `cache` are referenced by state database of `output` and by `lockfile`,
+/// so `cache gc` keeps them
+pub async fn register(cache: &Path, output: Option<&Path>, lockfile: Option<&Path>) -> io::Result<()> {
+    let mut registry = Registry::load(cache).await?;
+    let mut changed = false;
+    if let Some(output) = output {
+        changed |= registry.outputs.insert(paths::absolute(output).await?);
+    }
+    if let Some(lockfile) = lockfile {
+        changed |= registry.lockfiles.insert(paths::absolute(lockfile).await?);
+    }
+    if changed {
+        registry.save(cache).await?;
+    }
+    Ok(())
+}
+
+/// File of the cache folder
+#[derive(Clone, Debug)]
+struct CachedFile {
+    path: PathBuf,
+    name: String,
+    size: u64,
+    /// Last access time, or modification time if the file system doesn't record it
+    used: SystemTime,
+    /// Interrupted download
+    partial: bool,
+}
+
+/// Files of the cache folder, least recently used first. Missing folder is an empty cache
+async fn list(cache: &Path) -> io::Result<Vec<CachedFile>> {
+    let mut files = Vec::new();
+    let mut entries = match tokio::fs::read_dir(cache).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(files),
+        Err(e) => return Err(e),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Registry and lock file
+        if name.starts_with('.') {
+            continue;
+        }
+        files.push(CachedFile {
+            path: entry.path(),
+            partial: name.ends_with(".part"),
+            name,
+            size: metadata.len(),
+            used: metadata.accessed().or_else(|_| metadata.modified()).unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+    }
+    files.sort_by(|a, b| a.used.cmp(&b.used).then_with(|| a.name.cmp(&b.name)));
+    Ok(files)
+}
+
+fn age_days(time: SystemTime) -> u64 {
+    SystemTime::now().duration_since(time).unwrap_or_default().as_secs() / 86400
+}
+
+/// Print number and size of cached archives and partial downloads
+pub async fn info(cache: &Path, progress: &Progress) -> Result<(), Error> {
+    let files = list(cache).await?;
+    let (partial, archives): (Vec<&CachedFile>, Vec<&CachedFile>) = files.iter().partition(|file| file.partial);
+    let mut text = format!("Cache folder: {}\n", cache.display());
+    text += &format!("Archives: {} ({})\n", archives.len(), HumanBytes(archives.iter().map(|file| file.size).sum()));
+    text += &format!("Partial downloads: {} ({})\n", partial.len(), HumanBytes(partial.iter().map(|file| file.size).sum()));
+    if let (Some(oldest), Some(newest)) = (archives.first(), archives.last()) {
+        text += &format!("Least recently used: {} ({} days ago)\n", &oldest.name, age_days(oldest.used));
+        text += &format!("Most recently used: {} ({} days ago)\n", &newest.name, age_days(newest.used));
+    }
+    progress.report("cache info", &text);
+    Ok(())
+}
+
+/// Remove partial downloads and archives which weren't used for `keep_days`, then least recently used
+/// archives until the cache fits into `max_size`
+pub async fn prune(cache: &Path, keep_days: Option<u64>, max_size: Option<u64>, progress: &Progress) -> Result<(), Error> {
+    let files = list(cache).await?;
+    let cutoff = keep_days.map(|days| SystemTime::now() - Duration::from_secs(days * 86400));
+    let mut total: u64 = files.iter().map(|file| file.size).sum();
+    let mut removed = 0;
+    let mut freed = 0;
+    for file in files.iter() {
+        let expired = cutoff.map_or(false, |cutoff| file.used < cutoff);
+        let oversized = max_size.map_or(false, |max_size| total > max_size);
+        if !file.partial && !expired && !oversized {
+            continue;
+        }
+        tokio::fs::remove_file(&file.path).await?;
+        total -= file.size;
+        freed += file.size;
+        removed += 1;
+    }
+    progress.info(&format!("Removed {} files, freed {}, cache takes {}", removed, HumanBytes(freed), HumanBytes(total)));
+    Ok(())
+}
+
+/// Try to read every cached archive. Returns number of damaged archives left in the cache
+pub async fn verify(cache: &Path, delete: bool, progress: &Progress) -> Result<usize, Error> {
+    let files = list(cache).await?;
+    let mut damaged = 0;
+    let mut removed = 0;
+    let mut checked = 0;
+    for file in files.iter().filter(|file| !file.partial) {
+        let data = tokio::fs::read(&file.path).await?;
+        checked += 1;
+        if let Err(e) = tokio::task::spawn_blocking(move || list_archive_files(&data[..])).await? {
+            if delete {
+                tokio::fs::remove_file(&file.path).await?;
+                progress.warn(&format!("Removed damaged archive {}: {}", &file.name, e));
+                removed += 1;
+            } else {
+                progress.warn(&format!("Damaged archive {}: {}", &file.name, e));
+                damaged += 1;
+            }
+        }
+    }
+    progress.info(&format!("Verified {} archives, {} damaged, {} removed", checked, damaged + removed, removed));
+    Ok(damaged)
+}
+
+/// Remove cached archives which no registered output or lockfile references. Outputs and lockfiles
+/// which no longer exist are dropped from the registry
+pub async fn gc(cache: &Path, dry_run: bool, progress: &Progress) -> Result<(), Error> {
+    let mut registry = Registry::load(cache).await?;
+    let mut referenced = HashSet::new();
+    let mut missing = Vec::new();
+    for output in registry.outputs.iter() {
+        match State::load(output).await {
+            Ok(state) => referenced.extend(state.packages.into_iter().map(|package| package.file_name)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => missing.push(output.clone()),
+            Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", output.display(), e)).into()),
+        }
+    }
+    registry.outputs.retain(|output| !missing.contains(output));
+    missing.clear();
+    for lockfile in registry.lockfiles.iter() {
+        match Lockfile::load(lockfile).await {
+            Ok(lockfile) => referenced.extend(lockfile.packages.into_iter().map(|package| package.file_name)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => missing.push(lockfile.clone()),
+            Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", lockfile.display(), e)).into()),
+        }
+    }
+    registry.lockfiles.retain(|lockfile| !missing.contains(lockfile));
+
+    let mut removed = 0;
+    let mut freed = 0;
+    for file in list(cache).await?.iter().filter(|file| file.partial || !referenced.contains(&file.name)) {
+        if dry_run {
+            progress.info(&format!("Would remove {}", &file.name));
+        } else {
+            tokio::fs::remove_file(&file.path).await?;
+        }
+        removed += 1;
+        freed += file.size;
+    }
+    if !dry_run {
+        registry.save(cache).await?;
+    }
+    progress.info(&format!("{} {} unreferenced files, {}, {} outputs and {} lockfiles reference the cache",
+                           if dry_run { "Would remove" } else { "Removed" }, removed, HumanBytes(freed),
+                           registry.outputs.len(), registry.lockfiles.len()));
+    Ok(())
+}
@@ -0,0 +1,299 @@
+//! C interface of the library, built with the `capi` feature, for build orchestrators which aren't
+//! written in Rust. Declarations are in `include/windows_toolchain_builder.h`
+use crate::builder::ToolchainBuilder;
+use crate::error::Result;
+use crate::event::Event;
+use crate::source::SourceKind;
+use crate::Failure;
+use archlinux_repo::Package;
+use regex::Regex;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::future::Future;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+use std::str::FromStr;
+
+/// Argument is null, isn't UTF-8 or has an invalid value
+pub const WTB_INVALID_ARGUMENT: c_int = -1;
+/// Library panicked
+pub const WTB_PANIC: c_int = -2;
+
+pub const WTB_EVENT_MESSAGE: c_int = 0;
+pub const WTB_EVENT_WARNING: c_int = 1;
+pub const WTB_EVENT_REPOSITORY_LOAD: c_int = 2;
+pub const WTB_EVENT_RESOLVING: c_int = 3;
+pub const WTB_EVENT_RESOLVED: c_int = 4;
+pub const WTB_EVENT_STARTED: c_int = 5;
+pub const WTB_EVENT_DOWNLOAD: c_int = 6;
+pub const WTB_EVENT_RECEIVED: c_int = 7;
+pub const WTB_EVENT_EXTRACT: c_int = 8;
+pub const WTB_EVENT_INSTALLED: c_int = 9;
+
+/// Progress event passed to the callback. Strings are valid only during the call
+#[repr(C)]
+pub struct WtbEvent {
+    /// One of `WTB_EVENT_*`
+    pub kind: c_int,
+    /// Package the event is about, or null
+    pub package: *const c_char,
+    /// Message or extracted file, or null
+    pub text: *const c_char,
+    /// Received bytes, processed entries or resolved packages
+    pub current: u64,
+    /// Total of `current`, 0 if unknown
+    pub total: u64,
+}
+
+pub type WtbEventCallback = extern "C" fn(event: *const WtbEvent, user_data: *mut c_void);
+pub type WtbPackageCallback = extern "C" fn(name: *const c_char, version: *const c_char, user_data: *mut c_void);
+
+/// Builder behind the opaque pointer of the C interface
+pub struct WtbBuilder {
+    builder: ToolchainBuilder,
+}
+
+/// Pointer the caller gets back in its callback. The caller makes the callback safe to call from any thread
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn c_string(text: &str) -> CString {
+    CString::new(text.replace('\0', " ")).unwrap()
+}
+
+fn set_error(message: &str) {
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(c_string(message)));
+}
+
+unsafe fn string<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        return None;
+    }
+    CStr::from_ptr(value).to_str().ok()
+}
+
+/// Parse `value` and apply it to the builder
+unsafe fn set<T>(builder: *mut WtbBuilder, value: *const c_char, parse: impl FnOnce(&str) -> std::result::Result<T, String>,
+                 apply: impl FnOnce(ToolchainBuilder, T) -> ToolchainBuilder) -> c_int {
+    let (builder, value) = match (builder.as_mut(), string(value)) {
+        (Some(builder), Some(value)) => (builder, value),
+        _ => {
+            set_error("Argument is null or isn't UTF-8");
+            return WTB_INVALID_ARGUMENT;
+        }
+    };
+    match parse(value) {
+        Ok(value) => {
+            let current = std::mem::take(&mut builder.builder);
+            builder.builder = apply(current, value);
+            0
+        }
+        Err(e) => {
+            set_error(&e);
+            WTB_INVALID_ARGUMENT
+        }
+    }
+}
+
+/// Take the builder and run `operation` with it on a new runtime. On failure, the message is recorded
+/// for `wtb_last_error` and the exit code of the command line program is returned
+unsafe fn run<T, F>(builder: *mut WtbBuilder, operation: impl FnOnce(ToolchainBuilder) -> F) -> std::result::Result<T, c_int>
+    where F: Future<Output = Result<T>> {
+    if builder.is_null() {
+        set_error("Builder is null");
+        return Err(WTB_INVALID_ARGUMENT);
+    }
+    let builder = Box::from_raw(builder).builder;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| -> std::io::Result<Result<T>> {
+        let mut runtime = tokio::runtime::Runtime::new()?;
+        Ok(runtime.block_on(operation(builder)))
+    }));
+    match result {
+        Ok(Ok(Ok(value))) => Ok(value),
+        Ok(Ok(Err(e))) => {
+            set_error(&e.to_string());
+            Err(Failure::classify(&e).exit_code())
+        }
+        Ok(Err(e)) => {
+            set_error(&format!("Failed to start runtime: {}", e));
+            Err(Failure::Other.exit_code())
+        }
+        Err(_) => {
+            set_error("Library panicked");
+            Err(WTB_PANIC)
+        }
+    }
+}
+
+fn report_packages(packages: &[Package], callback: Option<WtbPackageCallback>, user_data: *mut c_void) {
+    if let Some(callback) = callback {
+        for package in packages.iter() {
+            let (name, version) = (c_string(&package.name), c_string(&package.version));
+            callback(name.as_ptr(), version.as_ptr(), user_data);
+        }
+    }
+}
+
+fn emit(callback: WtbEventCallback, user_data: &UserData, event: &Event) {
+    let (kind, package, text, current, total): (c_int, Option<&str>, Option<&str>, u64, u64) = match event {
+        Event::Message { text, warning: false } => (WTB_EVENT_MESSAGE, None, Some(text), 0, 0),
+        Event::Message { text, warning: true } => (WTB_EVENT_WARNING, None, Some(text), 0, 0),
+        Event::RepositoryLoad { received, size } => (WTB_EVENT_REPOSITORY_LOAD, None, None, *received, size.unwrap_or(0)),
+        Event::Resolving { package } => (WTB_EVENT_RESOLVING, Some(package), None, 0, 0),
+        Event::Resolved { packages } => (WTB_EVENT_RESOLVED, None, None, *packages as u64, *packages as u64),
+        Event::Started { packages } => (WTB_EVENT_STARTED, None, None, 0, packages.len() as u64),
+        Event::Download { package, received, size } => (WTB_EVENT_DOWNLOAD, Some(package), None, *received, *size),
+        Event::Received { bytes } => (WTB_EVENT_RECEIVED, None, None, *bytes, 0),
+        Event::Extract { package, file, processed, entries } => (WTB_EVENT_EXTRACT, Some(package), file.as_deref(), *processed, *entries),
+        Event::Installed { package } => (WTB_EVENT_INSTALLED, Some(package), None, 0, 0),
+    };
+    let (package, text) = (package.map(c_string), text.map(c_string));
+    let event = WtbEvent {
+        kind,
+        package: package.as_ref().map_or(ptr::null(), |package| package.as_ptr()),
+        text: text.as_ref().map_or(ptr::null(), |text| text.as_ptr()),
+        current,
+        total,
+    };
+    callback(&event, user_data.0);
+}
+
+/// Message of the last failed call on this thread, or null. Valid until the next failed call
+#[no_mangle]
+pub extern "C" fn wtb_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ref().map_or(ptr::null(), |error| error.as_ptr()))
+}
+
+/// Builder with the defaults of `ToolchainBuilder::new`. Freed by `wtb_builder_free` or by an operation
+#[no_mangle]
+pub extern "C" fn wtb_builder_new() -> *mut WtbBuilder {
+    Box::into_raw(Box::new(WtbBuilder { builder: ToolchainBuilder::new() }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wtb_builder_free(builder: *mut WtbBuilder) {
+    if !builder.is_null() {
+        drop(Box::from_raw(builder));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wtb_builder_set_package(builder: *mut WtbBuilder, name: *const c_char) -> c_int {
+    set(builder, name, |name| Ok(name.to_owned()), |builder, name| builder.package(&name))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wtb_builder_set_repository(builder: *mut WtbBuilder, url: *const c_char) -> c_int {
+    set(builder, url, |url| Ok(url.to_owned()), |builder, url| builder.repository(&url))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wtb_builder_set_repository_name(builder: *mut WtbBuilder, name: *const c_char) -> c_int {
+    set(builder, name, |name| Ok(name.to_owned()), |builder, name| builder.repository_name(&name))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wtb_builder_set_architecture(builder: *mut WtbBuilder, architecture: *const c_char) -> c_int {
+    set(builder, architecture, |architecture| Ok(architecture.to_owned()), |builder, architecture| builder.architecture(&architecture))
+}
+
+/// Repository format, like `--source`
+#[no_mangle]
+pub unsafe extern "C" fn wtb_builder_set_source(builder: *mut WtbBuilder, kind: *const c_char) -> c_int {
+    set(builder, kind, SourceKind::from_str, |builder, kind| builder.configure(|config| config.source = kind))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wtb_builder_set_output(builder: *mut WtbBuilder, path: *const c_char) -> c_int {
+    set(builder, path, |path| Ok(PathBuf::from(path)), |builder, path| builder.output(path))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wtb_builder_set_cache_dir(builder: *mut WtbBuilder, path: *const c_char) -> c_int {
+    set(builder, path, |path| Ok(PathBuf::from(path)), |builder, path| builder.cache_dir(path))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wtb_builder_add_include(builder: *mut WtbBuilder, pattern: *const c_char) -> c_int {
+    set(builder, pattern, |pattern| Regex::new(pattern).map_err(|e| e.to_string()), |builder, pattern| builder.include(pattern))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wtb_builder_add_exclude(builder: *mut WtbBuilder, pattern: *const c_char) -> c_int {
+    set(builder, pattern, |pattern| Regex::new(pattern).map_err(|e| e.to_string()), |builder, pattern| builder.exclude(pattern))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wtb_builder_set_parallelism(builder: *mut WtbBuilder, tasks: u32) -> c_int {
+    match builder.as_mut() {
+        Some(builder) if tasks > 0 => {
+            let current = std::mem::take(&mut builder.builder);
+            builder.builder = current.parallelism(tasks);
+            0
+        }
+        _ => {
+            set_error("Builder is null or task count is 0");
+            WTB_INVALID_ARGUMENT
+        }
+    }
+}
+
+/// Call `callback` with every progress event. It is called from worker threads
+#[no_mangle]
+pub unsafe extern "C" fn wtb_builder_on_event(builder: *mut WtbBuilder, callback: Option<WtbEventCallback>, user_data: *mut c_void) -> c_int {
+    match (builder.as_mut(), callback) {
+        (Some(builder), Some(callback)) => {
+            let user_data = UserData(user_data);
+            let current = std::mem::take(&mut builder.builder);
+            builder.builder = current.on_event(move |event| emit(callback, &user_data, event));
+            0
+        }
+        _ => {
+            set_error("Builder or callback is null");
+            WTB_INVALID_ARGUMENT
+        }
+    }
+}
+
+/// Install the package with its dependencies. Frees the builder
+#[no_mangle]
+pub unsafe extern "C" fn wtb_build(builder: *mut WtbBuilder) -> c_int {
+    match run(builder, ToolchainBuilder::build) {
+        Ok(()) => 0,
+        Err(code) => code,
+    }
+}
+
+/// Resolve the package with its dependencies and call `callback` with every package, nothing is
+/// installed. Frees the builder
+#[no_mangle]
+pub unsafe extern "C" fn wtb_resolve(builder: *mut WtbBuilder, callback: Option<WtbPackageCallback>, user_data: *mut c_void) -> c_int {
+    match run(builder, ToolchainBuilder::resolve) {
+        Ok(packages) => {
+            report_packages(&packages, callback, user_data);
+            0
+        }
+        Err(code) => code,
+    }
+}
+
+/// Download archives of the package and its dependencies into the cache folder and call `callback`
+/// with every package. Frees the builder
+#[no_mangle]
+pub unsafe extern "C" fn wtb_download(builder: *mut WtbBuilder, callback: Option<WtbPackageCallback>, user_data: *mut c_void) -> c_int {
+    match run(builder, ToolchainBuilder::download) {
+        Ok(packages) => {
+            report_packages(&packages, callback, user_data);
+            0
+        }
+        Err(code) => code,
+    }
+}
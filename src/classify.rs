@@ -0,0 +1,248 @@
+//! File type detection by magic bytes
+use std::convert::TryInto;
+use std::str::FromStr;
+
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+const AR_HEADER_SIZE: usize = 60;
+const IMAGE_FILE_DLL: u16 = 0x2000;
+const COFF_MACHINES: &[u16] = &[0x14c, 0x8664, 0x1c4, 0xaa64];
+
+/// Detected file kind
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileKind {
+    /// PE executable (`.exe`)
+    PeExecutable,
+    /// PE dynamic library (`.dll`)
+    PeLibrary,
+    /// `ar` archive with regular object files
+    StaticLibrary,
+    /// `ar` archive with DLL import stubs (`.dll.a`)
+    ImportLibrary,
+    /// COFF object file
+    Object,
+    /// Anything else
+    Other,
+}
+
+impl FileKind {
+    pub fn is_pe(&self) -> bool {
+        *self == FileKind::PeExecutable || *self == FileKind::PeLibrary
+    }
+
+    pub fn is_library(&self) -> bool {
+        *self == FileKind::StaticLibrary || *self == FileKind::ImportLibrary
+    }
+
+    pub fn is_binary(&self) -> bool {
+        *self != FileKind::Other
+    }
+}
+
+/// Detect file kind of `data`
+pub fn classify(data: &[u8]) -> FileKind {
+    if data.starts_with(AR_MAGIC) {
+        return if is_import_library(data) {
+            FileKind::ImportLibrary
+        } else {
+            FileKind::StaticLibrary
+        };
+    }
+    if let Some(characteristics) = pe_characteristics(data) {
+        return if characteristics & IMAGE_FILE_DLL != 0 {
+            FileKind::PeLibrary
+        } else {
+            FileKind::PeExecutable
+        };
+    }
+    if let Some(machine) = read_u16(data, 0) {
+        if COFF_MACHINES.contains(&machine) {
+            return FileKind::Object;
+        }
+    }
+    FileKind::Other
+}
+
+/// Check `ar` archive members for import stubs: short import objects produced by MSVC and LLVM
+/// tools, or COFF objects with `.idata$N` sections produced by dlltool
+fn is_import_library(data: &[u8]) -> bool {
+    let mut offset = AR_MAGIC.len();
+    while let Some(header) = data.get(offset..offset + AR_HEADER_SIZE) {
+        let name = String::from_utf8_lossy(&header[..16]);
+        let size: usize = match String::from_utf8_lossy(&header[48..58]).trim().parse() {
+            Ok(size) => size,
+            Err(_) => return false,
+        };
+        let start = offset + AR_HEADER_SIZE;
+        let member = match data.get(start..start + size) {
+            Some(member) => member,
+            None => return false,
+        };
+        let is_index = name.starts_with("/ ") || name.starts_with("// ") || name.starts_with("/SYM64/");
+        if !is_index && is_import_member(member) {
+            return true;
+        }
+        offset = start + size + size % 2;
+    }
+    false
+}
+
+fn is_import_member(member: &[u8]) -> bool {
+    if member.starts_with(&[0, 0, 0xff, 0xff]) {
+        return true;
+    }
+    let section_count = match read_u16(member, 2) {
+        Some(count) => count as usize,
+        None => return false,
+    };
+    let optional_size = match read_u16(member, 16) {
+        Some(size) => size as usize,
+        None => return false,
+    };
+    (0..section_count).any(|index| {
+        let header = 20 + optional_size + index * 40;
+        member.get(header..header + 7).map(|name| name == b".idata$").unwrap_or(false)
+    })
+}
+
+fn pe_characteristics(data: &[u8]) -> Option<u16> {
+    if !data.starts_with(b"MZ") {
+        return None;
+    }
+    let pe_offset = read_u32(data, 0x3c)? as usize;
+    if data.get(pe_offset..pe_offset + 4)? != b"PE\0\0" {
+        return None;
+    }
+    read_u16(data, pe_offset + 22)
+}
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset.checked_add(2)?)?.try_into().ok()?))
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset.checked_add(4)?)?.try_into().ok()?))
+}
+
+/// Filter which decides by file content
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ContentFilter {
+    /// Keep only PE executables and DLLs
+    PeOnly,
+    /// Drop static and import libraries
+    NoLibraries,
+    /// Drop everything which isn't a recognized binary
+    BinariesOnly,
+}
+
+impl FromStr for ContentFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pe-only" => Ok(ContentFilter::PeOnly),
+            "no-libs" => Ok(ContentFilter::NoLibraries),
+            "binaries-only" => Ok(ContentFilter::BinariesOnly),
+            _ => Err(format!("Unknown content filter: \"{}\"", s)),
+        }
+    }
+}
+
+impl ContentFilter {
+    pub fn keeps(&self, kind: FileKind) -> bool {
+        match self {
+            ContentFilter::PeOnly => kind.is_pe(),
+            ContentFilter::NoLibraries => !kind.is_library(),
+            ContentFilter::BinariesOnly => kind.is_binary(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `ar` archive with `members`
+    fn ar(members: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut data = AR_MAGIC.to_vec();
+        for (name, member) in members {
+            data.extend_from_slice(format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", name, 0, 0, 0, 644, member.len()).as_bytes());
+            data.extend_from_slice(member);
+            if member.len() % 2 == 1 {
+                data.push(b'\n');
+            }
+        }
+        data
+    }
+
+    /// AMD64 COFF object with one section named `section`
+    fn object(section: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; 20 + 40];
+        data[0..2].copy_from_slice(&0x8664u16.to_le_bytes());
+        data[2..4].copy_from_slice(&1u16.to_le_bytes());
+        data[20..20 + section.len()].copy_from_slice(section);
+        data
+    }
+
+    fn pe(characteristics: u16) -> Vec<u8> {
+        let mut data = vec![0; 0x80];
+        data[0..2].copy_from_slice(b"MZ");
+        data[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        data[0x40..0x44].copy_from_slice(b"PE\0\0");
+        data[0x40 + 22..0x40 + 24].copy_from_slice(&characteristics.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn classifies_pe() {
+        assert_eq!(classify(&pe(0x0022)), FileKind::PeExecutable);
+        assert_eq!(classify(&pe(0x2022)), FileKind::PeLibrary);
+    }
+
+    #[test]
+    fn classifies_object() {
+        assert_eq!(classify(&object(b".text")), FileKind::Object);
+        assert_eq!(classify(b"#!/bin/sh\n"), FileKind::Other);
+        assert_eq!(classify(b""), FileKind::Other);
+    }
+
+    #[test]
+    fn classifies_static_library() {
+        let data = ar(&[("/", vec![0; 4]), ("crt.o/", object(b".text"))]);
+        assert_eq!(classify(&data), FileKind::StaticLibrary);
+    }
+
+    #[test]
+    fn classifies_short_import_library() {
+        let data = ar(&[("/", vec![0; 4]), ("zlib1.dll/", vec![0, 0, 0xff, 0xff, 0, 0])]);
+        assert_eq!(classify(&data), FileKind::ImportLibrary);
+    }
+
+    #[test]
+    fn classifies_dlltool_import_library() {
+        let data = ar(&[("head.o/", vec![1, 2, 3]), ("stub.o/", object(b".idata$5"))]);
+        assert_eq!(classify(&data), FileKind::ImportLibrary);
+    }
+
+    #[test]
+    fn index_members_are_not_import_stubs() {
+        let data = ar(&[("/", vec![0, 0, 0xff, 0xff]), ("crt.o/", object(b".text"))]);
+        assert_eq!(classify(&data), FileKind::StaticLibrary);
+    }
+
+    #[test]
+    fn truncated_archive_is_static_library() {
+        let mut data = ar(&[("stub.o/", object(b".idata$5"))]);
+        data.truncate(data.len() - 10);
+        assert_eq!(classify(&data), FileKind::StaticLibrary);
+    }
+
+    #[test]
+    fn content_filters() {
+        assert!(ContentFilter::PeOnly.keeps(FileKind::PeLibrary));
+        assert!(!ContentFilter::PeOnly.keeps(FileKind::ImportLibrary));
+        assert!(!ContentFilter::NoLibraries.keeps(FileKind::StaticLibrary));
+        assert!(ContentFilter::NoLibraries.keeps(FileKind::Other));
+        assert!(!ContentFilter::BinariesOnly.keeps(FileKind::Other));
+        assert_eq!(ContentFilter::from_str("no-libs"), Ok(ContentFilter::NoLibraries));
+    }
+}
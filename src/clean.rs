@@ -0,0 +1,78 @@
+//! Removal of output files which no installed package owns
+use crate::manifest::Manifest;
+use crate::owners::{self, OWNERS_PATH};
+use crate::paths;
+use crate::progress::Progress;
+use crate::remove::remove_file;
+use crate::state::State;
+use std::collections::HashSet;
+use crate::error::Error;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+/// Options of `clean` command
+#[derive(Clone, Debug)]
+pub struct CleanOptions {
+    /// Output folder to clean
+    pub output: PathBuf,
+    /// Only list files which would be removed
+    pub dry_run: bool,
+    /// Remove files without asking
+    pub yes: bool,
+}
+
+/// Ask whether `count` files should be removed
+fn confirm(count: usize) -> io::Result<bool> {
+    let mut stderr = io::stderr();
+    write!(stderr, "Remove {} files? [y/N] ", count)?;
+    stderr.flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(matches!(line.trim(), "y" | "Y" | "yes"))
+}
+
+/// List files missing from the manifest and files of packages which the state database doesn't list.
+/// They are removed with `--yes` or when the user confirms it in the terminal
+pub async fn clean(options: &CleanOptions, progress: &Progress) -> Result<(), Error> {
+    let mut manifest = Manifest::load(&options.output).await?;
+    let installed: Option<HashSet<String>> = State::load(&options.output).await.ok()
+        .map(|state| state.packages.into_iter().map(|package| package.name).collect());
+    let is_orphan = |package: &str| match installed.as_ref() {
+        Some(installed) => package != env!("CARGO_PKG_NAME") && !installed.contains(package),
+        None => false,
+    };
+
+    let owned: HashSet<String> = manifest.files.iter()
+        .filter(|entry| !is_orphan(&entry.package))
+        .map(|entry| entry.path.clone())
+        .collect();
+    let output = options.output.clone();
+    let mut orphans: Vec<String> = tokio::task::spawn_blocking(move || paths::list_files(&output)).await??
+        .into_iter()
+        .filter(|path| !owned.contains(path))
+        .collect();
+    orphans.sort();
+    for path in orphans.iter() {
+        progress.info(&format!("Orphaned {}", path));
+    }
+    let remove = !options.dry_run && !orphans.is_empty() && (options.yes || (progress.interactive() && confirm(orphans.len())?));
+    if remove {
+        for path in orphans.iter() {
+            remove_file(&options.output, path).await?;
+        }
+        progress.info(&format!("Removed {} orphaned files", orphans.len()));
+    } else if !options.dry_run && !orphans.is_empty() && !options.yes {
+        progress.info(&format!("{} orphaned files, run with --yes to remove them", orphans.len()));
+    } else {
+        progress.info(&format!("{} orphaned files", orphans.len()));
+    }
+
+    // Files of orphaned packages are among orphans, so their entries are dropped only with the files
+    let prune = !options.dry_run && (remove || orphans.is_empty());
+    if prune && manifest.files.iter().any(|entry| is_orphan(&entry.package)) {
+        manifest.files.retain(|entry| !is_orphan(&entry.package));
+        manifest.save(&options.output).await?;
+        tokio::fs::write(paths::join(&options.output, OWNERS_PATH), owners::encode(&manifest)).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,183 @@
+//! Detection of files which clobber each other on case-insensitive file systems
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// What to do with files which differ from already extracted ones only by case
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CollisionPolicy {
+    /// Abort the run
+    Fail,
+    /// Append `~N` to the colliding file or folder name
+    Rename,
+}
+
+impl FromStr for CollisionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(CollisionPolicy::Fail),
+            "rename" => Ok(CollisionPolicy::Rename),
+            _ => Err(format!("Unknown collision policy: \"{}\"", s)),
+        }
+    }
+}
+
+/// Detected collision
+#[derive(Clone, Debug)]
+pub struct Collision {
+    /// Path of already claimed file
+    pub existing: String,
+    /// Package which owns already claimed file
+    pub owner: String,
+}
+
+struct Owner {
+    path: String,
+    package: String,
+}
+
+#[derive(Default)]
+struct Index {
+    /// Owners of claimed files and folders by case-folded path. Folder paths end with `/`
+    owners: HashMap<String, Owner>,
+    /// Renamed folder paths by original ones, so all files of a folder are moved to the same one
+    renamed_folders: HashMap<String, String>,
+}
+
+impl Index {
+    fn claim(&mut self, path: &str, package: &str) -> Result<(), Collision> {
+        let owner = self.owners.entry(path.to_lowercase())
+            .or_insert_with(|| Owner { path: path.to_owned(), package: package.to_owned() });
+        if owner.path == path {
+            Ok(())
+        } else {
+            Err(Collision { existing: owner.path.clone(), owner: owner.package.clone() })
+        }
+    }
+
+    /// Claim first unclaimed variant of `name` with `~N` suffix in folder `dir`. Folder names end with `/`
+    fn claim_renamed(&mut self, dir: &str, name: &str, package: &str) -> String {
+        let (name, slash) = match name.strip_suffix('/') {
+            Some(name) => (name, "/"),
+            None => (name, ""),
+        };
+        let (stem, extension) = match name.find('.') {
+            Some(index) if index > 0 && slash.is_empty() => name.split_at(index),
+            _ => (name, ""),
+        };
+        let mut counter = 1;
+        loop {
+            let candidate = format!("{}{}~{}{}{}", dir, stem, counter, extension, slash);
+            if let Entry::Vacant(entry) = self.owners.entry(candidate.to_lowercase()) {
+                entry.insert(Owner { path: candidate.clone(), package: package.to_owned() });
+                return candidate;
+            }
+            counter += 1;
+        }
+    }
+}
+
+/// Folders of `path` with trailing `/`, outermost first, and then `path` itself
+fn prefixes(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/').map(move |(index, _)| &path[..index + 1]).chain(std::iter::once(path))
+}
+
+/// Index of all output paths of the run and of their folders, keyed by case-folded path
+pub struct CaseIndex {
+    index: Mutex<Index>,
+}
+
+impl CaseIndex {
+    pub fn new() -> Self {
+        CaseIndex { index: Mutex::new(Index::default()) }
+    }
+
+    /// Claim `path` for `package`. Same path can be claimed multiple times, but paths differing only by
+    /// case can't, and neither can paths with folders differing only by case, like `Include/a.h` and
+    /// `include/b.h`, which would be merged into one folder
+    pub fn claim(&self, path: &str, package: &str) -> Result<(), Collision> {
+        let mut index = self.index.lock().unwrap();
+        prefixes(path).try_for_each(|prefix| index.claim(prefix, package))
+    }
+
+    /// Claim `path` with every folder and the file name which collide renamed to the first unclaimed
+    /// variant with `~N` suffix
+    pub fn claim_renamed(&self, path: &str, package: &str) -> String {
+        let mut index = self.index.lock().unwrap();
+        let mut claimed = String::new();
+        let mut start = 0;
+        for prefix in prefixes(path) {
+            let name = &prefix[start..];
+            start = prefix.len();
+            let is_folder = prefix.ends_with('/') && prefix != path;
+            if is_folder {
+                if let Some(renamed) = index.renamed_folders.get(prefix) {
+                    claimed = renamed.clone();
+                    continue;
+                }
+            }
+            let candidate = format!("{}{}", &claimed, name);
+            if index.claim(&candidate, package).is_ok() {
+                claimed = candidate;
+                continue;
+            }
+            let renamed = index.claim_renamed(&claimed, name, package);
+            if is_folder {
+                index.renamed_folders.insert(prefix.to_owned(), renamed.clone());
+            }
+            claimed = renamed;
+        }
+        claimed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_path_is_claimed_again() {
+        let index = CaseIndex::new();
+        assert!(index.claim("bin/gcc.exe", "gcc").is_ok());
+        assert!(index.claim("bin/gcc.exe", "gcc").is_ok());
+        assert!(index.claim("bin/ld.exe", "binutils").is_ok());
+    }
+
+    #[test]
+    fn file_differing_by_case_collides() {
+        let index = CaseIndex::new();
+        index.claim("lib/Foo.h", "a").unwrap();
+        let collision = index.claim("lib/foo.h", "b").unwrap_err();
+        assert_eq!(collision.existing, "lib/Foo.h");
+        assert_eq!(collision.owner, "a");
+    }
+
+    #[test]
+    fn folder_differing_by_case_collides() {
+        let index = CaseIndex::new();
+        index.claim("Include/a.h", "a").unwrap();
+        let collision = index.claim("include/b.h", "b").unwrap_err();
+        assert_eq!(collision.existing, "Include/");
+        assert_eq!(collision.owner, "a");
+    }
+
+    #[test]
+    fn renamed_file_keeps_extension() {
+        let index = CaseIndex::new();
+        index.claim("lib/Foo.h", "a").unwrap();
+        assert_eq!(index.claim_renamed("lib/foo.h", "b"), "lib/foo~1.h");
+        assert_eq!(index.claim_renamed("lib/FOO.h", "c"), "lib/FOO~2.h");
+    }
+
+    #[test]
+    fn files_of_renamed_folder_share_it() {
+        let index = CaseIndex::new();
+        index.claim("Include/a.h", "a").unwrap();
+        assert_eq!(index.claim_renamed("include/b.h", "b"), "include~1/b.h");
+        assert_eq!(index.claim_renamed("include/sub/c.h", "b"), "include~1/sub/c.h");
+        assert!(index.claim("Include/d.h", "a").is_ok());
+    }
+}
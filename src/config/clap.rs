@@ -1,108 +1,1269 @@
 //! This module provides configuration from CLI arguments
-use clap::{ArgMatches, App, Arg};
-use crate::config::{IntoConfig, Config};
+use clap::{ArgMatches, App, AppSettings, Arg, ErrorKind, SubCommand};
+use crate::config::{IntoConfig, Config, Command};
+use crate::error::ConfigError;
+use crate::config::file::ConfigFile;
+use crate::pick::PackageChoice;
 use std::str::FromStr;
 use regex::Regex;
-use std::path::PathBuf;
+use std::path::{PathBuf, Path};
+use std::time::Duration;
+use crate::sanitize::NamePolicy;
+use crate::paths::PathRemap;
+use crate::collision::CollisionPolicy;
+use crate::archive::ArchiveFormat;
+use crate::layout::Layout;
+use crate::text::{LineEnding, PrefixRewrite};
+use crate::classify::ContentFilter;
+use crate::tool::CommandTemplate;
+use crate::progress::ProgressMode;
+use crate::logging;
+use tracing::Level;
+use crate::hooks::Hook;
+use crate::download::default_cache_dir;
+use crate::relocate::Relocation;
+use crate::imports::ImportCheck;
+use crate::package::{PackageOptions, PackageFormat};
+use crate::installer::{InstallerOptions, InstallerKind};
+use crate::remove::RemoveOptions;
+use crate::cache::{CacheAction, CacheOptions};
+use crate::clean::CleanOptions;
+use crate::diff::DiffOptions;
+use crate::mirror::MirrorOptions;
+use crate::outdated::OutdatedOptions;
+use crate::owners::OwnsOptions;
+use crate::verify::VerifyOptions;
+use crate::state::{AddOptions, UpdateOptions};
+use crate::snapshot::RollbackOptions;
+use crate::bundle::{StateAction, StateOptions};
+use crate::license::{LicensePolicy, Glob, LicenseAction};
+use crate::generate::ToolchainMode;
+use crate::space;
+use crate::source::SourceKind;
+use crate::script::ScriptFilter;
+use crate::wasm::WasmPlugin;
+use crate::urls::UrlRewrite;
 
 impl IntoConfig for ArgMatches<'static> {
-    fn to_config(&self) -> Config {
+    fn to_config(&self) -> Result<Config, ConfigError> {
         let cpu_count = num_cpus::get().to_string();
-        Config {
-            package: self.value_of("package").unwrap().to_string(),
+        let file = ConfigFile::load(self.value_of("config").map(Path::new)).map_err(ConfigError::Invalid)?;
+        let filter_script = self.value_of("filter-script")
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read {}: {}", path, e))
+                    .and_then(|source| ScriptFilter::new(&source))
+            })
+            .transpose()
+            .map_err(ConfigError::Invalid)?;
+        let wasm_plugins = file.wasm_plugins.iter()
+            .map(|path| WasmPlugin::load(path))
+            .collect::<Result<Vec<WasmPlugin>, String>>()
+            .map_err(ConfigError::Invalid)?;
+        let stage_hooks = file.hooks.to_hooks().map_err(ConfigError::Invalid)?;
+        let file_url_rewrites = file.rewrite_url.iter()
+            .map(|rewrite| UrlRewrite::new(&rewrite.package, &rewrite.url))
+            .collect::<Result<Vec<UrlRewrite>, String>>()
+            .map_err(ConfigError::Invalid)?;
+        let mut theme = file.progress;
+        theme.color &= !self.is_present("no-color") && std::env::var_os("NO_COLOR").is_none();
+        let log_level = self.value_of("log-level").map(|val| Level::from_str(val).unwrap())
+            .or_else(|| logging::verbosity(self.occurrences_of("verbose")));
+        let command = match self.subcommand() {
+            ("package", Some(matches)) => {
+                let self_extract = matches.value_of("self-extract");
+                let destination = PathBuf::from(self_extract.or(matches.value_of("archive")).unwrap());
+                let format = match self_extract {
+                    Some(_) => PackageFormat::SevenZip,
+                    None => PackageFormat::from_str(matches.value_of("format").unwrap()).unwrap(),
+                };
+                Command::Package(PackageOptions {
+                    format,
+                    root: matches.value_of("root")
+                        .map(String::from)
+                        .unwrap_or_else(|| archive_stem(&destination)),
+                    destination,
+                    level: matches.value_of("level").map(|val| u32::from_str(val).unwrap()),
+                    seven_zip: matches.value_of("7z-program").unwrap().to_string(),
+                    self_extract: self_extract.is_some(),
+                    reproducible: matches.is_present("reproducible"),
+                    sfx_module: matches.value_of("sfx-module").map(PathBuf::from),
+                })
+            }
+            ("installer", Some(matches)) => {
+                let name = matches.value_of("name")
+                    .map(String::from)
+                    .or_else(|| Path::new(self.value_of("output").unwrap()).file_name().map(|name| name.to_string_lossy().into_owned()))
+                    .unwrap_or_else(|| String::from("toolchain"));
+                Command::Installer(InstallerOptions {
+                    kind: InstallerKind::from_str(matches.value_of("kind").unwrap()).unwrap(),
+                    destination: PathBuf::from(matches.value_of("destination").unwrap()),
+                    version: matches.value_of("product-version").unwrap().to_string(),
+                    publisher: matches.value_of("publisher").map(String::from).unwrap_or_else(|| name.clone()),
+                    add_to_path: matches.is_present("add-to-path"),
+                    name,
+                })
+            }
+            ("remove", Some(matches)) => Command::Remove(RemoveOptions {
+                packages: matches.values_of("packages").map(|v| v.map(String::from).collect()).unwrap_or(Vec::new()),
+                all: matches.is_present("all"),
+            }),
+            ("add", Some(matches)) => Command::Add(AddOptions {
+                output: PathBuf::from(self.value_of("output").unwrap()),
+                packages: matches.values_of("packages").unwrap().map(String::from).collect(),
+            }),
+            ("cache", Some(matches)) => Command::Cache(CacheOptions {
+                action: match matches.subcommand() {
+                    ("prune", Some(matches)) => CacheAction::Prune {
+                        keep_days: matches.value_of("keep-days").map(|val| u64::from_str(val).unwrap()),
+                        max_size: matches.value_of("max-size").map(|val| space::parse_size(val).unwrap()),
+                    },
+                    ("verify", Some(matches)) => CacheAction::Verify { delete: matches.is_present("delete") },
+                    ("gc", Some(matches)) => CacheAction::Gc { dry_run: matches.is_present("dry-run") },
+                    _ => CacheAction::Info,
+                },
+            }),
+            ("clean", Some(matches)) => Command::Clean(CleanOptions {
+                output: PathBuf::from(matches.value_of("output-dir").or(self.value_of("output")).unwrap()),
+                dry_run: matches.is_present("dry-run"),
+                yes: matches.is_present("yes"),
+            }),
+            ("diff", Some(matches)) => Command::Diff(DiffOptions {
+                old: PathBuf::from(matches.value_of("old").unwrap()),
+                new: PathBuf::from(matches.value_of("new").unwrap()),
+            }),
+            ("mirror", Some(matches)) => Command::Mirror(MirrorOptions {
+                package: matches.value_of("package").unwrap().to_string(),
+                destination: PathBuf::from(matches.value_of("destination").unwrap()),
+                regenerate_db: matches.is_present("regenerate-db"),
+            }),
+            ("outdated", Some(matches)) => Command::Outdated(OutdatedOptions {
+                output: PathBuf::from(matches.value_of("output-dir").or(self.value_of("output")).unwrap()),
+                lockfile: matches.value_of("lockfile").map(PathBuf::from),
+            }),
+            ("owns-local", Some(matches)) => Command::Owns(OwnsOptions {
+                paths: matches.values_of("paths").unwrap().map(PathBuf::from).collect(),
+            }),
+            ("rollback", Some(matches)) => Command::Rollback(RollbackOptions {
+                output: PathBuf::from(matches.value_of("output-dir").or(self.value_of("output")).unwrap()),
+                snapshot: matches.value_of("snapshot").map(String::from),
+                list: matches.is_present("list"),
+            }),
+            ("state", Some(matches)) => {
+                let (action, matches) = match matches.subcommand() {
+                    ("export", Some(matches)) => (StateAction::Export { bundle: PathBuf::from(matches.value_of("bundle").unwrap()) }, matches),
+                    ("import", Some(matches)) => (StateAction::Import { bundle: PathBuf::from(matches.value_of("bundle").unwrap()) }, matches),
+                    _ => unreachable!(),
+                };
+                Command::State(StateOptions {
+                    output: PathBuf::from(matches.value_of("output-dir").or(self.value_of("output")).unwrap()),
+                    action,
+                })
+            }
+            ("update", Some(matches)) => Command::Update(UpdateOptions {
+                output: PathBuf::from(matches.value_of("output-dir").or(self.value_of("output")).unwrap()),
+            }),
+            ("verify", Some(matches)) => Command::Verify(VerifyOptions {
+                output: PathBuf::from(matches.value_of("output-dir").or(self.value_of("output")).unwrap()),
+            }),
+            _ => Command::Build,
+        };
+        Ok(Config {
+            command,
+            package: self.value_of("package").unwrap_or_default().to_string(),
+            package_choice: if self.is_present("first") {
+                PackageChoice::First
+            } else if self.is_present("exact") {
+                PackageChoice::Exact
+            } else {
+                PackageChoice::Ask
+            },
+            source: SourceKind::from_str(self.value_of("source").unwrap()).unwrap(),
             repository: self.value_of("repository").unwrap().to_string(),
             repository_name: self.value_of("repository-name").unwrap().to_string(),
             architecture: self.value_of("architecture").unwrap().to_string(),
+            license_policy: LicensePolicy {
+                allow: self.values_of("allow-license").map(|v| v.map(|val| Glob::from_str(val).unwrap()).collect()).unwrap_or(Vec::new()),
+                deny: self.values_of("deny-license").map(|v| v.map(|val| Glob::from_str(val).unwrap()).collect()).unwrap_or(Vec::new()),
+                action: LicenseAction::from_str(self.value_of("license-action").unwrap()).unwrap(),
+            },
             parallelism: u32::from_str(&self.value_of("parallelism").unwrap_or(&cpu_count).to_string()).unwrap(),
+            extract_threads: u32::from_str(self.value_of("extract-threads").unwrap()).unwrap(),
             exclude: self.values_of("exclude").map(|v| v.map(|val| Regex::new(val).unwrap()).collect()).unwrap_or(Vec::new()),
             include: self.values_of("include").map(|v| v.map(|val| Regex::new(val).unwrap()).collect()).unwrap_or(Vec::new()),
-            output_folder: PathBuf::from(self.value_of("output").unwrap())
-        }
+            explain_filters: self.is_present("explain-filters"),
+            content_filters: self.values_of("content-filter").map(|v| v.map(|val| ContentFilter::from_str(val).unwrap()).collect()).unwrap_or(Vec::new()),
+            filter_script,
+            no_static_libs: self.is_present("no-static-libs"),
+            strip: self.is_present("strip"),
+            strip_tool: self.value_of("strip-tool").map(String::from),
+            compress_command: self.value_of("compress-cmd").map(|val| CommandTemplate::from_str(val).unwrap()),
+            hooks: self.values_of("hook").map(|v| v.map(|val| Hook::from_str(val).unwrap()).collect()).unwrap_or(Vec::new())
+                .into_iter()
+                .chain(self.values_of("fixup").map(|v| v.map(|val| Hook::builtin(val).unwrap()).collect()).unwrap_or(Vec::new()))
+                .collect(),
+            stage_hooks,
+            sign_command: self.value_of("sign-cmd").map(|val| CommandTemplate::from_str(val).unwrap()),
+            plugin: self.value_of("plugin").map(|val| CommandTemplate::from_str(val).unwrap()),
+            wasm_plugins,
+            plugin_timeout: Duration::from_secs(u64::from_str(self.value_of("plugin-timeout").unwrap()).unwrap()),
+            compress_exclude: self.values_of("compress-exclude").map(|v| v.map(|val| Regex::new(val).unwrap()).collect()).unwrap_or(Vec::new()),
+            text_files: self.values_of("text-files").map(|v| v.map(|val| Regex::new(val).unwrap()).collect()).unwrap_or(Vec::new()),
+            prefix_rewrites: self.values_of("rewrite-prefix").map(|v| v.map(|val| PrefixRewrite::from_str(val).unwrap()).collect()).unwrap_or(Vec::new()),
+            line_endings: self.value_of("line-endings").map(|val| LineEnding::from_str(val).unwrap()),
+            relocation: self.value_of("relocate").map(|val| Relocation::from_str(val).unwrap()),
+            notices: self.is_present("notices") || self.is_present("notices-concat"),
+            notices_concat: self.is_present("notices-concat"),
+            name_policy: NamePolicy::from_str(self.value_of("name-policy").unwrap()).unwrap(),
+            case_collisions: CollisionPolicy::from_str(self.value_of("case-collisions").unwrap()).unwrap(),
+            path_remaps: self.values_of("path-remap").map(|v| v.map(|val| PathRemap::from_str(val).unwrap()).collect()).unwrap_or(Vec::new()),
+            max_path: usize::from_str(self.value_of("max-path").unwrap()).unwrap(),
+            output_folder: PathBuf::from(self.value_of("output").unwrap()),
+            archive_output: self.value_of("archive-output").map(PathBuf::from),
+            cache_dir: self.value_of("cache-dir").map(PathBuf::from).unwrap_or_else(default_cache_dir),
+            url_rewrites: self.values_of("rewrite-url").map(|v| v.map(|val| UrlRewrite::from_str(val).unwrap()).collect()).unwrap_or(Vec::new())
+                .into_iter()
+                .chain(file_url_rewrites)
+                .collect(),
+            url_resolver: None,
+            resume: self.is_present("resume"),
+            wait_lock: self.is_present("wait-lock"),
+            log_level,
+            log_file: self.value_of("log-file").map(PathBuf::from),
+            notify_command: self.value_of("notify-cmd").map(|val| CommandTemplate::from_str(val).unwrap()),
+            notify_webhook: self.value_of("notify-webhook").map(String::from),
+            notify_desktop: self.is_present("notify-desktop") || file.notify.desktop,
+            theme,
+            progress_mode: if self.is_present("tui") {
+                ProgressMode::Tui
+            } else if self.is_present("quiet") {
+                ProgressMode::Quiet
+            } else if self.is_present("no-progress") {
+                ProgressMode::Plain
+            } else if log_level.is_some() && self.occurrences_of("progress-format") == 0 {
+                ProgressMode::Log
+            } else {
+                ProgressMode::from_str(self.value_of("progress-format").unwrap()).unwrap()
+            },
+            force: self.is_present("force"),
+            keep_archives: self.value_of("keep-archives").map(PathBuf::from),
+            checksums: self.value_of("checksums").map(PathBuf::from),
+            env_scripts: self.is_present("env-scripts"),
+            cmake_toolchain: self.value_of("emit-cmake-toolchain").map(String::from),
+            meson_file: self.value_of("emit-meson-file").map(String::from),
+            cargo_config: self.value_of("emit-cargo-config").map(String::from),
+            toolchain_mode: self.value_of("toolchain-mode").map(|val| ToolchainMode::from_str(val).unwrap()).unwrap_or_else(ToolchainMode::host_default),
+            layout: Layout::from_str(self.value_of("layout").unwrap()).unwrap(),
+            import_libs: self.is_present("import-libs"),
+            prune_to: self.values_of("prune-to").map(|v| v.map(String::from).collect()).unwrap_or(Vec::new()),
+            prune_keep: self.values_of("prune-keep").map(|v| v.map(String::from).collect()).unwrap_or(Vec::new()),
+            check_imports: self.value_of("check-imports").map(|val| ImportCheck::from_str(val).unwrap()),
+            reproducible: self.is_present("reproducible"),
+            max_output_size: self.value_of("max-output-size").map(|val| space::parse_size(val).unwrap()),
+            report_size: if self.is_present("report-size") {
+                Some(usize::from_str(self.value_of("report-size").unwrap_or("20")).unwrap())
+            } else {
+                None
+            },
+            write_lockfile: self.value_of("write-lockfile").map(PathBuf::from),
+            locked: self.value_of("locked").map(PathBuf::from),
+            allow_snapshot_fallback: self.is_present("allow-snapshot-fallback"),
+            stats: self.is_present("stats"),
+            stats_file: self.value_of("stats").map(PathBuf::from),
+            timings: self.value_of("timings").map(PathBuf::from),
+            metrics: self.value_of("metrics").map(PathBuf::from),
+            allow_imports: self.values_of("allow-import").map(|v| v.map(|val| Glob::from_str(val).unwrap()).collect()).unwrap_or(Vec::new()),
+        })
     }
 }
 
-fn args() -> Box<ArgMatches<'static>> {
-    Box::new(
-        App::new("windows-toolchain-builder")
-            .version(env!("CARGO_PKG_VERSION"))
-            .author("Aleksei Arsenev <alesharik4@gmail.com>")
-            .arg(
-                Arg::with_name("package")
-                    .index(1)
-                    .help("Package name")
-                    .required(true)
-            )
-            .arg(
-                Arg::with_name("repository")
-                    .short("r")
-                    .long("repository")
-                    .value_name("REPOSITORY")
-                    .help("Address to package repository")
-                    .takes_value(true)
-                    .default_value("http://repo.msys2.org/mingw")
-            )
-            .arg(
-                Arg::with_name("repository-name")
-                    .short("n")
-                    .long("reponame")
-                    .value_name("REPOSITORY_NAME")
-                    .help("Package repository name")
-                    .takes_value(true)
-                    .default_value("mingw64")
-            )
-            .arg(
-                Arg::with_name("output")
-                    .short("o")
-                    .long("output")
-                    .value_name("OUTPUT")
-                    .help("Output folder")
-                    .takes_value(true)
-                    .default_value("./")
-            )
-            .arg(
-                Arg::with_name("parallelism")
-                    .short("p")
-                    .value_name("PARALLELISM")
-                    .help("Download/extract thread pool parallelism")
-                    .takes_value(true)
-            )
-            .arg(
-                Arg::with_name("exclude")
-                    .short("e")
-                    .value_name("EXCLUDE")
-                    .help("Exclude files or folders by regex")
-                    .multiple(true)
-                    .takes_value(true)
-            )
-            .arg(
-                Arg::with_name("include")
-                    .short("i")
-                    .value_name("INCLUDE")
-                    .help("Include files or folders by regex. Only files which matches regex will be included. All files which matches include and exclude regex will *not* be included")
-                    .multiple(true)
-                    .takes_value(true)
-            )
-            .arg(
-                Arg::with_name("architecture")
-                    .short("a")
-                    .long("arch")
-                    .value_name("ARCH")
-                    .help("Package architecture")
-                    .takes_value(true)
-                    .default_value("x86_64")
-                    .validator(|arch| {
-                        if arch == "x86_64" || arch == "i686" {
-                            return Ok(());
-                        }
-                        Err(String::from(format!("Unknown architecture: \"{}\"", arch)))
-                    })
-            )
-            .get_matches()
-    )
+/// Archive file name without extensions
+fn archive_stem(path: &Path) -> String {
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    match name.find('.') {
+        Some(index) if index > 0 => name[..index].to_owned(),
+        _ => name,
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("windows-toolchain-builder")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("Aleksei Arsenev <alesharik4@gmail.com>")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .after_help("EXIT CODES:\n    0    Success\n    1    Other failure\n    2    Invalid arguments, config file or settings\n    3    Packages can't be resolved\n    4    Repository or package archive can't be downloaded or is damaged\n    5    Package can't be extracted\n    6    Output doesn't pass checks\n    130  Cancelled")
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("FILE")
+                .help("Read settings from FILE instead of windows-toolchain-builder/config.toml in the user configuration folder")
+                .takes_value(true)
+                .global(true)
+        )
+        .arg(
+            Arg::with_name("no-color")
+                .long("no-color")
+                .help("Don't color progress bars and logs. NO_COLOR environment variable does the same")
+                .global(true)
+        )
+        .arg(
+            Arg::with_name("reproducible")
+                .long("reproducible")
+                .help("Make archives bit-identical between runs: sort entries, use fixed modification time (SOURCE_DATE_EPOCH or zero) and fixed compressor settings")
+                .global(true)
+        )
+        .subcommand(
+            SubCommand::with_name("package")
+                .about("Pack files of the output folder (see --output) into a distributable archive")
+                .arg(
+                    Arg::with_name("archive")
+                        .index(1)
+                        .help("Archive to create")
+                        .required_unless("self-extract")
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Archive format")
+                        .takes_value(true)
+                        .possible_values(&["zip", "tar.zst", "7z"])
+                        .default_value("zip")
+                )
+                .arg(
+                    Arg::with_name("level")
+                        .long("level")
+                        .value_name("LEVEL")
+                        .help("Compression level: 1-21 for tar.zst, 0-9 for 7z. Ignored by zip")
+                        .takes_value(true)
+                        .validator(|level| u32::from_str(&level).map(|_| ()).map_err(|e| e.to_string()))
+                )
+                .arg(
+                    Arg::with_name("7z-program")
+                        .long("7z-program")
+                        .value_name("PROGRAM")
+                        .help("7-Zip executable used by 7z format")
+                        .takes_value(true)
+                        .default_value("7z")
+                )
+                .arg(
+                    Arg::with_name("self-extract")
+                        .long("self-extract")
+                        .value_name("EXE")
+                        .help("Create self-extracting executable instead of archive. Implies 7z format")
+                        .takes_value(true)
+                        .conflicts_with("archive")
+                )
+                .arg(
+                    Arg::with_name("sfx-module")
+                        .long("sfx-module")
+                        .value_name("FILE")
+                        .help("7-Zip SFX module used as --self-extract stub. Defaults to 7z.sfx next to 7z executable")
+                        .takes_value(true)
+                        .requires("self-extract")
+                )
+                .arg(
+                    Arg::with_name("root")
+                        .long("root")
+                        .value_name("NAME")
+                        .help("Top-level folder of the archive. Defaults to archive name without extension, use empty string to put files into the archive root")
+                        .takes_value(true)
+                        .empty_values(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("installer")
+                .about("Generate NSIS script or WiX source with payload for files of the output folder (see --output)")
+                .arg(
+                    Arg::with_name("destination")
+                        .index(1)
+                        .help("Folder to generate installer source in")
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("kind")
+                        .long("kind")
+                        .value_name("KIND")
+                        .help("Installer toolkit")
+                        .takes_value(true)
+                        .possible_values(&["nsis", "wix"])
+                        .default_value("nsis")
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .value_name("NAME")
+                        .help("Product name and installation folder name. Defaults to output folder name")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("product-version")
+                        .long("product-version")
+                        .value_name("VERSION")
+                        .help("Product version")
+                        .takes_value(true)
+                        .default_value("1.0.0")
+                )
+                .arg(
+                    Arg::with_name("publisher")
+                        .long("publisher")
+                        .value_name("PUBLISHER")
+                        .help("Product publisher. Defaults to product name")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("add-to-path")
+                        .long("add-to-path")
+                        .help("Add bin folder of the toolchain to system PATH on install and remove it on uninstall")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("Install packages with their dependencies which are not installed yet into the output folder (see --output), using settings it was built with")
+                .arg(
+                    Arg::with_name("packages")
+                        .index(1)
+                        .help("Packages to install")
+                        .multiple(true)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("cache")
+                .about("Manage the package archive cache set with --cache-dir")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("info")
+                        .about("Print number and size of cached archives")
+                )
+                .subcommand(
+                    SubCommand::with_name("prune")
+                        .about("Remove partial downloads, archives unused for --keep-days and least recently used archives above --max-size")
+                        .arg(
+                            Arg::with_name("keep-days")
+                                .long("keep-days")
+                                .value_name("DAYS")
+                                .help("Remove archives which weren't used for DAYS days")
+                                .takes_value(true)
+                                .validator(|days| u64::from_str(&days).map(|_| ()).map_err(|e| e.to_string()))
+                        )
+                        .arg(
+                            Arg::with_name("max-size")
+                                .long("max-size")
+                                .value_name("SIZE")
+                                .help("Remove least recently used archives until the cache takes at most SIZE (e.g. 5GiB)")
+                                .takes_value(true)
+                                .validator(|size| space::parse_size(&size).map(|_| ()))
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("gc")
+                        .about("Remove archives which no output or lockfile built with the cache references")
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .help("Only list archives which would be removed")
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("verify")
+                        .about("Check that cached archives can be read")
+                        .arg(
+                            Arg::with_name("delete")
+                                .long("delete")
+                                .help("Remove damaged archives, so they are downloaded again")
+                        )
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("clean")
+                .about("List files of an output folder which no installed package owns, like leftovers of removed packages and older versions, and remove them after confirmation")
+                .arg(
+                    Arg::with_name("output-dir")
+                        .index(1)
+                        .help("Output folder to clean. Defaults to --output")
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Only list files which would be removed, without asking")
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("Remove files without asking")
+                        .conflicts_with("dry-run")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Show added, removed and changed files and package versions between two outputs")
+                .arg(
+                    Arg::with_name("old")
+                        .index(1)
+                        .help("Old output folder or its manifest.json")
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("new")
+                        .index(2)
+                        .help("New output folder or its manifest.json")
+                        .required(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("mirror")
+                .about("Download a package with its dependencies and repository databases into a pacman repository folder")
+                .arg(
+                    Arg::with_name("package")
+                        .index(1)
+                        .help("Package name")
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("destination")
+                        .index(2)
+                        .help("Repository folder")
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("regenerate-db")
+                        .long("regenerate-db")
+                        .help("Write databases which list only mirrored packages instead of upstream ones")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("outdated")
+                .about("List installed packages which have newer versions in the repository without downloading them")
+                .arg(
+                    Arg::with_name("output-dir")
+                        .index(1)
+                        .help("Output folder to check. Defaults to --output")
+                )
+                .arg(
+                    Arg::with_name("lockfile")
+                        .long("lockfile")
+                        .value_name("FILE")
+                        .help("Check packages pinned by lockfile FILE instead of the output")
+                        .takes_value(true)
+                        .conflicts_with("output-dir")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("owns-local")
+                .about("Show which package provided files of a built toolchain")
+                .arg(
+                    Arg::with_name("paths")
+                        .index(1)
+                        .help("Files inside a built toolchain, or paths relative to the output folder (see --output)")
+                        .multiple(true)
+                        .required(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("Delete files contributed by packages and dependencies nothing else requires from the output folder (see --output), using its manifest")
+                .arg(
+                    Arg::with_name("packages")
+                        .index(1)
+                        .help("Packages to remove. Files also owned by other packages are kept")
+                        .multiple(true)
+                        .required_unless("all")
+                )
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .help("Remove every file listed in the manifest together with tool data")
+                        .conflicts_with("packages")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("rollback")
+                .about("Restore an output folder to the state before the last update")
+                .arg(
+                    Arg::with_name("output-dir")
+                        .index(1)
+                        .help("Output folder to restore. Defaults to --output")
+                )
+                .arg(
+                    Arg::with_name("snapshot")
+                        .long("snapshot")
+                        .value_name("NAME")
+                        .help("Snapshot to restore instead of the latest one")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("list")
+                        .long("list")
+                        .help("List snapshots of the output")
+                        .conflicts_with("snapshot")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("state")
+                .about("Move state of an output folder to another machine")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("export")
+                        .about("Write manifest, state database and lockfile of an output folder into a tar bundle")
+                        .arg(
+                            Arg::with_name("bundle")
+                                .index(1)
+                                .help("Bundle to create")
+                                .required(true)
+                        )
+                        .arg(
+                            Arg::with_name("output-dir")
+                                .index(2)
+                                .help("Output folder to export. Defaults to --output")
+                        )
+                )
+                .subcommand(
+                    SubCommand::with_name("import")
+                        .about("Rebuild an empty output folder with packages pinned by a bundle, or take over management of a folder which already has its files")
+                        .arg(
+                            Arg::with_name("bundle")
+                                .index(1)
+                                .help("Bundle to import")
+                                .required(true)
+                        )
+                        .arg(
+                            Arg::with_name("output-dir")
+                                .index(2)
+                                .help("Output folder to import into. Defaults to --output")
+                        )
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("update")
+                .about("Rebuild an output folder with settings it was built with, extracting only new and changed packages and removing files they no longer have")
+                .arg(
+                    Arg::with_name("output-dir")
+                        .index(1)
+                        .help("Output folder to update. Defaults to --output")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Re-hash files of an output folder and report modified, deleted and unexpected files")
+                .arg(
+                    Arg::with_name("output-dir")
+                        .index(1)
+                        .help("Output folder to check. Defaults to --output")
+                )
+        )
+        .arg(
+            Arg::with_name("package")
+                .index(1)
+                .help("Package name")
+                .required(true)
+        )
+        .arg(
+            Arg::with_name("first")
+                .long("first")
+                .help("If package name doesn't match a package exactly, use the best of packages which name starts with or contains it instead of asking")
+                .conflicts_with("exact")
+                .global(true)
+        )
+        .arg(
+            Arg::with_name("exact")
+                .long("exact")
+                .help("Fail if package name doesn't match a package exactly instead of asking")
+                .global(true)
+        )
+        .arg(
+            Arg::with_name("repository")
+                .short("r")
+                .long("repository")
+                .value_name("REPOSITORY")
+                .help("Address to package repository")
+                .takes_value(true)
+                .default_value("http://repo.msys2.org/mingw")
+        )
+        .arg(
+            Arg::with_name("source")
+                .long("source")
+                .value_name("FORMAT")
+                .help("Format of the repository. rpm reads repodata of RPM repositories like Fedora ones, cygwin reads setup.ini of Cygwin mirrors, conda reads repodata.json of conda channels (experimental). For them, REPOSITORY is used without architecture and packages are placed into REPOSITORY_NAME folder")
+                .takes_value(true)
+                .possible_values(&["pacman", "rpm", "cygwin", "conda"])
+                .default_value("pacman")
+        )
+        .arg(
+            Arg::with_name("repository-name")
+                .short("n")
+                .long("reponame")
+                .value_name("REPOSITORY_NAME")
+                .help("Package repository name")
+                .takes_value(true)
+                .default_value("mingw64")
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("OUTPUT")
+                .help("Output folder. Use - to write tar stream to stdout")
+                .takes_value(true)
+                .default_value("./")
+        )
+        .arg(
+            Arg::with_name("archive-output")
+                .long("archive-output")
+                .value_name("ARCHIVE")
+                .help("Write files into .tar, .tar.gz, .tar.zst or .zip archive instead of the output folder")
+                .takes_value(true)
+                .validator(|path| {
+                    if ArchiveFormat::from_path(Path::new(&path)).is_some() {
+                        return Ok(());
+                    }
+                    Err(format!("Unknown archive format: \"{}\"", path))
+                })
+        )
+        .arg(
+            Arg::with_name("cache-dir")
+                .long("cache-dir")
+                .value_name("DIR")
+                .help("Package archive cache folder")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("rewrite-url")
+                .long("rewrite-url")
+                .value_name("PACKAGE=URL")
+                .help("Download archives of packages matching PACKAGE glob from URL instead of the repository, e.g. from an internal artifact store. {url}, {file_name} and {name} placeholders are replaced with repository URL of the archive, archive file name and package name. The first matching rewrite is used, rewrites of the config file go after these")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+                .validator(|rewrite| UrlRewrite::from_str(&rewrite).map(|_| ()))
+        )
+        .arg(
+            Arg::with_name("resume")
+                .long("resume")
+                .help("Continue interrupted run, skipping already extracted packages and continuing partial downloads")
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Print only warnings and errors")
+        )
+        .arg(
+            Arg::with_name("no-progress")
+                .long("no-progress")
+                .help("Print a timestamped line per downloaded and extracted package instead of progress bars. Default when stderr isn't a terminal")
+                .conflicts_with("quiet")
+        )
+        .arg(
+            Arg::with_name("tui")
+                .long("tui")
+                .help("Show interactive dashboard with dependency tree, active downloads and extractions, throughput graph and scrollable log")
+                .conflicts_with_all(&["quiet", "no-progress"])
+        )
+        .arg(
+            Arg::with_name("progress-format")
+                .long("progress-format")
+                .value_name("FORMAT")
+                .help("How progress is reported: auto, bars, plain, quiet, json (newline-delimited events on stdout) or github (GitHub Actions groups and annotations, default when running there)")
+                .takes_value(true)
+                .possible_values(&["auto", "bars", "plain", "quiet", "json", "github"])
+                .default_value("auto")
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .help("Print debug log instead of progress bars, -vv also prints every extracted and skipped file")
+        )
+        .arg(
+            Arg::with_name("log-level")
+                .long("log-level")
+                .value_name("LEVEL")
+                .help("Print log events up to LEVEL instead of progress bars")
+                .takes_value(true)
+                .possible_values(&["error", "warn", "info", "debug", "trace"])
+                .conflicts_with("verbose")
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .value_name("FILE")
+                .help("Write detailed log with every extracted and skipped file into FILE, console output isn't changed")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("notify-cmd")
+                .long("notify-cmd")
+                .value_name("COMMAND")
+                .help("Run COMMAND when the run finishes, with JSON of status, package count, duration and errors on stdin. {status} is replaced with success, failure or cancelled")
+                .takes_value(true)
+                .validator(|command| CommandTemplate::from_str(&command).map(|_| ()))
+        )
+        .arg(
+            Arg::with_name("notify-webhook")
+                .long("notify-webhook")
+                .value_name("URL")
+                .help("POST JSON of status, package count, duration and errors to URL when the run finishes")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("notify-desktop")
+                .long("notify-desktop")
+                .help("Show desktop notification with the result when the run finishes, if it runs in a terminal. Can be turned on with desktop = true in [notify] section of the config file")
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Extract all packages even if the output is up to date")
+        )
+        .arg(
+            Arg::with_name("wait-lock")
+                .long("wait-lock")
+                .help("Wait for other runs using the same output or cache folder instead of failing")
+        )
+        .arg(
+            Arg::with_name("keep-archives")
+                .long("keep-archives")
+                .value_name("DIR")
+                .help("Save downloaded package archives into DIR")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("checksums")
+                .long("checksums")
+                .value_name("FILE")
+                .help("Write sha256sum-compatible checksums of all written files into FILE")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("env-scripts")
+                .long("env-scripts")
+                .help("Generate env.bat, env.ps1 and env.sh which set PATH, PKG_CONFIG_PATH, CMAKE_PREFIX_PATH, CC and CXX")
+        )
+        .arg(
+            Arg::with_name("emit-cmake-toolchain")
+                .long("emit-cmake-toolchain")
+                .value_name("FILE")
+                .help("Generate CMake toolchain file at FILE relative to the output root")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("emit-meson-file")
+                .long("emit-meson-file")
+                .value_name("FILE")
+                .help("Generate Meson cross (or native, see --toolchain-mode) file at FILE relative to the output root")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("emit-cargo-config")
+                .long("emit-cargo-config")
+                .value_name("FILE")
+                .help("Generate Cargo config for Rust windows-gnu targets at FILE relative to the output root, e.g. .cargo/config.toml")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("import-libs")
+                .long("import-libs")
+                .help("Generate MSVC .lib import libraries from export tables of extracted DLLs")
+        )
+        .arg(
+            Arg::with_name("prune-to")
+                .long("prune-to")
+                .value_name("BINARY")
+                .help("Keep only these executables, DLLs they import transitively and --prune-keep folders. Requires directory output")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+        )
+        .arg(
+            Arg::with_name("prune-keep")
+                .long("prune-keep")
+                .value_name("FOLDER")
+                .help("Folder relative to the output root which is kept by --prune-to, e.g. mingw64/lib/gcc")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+                .requires("prune-to")
+        )
+        .arg(
+            Arg::with_name("check-imports")
+                .long("check-imports")
+                .value_name("ACTION")
+                .help("Check that every DLL imported by extracted binaries is in the output or is a Windows DLL, and fail or warn otherwise")
+                .takes_value(true)
+                .possible_values(&["fail", "warn"])
+        )
+        .arg(
+            Arg::with_name("max-output-size")
+                .long("max-output-size")
+                .value_name("SIZE")
+                .help("Fail if extracted files take more than SIZE (e.g. 800MiB, 2GB) and list the biggest packages and files")
+                .takes_value(true)
+                .validator(|size| space::parse_size(&size).map(|_| ()))
+        )
+        .arg(
+            Arg::with_name("report-size")
+                .long("report-size")
+                .value_name("N")
+                .help("Print output size by package and by top-level folder, plus N (default 20) largest files")
+                .takes_value(true)
+                .min_values(0)
+                .validator(|count| usize::from_str(&count).map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("write-lockfile")
+                .long("write-lockfile")
+                .value_name("FILE")
+                .help("Write version, archive name, SHA-256 and URL of every installed package into FILE")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("locked")
+                .long("locked")
+                .value_name("FILE")
+                .help("Install exactly the package archives pinned by lockfile FILE, checking their SHA-256")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("allow-snapshot-fallback")
+                .long("allow-snapshot-fallback")
+                .help("Download pinned archives the repository no longer serves from URLs recorded in the lockfile")
+                .requires("locked")
+        )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .value_name("FILE")
+                .help("Print download and extraction time, size and file count of every package, or write them as JSON into FILE")
+                .takes_value(true)
+                .min_values(0)
+        )
+        .arg(
+            Arg::with_name("timings")
+                .long("timings")
+                .value_name("FILE")
+                .help("Write duration of repository loading, dependency resolution, download and extraction of every package, post-processing and commit as JSON into FILE")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("metrics")
+                .long("metrics")
+                .value_name("FILE")
+                .help("Write run result, duration, downloaded bytes and package count into FILE in Prometheus textfile format. Counters are added to the ones of the existing FILE")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("allow-import")
+                .long("allow-import")
+                .value_name("DLL")
+                .help("Glob of DLL names which are provided by the target system and are not checked by --check-imports")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+        )
+        .arg(
+            Arg::with_name("toolchain-mode")
+                .long("toolchain-mode")
+                .value_name("MODE")
+                .help("Use extracted compilers (native) or host cross compilers (cross) in generated build system files. Defaults to native on Windows and cross elsewhere")
+                .takes_value(true)
+                .possible_values(&["native", "cross"])
+        )
+        .arg(
+            Arg::with_name("layout")
+                .long("layout")
+                .value_name("LAYOUT")
+                .help("Write all packages into one tree (merged) or each package into <name>-<version> folder (per-package)")
+                .takes_value(true)
+                .possible_values(&["merged", "per-package"])
+                .default_value("merged")
+        )
+        .arg(
+            Arg::with_name("allow-license")
+                .long("allow-license")
+                .value_name("LICENSE")
+                .help("Allow only packages which licenses all match one of these globs, e.g. 'MIT' or 'BSD*'")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+        )
+        .arg(
+            Arg::with_name("deny-license")
+                .long("deny-license")
+                .value_name("LICENSE")
+                .help("Deny packages which have license matching glob, e.g. 'GPL-3*'")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+        )
+        .arg(
+            Arg::with_name("license-action")
+                .long("license-action")
+                .value_name("ACTION")
+                .help("Fail or only warn when packages with disallowed licenses are required")
+                .takes_value(true)
+                .possible_values(&["fail", "warn"])
+                .default_value("fail")
+        )
+        .arg(
+            Arg::with_name("parallelism")
+                .short("p")
+                .value_name("PARALLELISM")
+                .help("Download/extract thread pool parallelism")
+                .takes_value(true)
+                .validator(|tasks| match u32::from_str(&tasks) {
+                    Ok(0) => Err(String::from("parallelism must be at least 1")),
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(e.to_string()),
+                })
+        )
+        .arg(
+            Arg::with_name("extract-threads")
+                .long("extract-threads")
+                .value_name("THREADS")
+                .help("File writer count used to extract a single package")
+                .takes_value(true)
+                .default_value("4")
+                .validator(|count| u32::from_str(&count).map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .short("e")
+                .value_name("EXCLUDE")
+                .help("Exclude files or folders by regex")
+                .multiple(true)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("include")
+                .short("i")
+                .value_name("INCLUDE")
+                .help("Include files or folders by regex. Only files which matches regex will be included. All files which matches include and exclude regex will *not* be included")
+                .multiple(true)
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("explain-filters")
+                .long("explain-filters")
+                .help("Print for every archive entry which include or exclude rule matched it, or that none did")
+        )
+        .arg(
+            Arg::with_name("content-filter")
+                .long("content-filter")
+                .value_name("FILTER")
+                .help("Filter files by content: keep only PE executables and DLLs (pe-only), drop static and import libraries (no-libs) or drop everything which isn't a binary (binaries-only)")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+                .possible_values(&["pe-only", "no-libs", "binaries-only"])
+        )
+        .arg(
+            Arg::with_name("filter-script")
+                .long("filter-script")
+                .value_name("FILE")
+                .help("Rhai script with filter(package, path, size, is_binary) function, called for every file which passed other filters. It returns true to keep the file, false to drop it or a string with another output path to move it to")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("no-static-libs")
+                .long("no-static-libs")
+                .help("Drop static libraries. Import libraries (.dll.a) are kept")
+        )
+        .arg(
+            Arg::with_name("strip")
+                .long("strip")
+                .help("Strip debug information from .exe, .dll and, with --strip-tool, .a files")
+        )
+        .arg(
+            Arg::with_name("strip-tool")
+                .long("strip-tool")
+                .value_name("TOOL")
+                .help("External strip tool, e.g. x86_64-w64-mingw32-strip or llvm-strip")
+                .takes_value(true)
+                .requires("strip")
+        )
+        .arg(
+            Arg::with_name("compress-cmd")
+                .long("compress-cmd")
+                .value_name("COMMAND")
+                .help("Compress .exe and .dll files with COMMAND, e.g. \"upx --best {file}\". File path is appended if {file} is not used")
+                .takes_value(true)
+                .validator(|command| CommandTemplate::from_str(&command).map(|_| ()))
+        )
+        .arg(
+            Arg::with_name("compress-exclude")
+                .long("compress-exclude")
+                .value_name("EXCLUDE")
+                .help("Exclude executables and DLLs from compression by regex")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+                .requires("compress-cmd")
+                .validator(|regex| Regex::new(&regex).map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("sign-cmd")
+                .long("sign-cmd")
+                .value_name("COMMAND")
+                .help("Sign .exe and .dll files with COMMAND, e.g. \"signtool sign /a {file}\" or \"osslsigncode sign -pkcs12 cert.p12 -in {in} -out {out}\". {name} is the file name. File path is appended if no path placeholder is used")
+                .takes_value(true)
+                .validator(|command| CommandTemplate::from_str(&command).map(|_| ()))
+        )
+        .arg(
+            Arg::with_name("plugin")
+                .long("plugin")
+                .value_name("COMMAND")
+                .help("Start COMMAND as a plugin, which exchanges JSON lines with this program over stdin and stdout. It can drop packages of the resolved tree, keep, drop or move extracted files and change the output before it is committed")
+                .takes_value(true)
+                .validator(|command| CommandTemplate::from_str(&command).map(|_| ()))
+        )
+        .arg(
+            Arg::with_name("plugin-timeout")
+                .long("plugin-timeout")
+                .value_name("SECONDS")
+                .help("Fail the run when --plugin doesn't reply to a request in SECONDS")
+                .takes_value(true)
+                .default_value("60")
+                .validator(|seconds| match u64::from_str(&seconds) {
+                    Ok(0) => Err(String::from("timeout must be at least 1 second")),
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(e.to_string()),
+                })
+        )
+        .arg(
+            Arg::with_name("hook")
+                .long("hook")
+                .value_name("PACKAGE=COMMAND")
+                .help("Run COMMAND in the output when all packages are extracted, once for every extracted package matching PACKAGE glob. {root}, {package} and {version} placeholders are available. Files the command creates or changes are added to the manifest")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+                .validator(|hook| Hook::from_str(&hook).map(|_| ()))
+        )
+        .arg(
+            Arg::with_name("fixup")
+                .long("fixup")
+                .value_name("FIXUP")
+                .help("Apply built-in fixup: qt-conf writes qt.conf next to qmake of Qt, pixbuf-loaders generates loaders.cache of gdk-pixbuf (needs wine outside of Windows)")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+                .possible_values(&["qt-conf", "pixbuf-loaders"])
+        )
+        .arg(
+            Arg::with_name("text-files")
+                .long("text-files")
+                .value_name("TEXT_FILES")
+                .help("Match text files by regex. Text transformations are applied only to them")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+                .default_value(r"\.(la|pc|cmake|conf|ini|txt|sh)$")
+                .validator(|regex| Regex::new(&regex).map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("rewrite-prefix")
+                .long("rewrite-prefix")
+                .value_name("FROM=TO")
+                .help("Replace hardcoded build prefix FROM with TO inside text files, e.g. /mingw64=C:/toolchain")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+                .validator(|rewrite| PrefixRewrite::from_str(&rewrite).map(|_| ()))
+        )
+        .arg(
+            Arg::with_name("line-endings")
+                .long("line-endings")
+                .value_name("ENDING")
+                .help("Convert line endings of text files")
+                .takes_value(true)
+                .possible_values(&["lf", "crlf"])
+        )
+        .arg(
+            Arg::with_name("relocate")
+                .long("relocate")
+                .value_name("TARGET")
+                .help("Rewrite build prefix in pkg-config and CMake package files: \"relative\" for paths relative to the file or the folder the output will be installed to")
+                .takes_value(true)
+                .validator(|relocation| Relocation::from_str(&relocation).map(|_| ()))
+        )
+        .arg(
+            Arg::with_name("notices")
+                .long("notices")
+                .help("Copy license files of every package into THIRD-PARTY-NOTICES/<package>, even if they are filtered out")
+        )
+        .arg(
+            Arg::with_name("notices-concat")
+                .long("notices-concat")
+                .help("Like --notices, and also concatenate all license files into THIRD-PARTY-NOTICES.txt")
+        )
+        .arg(
+            Arg::with_name("name-policy")
+                .long("name-policy")
+                .value_name("POLICY")
+                .help("What to do with files which names are reserved or contain characters invalid on Windows")
+                .takes_value(true)
+                .possible_values(&["rename", "skip", "keep"])
+                .default_value("rename")
+        )
+        .arg(
+            Arg::with_name("case-collisions")
+                .long("case-collisions")
+                .value_name("POLICY")
+                .help("What to do with files and folders which differ from other ones only by case")
+                .takes_value(true)
+                .possible_values(&["fail", "rename"])
+                .default_value("fail")
+        )
+        .arg(
+            Arg::with_name("path-remap")
+                .long("path-remap")
+                .value_name("FROM=TO")
+                .help("Replace output path prefix FROM with TO, e.g. to shorten too long paths")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+                .validator(|remap| PathRemap::from_str(&remap).map(|_| ()))
+        )
+        .arg(
+            Arg::with_name("max-path")
+                .long("max-path")
+                .value_name("LENGTH")
+                .help("Warn about output paths longer than LENGTH characters")
+                .takes_value(true)
+                .default_value("260")
+                .validator(|length| usize::from_str(&length).map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(
+            Arg::with_name("architecture")
+                .short("a")
+                .long("arch")
+                .value_name("ARCH")
+                .help("Package architecture")
+                .takes_value(true)
+                .default_value("x86_64")
+                .validator(|arch| {
+                    if arch == "x86_64" || arch == "i686" {
+                        return Ok(());
+                    }
+                    Err(String::from(format!("Unknown architecture: \"{}\"", arch)))
+                })
+        )
+}
+
+/// Parse CLI arguments, deserialize them to configuration and return it. Help and version requests
+/// are errors too, `clap::Error::exit` prints them
+pub fn config() -> Result<Config, clap::Error> {
+    app().get_matches_safe()?
+        .to_config()
+        .map_err(|e| clap::Error::with_description(&e.to_string(), ErrorKind::InvalidValue))
 }
 
-/// Parse CLI arguments, deserialize them to configuration and return it.
-/// Will panic when have illegal or insufficient arguments.
-pub fn config() -> Config {
-    args().to_config()
+/// Parse arguments recorded by an earlier run, without program name
+pub fn config_from(args: &[String]) -> Result<Config, ConfigError> {
+    let args = std::iter::once(String::from("windows-toolchain-builder")).chain(args.iter().cloned());
+    app().get_matches_from_safe(args)
+        .map_err(|e| ConfigError::Invalid(e.message))?
+        .to_config()
 }
\ No newline at end of file
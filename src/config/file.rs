@@ -0,0 +1,95 @@
+//! Settings which rarely change between runs, read from `config.toml` in the configuration folder of
+//! the user or from the file of `--config`
+use crate::hooks::{HookFailure, StageHooks};
+use crate::progress::Theme;
+use crate::tool::CommandTemplate;
+use crate::urls::UrlRewrite;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Look of progress bars
+    pub progress: Theme,
+    /// Notifications about finished runs
+    pub notify: NotifySettings,
+    /// Package archive URL rewrites, like `--rewrite-url`
+    pub rewrite_url: Vec<UrlRewriteSettings>,
+    /// Commands which run at stages of the installation
+    pub hooks: HookSettings,
+    /// WebAssembly modules which drop packages of the tree and filter files, used in this order
+    pub wasm_plugins: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifySettings {
+    /// Show desktop notification when an interactive run finishes, like `--notify-desktop`
+    pub desktop: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UrlRewriteSettings {
+    /// Glob of package names
+    pub package: String,
+    /// URL template with `{url}`, `{file_name}` and `{name}` placeholders
+    pub url: String,
+}
+
+/// Command templates with `{package}`, `{version}` and `{output}` placeholders. Package of `post_build`
+/// is the installed one, output is the folder files are written into
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HookSettings {
+    /// Runs for every package before it is extracted
+    pub pre_extract: Option<String>,
+    /// Runs for every package after it is extracted
+    pub post_extract: Option<String>,
+    /// Runs once when the output is complete
+    pub post_build: Option<String>,
+    /// What to do when a command fails or can't be started
+    pub on_failure: HookFailure,
+}
+
+impl HookSettings {
+    pub fn to_hooks(&self) -> Result<StageHooks, String> {
+        let parse = |command: &Option<String>| command.as_deref().map(CommandTemplate::from_str).transpose();
+        Ok(StageHooks {
+            pre_extract: parse(&self.pre_extract)?,
+            post_extract: parse(&self.post_extract)?,
+            post_build: parse(&self.post_build)?,
+            failure: self.on_failure,
+        })
+    }
+}
+
+/// Location of the config file used without `--config`
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("windows-toolchain-builder").join("config.toml"))
+}
+
+impl ConfigFile {
+    /// Read config file at `path`, or the default one if it exists
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => match default_path() {
+                Some(path) if path.is_file() => path,
+                _ => return Ok(ConfigFile::default()),
+            },
+        };
+        let data = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let file: ConfigFile = toml::from_str(&data).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+        if file.progress.chars.chars().count() < 2 {
+            return Err(format!("Invalid config file {}: progress.chars must have at least 2 characters", path.display()));
+        }
+        file.hooks.to_hooks().map_err(|e| format!("Invalid config file {}: hooks: {}", path.display(), e))?;
+        for rewrite in file.rewrite_url.iter() {
+            UrlRewrite::new(&rewrite.package, &rewrite.url).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+        }
+        Ok(file)
+    }
+}
@@ -1,36 +1,394 @@
 use regex::Regex;
+use tracing::Level;
 use std::path::PathBuf;
+use std::time::Duration;
+use crate::sanitize::NamePolicy;
+use crate::paths::PathRemap;
+use crate::collision::CollisionPolicy;
+use crate::layout::Layout;
+use crate::text::{LineEnding, PrefixRewrite};
+use crate::classify::ContentFilter;
+use crate::tool::CommandTemplate;
+use crate::progress::{ProgressMode, Theme};
+use crate::hooks::{Hook, StageHooks};
+use crate::license::{Glob, LicenseAction, LicensePolicy};
+use crate::generate::ToolchainMode;
+use crate::relocate::Relocation;
+use crate::imports::ImportCheck;
+use crate::package::PackageOptions;
+use crate::installer::InstallerOptions;
+use crate::remove::RemoveOptions;
+use crate::cache::CacheOptions;
+use crate::clean::CleanOptions;
+use crate::diff::DiffOptions;
+use crate::mirror::MirrorOptions;
+use crate::outdated::OutdatedOptions;
+use crate::owners::OwnsOptions;
+use crate::verify::VerifyOptions;
+use crate::state::{AddOptions, UpdateOptions};
+use crate::snapshot::RollbackOptions;
+use crate::bundle::StateOptions;
+use crate::error::ConfigError;
+use crate::archive::ArchiveOptions;
+use crate::pick::PackageChoice;
+use crate::manifest::sha256_hex;
+use crate::download::default_cache_dir;
+use crate::source::SourceKind;
+use crate::script::ScriptFilter;
+use crate::wasm::WasmPlugin;
+use crate::urls::{UrlResolver, UrlRewrite};
 
 pub mod clap;
+pub mod file;
+
+/// Action requested from the command line
+#[derive(Clone, Debug)]
+pub enum Command {
+    /// Download and extract the package with its dependencies into the output
+    Build,
+    /// Pack the output folder into an archive
+    Package(PackageOptions),
+    /// Generate installer source for the output folder
+    Installer(InstallerOptions),
+    /// Install packages with their missing dependencies into an existing output
+    Add(AddOptions),
+    /// Inspect and shrink the package archive cache
+    Cache(CacheOptions),
+    /// Remove files of an output folder which no installed package owns
+    Clean(CleanOptions),
+    /// Compare two outputs
+    Diff(DiffOptions),
+    /// Download the package tree into a pacman repository folder
+    Mirror(MirrorOptions),
+    /// List installed packages which have newer versions in the repository
+    Outdated(OutdatedOptions),
+    /// Print packages which provided files of the output
+    Owns(OwnsOptions),
+    /// Delete files of packages and dependencies nothing else requires from the output folder
+    Remove(RemoveOptions),
+    /// Restore an output folder from snapshot taken by `update`
+    Rollback(RollbackOptions),
+    /// Export the output state into a bundle or rebuild the output from one
+    State(StateOptions),
+    /// Install new and changed packages into an existing output
+    Update(UpdateOptions),
+    /// Check files of an output folder against its manifest
+    Verify(VerifyOptions),
+}
+
+impl Command {
+    /// Subcommand name on the command line, `build` for the default command
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Build => "build",
+            Command::Package(_) => "package",
+            Command::Installer(_) => "installer",
+            Command::Add(_) => "add",
+            Command::Cache(_) => "cache",
+            Command::Clean(_) => "clean",
+            Command::Diff(_) => "diff",
+            Command::Mirror(_) => "mirror",
+            Command::Outdated(_) => "outdated",
+            Command::Owns(_) => "owns-local",
+            Command::Remove(_) => "remove",
+            Command::Rollback(_) => "rollback",
+            Command::State(_) => "state",
+            Command::Update(_) => "update",
+            Command::Verify(_) => "verify",
+        }
+    }
+}
 
 /// Application configuration
 #[derive(Clone, Debug)]
 pub struct Config {
+    /// Action to run
+    pub command: Command,
     /// Package name which will be used as root to download all stuff
     pub package: String,
+    /// What to do when package name matches several packages, but none exactly
+    pub package_choice: PackageChoice,
+    /// Format of the repository
+    pub source: SourceKind,
     /// Repository base URL (will be appended with architecture to get repo URL)
     pub repository: String,
     /// Repository name (required to download {}.db.tar.gz file)
     pub repository_name: String,
     /// Wanted architecture. Will be used with repository base URL to crete repo URL
     pub architecture: String,
+    /// Rules for package licenses, checked when the package tree is built
+    pub license_policy: LicensePolicy,
     /// Download/extract parallel task count
     pub parallelism: u32,
+    /// Parallel file writer count used while extracting a single package
+    pub extract_threads: u32,
     /// Match files/folders to exclude them from output
     pub exclude: Vec<Regex>,
     /// Match files/folders to include them into output. Have less priority than `exclude`. Will match
     /// all packages if empty.
     pub include: Vec<Regex>,
-    /// Output folder path. Will be created automatically with all parents, if not exist
+    /// Print which include or exclude rule decided about every archive entry
+    pub explain_filters: bool,
+    /// Filters which inspect file content. File is extracted only when all of them keep it
+    pub content_filters: Vec<ContentFilter>,
+    /// Script which keeps, drops or moves files which passed other filters
+    pub filter_script: Option<ScriptFilter>,
+    /// Drop static libraries, but keep import libraries
+    pub no_static_libs: bool,
+    /// Strip debug information from binaries
+    pub strip: bool,
+    /// External `strip`-compatible tool. Integrated PE stripper is used if not set
+    pub strip_tool: Option<String>,
+    /// Command used to compress executables and DLLs
+    pub compress_command: Option<CommandTemplate>,
+    /// Match executables and DLLs which must not be compressed
+    pub compress_exclude: Vec<Regex>,
+    /// Match text files which can be modified by text transformations
+    pub text_files: Vec<Regex>,
+    /// Build prefix replacements applied to text files
+    pub prefix_rewrites: Vec<PrefixRewrite>,
+    /// Line ending to convert text files to
+    pub line_endings: Option<LineEnding>,
+    /// Rewrite build prefix in pkg-config and CMake package files to be relative or to point to the install location
+    pub relocation: Option<Relocation>,
+    /// Copy license files of every package into the notices bundle, even if they are filtered out
+    pub notices: bool,
+    /// Also concatenate all license files into a single notices file
+    pub notices_concat: bool,
+    /// What to do with files which names can't be used on Windows
+    pub name_policy: NamePolicy,
+    /// What to do with files which differ from other files only by case
+    pub case_collisions: CollisionPolicy,
+    /// Output path prefix replacements used to shorten long paths
+    pub path_remaps: Vec<PathRemap>,
+    /// Output path length which triggers a warning
+    pub max_path: usize,
+    /// Output folder path. Will be created automatically with all parents, if not exist. `-` means
+    /// tar stream written to stdout
     pub output_folder: PathBuf,
+    /// Archive to write extracted files into instead of the output folder. Format is chosen by extension
+    pub archive_output: Option<PathBuf>,
+    /// Package archive cache folder
+    pub cache_dir: PathBuf,
+    /// Package archive URL rewrites of the command line, then of the config file
+    pub url_rewrites: Vec<UrlRewrite>,
+    /// Callback of the library API which changes package archive URLs, asked before `url_rewrites`
+    pub url_resolver: Option<UrlResolver>,
+    /// Continue interrupted run: keep extracted packages and partially downloaded archives
+    pub resume: bool,
+    /// Wait for other runs using the same output or cache folder instead of failing
+    pub wait_lock: bool,
+    /// How progress is reported
+    pub progress_mode: ProgressMode,
+    /// Look of progress bars from the config file, with colors turned off by `--no-color` or `NO_COLOR`
+    pub theme: Theme,
+    /// Most detailed level of printed log events. Logs are off if not set
+    pub log_level: Option<Level>,
+    /// File to write all log events into, including file and filter decisions
+    pub log_file: Option<PathBuf>,
+    /// Command to run with JSON notification on stdin when the run finishes
+    pub notify_command: Option<CommandTemplate>,
+    /// URL to POST JSON notification to when the run finishes
+    pub notify_webhook: Option<String>,
+    /// Show desktop notification when the run finishes in a terminal
+    pub notify_desktop: bool,
+    /// Extract packages even if the output already has them with the same settings
+    pub force: bool,
+    /// Folder to save downloaded package archives into
+    pub keep_archives: Option<PathBuf>,
+    /// File to write `sha256sum`-compatible checksums of all written files into
+    pub checksums: Option<PathBuf>,
+    /// Generate env.bat, env.ps1 and env.sh in the output root
+    pub env_scripts: bool,
+    /// Path of CMake toolchain file to generate, relative to the output root
+    pub cmake_toolchain: Option<String>,
+    /// Path of Meson cross or native file to generate, relative to the output root
+    pub meson_file: Option<String>,
+    /// Path of Cargo config file to generate, relative to the output root
+    pub cargo_config: Option<String>,
+    /// Whether generated build system files use extracted compilers or host cross compilers
+    pub toolchain_mode: ToolchainMode,
+    /// Generate MSVC import libraries for extracted DLLs
+    pub import_libs: bool,
+    /// Keep only files required by these executables
+    pub prune_to: Vec<String>,
+    /// Folders kept in addition to import closure of `prune_to` executables
+    pub prune_keep: Vec<String>,
+    /// Check that DLL imports of extracted binaries are satisfied by the output
+    pub check_imports: Option<ImportCheck>,
+    /// DLLs which are expected to be provided by the target system, in addition to Windows DLLs
+    pub allow_imports: Vec<Glob>,
+    /// Fail if total size of output files exceeds this many bytes
+    pub max_output_size: Option<u64>,
+    /// Print size breakdown by package and top-level folder with this many largest items
+    pub report_size: Option<usize>,
+    /// Collect per-package download and extraction statistics
+    pub stats: bool,
+    /// Write statistics as JSON into this file instead of printing a table
+    pub stats_file: Option<PathBuf>,
+    /// Write duration of run phases as JSON into this file
+    pub timings: Option<PathBuf>,
+    /// Prometheus textfile to write metrics of the run into
+    pub metrics: Option<PathBuf>,
+    /// Command which signs .exe and .dll files after all other processing
+    pub sign_command: Option<CommandTemplate>,
+    /// Program which takes part in resolution, filtering and post-processing over the plugin protocol
+    pub plugin: Option<CommandTemplate>,
+    /// Sandboxed WebAssembly modules of the config file which drop packages and filter files
+    pub wasm_plugins: Vec<WasmPlugin>,
+    /// Time `--plugin` has to reply to a request
+    pub plugin_timeout: Duration,
+    /// Commands and built-in fixups which run for matching packages when all packages are extracted
+    pub hooks: Vec<Hook>,
+    /// Commands of the config file which run before and after every package is extracted and when the
+    /// output is complete
+    pub stage_hooks: StageHooks,
+    /// Write lockfile with exact package archives of the output into this file
+    pub write_lockfile: Option<PathBuf>,
+    /// Install exactly the package archives pinned by this lockfile instead of resolving the package
+    pub locked: Option<PathBuf>,
+    /// Download pinned archives the repository no longer serves from URLs recorded in the lockfile
+    pub allow_snapshot_fallback: bool,
+    /// Placement of package files in the output
+    pub layout: Layout,
+    /// Make output archives bit-identical between runs with the same packages
+    pub reproducible: bool,
+}
+
+/// Defaults of the command line options. Progress is sent to `tracing` instead of drawn on the terminal
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            command: Command::Build,
+            package: String::new(),
+            package_choice: PackageChoice::default(),
+            source: SourceKind::Pacman,
+            repository: String::from("http://repo.msys2.org/mingw"),
+            repository_name: String::from("mingw64"),
+            architecture: String::from("x86_64"),
+            license_policy: LicensePolicy { allow: Vec::new(), deny: Vec::new(), action: LicenseAction::Fail },
+            parallelism: num_cpus::get() as u32,
+            extract_threads: 4,
+            exclude: Vec::new(),
+            include: Vec::new(),
+            explain_filters: false,
+            content_filters: Vec::new(),
+            filter_script: None,
+            no_static_libs: false,
+            strip: false,
+            strip_tool: None,
+            compress_command: None,
+            compress_exclude: Vec::new(),
+            text_files: vec![Regex::new(r"\.(la|pc|cmake|conf|ini|txt|sh)$").unwrap()],
+            prefix_rewrites: Vec::new(),
+            line_endings: None,
+            relocation: None,
+            notices: false,
+            notices_concat: false,
+            name_policy: NamePolicy::Rename,
+            case_collisions: CollisionPolicy::Fail,
+            path_remaps: Vec::new(),
+            max_path: 260,
+            output_folder: PathBuf::from("./"),
+            archive_output: None,
+            cache_dir: default_cache_dir(),
+            url_rewrites: Vec::new(),
+            url_resolver: None,
+            resume: false,
+            wait_lock: false,
+            progress_mode: ProgressMode::Log,
+            theme: Theme::default(),
+            log_level: None,
+            log_file: None,
+            notify_command: None,
+            notify_webhook: None,
+            notify_desktop: false,
+            force: false,
+            keep_archives: None,
+            checksums: None,
+            env_scripts: false,
+            cmake_toolchain: None,
+            meson_file: None,
+            cargo_config: None,
+            toolchain_mode: ToolchainMode::host_default(),
+            import_libs: false,
+            prune_to: Vec::new(),
+            prune_keep: Vec::new(),
+            check_imports: None,
+            allow_imports: Vec::new(),
+            max_output_size: None,
+            report_size: None,
+            stats: false,
+            stats_file: None,
+            timings: None,
+            metrics: None,
+            sign_command: None,
+            plugin: None,
+            wasm_plugins: Vec::new(),
+            plugin_timeout: Duration::from_secs(60),
+            hooks: Vec::new(),
+            stage_hooks: StageHooks::default(),
+            write_lockfile: None,
+            locked: None,
+            allow_snapshot_fallback: false,
+            layout: Layout::Merged,
+            reproducible: false,
+        }
+    }
 }
 
 impl Config {
     pub fn repository_url(&self) -> String {
         self.repository.clone() + "/" + &self.architecture
     }
+
+    pub fn output_to_stdout(&self) -> bool {
+        self.output_folder.as_os_str() == "-"
+    }
+
+    /// Hash of settings which affect output content. Settings of a single run, like parallelism or
+    /// output location, are ignored
+    pub fn fingerprint(&self) -> String {
+        let mut config = self.clone();
+        config.command = Command::Build;
+        config.parallelism = 0;
+        config.extract_threads = 0;
+        config.output_folder = PathBuf::new();
+        config.archive_output = None;
+        config.cache_dir = PathBuf::new();
+        config.url_rewrites = Vec::new();
+        config.url_resolver = None;
+        config.plugin_timeout = Duration::from_secs(0);
+        config.resume = false;
+        config.wait_lock = false;
+        config.progress_mode = ProgressMode::Bars;
+        config.theme = Theme::default();
+        config.log_level = None;
+        config.log_file = None;
+        config.notify_command = None;
+        config.notify_webhook = None;
+        config.notify_desktop = false;
+        config.force = false;
+        config.keep_archives = None;
+        config.checksums = None;
+        config.report_size = None;
+        config.stats = false;
+        config.stats_file = None;
+        config.timings = None;
+        config.metrics = None;
+        config.explain_filters = false;
+        config.write_lockfile = None;
+        config.locked = None;
+        config.allow_snapshot_fallback = false;
+        sha256_hex(format!("{:?}", config).as_bytes())
+    }
+
+    /// Settings of archive output
+    pub fn archive_options(&self) -> ArchiveOptions {
+        ArchiveOptions { level: None, reproducible: self.reproducible }
+    }
 }
 
 pub trait IntoConfig {
-    fn to_config(&self) -> Config;
+    fn to_config(&self) -> Result<Config, ConfigError>;
 }
\ No newline at end of file
@@ -0,0 +1,331 @@
+//! Interactive terminal dashboard of `--tui` mode: dependency tree with package states, active
+//! downloads and extractions, throughput graph and scrollable log.
+//!
+//! Dashboard is drawn on the alternate screen of stderr by its own thread. Arrows, PageUp/PageDown,
+//! Home and End scroll the log, `q` and Ctrl-C cancel the run. The log is printed to stderr when the
+//! dashboard is closed, so it isn't lost with the alternate screen
+use archlinux_repo::Package;
+use crate::progress::ProgressReporter;
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use indicatif::HumanBytes;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, Sparkline};
+use ratatui::Terminal;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Throughput samples kept for the graph, one per second
+const THROUGHPUT_SAMPLES: usize = 120;
+/// Interval between redraws and key polls
+const TICK: Duration = Duration::from_millis(100);
+
+pub struct Dashboard {
+    state: Mutex<State>,
+    stopped: AtomicBool,
+    color: bool,
+    /// Notified when user asks to cancel the run
+    cancel: Notify,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Status {
+    Pending,
+    Downloading,
+    Extracting,
+    Done,
+}
+
+impl Status {
+    fn symbol(self) -> &'static str {
+        match self {
+            Status::Pending => " ",
+            Status::Downloading => "↓",
+            Status::Extracting => "*",
+            Status::Done => "✓",
+        }
+    }
+}
+
+/// Package being downloaded or extracted
+struct Activity {
+    package: String,
+    status: Status,
+    pos: u64,
+    len: u64,
+}
+
+#[derive(Default)]
+struct State {
+    /// Dependency tree rows: package name and depth
+    tree: Vec<(String, usize)>,
+    statuses: HashMap<String, Status>,
+    activities: Vec<Activity>,
+    log: Vec<(String, bool)>,
+    /// Log lines above the bottom of the log pane
+    scroll: usize,
+    /// Bytes received per second
+    throughput: VecDeque<u64>,
+    /// Bytes received since the last sample
+    received: u64,
+    /// Bytes received during the run
+    total_received: u64,
+}
+
+impl Dashboard {
+    /// Switch terminal to the dashboard and start drawing it, with `color` or monochrome
+    pub fn start(color: bool) -> io::Result<Arc<Self>> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stderr(), EnterAlternateScreen)?;
+        let dashboard = Arc::new(Dashboard {
+            state: Mutex::new(State::default()),
+            stopped: AtomicBool::new(false),
+            color,
+            cancel: Notify::new(),
+            thread: Mutex::new(None),
+        });
+        let drawn = dashboard.clone();
+        *dashboard.thread.lock().unwrap() = Some(std::thread::spawn(move || {
+            if let Err(e) = drawn.run() {
+                drawn.message(&format!("Dashboard failed: {}", e), true);
+            }
+        }));
+        Ok(dashboard)
+    }
+
+    /// Restore the terminal and print the log
+    pub fn stop(&self) {
+        if self.stopped.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+        let _ = crossterm::execute!(io::stderr(), LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+        for (msg, _) in self.state.lock().unwrap().log.iter() {
+            eprintln!("{}", msg);
+        }
+    }
+
+    /// Wait until user asks to cancel the run
+    pub async fn cancelled(&self) {
+        self.cancel.notified().await
+    }
+}
+
+impl ProgressReporter for Dashboard {
+    fn message(&self, msg: &str, warning: bool) {
+        self.state.lock().unwrap().log.push((msg.to_owned(), warning));
+    }
+
+    /// Show dependency tree of packages to install
+    fn packages(&self, tree: &[Package]) {
+        let mut state = self.state.lock().unwrap();
+        state.tree = tree_rows(tree);
+        state.statuses = tree.iter().map(|package| (package.name.clone(), Status::Pending)).collect();
+    }
+
+    fn download(&self, package: &str, pos: u64, len: u64) {
+        self.state.lock().unwrap().update(package, Status::Downloading, pos, len);
+    }
+
+    fn received(&self, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.received += bytes;
+        state.total_received += bytes;
+    }
+
+    fn extract(&self, package: &str, _file: Option<&str>, pos: u64, len: u64) {
+        self.state.lock().unwrap().update(package, Status::Extracting, pos, len);
+    }
+
+    fn done(&self, package: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.activities.retain(|activity| activity.package != package);
+        state.statuses.insert(package.to_owned(), Status::Done);
+    }
+}
+
+impl Dashboard {
+    fn run(&self) -> io::Result<()> {
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stderr()))?;
+        terminal.hide_cursor()?;
+        let started = Instant::now();
+        let mut sampled = Instant::now();
+        while !self.stopped.load(Ordering::SeqCst) {
+            {
+                let mut state = self.state.lock().unwrap();
+                if sampled.elapsed() >= Duration::from_secs(1) {
+                    sampled = Instant::now();
+                    let received = std::mem::replace(&mut state.received, 0);
+                    state.throughput.push_back(received);
+                    if state.throughput.len() > THROUGHPUT_SAMPLES {
+                        state.throughput.pop_front();
+                    }
+                }
+                terminal.draw(|frame| state.draw(frame, started.elapsed(), self.color))?;
+            }
+            if event::poll(TICK)? {
+                if let Event::Key(key) = event::read()? {
+                    let mut state = self.state.lock().unwrap();
+                    match key.code {
+                        KeyCode::Char('q') => self.cancel.notify(),
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.cancel.notify(),
+                        KeyCode::Up => state.scroll_by(1),
+                        KeyCode::Down => state.scroll = state.scroll.saturating_sub(1),
+                        KeyCode::PageUp => state.scroll_by(10),
+                        KeyCode::PageDown => state.scroll = state.scroll.saturating_sub(10),
+                        KeyCode::Home => state.scroll = state.log.len(),
+                        KeyCode::End => state.scroll = 0,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        terminal.show_cursor()
+    }
+}
+
+impl State {
+    fn update(&mut self, package: &str, status: Status, pos: u64, len: u64) {
+        self.statuses.insert(package.to_owned(), status);
+        match self.activities.iter_mut().find(|activity| activity.package == package) {
+            Some(activity) => {
+                activity.status = status;
+                activity.pos = pos;
+                activity.len = len;
+            }
+            None => self.activities.push(Activity { package: package.to_owned(), status, pos, len }),
+        }
+    }
+
+    fn scroll_by(&mut self, lines: usize) {
+        self.scroll = (self.scroll + lines).min(self.log.len());
+    }
+
+    fn draw<B: ratatui::backend::Backend>(&self, frame: &mut ratatui::Frame<B>, elapsed: Duration, color: bool) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Percentage(60), Constraint::Min(5)])
+            .split(frame.size());
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(35), Constraint::Percentage(65)])
+            .split(rows[1]);
+        let right = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(8)])
+            .split(columns[1]);
+
+        let done = self.statuses.values().filter(|status| **status == Status::Done).count();
+        let summary = format!("packages {}/{}, received {} in {}s, q to cancel",
+                              done, self.statuses.len(), HumanBytes(self.total_received), elapsed.as_secs());
+        frame.render_widget(Paragraph::new(summary).block(Block::default().borders(Borders::ALL).title("Run")), rows[0]);
+
+        let tree: Vec<ListItem> = self.tree.iter()
+            .map(|(name, depth)| {
+                let status = self.statuses.get(name).copied().unwrap_or(Status::Pending);
+                let item = ListItem::new(format!("{} {}{}", status.symbol(), "  ".repeat(*depth), name));
+                match status {
+                    Status::Done => item.style(fg(color, Color::Green)),
+                    Status::Pending => item,
+                    _ => item.style(fg(color, Color::Yellow)),
+                }
+            })
+            .collect();
+        frame.render_widget(List::new(tree).block(Block::default().borders(Borders::ALL).title("Packages")), columns[0]);
+
+        let workers: Vec<ListItem> = self.activities.iter()
+            .map(|activity| {
+                let (verb, pos, len) = match activity.status {
+                    Status::Downloading => ("Downloading", HumanBytes(activity.pos).to_string(), HumanBytes(activity.len).to_string()),
+                    _ => ("Extracting", activity.pos.to_string(), activity.len.to_string()),
+                };
+                ListItem::new(format!("{} {} [{}] {}/{}", verb, &activity.package, gauge(activity.pos, activity.len, 30), pos, len))
+            })
+            .collect();
+        frame.render_widget(List::new(workers).block(Block::default().borders(Borders::ALL).title("Workers")), right[0]);
+
+        let throughput: Vec<u64> = self.throughput.iter().copied().collect();
+        let current = throughput.last().copied().unwrap_or(0);
+        frame.render_widget(
+            Sparkline::default()
+                .block(Block::default().borders(Borders::ALL).title(format!("Throughput {}/s", HumanBytes(current))))
+                .data(&throughput)
+                .style(fg(color, Color::Cyan)),
+            right[1],
+        );
+
+        let height = rows[2].height.saturating_sub(2) as usize;
+        let end = self.log.len() - self.scroll.min(self.log.len());
+        let start = end.saturating_sub(height);
+        let log: Vec<ListItem> = self.log[start..end].iter()
+            .map(|(msg, warning)| {
+                let item = ListItem::new(msg.clone());
+                if *warning {
+                    item.style(fg(color, Color::Yellow))
+                } else {
+                    item
+                }
+            })
+            .collect();
+        let title = if self.scroll > 0 { format!("Log (scrolled up {} lines)", self.scroll) } else { "Log".to_owned() };
+        frame.render_widget(List::new(log).block(Block::default().borders(Borders::ALL).title(title)), rows[2]);
+    }
+}
+
+/// Style with `foreground` color, or the default one if colors are off
+fn fg(color: bool, foreground: Color) -> Style {
+    if color {
+        Style::default().fg(foreground)
+    } else {
+        Style::default()
+    }
+}
+
+/// Text gauge of `width` characters
+fn gauge(pos: u64, len: u64, width: usize) -> String {
+    let filled = if len == 0 { 0 } else { (pos.min(len) as u128 * width as u128 / len as u128) as usize };
+    format!("{}{}", "#".repeat(filled), "-".repeat(width - filled))
+}
+
+/// Rows of `tree` as dependency tree: packages no other package depends on are roots, every package
+/// is shown once under the first package which depends on it
+fn tree_rows(tree: &[Package]) -> Vec<(String, usize)> {
+    let by_name: HashMap<&str, &Package> = tree.iter().map(|package| (package.name.as_str(), package)).collect();
+    let depends = |package: &Package| -> Vec<&str> {
+        package.depends.iter().flatten()
+            .map(|dependency| dependency.name.as_str())
+            .filter(|name| by_name.contains_key(name))
+            .collect()
+    };
+    let dependencies: HashSet<&str> = tree.iter().flat_map(|package| depends(package)).collect();
+    let mut rows = Vec::with_capacity(tree.len());
+    let mut shown = HashSet::new();
+    let mut stack: Vec<(&str, usize)> = tree.iter()
+        .filter(|package| !dependencies.contains(package.name.as_str()))
+        .map(|package| (package.name.as_str(), 0))
+        .rev()
+        .collect();
+    // Packages in dependency cycles have no roots, they are shown at the top level
+    stack.splice(0..0, tree.iter().rev().map(|package| (package.name.as_str(), 0)));
+    while let Some((name, depth)) = stack.pop() {
+        if !shown.insert(name) {
+            continue;
+        }
+        rows.push((name.to_owned(), depth));
+        for dependency in depends(by_name[name]).into_iter().rev() {
+            stack.push((dependency, depth + 1));
+        }
+    }
+    rows
+}
@@ -0,0 +1,123 @@
+//! Comparison of two outputs by their install manifests
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::progress::Progress;
+use std::collections::BTreeMap;
+use crate::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Options of `diff` command
+#[derive(Clone, Debug)]
+pub struct DiffOptions {
+    /// Old output folder or its manifest file
+    pub old: PathBuf,
+    /// New output folder or its manifest file
+    pub new: PathBuf,
+}
+
+/// Differences between two manifests
+#[derive(Default)]
+pub struct Difference {
+    pub added_packages: Vec<(String, String)>,
+    pub removed_packages: Vec<(String, String)>,
+    /// Package name with old and new versions
+    pub updated_packages: Vec<(String, String, String)>,
+    pub added_files: Vec<String>,
+    pub removed_files: Vec<String>,
+    /// Files with different content
+    pub changed_files: Vec<String>,
+}
+
+impl Difference {
+    pub fn new(old: &Manifest, new: &Manifest) -> Self {
+        let mut difference = Difference::default();
+        let old_packages = packages(old);
+        let new_packages = packages(new);
+        for (package, version) in old_packages.iter() {
+            match new_packages.get(package) {
+                None => difference.removed_packages.push((package.to_string(), version.to_string())),
+                Some(new_version) if new_version != version =>
+                    difference.updated_packages.push((package.to_string(), version.to_string(), new_version.to_string())),
+                Some(_) => {}
+            }
+        }
+        for (package, version) in new_packages.iter().filter(|(package, _)| !old_packages.contains_key(*package)) {
+            difference.added_packages.push((package.to_string(), version.to_string()));
+        }
+
+        let old_files = files(old);
+        let new_files = files(new);
+        for (path, entry) in old_files.iter() {
+            match new_files.get(path) {
+                None => difference.removed_files.push(path.to_string()),
+                Some(new_entry) if new_entry.sha256 != entry.sha256 => difference.changed_files.push(path.to_string()),
+                Some(_) => {}
+            }
+        }
+        difference.added_files = new_files.keys()
+            .filter(|path| !old_files.contains_key(*path))
+            .map(|path| path.to_string())
+            .collect();
+        difference
+    }
+
+    /// Human-readable report in `+`/`-`/`~` notation
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        if !self.added_packages.is_empty() || !self.removed_packages.is_empty() || !self.updated_packages.is_empty() {
+            text += "Packages:\n";
+            for (package, version) in self.added_packages.iter() {
+                text += &format!("  + {} {}\n", package, version);
+            }
+            for (package, version) in self.removed_packages.iter() {
+                text += &format!("  - {} {}\n", package, version);
+            }
+            for (package, old, new) in self.updated_packages.iter() {
+                text += &format!("  ~ {} {} -> {}\n", package, old, new);
+            }
+        }
+        if !self.added_files.is_empty() || !self.removed_files.is_empty() || !self.changed_files.is_empty() {
+            text += "Files:\n";
+            for path in self.added_files.iter() {
+                text += &format!("  + {}\n", path);
+            }
+            for path in self.removed_files.iter() {
+                text += &format!("  - {}\n", path);
+            }
+            for path in self.changed_files.iter() {
+                text += &format!("  ~ {}\n", path);
+            }
+        }
+        text += &format!("{} files added, {} removed, {} changed\n",
+                         self.added_files.len(), self.removed_files.len(), self.changed_files.len());
+        text
+    }
+}
+
+/// Print differences between two outputs
+pub async fn diff(options: &DiffOptions, progress: &Progress) -> Result<(), Error> {
+    let old = load(&options.old).await?;
+    let new = load(&options.new).await?;
+    progress.report("diff", &Difference::new(&old, &new).render());
+    Ok(())
+}
+
+/// Load manifest of output folder or manifest file
+async fn load(path: &Path) -> Result<Manifest, Error> {
+    if tokio::fs::metadata(path).await?.is_dir() {
+        Ok(Manifest::load(path).await?)
+    } else {
+        Ok(Manifest::from_json(&tokio::fs::read(path).await?)?)
+    }
+}
+
+fn packages(manifest: &Manifest) -> BTreeMap<&str, &str> {
+    manifest.files.iter()
+        .map(|entry| (entry.package.as_str(), entry.version.as_str()))
+        .collect()
+}
+
+fn files(manifest: &Manifest) -> BTreeMap<&str, &ManifestEntry> {
+    manifest.files.iter()
+        .map(|entry| (entry.path.as_str(), entry))
+        .collect()
+}
@@ -0,0 +1,136 @@
+//! Package downloads through the on-disk package cache
+use archlinux_repo::Package;
+use reqwest::{Client, StatusCode};
+use reqwest::header::RANGE;
+use crate::error::Error;
+use std::path::{Path, PathBuf};
+use tokio::fs::OpenOptions;
+use crate::progress::PackageDownloadProgress;
+use crate::manifest::sha256_hex;
+use crate::metrics;
+
+/// Downloads package archives into the cache folder. Archive is downloaded into `<file>.part` and
+/// renamed when complete, so the cache never contains truncated archives
+pub struct Downloader {
+    client: Client,
+    repository_url: String,
+    cache: PathBuf,
+    resume: bool,
+}
+
+impl Downloader {
+    /// Create downloader. With `resume` partially downloaded archives are continued instead of restarted
+    pub fn new(repository_url: &str, cache: &Path, resume: bool) -> Self {
+        Downloader {
+            client: Client::new(),
+            repository_url: repository_url.trim_end_matches('/').to_owned(),
+            cache: cache.to_owned(),
+            resume,
+        }
+    }
+
+    /// URL of repository file `name`
+    pub fn url(&self, name: &str) -> String {
+        format!("{}/{}", &self.repository_url, name)
+    }
+
+    /// Path of cached package archive
+    pub fn cached_path(&self, package: &Package) -> PathBuf {
+        self.cache.join(&package.file_name)
+    }
+
+    /// SHA-256 of package archive `file_name` from the cache, downloaded from `url` if it isn't cached
+    pub async fn archive_sha256(&self, file_name: &str, url: &str) -> Result<String, Error> {
+        let data = match tokio::fs::read(self.cache.join(file_name)).await {
+            Ok(data) => data,
+            Err(_) => self.fetch_url(url).await?
+                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("Repository has no {}", file_name)))?,
+        };
+        Ok(sha256_hex(&data))
+    }
+
+    /// Return cached archive or download it
+    pub async fn download(&self, package: &Package, progress: &PackageDownloadProgress) -> Result<Vec<u8>, Error> {
+        self.download_from(package, &self.url(&package.file_name), progress).await
+    }
+
+    /// Return cached archive or download it from `url`
+    pub async fn download_from(&self, package: &Package, url: &str, progress: &PackageDownloadProgress) -> Result<Vec<u8>, Error> {
+        use tokio::io::AsyncWriteExt;
+
+        let path = self.cached_path(package);
+        if let Ok(data) = tokio::fs::read(&path).await {
+            tracing::debug!(path = %path.display(), "taken from cache");
+            progress.cached(data.len() as u64);
+            return Ok(data);
+        }
+        let mut part = path.as_os_str().to_owned();
+        part.push(".part");
+        let part = PathBuf::from(part);
+
+        let offset = if self.resume {
+            tokio::fs::metadata(&part).await.map(|meta| meta.len()).unwrap_or(0)
+        } else {
+            0
+        };
+        tracing::debug!(url, offset, "downloading");
+        let mut request = self.client.get(url);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+        let mut response = request.send().await?.error_for_status()?;
+        let append = offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+        let mut bytes_read = if append { offset } else { 0 };
+        let length = bytes_read + response.content_length().unwrap_or(package.compressed_size);
+        if append {
+            progress.resumed(offset, length);
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&part).await?;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk[..]).await?;
+            bytes_read += chunk.len() as u64;
+            metrics::downloaded(chunk.len() as u64);
+            progress.chunk(bytes_read, length);
+        }
+        file.flush().await?;
+        drop(file);
+        tokio::fs::rename(&part, &path).await?;
+        tracing::debug!(bytes = bytes_read, resumed = append, "downloaded");
+        Ok(tokio::fs::read(&path).await?)
+    }
+
+    /// Download repository file `name` without caching. Returns `None` if the repository doesn't have it
+    pub async fn fetch(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.fetch_url(&self.url(name)).await
+    }
+
+    /// Download `url` without caching. Returns `None` if the server doesn't have it
+    pub async fn fetch_url(&self, url: &str) -> Result<Option<Vec<u8>>, Error> {
+        let response = self.client.get(url).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.bytes().await?.to_vec()))
+    }
+}
+
+/// Check if download failed because the server doesn't have the file
+pub fn is_not_found(error: &Error) -> bool {
+    match error.root() {
+        Error::Network(error) => error.status() == Some(StatusCode::NOT_FOUND),
+        _ => false,
+    }
+}
+
+/// Default package cache folder
+pub fn default_cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("windows-toolchain-builder")
+}
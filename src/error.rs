@@ -0,0 +1,214 @@
+//! Errors which stop a run. Errors are grouped by what failed, so callers can match on the class
+//! without parsing messages, and carry the phase and package they happened in
+use indicatif::HumanBytes;
+use serde::Serialize;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Step of the run which failed
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Repository,
+    Resolve,
+    Download,
+    Extract,
+    PostProcessing,
+    Commit,
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Packages can't be resolved
+    #[error(transparent)]
+    Resolution(#[from] ResolutionError),
+    /// Repository database can't be loaded
+    #[error("Failed to load repository: {0}")]
+    Repository(String),
+    /// HTTP request failed
+    #[error(transparent)]
+    Network(#[from] reqwest::Error),
+    /// Package archive is damaged or doesn't match the lockfile
+    #[error(transparent)]
+    Download(#[from] DownloadError),
+    /// Package archive can't be extracted into the output
+    #[error(transparent)]
+    Extraction(#[from] ExtractionError),
+    /// Output doesn't pass checks
+    #[error(transparent)]
+    Verification(#[from] VerificationError),
+    /// Settings can't be used together
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    /// File can't be read or written, or a program can't be started
+    #[error(transparent)]
+    Filesystem(#[from] io::Error),
+    /// Blocking task panicked or was cancelled
+    #[error(transparent)]
+    Task(#[from] tokio::task::JoinError),
+    #[error("Cancelled, run again with --resume to continue")]
+    Cancelled,
+    #[error("{0} is used by another run, pass --wait-lock to wait for it")]
+    Locked(String),
+    #[error("Files failed to sign: {}", .0.join(", "))]
+    SigningFailed(Vec<String>),
+    #[error("Hook of package {package} failed: {status}")]
+    HookFailed { package: String, status: String },
+    /// Plugin can't be talked to or sent a wrong reply
+    #[error("Plugin {program} failed: {message}")]
+    Plugin { program: String, message: String },
+    /// Error of `package` in `phase`
+    #[error("{package}: {source}")]
+    Package { phase: Phase, package: String, #[source] source: Box<Error> },
+    /// Error in `phase` of the run
+    #[error("{source}")]
+    Phase { phase: Phase, #[source] source: Box<Error> },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Error)]
+pub enum ResolutionError {
+    #[error("Package {0} not found")]
+    PackageNotFound(String),
+    #[error("Package {name} not found, but it matches {}. Pass one of them or use --first", .candidates.join(", "))]
+    AmbiguousPackage { name: String, candidates: Vec<String> },
+    #[error("Packages with disallowed licenses: {}", .0.join(", "))]
+    LicenseDenied(Vec<String>),
+    #[error("Repository no longer has pinned archive {0}, use --allow-snapshot-fallback to download it from the locked URL")]
+    PinnedArchiveMissing(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Error)]
+pub enum DownloadError {
+    #[error("Archive {file} has SHA-256 {actual}, but lockfile pins {expected}")]
+    ArchiveHashMismatch { file: String, expected: String, actual: String },
+    #[error("{0} cached archives are damaged, remove them with cache verify --delete")]
+    DamagedArchives(usize),
+}
+
+#[derive(Debug, Error)]
+pub enum ExtractionError {
+    /// Archive can't be decompressed or read
+    #[error(transparent)]
+    Archive(#[from] compress_tools::Error),
+    #[error("File {path} of package {package} differs from file {existing} of package {owner} only by case")]
+    CaseCollision { path: String, package: String, existing: String, owner: String },
+    #[error("Not enough free space for {path}: {} required, {} available", HumanBytes(*.required), HumanBytes(*.available))]
+    InsufficientSpace { path: String, required: u64, available: u64 },
+    #[error("Filter script failed on {path}: {message}")]
+    FilterScript { path: String, message: String },
+    #[error("{option} moved {path} to invalid output path {target}: {message}")]
+    InvalidTarget { path: String, target: String, option: String, message: String },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Error)]
+pub enum VerificationError {
+    #[error("{0} files don't match the manifest")]
+    ManifestMismatch(usize),
+    #[error("{0} DLL imports are not satisfied by the output")]
+    MissingImports(usize),
+    #[error("Binary {0} is not in the output")]
+    BinaryNotFound(String),
+    #[error("Output takes {}, which exceeds limit of {}", HumanBytes(*.size), HumanBytes(*.limit))]
+    OutputTooLarge { size: u64, limit: u64 },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Error)]
+pub enum ConfigError {
+    #[error("--prune-to can't be used with archive output")]
+    PruneArchive,
+    #[error("Hooks which run programs can't be used with archive output")]
+    HooksArchive,
+    #[error("JSON progress can't be written to stdout together with the output stream")]
+    JsonStdout,
+    #[error("Unknown archive format of {}, use .zip, .tar, .tar.gz or .tar.zst", .0.display())]
+    UnsupportedArchive(PathBuf),
+    /// Arguments or config file can't be parsed
+    #[error("{0}")]
+    Invalid(String),
+}
+
+impl From<compress_tools::Error> for Error {
+    fn from(error: compress_tools::Error) -> Self {
+        Error::Extraction(error.into())
+    }
+}
+
+impl Error {
+    /// Wrap error which happened in `phase` of the run
+    pub fn in_phase(phase: Phase, source: impl Into<Error>) -> Self {
+        Error::Phase { phase, source: Box::new(source.into()) }
+    }
+
+    /// Wrap error which happened in `phase` of installing `package`
+    pub fn in_package(phase: Phase, package: &str, source: impl Into<Error>) -> Self {
+        Error::Package { phase, package: package.to_owned(), source: Box::new(source.into()) }
+    }
+
+    /// Error without phase and package context
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Package { source, .. } | Error::Phase { source, .. } => source.root(),
+            error => error,
+        }
+    }
+
+    /// Phase the error happened in
+    pub fn phase(&self) -> Option<Phase> {
+        match self {
+            Error::Package { phase, .. } | Error::Phase { phase, .. } => Some(*phase),
+            _ => None,
+        }
+    }
+
+    /// Package the error happened in
+    pub fn package(&self) -> Option<&str> {
+        match self {
+            Error::Package { package, .. } => Some(package),
+            Error::Phase { source, .. } => source.package(),
+            _ => None,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.root(), Error::Cancelled)
+    }
+
+    /// Stable identifier of the error for the error report
+    pub fn code(&self) -> &'static str {
+        match self.root() {
+            Error::Resolution(ResolutionError::PackageNotFound(_)) => "package_not_found",
+            Error::Resolution(ResolutionError::AmbiguousPackage { .. }) => "ambiguous_package",
+            Error::Resolution(ResolutionError::LicenseDenied(_)) => "license_denied",
+            Error::Resolution(ResolutionError::PinnedArchiveMissing(_)) => "pinned_archive_missing",
+            Error::Repository(_) => "repository",
+            Error::Network(_) => "http",
+            Error::Download(DownloadError::ArchiveHashMismatch { .. }) => "archive_hash_mismatch",
+            Error::Download(DownloadError::DamagedArchives(_)) => "damaged_archives",
+            Error::Extraction(ExtractionError::Archive(_)) => "archive",
+            Error::Extraction(ExtractionError::CaseCollision { .. }) => "case_collision",
+            Error::Extraction(ExtractionError::InsufficientSpace { .. }) => "insufficient_space",
+            Error::Extraction(ExtractionError::FilterScript { .. }) => "filter_script",
+            Error::Extraction(ExtractionError::InvalidTarget { .. }) => "invalid_target",
+            Error::Verification(VerificationError::ManifestMismatch(_)) => "verification_failed",
+            Error::Verification(VerificationError::MissingImports(_)) => "missing_imports",
+            Error::Verification(VerificationError::BinaryNotFound(_)) => "binary_not_found",
+            Error::Verification(VerificationError::OutputTooLarge { .. }) => "output_too_large",
+            Error::Config(ConfigError::PruneArchive) => "prune_archive",
+            Error::Config(ConfigError::HooksArchive) => "hooks_archive",
+            Error::Config(ConfigError::JsonStdout) => "json_stdout",
+            Error::Config(ConfigError::UnsupportedArchive(_)) => "unsupported_archive",
+            Error::Config(ConfigError::Invalid(_)) => "invalid_config",
+            Error::Filesystem(_) => "io",
+            Error::Task(_) => "task",
+            Error::Cancelled => "cancelled",
+            Error::Locked(_) => "locked",
+            Error::SigningFailed(_) => "signing_failed",
+            Error::HookFailed { .. } => "hook_failed",
+            Error::Plugin { .. } => "plugin",
+            Error::Package { .. } | Error::Phase { .. } => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Typed progress events of an installation, for programs which present the progress themselves
+use crate::progress::ProgressReporter;
+use archlinux_repo::Package;
+use futures::channel::mpsc::{self, UnboundedReceiver};
+use futures::task::{Context, Poll};
+use futures::Stream;
+use std::pin::Pin;
+
+/// What happened during the installation
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Event {
+    /// Message shown to the user
+    Message { text: String, warning: bool },
+    /// `received` of `size` bytes of the repository database are downloaded
+    RepositoryLoad { received: u64, size: Option<u64> },
+    /// Dependencies of `package` are being resolved
+    Resolving { package: String },
+    /// Dependency tree of `packages` packages is resolved
+    Resolved { packages: usize },
+    /// Installation of `packages` starts
+    Started { packages: Vec<String> },
+    /// `received` of `size` bytes of the package archive are downloaded
+    Download { package: String, received: u64, size: u64 },
+    /// `bytes` are received from the network
+    Received { bytes: u64 },
+    /// `processed` of `entries` archive entries of the package are extracted, the last one is `file`
+    Extract { package: String, file: Option<String>, processed: u64, entries: u64 },
+    /// Package is installed
+    Installed { package: String },
+}
+
+/// Handler of installation events
+pub type EventHandler = Box<dyn Fn(&Event) + Send + Sync>;
+
+/// Events of an installation received asynchronously. Stream ends when the installation finishes
+pub struct EventStream {
+    receiver: UnboundedReceiver<Event>,
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+/// Handler which sends events into the returned stream. Events are buffered until they are read, so
+/// a slow reader doesn't slow down the installation
+pub fn channel() -> (EventHandler, EventStream) {
+    let (sender, receiver) = mpsc::unbounded();
+    // Nobody is interested in the events anymore when the stream is dropped
+    let handler: EventHandler = Box::new(move |event| { let _ = sender.unbounded_send(event.clone()); });
+    (handler, EventStream { receiver })
+}
+
+/// Reporter which turns progress into events and passes them to handlers. Used with
+/// `Progress::with_reporter` to receive events of `Program`
+pub struct EventReporter {
+    handlers: Vec<EventHandler>,
+}
+
+impl EventReporter {
+    pub fn new(handlers: Vec<EventHandler>) -> Self {
+        EventReporter { handlers }
+    }
+
+    fn emit(&self, event: Event) {
+        for handler in self.handlers.iter() {
+            handler(&event);
+        }
+    }
+}
+
+impl ProgressReporter for EventReporter {
+    fn message(&self, msg: &str, warning: bool) {
+        self.emit(Event::Message { text: msg.to_owned(), warning });
+    }
+
+    fn repo_load(&self, progress: &archlinux_repo::Progress) {
+        if let archlinux_repo::Progress::LoadingDbChunk(received, size) = progress {
+            self.emit(Event::RepositoryLoad { received: *received, size: *size });
+        }
+    }
+
+    fn tree_index(&self, package: &Package) {
+        self.emit(Event::Resolving { package: package.name.clone() });
+    }
+
+    fn tree_done(&self, packages: usize) {
+        self.emit(Event::Resolved { packages });
+    }
+
+    fn packages(&self, tree: &[Package]) {
+        self.emit(Event::Started { packages: tree.iter().map(|package| package.name.clone()).collect() });
+    }
+
+    fn download(&self, package: &str, pos: u64, len: u64) {
+        self.emit(Event::Download { package: package.to_owned(), received: pos, size: len });
+    }
+
+    fn received(&self, bytes: u64) {
+        self.emit(Event::Received { bytes });
+    }
+
+    fn extract(&self, package: &str, file: Option<&str>, pos: u64, len: u64) {
+        self.emit(Event::Extract { package: package.to_owned(), file: file.map(String::from), processed: pos, entries: len });
+    }
+
+    fn done(&self, package: &str) {
+        self.emit(Event::Installed { package: package.to_owned() });
+    }
+}
@@ -0,0 +1,52 @@
+//! Package archive decoding for parallel extraction
+use compress_tools::uncompress_archive_file;
+use futures::channel::mpsc::{channel, Receiver, Sender};
+use futures::SinkExt;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+use tokio::task::JoinHandle;
+
+/// Archive entry selected for extraction
+pub struct Entry {
+    /// Path inside the archive
+    pub path: String,
+    /// Path relative to the output folder
+    pub target: String,
+    /// Copy of a license file for the notices bundle
+    pub notice: bool,
+}
+
+/// Decoded archive entry
+pub struct DecodedFile {
+    pub entry: Entry,
+    pub data: Vec<u8>,
+}
+
+/// Decode `files` from `archive` on a blocking thread and distribute them between `workers` channels.
+/// Entries with the same target path always go to the same channel, so their write order is preserved.
+/// Decoding stops when all receivers are dropped.
+pub fn decode(archive: Vec<u8>, files: Vec<Entry>, workers: usize) -> (JoinHandle<compress_tools::Result<()>>, Vec<Receiver<DecodedFile>>) {
+    let workers = workers.max(1);
+    let (mut senders, receivers): (Vec<Sender<DecodedFile>>, Vec<Receiver<DecodedFile>>) = (0..workers)
+        .map(|_| channel(1))
+        .unzip();
+    let handle = tokio::task::spawn_blocking(move || {
+        for entry in files {
+            let mut data = Vec::<u8>::new();
+            uncompress_archive_file(&archive[..], Cursor::new(&mut data), &entry.path)?;
+            let sender = &mut senders[worker_index(&entry.target, workers)];
+            if futures::executor::block_on(sender.send(DecodedFile { entry, data })).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    });
+    (handle, receivers)
+}
+
+fn worker_index(path: &str, workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    (hasher.finish() % workers as u64) as usize
+}
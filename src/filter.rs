@@ -0,0 +1,59 @@
+//! Include and exclude rules of package archive entries
+use regex::Regex;
+use std::fmt::{Display, Formatter};
+
+/// Why an archive entry is extracted or skipped
+pub enum Decision<'a> {
+    /// Folder entries are not extracted, folders are created for files
+    Folder,
+    /// Files like `.PKGINFO` and `.MTREE` describe the package itself
+    Metadata,
+    Excluded(&'a Regex),
+    Included(&'a Regex),
+    /// No include rule matches
+    NotIncluded,
+    /// There are no include rules
+    Default,
+}
+
+impl Decision<'_> {
+    pub fn is_extracted(&self) -> bool {
+        match self {
+            Decision::Included(_) | Decision::Default => true,
+            _ => false,
+        }
+    }
+}
+
+impl Display for Decision<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Decision::Folder => write!(f, "skipped: folder entry"),
+            Decision::Metadata => write!(f, "skipped: package metadata"),
+            Decision::Excluded(regex) => write!(f, "excluded by -e {}", regex.as_str()),
+            Decision::Included(regex) => write!(f, "included by -i {}", regex.as_str()),
+            Decision::NotIncluded => write!(f, "skipped: no -i rule matches"),
+            Decision::Default => write!(f, "included: no -i rules"),
+        }
+    }
+}
+
+/// Decide if `file` is extracted. Exclude rules win over include rules
+pub fn decide<'a>(file: &str, include: &'a [Regex], exclude: &'a [Regex]) -> Decision<'a> {
+    if file.ends_with('/') {
+        return Decision::Folder;
+    }
+    if file.starts_with('.') {
+        return Decision::Metadata;
+    }
+    if let Some(regex) = exclude.iter().find(|regex| regex.is_match(file)) {
+        return Decision::Excluded(regex);
+    }
+    if include.is_empty() {
+        return Decision::Default;
+    }
+    match include.iter().find(|regex| regex.is_match(file)) {
+        Some(regex) => Decision::Included(regex),
+        None => Decision::NotIncluded,
+    }
+}
@@ -0,0 +1,42 @@
+//! Advisory locks which keep simultaneous runs from writing into the same output or cache folder
+use crate::progress::Progress;
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// Lock file name inside the locked folder
+pub const LOCK_FILE: &str = ".lock";
+
+/// Exclusive lock of a folder, released when dropped or when the process exits
+pub struct FolderLock {
+    _file: File,
+}
+
+/// Outcome of a lock attempt
+pub enum Attempt {
+    Locked(FolderLock),
+    /// Another process holds the lock
+    Busy,
+}
+
+impl FolderLock {
+    /// Lock `folder`, which is created if missing. With `wait` the call blocks until other process
+    /// releases the lock, otherwise `Busy` is returned
+    pub async fn acquire(folder: &Path, wait: bool, progress: &Progress) -> io::Result<Attempt> {
+        tokio::fs::create_dir_all(folder).await?;
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(folder.join(LOCK_FILE))?;
+        match file.try_lock_exclusive() {
+            Ok(()) => return Ok(Attempt::Locked(FolderLock { _file: file })),
+            Err(e) if e.kind() != fs2::lock_contended_error().kind() => return Err(e),
+            Err(_) if !wait => return Ok(Attempt::Busy),
+            Err(_) => {}
+        }
+        progress.info(&format!("Waiting for another run to release {}", folder.display()));
+        let file = tokio::task::spawn_blocking(move || file.lock_exclusive().map(|_| file)).await??;
+        Ok(Attempt::Locked(FolderLock { _file: file }))
+    }
+}
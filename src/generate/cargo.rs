@@ -0,0 +1,61 @@
+//! Cargo cross compilation config
+use crate::generate::{Toolchain, Target, ToolchainMode, path_to_root};
+
+/// Render `.cargo/config.toml` which will be written to `path` relative to the output root. Cargo
+/// resolves relative linker paths against the parent of the `.cargo` folder, but `rustflags` are
+/// passed to rustc as is, so library search paths require absolute output location `root`
+pub fn config_file(toolchain: &Toolchain, target: &Target, mode: ToolchainMode, path: &str, root: Option<&str>) -> String {
+    let cargo_folder = path.rfind('/').map(|index| &path[..index]).unwrap_or("");
+    let prefix = match root {
+        Some(root) => join(root, &toolchain.prefix),
+        None => join(&path_to_root(cargo_folder), &toolchain.prefix),
+    };
+    let triple = target.rust_triple();
+
+    let mut file = format!("# Generated by {} {}\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    file += "[build]\n";
+    file += &format!("target = {}\n\n", string(&triple));
+    file += &format!("[target.{}]\n", &triple);
+    match mode {
+        ToolchainMode::Native => {
+            if let Some(cc) = toolchain.cc.as_ref() {
+                file += &format!("linker = {}\n", string(&join(&prefix, &format!("bin/{}", cc))));
+            }
+            if toolchain.has("bin/ar.exe") {
+                file += &format!("ar = {}\n", string(&join(&prefix, "bin/ar.exe")));
+            }
+        }
+        ToolchainMode::Cross => {
+            let (cc, _) = target.cross_compilers();
+            file += &format!("linker = {}\n", string(&cc));
+        }
+    }
+    let mut flags = Vec::new();
+    if root.is_some() {
+        flags.push(string(&format!("-Lnative={}", join(&prefix, "lib"))));
+    }
+    if target.clang && mode == ToolchainMode::Cross {
+        flags.push(string(&format!("-Clink-arg=--target={}", &target.triple)));
+    }
+    if !flags.is_empty() {
+        file += &format!("rustflags = [{}]\n", flags.join(", "));
+    }
+    if root.is_none() {
+        file += "# Output location is unknown, add library search path after unpacking:\n";
+        file += &format!("# rustflags = [\"-Lnative=<output>/{}\"]\n", join(&toolchain.prefix, "lib"));
+    }
+    file
+}
+
+fn join(base: &str, path: &str) -> String {
+    match (base.is_empty(), path.is_empty()) {
+        (true, _) => path.to_owned(),
+        (false, true) => base.to_owned(),
+        (false, false) => format!("{}/{}", base.trim_end_matches('/'), path),
+    }
+}
+
+/// TOML basic string
+fn string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
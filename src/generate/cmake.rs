@@ -0,0 +1,50 @@
+//! CMake toolchain file
+use crate::generate::{Toolchain, Target, ToolchainMode, path_to_root};
+
+/// Render toolchain file which will be written to `path` relative to the output root. All paths
+/// are relative to the toolchain file, so the output can be moved
+pub fn toolchain_file(toolchain: &Toolchain, target: &Target, mode: ToolchainMode, path: &str) -> String {
+    let mut file = format!("# Generated by {} {}\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    file += &format!("get_filename_component(WTB_ROOT \"${{CMAKE_CURRENT_LIST_DIR}}/{}\" ABSOLUTE)\n", path_to_root(path));
+    file += &format!("set(WTB_PREFIX \"${{WTB_ROOT}}/{}\")\n\n", &toolchain.prefix);
+    match mode {
+        ToolchainMode::Native => {
+            if let Some(cc) = toolchain.cc.as_ref() {
+                file += &format!("set(CMAKE_C_COMPILER \"${{WTB_PREFIX}}/bin/{}\")\n", cc);
+            }
+            if let Some(cxx) = toolchain.cxx.as_ref() {
+                file += &format!("set(CMAKE_CXX_COMPILER \"${{WTB_PREFIX}}/bin/{}\")\n", cxx);
+            }
+            if toolchain.has("bin/windres.exe") {
+                file += "set(CMAKE_RC_COMPILER \"${WTB_PREFIX}/bin/windres.exe\")\n";
+            }
+            if toolchain.has("bin/pkg-config.exe") {
+                file += "set(PKG_CONFIG_EXECUTABLE \"${WTB_PREFIX}/bin/pkg-config.exe\")\n";
+            }
+            file += "list(APPEND CMAKE_PREFIX_PATH \"${WTB_PREFIX}\")\n";
+            file += "set(ENV{PKG_CONFIG_PATH} \"${WTB_PREFIX}/lib/pkgconfig;${WTB_PREFIX}/share/pkgconfig\")\n";
+        }
+        ToolchainMode::Cross => {
+            let (cc, cxx) = target.cross_compilers();
+            file += "set(CMAKE_SYSTEM_NAME Windows)\n";
+            file += &format!("set(CMAKE_SYSTEM_PROCESSOR {})\n", &target.cpu);
+            file += &format!("set(CMAKE_C_COMPILER {})\n", cc);
+            file += &format!("set(CMAKE_CXX_COMPILER {})\n", cxx);
+            if target.clang {
+                file += &format!("set(CMAKE_C_COMPILER_TARGET {})\n", &target.triple);
+                file += &format!("set(CMAKE_CXX_COMPILER_TARGET {})\n", &target.triple);
+            } else {
+                file += &format!("set(CMAKE_RC_COMPILER {}-windres)\n", &target.triple);
+            }
+            file += "set(CMAKE_FIND_ROOT_PATH \"${WTB_PREFIX}\")\n";
+            file += "set(CMAKE_FIND_ROOT_PATH_MODE_PROGRAM NEVER)\n";
+            file += "set(CMAKE_FIND_ROOT_PATH_MODE_LIBRARY ONLY)\n";
+            file += "set(CMAKE_FIND_ROOT_PATH_MODE_INCLUDE ONLY)\n";
+            file += "set(CMAKE_FIND_ROOT_PATH_MODE_PACKAGE ONLY)\n";
+            file += "set(ENV{PKG_CONFIG_LIBDIR} \"${WTB_PREFIX}/lib/pkgconfig:${WTB_PREFIX}/share/pkgconfig\")\n";
+            // .pc files refer to /<environment>, which is the prefix under the output root
+            file += "set(ENV{PKG_CONFIG_SYSROOT_DIR} \"${WTB_ROOT}\")\n";
+        }
+    }
+    file
+}
@@ -0,0 +1,55 @@
+//! Environment setup scripts
+use crate::generate::{Toolchain, windows_path};
+
+/// Script names relative to the output root
+pub const ENV_BAT: &str = "env.bat";
+pub const ENV_PS1: &str = "env.ps1";
+pub const ENV_SH: &str = "env.sh";
+
+/// `cmd.exe` script
+pub fn bat(toolchain: &Toolchain) -> String {
+    let path = |p: &str| format!("%WTB_ROOT%{}", windows_path(&toolchain.path(p)));
+    let mut script = String::from("@echo off\r\nset \"WTB_ROOT=%~dp0\"\r\n");
+    script += &format!("set \"PATH={};%PATH%\"\r\n", path("bin"));
+    script += &format!("set \"PKG_CONFIG_PATH={};{}\"\r\n", path("lib/pkgconfig"), path("share/pkgconfig"));
+    script += &format!("set \"CMAKE_PREFIX_PATH={}\"\r\n", path(""));
+    if let Some(cc) = toolchain.cc.as_ref() {
+        script += &format!("set \"CC={}\"\r\n", path(&format!("bin/{}", cc)));
+    }
+    if let Some(cxx) = toolchain.cxx.as_ref() {
+        script += &format!("set \"CXX={}\"\r\n", path(&format!("bin/{}", cxx)));
+    }
+    script
+}
+
+/// PowerShell script
+pub fn ps1(toolchain: &Toolchain) -> String {
+    let path = |p: &str| format!("$WtbRoot\\{}", windows_path(&toolchain.path(p)));
+    let mut script = String::from("$WtbRoot = $PSScriptRoot\r\n");
+    script += &format!("$env:PATH = \"{};\" + $env:PATH\r\n", path("bin"));
+    script += &format!("$env:PKG_CONFIG_PATH = \"{};{}\"\r\n", path("lib/pkgconfig"), path("share/pkgconfig"));
+    script += &format!("$env:CMAKE_PREFIX_PATH = \"{}\"\r\n", path(""));
+    if let Some(cc) = toolchain.cc.as_ref() {
+        script += &format!("$env:CC = \"{}\"\r\n", path(&format!("bin/{}", cc)));
+    }
+    if let Some(cxx) = toolchain.cxx.as_ref() {
+        script += &format!("$env:CXX = \"{}\"\r\n", path(&format!("bin/{}", cxx)));
+    }
+    script
+}
+
+/// POSIX shell script, meant to be sourced
+pub fn sh(toolchain: &Toolchain) -> String {
+    let path = |p: &str| format!("$WTB_ROOT/{}", toolchain.path(p));
+    let mut script = String::from("WTB_ROOT=\"$(cd \"$(dirname \"${BASH_SOURCE[0]:-$0}\")\" && pwd)\"\n");
+    script += &format!("export PATH=\"{}:$PATH\"\n", path("bin"));
+    script += &format!("export PKG_CONFIG_PATH=\"{}:{}\"\n", path("lib/pkgconfig"), path("share/pkgconfig"));
+    script += &format!("export CMAKE_PREFIX_PATH=\"{}\"\n", path(""));
+    if let Some(cc) = toolchain.cc.as_ref() {
+        script += &format!("export CC=\"{}\"\n", path(&format!("bin/{}", cc)));
+    }
+    if let Some(cxx) = toolchain.cxx.as_ref() {
+        script += &format!("export CXX=\"{}\"\n", path(&format!("bin/{}", cxx)));
+    }
+    script
+}
@@ -0,0 +1,69 @@
+//! Meson cross and native machine files
+use crate::generate::{Toolchain, Target, ToolchainMode, path_to_root};
+
+/// Render machine file which will be written to `path` relative to the output root. Paths are
+/// relative to the file with `@DIRNAME@`, which requires Meson 0.58 or newer
+pub fn machine_file(toolchain: &Toolchain, target: &Target, mode: ToolchainMode, path: &str) -> String {
+    let mut file = format!("# Generated by {} {}\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    file += "[constants]\n";
+    let root = path_to_root(path);
+    if root.is_empty() {
+        file += "root = '@DIRNAME@'\n";
+    } else {
+        file += &format!("root = '@DIRNAME@' / '{}'\n", root);
+    }
+    if toolchain.prefix.is_empty() {
+        file += "prefix = root\n\n";
+    } else {
+        file += &format!("prefix = root / '{}'\n\n", &toolchain.prefix);
+    }
+
+    file += "[binaries]\n";
+    match mode {
+        ToolchainMode::Native => {
+            if let Some(cc) = toolchain.cc.as_ref() {
+                file += &format!("c = prefix / 'bin/{}'\n", cc);
+            }
+            if let Some(cxx) = toolchain.cxx.as_ref() {
+                file += &format!("cpp = prefix / 'bin/{}'\n", cxx);
+            }
+            for (binary, exe) in &[("ar", "ar.exe"), ("strip", "strip.exe"), ("windres", "windres.exe"), ("pkgconfig", "pkg-config.exe")] {
+                if toolchain.has(&format!("bin/{}", exe)) {
+                    file += &format!("{} = prefix / 'bin/{}'\n", binary, exe);
+                }
+            }
+        }
+        ToolchainMode::Cross => {
+            if target.clang {
+                file += &format!("c = ['clang', '--target={}']\n", &target.triple);
+                file += &format!("cpp = ['clang++', '--target={}']\n", &target.triple);
+                file += "ar = 'llvm-ar'\nstrip = 'llvm-strip'\nwindres = 'llvm-windres'\n";
+            } else {
+                let (cc, cxx) = target.cross_compilers();
+                file += &format!("c = '{}'\ncpp = '{}'\n", cc, cxx);
+                file += &format!("ar = '{0}-ar'\nstrip = '{0}-strip'\nwindres = '{0}-windres'\n", &target.triple);
+            }
+            file += "pkgconfig = 'pkg-config'\n";
+        }
+    }
+
+    file += "\n[properties]\n";
+    file += "pkg_config_libdir = [prefix / 'lib/pkgconfig', prefix / 'share/pkgconfig']\n";
+    if mode == ToolchainMode::Cross {
+        // .pc files refer to /<environment>, which is the prefix under the output root
+        file += "sys_root = root\n";
+        file += "\n[host_machine]\n";
+        file += "system = 'windows'\n";
+        file += &format!("cpu_family = '{}'\n", cpu_family(&target.cpu));
+        file += &format!("cpu = '{}'\n", &target.cpu);
+        file += "endian = 'little'\n";
+    }
+    file
+}
+
+fn cpu_family(cpu: &str) -> &str {
+    match cpu {
+        "i686" => "x86",
+        cpu => cpu,
+    }
+}
@@ -0,0 +1,164 @@
+//! Generators of files which make the extracted toolchain usable from other tools
+use crate::manifest::Manifest;
+use std::collections::{BTreeMap, HashSet};
+use std::str::FromStr;
+
+pub mod cargo;
+pub mod cmake;
+pub mod env;
+pub mod meson;
+
+/// How generated build system files use the toolchain
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ToolchainMode {
+    /// Extracted compilers are executed directly, requires Windows host
+    Native,
+    /// Host cross compiler is used with the extracted tree as find root
+    Cross,
+}
+
+impl FromStr for ToolchainMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native" => Ok(ToolchainMode::Native),
+            "cross" => Ok(ToolchainMode::Cross),
+            _ => Err(format!("Unknown toolchain mode: \"{}\"", s)),
+        }
+    }
+}
+
+impl ToolchainMode {
+    /// Native on Windows hosts, cross otherwise
+    pub fn host_default() -> Self {
+        if cfg!(windows) { ToolchainMode::Native } else { ToolchainMode::Cross }
+    }
+}
+
+/// Target machine of MSYS2 environment
+#[derive(Clone, Debug)]
+pub struct Target {
+    /// GNU target triple, e.g. `x86_64-w64-mingw32`
+    pub triple: String,
+    /// CMake/Meson CPU name
+    pub cpu: String,
+    /// Environment uses LLVM toolchain (clang64, clang32, clangarm64)
+    pub clang: bool,
+}
+
+impl Target {
+    /// Build target for repository `environment` name and package architecture
+    pub fn new(environment: &str, architecture: &str) -> Self {
+        let cpu = if environment.contains("arm64") {
+            "aarch64"
+        } else if environment.ends_with("32") || architecture == "i686" {
+            "i686"
+        } else {
+            "x86_64"
+        };
+        Target {
+            triple: format!("{}-w64-mingw32", cpu),
+            cpu: cpu.to_owned(),
+            clang: environment.starts_with("clang"),
+        }
+    }
+
+    /// Rust target triple
+    pub fn rust_triple(&self) -> String {
+        if self.clang {
+            format!("{}-pc-windows-gnullvm", &self.cpu)
+        } else {
+            format!("{}-pc-windows-gnu", &self.cpu)
+        }
+    }
+
+    /// Host cross compiler names for C and C++
+    pub fn cross_compilers(&self) -> (String, String) {
+        if self.clang {
+            (String::from("clang"), String::from("clang++"))
+        } else {
+            (format!("{}-gcc", &self.triple), format!("{}-g++", &self.triple))
+        }
+    }
+}
+
+/// Toolchain layout detected from the output files
+#[derive(Clone, Debug)]
+pub struct Toolchain {
+    /// Installation prefix relative to the output root, `/`-separated. Empty if files are in the root
+    pub prefix: String,
+    /// C compiler executable name
+    pub cc: Option<String>,
+    /// C++ compiler executable name
+    pub cxx: Option<String>,
+    /// All files of the output, `/`-separated
+    files: HashSet<String>,
+}
+
+const COMPILERS: &[(&str, &str)] = &[("gcc.exe", "g++.exe"), ("clang.exe", "clang++.exe")];
+
+impl Toolchain {
+    /// Detect the prefix with the most executables in its `bin` folder, or use `fallback_prefix`
+    /// if there are no executables at all
+    pub fn detect(manifest: &Manifest, fallback_prefix: &str) -> Self {
+        let mut prefixes = BTreeMap::<&str, usize>::new();
+        for entry in manifest.files.iter() {
+            let prefix = if entry.path.starts_with("bin/") {
+                Some("")
+            } else {
+                entry.path.find("/bin/").map(|index| &entry.path[..index])
+            };
+            if let Some(prefix) = prefix {
+                *prefixes.entry(prefix).or_insert(0) += 1;
+            }
+        }
+        let prefix = prefixes.iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(prefix, _)| prefix.to_string())
+            .unwrap_or_else(|| fallback_prefix.to_owned());
+
+        let files: HashSet<String> = manifest.files.iter().map(|entry| entry.path.clone()).collect();
+        let bin = |name: &str| -> Option<String> {
+            let path = join(&prefix, &format!("bin/{}", name));
+            if files.contains(&path) { Some(name.to_owned()) } else { None }
+        };
+        let compilers = COMPILERS.iter().find_map(|(cc, cxx)| bin(*cc).map(|cc| (cc, bin(*cxx))));
+        Toolchain {
+            cc: compilers.as_ref().map(|(cc, _)| cc.clone()),
+            cxx: compilers.and_then(|(_, cxx)| cxx),
+            prefix,
+            files,
+        }
+    }
+
+    /// Check if there is `path` inside the prefix
+    pub fn has(&self, path: &str) -> bool {
+        self.files.contains(&self.path(path))
+    }
+
+    /// Path relative to the output root of `path` inside the prefix
+    pub fn path(&self, path: &str) -> String {
+        join(&self.prefix, path)
+    }
+}
+
+fn join(prefix: &str, path: &str) -> String {
+    match (prefix.is_empty(), path.is_empty()) {
+        (true, _) => path.to_owned(),
+        (false, true) => prefix.to_owned(),
+        (false, false) => format!("{}/{}", prefix, path),
+    }
+}
+
+/// Relative path from the folder of output file `path` to the output root, `/`-separated and
+/// empty if the file is in the root
+pub fn path_to_root(path: &str) -> String {
+    let depth = path.trim_start_matches('/').matches('/').count();
+    vec![".."; depth].join("/")
+}
+
+/// Convert `/`-separated relative path to Windows form
+pub fn windows_path(path: &str) -> String {
+    path.replace('/', "\\")
+}
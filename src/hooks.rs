@@ -0,0 +1,193 @@
+//! Fixup hooks which run when all packages are extracted, for files which the package manager
+//! normally generates in post-install scripts, and commands of `[hooks]` of the config file which run
+//! at stages of the installation
+use crate::license::Glob;
+use crate::manifest::Manifest;
+use crate::paths;
+use crate::tool::CommandTemplate;
+use serde::Deserialize;
+use std::io;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// What a hook does
+#[derive(Clone, Debug)]
+pub enum HookAction {
+    /// Run command in the output root
+    Command(CommandTemplate),
+    /// Write `qt.conf` next to `qmake`, so Qt finds plugins relative to its binaries
+    QtConf,
+    /// Generate `loaders.cache` of gdk-pixbuf with `gdk-pixbuf-query-loaders`
+    PixbufLoaders,
+}
+
+/// Action which runs once for every extracted package matching any of `packages`
+#[derive(Clone, Debug)]
+pub struct Hook {
+    pub packages: Vec<Glob>,
+    pub action: HookAction,
+}
+
+impl FromStr for Hook {
+    type Err = String;
+
+    /// Parse `PACKAGE=COMMAND`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let index = s.find('=').ok_or_else(|| format!("Hook must be PACKAGE=COMMAND: \"{}\"", s))?;
+        Ok(Hook {
+            packages: vec![Glob::from_str(&s[..index])?],
+            action: HookAction::Command(CommandTemplate::from_str(&s[index + 1..])?),
+        })
+    }
+}
+
+impl Hook {
+    /// Built-in fixup by name
+    pub fn builtin(name: &str) -> Result<Self, String> {
+        let (patterns, action): (&[&str], HookAction) = match name {
+            "qt-conf" => (&["*-qt5-base", "*-qt6-base"][..], HookAction::QtConf),
+            "pixbuf-loaders" => (&["*-gdk-pixbuf2"][..], HookAction::PixbufLoaders),
+            _ => return Err(format!("Unknown fixup: \"{}\"", name)),
+        };
+        Ok(Hook {
+            packages: patterns.iter().map(|pattern| Glob::from_str(pattern).unwrap()).collect(),
+            action,
+        })
+    }
+
+    pub fn matches(&self, package: &str) -> bool {
+        self.packages.iter().any(|glob| glob.is_match(package))
+    }
+
+    /// Hook runs programs over extracted files, so they must be on disk
+    pub fn needs_files(&self) -> bool {
+        match self.action {
+            HookAction::QtConf => false,
+            HookAction::Command(_) | HookAction::PixbufLoaders => true,
+        }
+    }
+}
+
+/// `qt.conf` files for every `qmake` of `package` which doesn't have one
+pub fn qt_conf(manifest: &Manifest, package: &str) -> Vec<(String, Vec<u8>)> {
+    manifest.files.iter()
+        .filter(|entry| entry.package == package)
+        .map(|entry| entry.path.as_str())
+        .filter(|path| {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            name.starts_with("qmake") && name.ends_with(".exe")
+        })
+        .map(|path| format!("{}qt.conf", &path[..path.rfind('/').map_or(0, |index| index + 1)]))
+        .filter(|conf| !manifest.files.iter().any(|entry| &entry.path == conf))
+        .map(|conf| (conf, b"[Paths]\r\nPrefix = ..\r\n".to_vec()))
+        .collect()
+}
+
+/// Command which updates `loaders.cache` of `package` extracted into `root`. Windows binaries run
+/// through `wine` on other systems
+pub fn pixbuf_loaders(manifest: &Manifest, package: &str, root: &Path) -> Option<CommandTemplate> {
+    let entry = manifest.files.iter()
+        .find(|entry| entry.package == package && entry.path.ends_with("bin/gdk-pixbuf-query-loaders.exe"))?;
+    let program = paths::join(root, &entry.path).to_string_lossy().into_owned();
+    let args = vec![String::from("--update-cache")];
+    if cfg!(windows) {
+        Some(CommandTemplate::new(&program, args))
+    } else {
+        Some(CommandTemplate::new("wine", std::iter::once(program).chain(args).collect()))
+    }
+}
+
+/// Stage of the installation a `[hooks]` command runs at
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stage {
+    /// Package is downloaded, but not extracted yet
+    PreExtract,
+    /// Package is extracted
+    PostExtract,
+    /// Output is complete
+    PostBuild,
+}
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::PreExtract => "pre_extract",
+            Stage::PostExtract => "post_extract",
+            Stage::PostBuild => "post_build",
+        }
+    }
+}
+
+/// What to do when a `[hooks]` command fails
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookFailure {
+    /// Stop the run
+    Fail,
+    /// Print a warning and continue
+    Warn,
+    /// Continue silently
+    Ignore,
+}
+
+impl Default for HookFailure {
+    fn default() -> Self {
+        HookFailure::Fail
+    }
+}
+
+/// Commands of `[hooks]` of the config file
+#[derive(Clone, Debug, Default)]
+pub struct StageHooks {
+    pub pre_extract: Option<CommandTemplate>,
+    pub post_extract: Option<CommandTemplate>,
+    pub post_build: Option<CommandTemplate>,
+    pub failure: HookFailure,
+}
+
+impl StageHooks {
+    /// Command which runs at `stage`
+    pub fn command(&self, stage: Stage) -> Option<&CommandTemplate> {
+        match stage {
+            Stage::PreExtract => self.pre_extract.as_ref(),
+            Stage::PostExtract => self.post_extract.as_ref(),
+            Stage::PostBuild => self.post_build.as_ref(),
+        }
+    }
+}
+
+/// Run hook command in `root` with `{root}`, `{package}` and `{version}` placeholders. Returns exit
+/// status with combined output
+pub fn run(template: &CommandTemplate, root: &Path, package: &str, version: &str) -> io::Result<(ExitStatus, String)> {
+    let root_path = root.to_string_lossy().into_owned();
+    let mut command = template.command(&[("root", &root_path), ("package", package), ("version", version)]);
+    command.current_dir(root);
+    captured(command)
+}
+
+/// Run `[hooks]` command with `{package}`, `{version}` and `{output}` placeholders. Returns exit status
+/// with combined output
+pub fn run_stage(template: &CommandTemplate, package: &str, version: &str, output: &Path) -> io::Result<(ExitStatus, String)> {
+    let output = output.to_string_lossy().into_owned();
+    captured(template.command(&[("package", package), ("version", version), ("output", &output)]))
+}
+
+fn captured(mut command: Command) -> io::Result<(ExitStatus, String)> {
+    let output = command.stdin(Stdio::null()).output()?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text += &String::from_utf8_lossy(&output.stderr);
+    Ok((output.status, text))
+}
+
+/// Files of `root` modified after `since`
+pub fn changed_files(root: &Path, since: SystemTime) -> io::Result<Vec<String>> {
+    let mut changed = Vec::new();
+    for path in paths::list_files(root)? {
+        if std::fs::metadata(paths::join(root, &path))?.modified()? >= since {
+            changed.push(path);
+        }
+    }
+    Ok(changed)
+}
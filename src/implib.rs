@@ -0,0 +1,330 @@
+//! MSVC import library generation from DLL export tables.
+//!
+//! Libraries have the same layout as ones produced by `lib.exe` and `llvm-dlltool`: COFF archive
+//! with import descriptor objects followed by short import object for every exported symbol
+use crate::pe::{Export, PeImage};
+
+const ARCHIVE_MAGIC: &[u8] = b"!<arch>\n";
+const MEMBER_HEADER_SIZE: usize = 60;
+const COFF_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+const RELOCATION_SIZE: usize = 10;
+const SYMBOL_SIZE: usize = 18;
+
+const MACHINE_I386: u16 = 0x14c;
+const MACHINE_AMD64: u16 = 0x8664;
+const MACHINE_ARM64: u16 = 0xaa64;
+const IMAGE_FILE_32BIT_MACHINE: u16 = 0x100;
+
+const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x40;
+const IMAGE_SCN_ALIGN_2BYTES: u32 = 0x20_0000;
+const IMAGE_SCN_ALIGN_4BYTES: u32 = 0x30_0000;
+const IMAGE_SCN_ALIGN_8BYTES: u32 = 0x40_0000;
+const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+const IDATA: u32 = IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE;
+
+const IMAGE_SYM_CLASS_EXTERNAL: u8 = 2;
+const IMAGE_SYM_CLASS_STATIC: u8 = 3;
+const IMAGE_SYM_CLASS_SECTION: u8 = 0x68;
+
+const IMPORT_CODE: u16 = 0;
+const IMPORT_DATA: u16 = 1;
+const IMPORT_NAME: u16 = 1;
+const IMPORT_NAME_NOPREFIX: u16 = 2;
+
+const NULL_IMPORT_DESCRIPTOR: &str = "__NULL_IMPORT_DESCRIPTOR";
+
+/// Generate import library for DLL `data`. Returns `None` if it isn't a DLL with exports for a
+/// supported machine
+pub fn generate(data: &[u8]) -> Option<Vec<u8>> {
+    let image = PeImage::parse(data)?;
+    let machine = image.machine;
+    let relocation = match machine {
+        MACHINE_I386 => 0x7,
+        MACHINE_AMD64 => 0x3,
+        MACHINE_ARM64 => 0x2,
+        _ => return None,
+    };
+    let (dll, exports) = image.exports()?;
+    if exports.is_empty() {
+        return None;
+    }
+    let stem = match dll.rfind('.') {
+        Some(index) => &dll[..index],
+        None => dll.as_str(),
+    };
+    let descriptor = format!("__IMPORT_DESCRIPTOR_{}", stem);
+    let thunk = format!("\x7f{}_NULL_THUNK_DATA", stem);
+
+    let mut members = vec![
+        Member {
+            data: import_descriptor(machine, relocation, &dll, &descriptor, &thunk),
+            symbols: vec![descriptor],
+        },
+        Member { data: null_import_descriptor(machine), symbols: vec![NULL_IMPORT_DESCRIPTOR.to_owned()] },
+        Member { data: null_thunk(machine, &thunk), symbols: vec![thunk] },
+    ];
+    for export in exports.iter() {
+        // 32-bit C symbols are decorated with `_`, which is removed from the imported name
+        let (symbol, name_type) = if machine == MACHINE_I386 && !export.name.starts_with('?') {
+            (format!("_{}", &export.name), IMPORT_NAME_NOPREFIX)
+        } else {
+            (export.name.clone(), IMPORT_NAME)
+        };
+        let mut symbols = vec![format!("__imp_{}", &symbol)];
+        if !export.data {
+            symbols.push(symbol.clone());
+        }
+        members.push(Member { data: short_import(machine, &symbol, &dll, export, name_type), symbols });
+    }
+    Some(archive(&dll, &members))
+}
+
+fn short_import(machine: u16, symbol: &str, dll: &str, export: &Export, name_type: u16) -> Vec<u8> {
+    let import_type = if export.data { IMPORT_DATA } else { IMPORT_CODE };
+    let size = symbol.len() + 1 + dll.len() + 1;
+    let mut data = Vec::with_capacity(COFF_HEADER_SIZE + size);
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&0xffffu16.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&machine.to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&(size as u32).to_le_bytes());
+    data.extend_from_slice(&export.ordinal.to_le_bytes());
+    data.extend_from_slice(&(import_type | name_type << 2).to_le_bytes());
+    data.extend_from_slice(symbol.as_bytes());
+    data.push(0);
+    data.extend_from_slice(dll.as_bytes());
+    data.push(0);
+    data
+}
+
+/// Object with `.idata$2` import directory entry of the DLL and its name in `.idata$6`
+fn import_descriptor(machine: u16, relocation: u16, dll: &str, descriptor: &str, thunk: &str) -> Vec<u8> {
+    let mut name = dll.as_bytes().to_vec();
+    name.push(0);
+    if name.len() % 2 != 0 {
+        name.push(0);
+    }
+    let sections = vec![
+        Section {
+            name: ".idata$2",
+            data: vec![0; 20],
+            // Name, ImportLookupTableRVA and ImportAddressTableRVA of the directory entry
+            relocations: vec![(12, 2, relocation), (0, 3, relocation), (16, 4, relocation)],
+            characteristics: IDATA | IMAGE_SCN_ALIGN_4BYTES,
+        },
+        Section {
+            name: ".idata$6",
+            data: name,
+            relocations: Vec::new(),
+            characteristics: IDATA | IMAGE_SCN_ALIGN_2BYTES,
+        },
+    ];
+    let symbols = vec![
+        Symbol::new(descriptor, 1, 0, IMAGE_SYM_CLASS_EXTERNAL),
+        Symbol::new(".idata$2", 1, IDATA, IMAGE_SYM_CLASS_SECTION),
+        Symbol::new(".idata$6", 2, 0, IMAGE_SYM_CLASS_STATIC),
+        Symbol::new(".idata$4", 0, IDATA, IMAGE_SYM_CLASS_SECTION),
+        Symbol::new(".idata$5", 0, IDATA, IMAGE_SYM_CLASS_SECTION),
+        Symbol::new(NULL_IMPORT_DESCRIPTOR, 0, 0, IMAGE_SYM_CLASS_EXTERNAL),
+        Symbol::new(thunk, 0, 0, IMAGE_SYM_CLASS_EXTERNAL),
+    ];
+    object(machine, &sections, &symbols)
+}
+
+/// Object with zero entry which terminates import directory
+fn null_import_descriptor(machine: u16) -> Vec<u8> {
+    let sections = vec![Section {
+        name: ".idata$3",
+        data: vec![0; 20],
+        relocations: Vec::new(),
+        characteristics: IDATA | IMAGE_SCN_ALIGN_4BYTES,
+    }];
+    let symbols = vec![Symbol::new(NULL_IMPORT_DESCRIPTOR, 1, 0, IMAGE_SYM_CLASS_EXTERNAL)];
+    object(machine, &sections, &symbols)
+}
+
+/// Object with zero entries which terminate import lookup and import address tables of the DLL
+fn null_thunk(machine: u16, thunk: &str) -> Vec<u8> {
+    let (size, alignment) = if machine == MACHINE_I386 {
+        (4, IMAGE_SCN_ALIGN_4BYTES)
+    } else {
+        (8, IMAGE_SCN_ALIGN_8BYTES)
+    };
+    let sections = vec![
+        Section { name: ".idata$5", data: vec![0; size], relocations: Vec::new(), characteristics: IDATA | alignment },
+        Section { name: ".idata$4", data: vec![0; size], relocations: Vec::new(), characteristics: IDATA | alignment },
+    ];
+    let symbols = vec![Symbol::new(thunk, 1, 0, IMAGE_SYM_CLASS_EXTERNAL)];
+    object(machine, &sections, &symbols)
+}
+
+struct Section {
+    name: &'static str,
+    data: Vec<u8>,
+    /// Offset, symbol index and type
+    relocations: Vec<(u32, u32, u16)>,
+    characteristics: u32,
+}
+
+struct Symbol<'a> {
+    name: &'a str,
+    section: i16,
+    value: u32,
+    class: u8,
+}
+
+impl<'a> Symbol<'a> {
+    fn new(name: &'a str, section: i16, value: u32, class: u8) -> Self {
+        Symbol { name, section, value, class }
+    }
+}
+
+fn object(machine: u16, sections: &[Section], symbols: &[Symbol]) -> Vec<u8> {
+    let mut offset = COFF_HEADER_SIZE + sections.len() * SECTION_HEADER_SIZE;
+    let mut headers = Vec::new();
+    let mut contents = Vec::new();
+    for section in sections.iter() {
+        let mut name = [0u8; 8];
+        name[..section.name.len()].copy_from_slice(section.name.as_bytes());
+        let relocations = offset + section.data.len();
+        headers.extend_from_slice(&name);
+        headers.extend_from_slice(&0u32.to_le_bytes());
+        headers.extend_from_slice(&0u32.to_le_bytes());
+        headers.extend_from_slice(&(section.data.len() as u32).to_le_bytes());
+        headers.extend_from_slice(&(offset as u32).to_le_bytes());
+        headers.extend_from_slice(&(if section.relocations.is_empty() { 0 } else { relocations as u32 }).to_le_bytes());
+        headers.extend_from_slice(&0u32.to_le_bytes());
+        headers.extend_from_slice(&(section.relocations.len() as u16).to_le_bytes());
+        headers.extend_from_slice(&0u16.to_le_bytes());
+        headers.extend_from_slice(&section.characteristics.to_le_bytes());
+
+        contents.extend_from_slice(&section.data);
+        for (address, symbol, kind) in section.relocations.iter() {
+            contents.extend_from_slice(&address.to_le_bytes());
+            contents.extend_from_slice(&symbol.to_le_bytes());
+            contents.extend_from_slice(&kind.to_le_bytes());
+        }
+        offset = relocations + section.relocations.len() * RELOCATION_SIZE;
+    }
+
+    let mut strings = Vec::new();
+    let mut table = Vec::with_capacity(symbols.len() * SYMBOL_SIZE);
+    for symbol in symbols.iter() {
+        if symbol.name.len() <= 8 {
+            let mut name = [0u8; 8];
+            name[..symbol.name.len()].copy_from_slice(symbol.name.as_bytes());
+            table.extend_from_slice(&name);
+        } else {
+            table.extend_from_slice(&0u32.to_le_bytes());
+            table.extend_from_slice(&(4 + strings.len() as u32).to_le_bytes());
+            strings.extend_from_slice(symbol.name.as_bytes());
+            strings.push(0);
+        }
+        table.extend_from_slice(&symbol.value.to_le_bytes());
+        table.extend_from_slice(&symbol.section.to_le_bytes());
+        table.extend_from_slice(&0u16.to_le_bytes());
+        table.push(symbol.class);
+        table.push(0);
+    }
+
+    let characteristics = if machine == MACHINE_I386 { IMAGE_FILE_32BIT_MACHINE } else { 0 };
+    let mut data = Vec::with_capacity(offset + table.len() + 4 + strings.len());
+    data.extend_from_slice(&machine.to_le_bytes());
+    data.extend_from_slice(&(sections.len() as u16).to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&(offset as u32).to_le_bytes());
+    data.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&characteristics.to_le_bytes());
+    data.extend_from_slice(&headers);
+    data.extend_from_slice(&contents);
+    data.extend_from_slice(&table);
+    data.extend_from_slice(&(4 + strings.len() as u32).to_le_bytes());
+    data.extend_from_slice(&strings);
+    data
+}
+
+struct Member {
+    data: Vec<u8>,
+    symbols: Vec<String>,
+}
+
+/// Build archive with both linker members. All members are named after the DLL
+fn archive(dll: &str, members: &[Member]) -> Vec<u8> {
+    let symbols: Vec<(&str, usize)> = members.iter()
+        .enumerate()
+        .flat_map(|(index, member)| member.symbols.iter().map(move |symbol| (symbol.as_str(), index)))
+        .collect();
+    let names_size: usize = symbols.iter().map(|(symbol, _)| symbol.len() + 1).sum();
+    let first_size = 4 + symbols.len() * 4 + names_size;
+    let second_size = 4 + members.len() * 4 + 4 + symbols.len() * 2 + names_size;
+    let (member_name, long_names) = if dll.len() < 16 {
+        (format!("{}/", dll), None)
+    } else {
+        (String::from("/0"), Some(format!("{}\0", dll).into_bytes()))
+    };
+
+    let mut offset = ARCHIVE_MAGIC.len() + MEMBER_HEADER_SIZE + padded(first_size) + MEMBER_HEADER_SIZE + padded(second_size);
+    if let Some(long_names) = long_names.as_ref() {
+        offset += MEMBER_HEADER_SIZE + padded(long_names.len());
+    }
+    let mut offsets = Vec::with_capacity(members.len());
+    for member in members.iter() {
+        offsets.push(offset as u32);
+        offset += MEMBER_HEADER_SIZE + padded(member.data.len());
+    }
+
+    let mut first = Vec::with_capacity(first_size);
+    first.extend_from_slice(&(symbols.len() as u32).to_be_bytes());
+    for (_, index) in symbols.iter() {
+        first.extend_from_slice(&offsets[*index].to_be_bytes());
+    }
+    for (symbol, _) in symbols.iter() {
+        first.extend_from_slice(symbol.as_bytes());
+        first.push(0);
+    }
+
+    let mut sorted = symbols.clone();
+    sorted.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+    let mut second = Vec::with_capacity(second_size);
+    second.extend_from_slice(&(members.len() as u32).to_le_bytes());
+    for offset in offsets.iter() {
+        second.extend_from_slice(&offset.to_le_bytes());
+    }
+    second.extend_from_slice(&(sorted.len() as u32).to_le_bytes());
+    for (_, index) in sorted.iter() {
+        second.extend_from_slice(&(*index as u16 + 1).to_le_bytes());
+    }
+    for (symbol, _) in sorted.iter() {
+        second.extend_from_slice(symbol.as_bytes());
+        second.push(0);
+    }
+
+    let mut data = Vec::with_capacity(offset);
+    data.extend_from_slice(ARCHIVE_MAGIC);
+    append_member(&mut data, "/", &first);
+    append_member(&mut data, "/", &second);
+    if let Some(long_names) = long_names.as_ref() {
+        append_member(&mut data, "//", long_names);
+    }
+    for member in members.iter() {
+        append_member(&mut data, &member_name, &member.data);
+    }
+    data
+}
+
+fn append_member(archive: &mut Vec<u8>, name: &str, data: &[u8]) {
+    let header = format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", name, 0, 0, 0, 644, data.len());
+    archive.extend_from_slice(header.as_bytes());
+    archive.extend_from_slice(data);
+    if data.len() % 2 != 0 {
+        archive.push(b'\n');
+    }
+}
+
+fn padded(size: usize) -> usize {
+    size + size % 2
+}
@@ -0,0 +1,103 @@
+//! Validation of DLL imports of the extracted binaries
+use crate::license::Glob;
+use crate::manifest::Manifest;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// DLLs shipped with Windows
+const SYSTEM_DLLS: &[&str] = &[
+    "api-ms-win-*", "ext-ms-*", "advapi32.dll", "bcrypt.dll", "cfgmgr32.dll", "comctl32.dll",
+    "comdlg32.dll", "crypt32.dll", "d2d1.dll", "d3d*.dll", "dbghelp.dll", "dnsapi.dll", "dwmapi.dll",
+    "dwrite.dll", "dxgi.dll", "gdi32.dll", "gdiplus.dll", "hid.dll", "imm32.dll", "iphlpapi.dll",
+    "kernel32.dll", "msimg32.dll", "msvcrt.dll", "mswsock.dll", "ncrypt.dll", "netapi32.dll",
+    "ntdll.dll", "ole32.dll", "oleaut32.dll", "opengl32.dll", "psapi.dll", "rpcrt4.dll",
+    "secur32.dll", "setupapi.dll", "shell32.dll", "shlwapi.dll", "ucrtbase.dll", "user32.dll",
+    "userenv.dll", "uxtheme.dll", "version.dll", "winhttp.dll", "wininet.dll", "winmm.dll",
+    "winspool.drv", "wldap32.dll", "ws2_32.dll", "wsock32.dll",
+];
+
+/// What to do with unsatisfied imports
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImportCheck {
+    Fail,
+    Warn,
+}
+
+impl FromStr for ImportCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(ImportCheck::Fail),
+            "warn" => Ok(ImportCheck::Warn),
+            _ => Err(format!("Unknown import check action: \"{}\"", s)),
+        }
+    }
+}
+
+/// Import which isn't satisfied by the output
+#[derive(Clone, Debug)]
+pub struct MissingImport {
+    /// Path of the importing binary
+    pub path: String,
+    /// Imported DLL name
+    pub dll: String,
+}
+
+/// Lookup of DLLs in the output. DLL is found if it is in the folder of the importing binary or in
+/// any `bin` folder, which is expected to be in `PATH`
+pub struct Resolver<'a> {
+    files: HashMap<String, &'a str>,
+    bin_folders: Vec<&'a str>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(manifest: &'a Manifest) -> Self {
+        let files = manifest.files.iter()
+            .map(|entry| (entry.path.to_lowercase(), entry.path.as_str()))
+            .collect();
+        let mut bin_folders: Vec<&str> = manifest.files.iter()
+            .filter_map(|entry| folder(&entry.path))
+            .filter(|folder| *folder == "bin" || folder.ends_with("/bin"))
+            .collect();
+        bin_folders.sort();
+        bin_folders.dedup();
+        Resolver { files, bin_folders }
+    }
+
+    /// Path of `dll` imported by binary with `path`
+    pub fn resolve(&self, path: &str, dll: &str) -> Option<&'a str> {
+        let dll = dll.to_lowercase();
+        folder(path).into_iter()
+            .chain(self.bin_folders.iter().cloned())
+            .map(|folder| format!("{}/{}", folder.to_lowercase(), &dll))
+            .chain(std::iter::once(dll.clone()))
+            .find_map(|candidate| self.files.get(&candidate).cloned())
+    }
+}
+
+/// Check if `dll` is shipped with Windows or allowed by `allow`
+pub fn is_system(dll: &str, allow: &[Glob]) -> bool {
+    SYSTEM_DLLS.iter().any(|pattern| Glob::from_str(pattern).unwrap().is_match(dll)) ||
+        allow.iter().any(|glob| glob.is_match(dll))
+}
+
+/// Find imports of output binaries which are neither system DLLs, nor allowed by `allow`, nor
+/// present in the output
+pub fn missing(manifest: &Manifest, allow: &[Glob]) -> Vec<MissingImport> {
+    let resolver = Resolver::new(manifest);
+    let mut missing = Vec::new();
+    for entry in manifest.files.iter() {
+        for dll in entry.imports.iter() {
+            if !is_system(dll, allow) && resolver.resolve(&entry.path, dll).is_none() {
+                missing.push(MissingImport { path: entry.path.clone(), dll: dll.clone() });
+            }
+        }
+    }
+    missing.sort_by(|a, b| a.path.cmp(&b.path).then_with(|| a.dll.cmp(&b.dll)));
+    missing
+}
+
+fn folder(path: &str) -> Option<&str> {
+    path.rfind('/').map(|index| &path[..index])
+}
@@ -0,0 +1,225 @@
+//! Windows installer sources generated from the output manifest.
+//!
+//! Generated folder holds the installer source next to `payload` folder with copies of all output
+//! files, so it can be built with `makensis installer.nsi` or `candle installer.wxs && light installer.wixobj`
+use crate::generate::{Target, Toolchain, windows_path};
+use crate::manifest::{Manifest, sha256_hex};
+use crate::paths;
+use crate::progress::Progress;
+use std::collections::BTreeMap;
+use crate::error::Error;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const PAYLOAD_FOLDER: &str = "payload";
+const NSIS_SCRIPT: &str = "installer.nsi";
+const WIX_SOURCE: &str = "installer.wxs";
+
+/// Installer toolkit
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InstallerKind {
+    Nsis,
+    Wix,
+}
+
+impl FromStr for InstallerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nsis" => Ok(InstallerKind::Nsis),
+            "wix" => Ok(InstallerKind::Wix),
+            _ => Err(format!("Unknown installer kind: \"{}\"", s)),
+        }
+    }
+}
+
+/// Options of `installer` command
+#[derive(Clone, Debug)]
+pub struct InstallerOptions {
+    pub kind: InstallerKind,
+    /// Folder to generate installer source in
+    pub destination: PathBuf,
+    /// Product name, also used as installation folder name
+    pub name: String,
+    pub version: String,
+    pub publisher: String,
+    /// Add `bin` folder of the toolchain to system `PATH`
+    pub add_to_path: bool,
+}
+
+/// Generate installer source and payload for all files listed in the manifest of `output` folder
+pub async fn installer(output: &Path, options: &InstallerOptions, target: &Target, progress: &Progress) -> Result<(), Error> {
+    let mut manifest = Manifest::load(output).await?;
+    manifest.files.sort_by(|a, b| a.path.cmp(&b.path));
+    let payload = options.destination.join(PAYLOAD_FOLDER);
+    let packing = progress.packing(&payload.to_string_lossy(), manifest.files.len());
+    for entry in manifest.files.iter() {
+        let target = paths::join(&payload, &entry.path);
+        tokio::fs::create_dir_all(target.parent().unwrap()).await?;
+        tokio::fs::copy(paths::join(output, &entry.path), &target).await?;
+        packing.file(&entry.path);
+    }
+    packing.complete();
+
+    let bin = Toolchain::detect(&manifest, "").path("bin");
+    let (path, source) = match options.kind {
+        InstallerKind::Nsis => (NSIS_SCRIPT, nsis(&manifest, options, target, &bin)),
+        InstallerKind::Wix => (WIX_SOURCE, wix(&manifest, options, target, &bin)),
+    };
+    tokio::fs::write(options.destination.join(path), source).await?;
+    Ok(())
+}
+
+/// Render NSIS script. `PATH` is changed with EnVar plugin
+fn nsis(manifest: &Manifest, options: &InstallerOptions, target: &Target, bin: &str) -> String {
+    let mut script = format!("; Generated by {} {}\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    script += "Unicode true\n";
+    script += &format!("!define NAME \"{}\"\n", nsis_string(&options.name));
+    script += &format!("!define VERSION \"{}\"\n", nsis_string(&options.version));
+    script += &format!("!define PUBLISHER \"{}\"\n", nsis_string(&options.publisher));
+    script += "!define UNINSTALL_KEY \"Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\${NAME}\"\n\n";
+    script += "Name \"${NAME} ${VERSION}\"\n";
+    script += "OutFile \"${NAME}-${VERSION}-setup.exe\"\n";
+    let program_files = if target.cpu == "i686" { "$PROGRAMFILES" } else { "$PROGRAMFILES64" };
+    script += &format!("InstallDir \"{}\\${{NAME}}\"\n", program_files);
+    script += "RequestExecutionLevel admin\n\n";
+    script += "Page directory\nPage instfiles\nUninstPage uninstConfirm\nUninstPage instfiles\n\n";
+
+    script += "Section \"Install\"\n";
+    script += "  SetOutPath \"$INSTDIR\"\n";
+    script += &format!("  File /r \"{}\\*\"\n", PAYLOAD_FOLDER);
+    script += "  WriteUninstaller \"$INSTDIR\\uninstall.exe\"\n";
+    script += "  WriteRegStr HKLM \"${UNINSTALL_KEY}\" \"DisplayName\" \"${NAME}\"\n";
+    script += "  WriteRegStr HKLM \"${UNINSTALL_KEY}\" \"DisplayVersion\" \"${VERSION}\"\n";
+    script += "  WriteRegStr HKLM \"${UNINSTALL_KEY}\" \"Publisher\" \"${PUBLISHER}\"\n";
+    script += "  WriteRegStr HKLM \"${UNINSTALL_KEY}\" \"UninstallString\" \"$\\\"$INSTDIR\\uninstall.exe$\\\"\"\n";
+    if options.add_to_path {
+        script += "  EnVar::SetHKLM\n";
+        script += &format!("  EnVar::AddValue \"PATH\" \"$INSTDIR\\{}\"\n", nsis_string(&windows_path(bin)));
+    }
+    script += "SectionEnd\n\n";
+
+    script += "Section \"Uninstall\"\n";
+    if options.add_to_path {
+        script += "  EnVar::SetHKLM\n";
+        script += &format!("  EnVar::DeleteValue \"PATH\" \"$INSTDIR\\{}\"\n", nsis_string(&windows_path(bin)));
+    }
+    for entry in manifest.files.iter() {
+        script += &format!("  Delete \"$INSTDIR\\{}\"\n", nsis_string(&windows_path(&entry.path)));
+    }
+    // Deepest folders go first, folders with user files are kept
+    let mut folders: Vec<&str> = manifest.files.iter().flat_map(|entry| parents(&entry.path)).collect();
+    folders.sort_by(|a, b| b.matches('/').count().cmp(&a.matches('/').count()).then_with(|| a.cmp(b)));
+    folders.dedup();
+    for folder in folders {
+        script += &format!("  RMDir \"$INSTDIR\\{}\"\n", nsis_string(&windows_path(folder)));
+    }
+    script += "  Delete \"$INSTDIR\\uninstall.exe\"\n";
+    script += "  RMDir \"$INSTDIR\"\n";
+    script += "  DeleteRegKey HKLM \"${UNINSTALL_KEY}\"\n";
+    script += "SectionEnd\n";
+    script
+}
+
+/// Folder of the WiX directory tree
+#[derive(Default)]
+struct Folder<'a> {
+    folders: BTreeMap<&'a str, Folder<'a>>,
+    files: Vec<&'a str>,
+}
+
+/// Render WiX 3 source. Every file is a separate component with generated GUID
+fn wix(manifest: &Manifest, options: &InstallerOptions, target: &Target, bin: &str) -> String {
+    let mut root = Folder::default();
+    for entry in manifest.files.iter() {
+        let mut folder = &mut root;
+        let mut parts: Vec<&str> = entry.path.split('/').collect();
+        parts.pop();
+        for part in parts {
+            folder = folder.folders.entry(part).or_default();
+        }
+        folder.files.push(&entry.path);
+    }
+    let (platform, program_files, win64) = match target.cpu.as_str() {
+        "i686" => ("x86", "ProgramFilesFolder", "no"),
+        "aarch64" => ("arm64", "ProgramFiles64Folder", "yes"),
+        _ => ("x64", "ProgramFiles64Folder", "yes"),
+    };
+
+    let mut source = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    source += &format!("<!-- Generated by {} {} -->\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    source += "<Wix xmlns=\"http://schemas.microsoft.com/wix/2006/wi\">\n";
+    source += &format!(
+        "  <Product Id=\"*\" Name=\"{}\" Language=\"1033\" Version=\"{}\" Manufacturer=\"{}\" UpgradeCode=\"{}\">\n",
+        xml(&options.name), xml(&options.version), xml(&options.publisher), guid(&format!("upgrade:{}", &options.name)),
+    );
+    source += &format!("    <Package InstallerVersion=\"500\" Compressed=\"yes\" InstallScope=\"perMachine\" Platform=\"{}\"/>\n", platform);
+    source += "    <MajorUpgrade DowngradeErrorMessage=\"A newer version of [ProductName] is already installed.\"/>\n";
+    source += "    <MediaTemplate EmbedCab=\"yes\"/>\n";
+    source += "    <Directory Id=\"TARGETDIR\" Name=\"SourceDir\">\n";
+    source += &format!("      <Directory Id=\"{}\">\n", program_files);
+    source += &format!("        <Directory Id=\"INSTALLFOLDER\" Name=\"{}\">\n", xml(&options.name));
+    let mut components = Vec::new();
+    write_folder(&mut source, &root, 5, win64, &mut 0, &mut components);
+    if options.add_to_path {
+        components.push(String::from("PathComponent"));
+        source += &format!("          <Component Id=\"PathComponent\" Guid=\"*\" Win64=\"{}\">\n", win64);
+        source += &format!(
+            "            <Environment Id=\"PathEntry\" Name=\"PATH\" Value=\"[INSTALLFOLDER]{}\" Action=\"set\" Part=\"last\" System=\"yes\" Permanent=\"no\"/>\n",
+            xml(&windows_path(bin)),
+        );
+        source += &format!(
+            "            <RegistryValue Root=\"HKLM\" Key=\"Software\\{}\" Name=\"Path\" Type=\"integer\" Value=\"1\" KeyPath=\"yes\"/>\n",
+            xml(&options.name),
+        );
+        source += "          </Component>\n";
+    }
+    source += "        </Directory>\n      </Directory>\n    </Directory>\n";
+    source += "    <Feature Id=\"Main\" Level=\"1\">\n";
+    for component in components.iter() {
+        source += &format!("      <ComponentRef Id=\"{}\"/>\n", component);
+    }
+    source += "    </Feature>\n  </Product>\n</Wix>\n";
+    source
+}
+
+fn write_folder(source: &mut String, folder: &Folder, depth: usize, win64: &str, folders: &mut usize, components: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    for (name, child) in folder.folders.iter() {
+        *folders += 1;
+        *source += &format!("{}<Directory Id=\"d{}\" Name=\"{}\">\n", &indent, folders, xml(name));
+        write_folder(source, child, depth + 1, win64, folders, components);
+        *source += &format!("{}</Directory>\n", &indent);
+    }
+    for path in folder.files.iter() {
+        let hash = sha256_hex(path.as_bytes());
+        let hash = &hash[..32];
+        *source += &format!("{}<Component Id=\"c{}\" Guid=\"*\" Win64=\"{}\">\n", &indent, hash, win64);
+        *source += &format!(
+            "{}  <File Id=\"f{}\" Source=\"{}\\{}\" KeyPath=\"yes\"/>\n",
+            &indent, hash, PAYLOAD_FOLDER, xml(&windows_path(path)),
+        );
+        *source += &format!("{}</Component>\n", &indent);
+        components.push(format!("c{}", hash));
+    }
+}
+
+/// Parent folders of `path`
+fn parents(path: &str) -> Vec<&str> {
+    path.match_indices('/').map(|(index, _)| &path[..index]).collect()
+}
+
+/// GUID derived from `seed`, so it is stable between runs
+fn guid(seed: &str) -> String {
+    let hash = sha256_hex(seed.as_bytes()).to_uppercase();
+    format!("{}-{}-{}-{}-{}", &hash[..8], &hash[8..12], &hash[12..16], &hash[16..20], &hash[20..32])
+}
+
+fn xml(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn nsis_string(value: &str) -> String {
+    value.replace('$', "$$").replace('"', "$\\\"")
+}
@@ -0,0 +1,173 @@
+//! Journal of moving the staged tree into the output folder.
+//!
+//! Staged files and files to delete are listed in `<output>/.wtb/journal.json` before the output is
+//! touched. Every replaced or deleted file is moved into `<output>/.wtb/journal.backup` first, and the
+//! journal is marked committed when all files are in place. If the process dies before that, the
+//! next run moves new files out and backups back, so the output never mixes files of two runs.
+use crate::paths;
+use crate::progress::Progress;
+use crate::staging::TOOL_FOLDER;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Journal location relative to the output root
+pub const JOURNAL_PATH: &str = ".wtb/journal.json";
+/// Folder with replaced and deleted files, relative to the output root
+const BACKUP_PATH: &str = ".wtb/journal.backup";
+
+#[derive(Serialize, Deserialize)]
+struct Journal {
+    /// All files are in place, only cleanup is left
+    committed: bool,
+    /// Staging directory which files are moved from
+    staging: PathBuf,
+    /// Completion list of the staging directory
+    done: PathBuf,
+    /// Staged files, relative to the staging directory
+    files: Vec<String>,
+    /// Output files to delete
+    removed: Vec<String>,
+}
+
+/// Move files of `staging` into `output` replacing existing ones and delete `removed` output files
+pub fn commit(staging: &Path, done: &Path, output: &Path, removed: &[String]) -> io::Result<()> {
+    let mut files = paths::list_files(staging)?;
+    let tool_folder = staging.join(TOOL_FOLDER);
+    if tool_folder.is_dir() {
+        files.extend(paths::list_files(&tool_folder)?.into_iter().map(|path| format!("{}/{}", TOOL_FOLDER, path)));
+    }
+    let mut journal = Journal {
+        committed: false,
+        staging: staging.to_owned(),
+        done: done.to_owned(),
+        files,
+        removed: removed.to_vec(),
+    };
+    write(output, &journal)?;
+
+    let backup = paths::join(output, BACKUP_PATH);
+    for path in journal.files.iter() {
+        let target = paths::join(output, path);
+        // File in place of a folder of the staged file is replaced as well
+        for (index, _) in path.match_indices('/') {
+            if paths::join(output, &path[..index]).is_file() {
+                move_aside(output, &backup, &path[..index])?;
+            }
+        }
+        move_aside(output, &backup, path)?;
+        std::fs::create_dir_all(target.parent().unwrap())?;
+        std::fs::rename(paths::join(staging, path), &target)?;
+    }
+    for path in journal.removed.iter() {
+        move_aside(output, &backup, path)?;
+    }
+    journal.committed = true;
+    write(output, &journal)?;
+    finish(output, &journal)?;
+    for path in journal.removed.iter() {
+        remove_empty_parents(output, &paths::join(output, path));
+    }
+    Ok(())
+}
+
+/// Complete or roll back commit interrupted by the previous run, if there was one
+pub async fn recover(output: &Path, progress: &Progress) -> io::Result<()> {
+    let data = match tokio::fs::read(paths::join(output, JOURNAL_PATH)).await {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let journal: Journal = serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let output = output.to_owned();
+    if journal.committed {
+        tokio::task::spawn_blocking(move || finish(&output, &journal)).await??;
+        progress.info("Completed cleanup of the interrupted run");
+    } else {
+        tokio::task::spawn_blocking(move || rollback(&output, &journal)).await??;
+        progress.warn("Output was left incomplete by the interrupted run, restored its previous files");
+    }
+    Ok(())
+}
+
+/// Put backups back and remove files moved from the staging directory. Staging directory is
+/// removed too, as it no longer has all extracted files
+fn rollback(output: &Path, journal: &Journal) -> io::Result<()> {
+    let staging = journal.staging.as_path();
+    let backup = paths::join(output, BACKUP_PATH);
+    for path in journal.files.iter() {
+        let target = paths::join(output, path);
+        if !paths::join(staging, path).exists() && !paths::join(&backup, path).is_file() && target.is_file() {
+            std::fs::remove_file(&target)?;
+            remove_empty_parents(output, &target);
+        }
+    }
+    if backup.is_dir() {
+        let mut saved = paths::list_files(&backup)?;
+        let tool_folder = backup.join(TOOL_FOLDER);
+        if tool_folder.is_dir() {
+            saved.extend(paths::list_files(&tool_folder)?.into_iter().map(|path| format!("{}/{}", TOOL_FOLDER, path)));
+        }
+        for path in saved.iter() {
+            let target = paths::join(output, path);
+            match std::fs::symlink_metadata(&target) {
+                Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(&target)?,
+                Ok(_) => std::fs::remove_file(&target)?,
+                Err(_) => {}
+            }
+            std::fs::create_dir_all(target.parent().unwrap())?;
+            std::fs::rename(paths::join(&backup, path), &target)?;
+        }
+    }
+    remove_all(staging)?;
+    remove_all(&journal.done)?;
+    remove_all(&backup)?;
+    std::fs::remove_file(paths::join(output, JOURNAL_PATH))
+}
+
+/// Remove backups, the staging directory and the journal of a committed run
+fn finish(output: &Path, journal: &Journal) -> io::Result<()> {
+    remove_all(&paths::join(output, BACKUP_PATH))?;
+    remove_all(&journal.staging)?;
+    remove_all(&journal.done)?;
+    std::fs::remove_file(paths::join(output, JOURNAL_PATH))
+}
+
+/// Move output file or folder `path` into the backup folder, if it exists
+fn move_aside(output: &Path, backup: &Path, path: &str) -> io::Result<()> {
+    let target = paths::join(output, path);
+    if std::fs::symlink_metadata(&target).is_err() {
+        return Ok(());
+    }
+    let saved = paths::join(backup, path);
+    std::fs::create_dir_all(saved.parent().unwrap())?;
+    std::fs::rename(&target, &saved)
+}
+
+/// Write journal through a temporary file, so it is either old or new after a crash
+fn write(output: &Path, journal: &Journal) -> io::Result<()> {
+    let path = paths::join(output, JOURNAL_PATH);
+    let part = paths::join(output, &format!("{}.part", JOURNAL_PATH));
+    let mut file = std::fs::File::create(&part)?;
+    file.write_all(&serde_json::to_vec(journal).unwrap())?;
+    file.sync_all()?;
+    std::fs::rename(&part, &path)
+}
+
+fn remove_all(path: &Path) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(_) => Ok(()),
+    }
+}
+
+fn remove_empty_parents(output: &Path, path: &Path) {
+    let mut folder = path.parent();
+    while let Some(dir) = folder {
+        if dir == output || std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+        folder = dir.parent();
+    }
+}
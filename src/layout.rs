@@ -0,0 +1,71 @@
+//! Output folder layouts
+use crate::sanitize;
+use archlinux_repo::Package;
+use std::str::FromStr;
+
+/// How package files are placed in the output
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Layout {
+    /// All packages share one tree
+    Merged,
+    /// Each package is written into its own `<name>-<version>` folder. Characters invalid on Windows,
+    /// like `:` of epoch versions, are replaced with `_`
+    PerPackage,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "merged" => Ok(Layout::Merged),
+            "per-package" => Ok(Layout::PerPackage),
+            _ => Err(format!("Unknown layout: \"{}\"", s)),
+        }
+    }
+}
+
+impl Layout {
+    /// Output path of package file `path`
+    pub fn target(&self, package: &Package, path: String) -> String {
+        match self {
+            Layout::Merged => path,
+            Layout::PerPackage => {
+                let folder = format!("{}-{}", &package.name, &package.version).replace('/', "_");
+                let folder = sanitize::sanitize(&folder).unwrap_or(folder);
+                format!("{}/{}", folder, path)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::PackageInfo;
+
+    fn package(version: &str) -> Package {
+        PackageInfo {
+            name: String::from("mingw64-gcc"),
+            version: version.to_owned(),
+            file_name: String::from("mingw64-gcc.rpm"),
+            architecture: String::from("noarch"),
+            ..PackageInfo::default()
+        }.to_package().unwrap()
+    }
+
+    #[test]
+    fn merged_keeps_path() {
+        assert_eq!(Layout::Merged.target(&package("10.2-1"), String::from("bin/gcc.exe")), "bin/gcc.exe");
+    }
+
+    #[test]
+    fn per_package_prefixes_path() {
+        assert_eq!(Layout::PerPackage.target(&package("10.2-1"), String::from("bin/gcc.exe")), "mingw64-gcc-10.2-1/bin/gcc.exe");
+    }
+
+    #[test]
+    fn per_package_folder_is_valid_on_windows() {
+        assert_eq!(Layout::PerPackage.target(&package("1:10.2-1"), String::from("bin/gcc.exe")), "mingw64-gcc-1_10.2-1/bin/gcc.exe");
+    }
+}
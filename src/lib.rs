@@ -0,0 +1,1587 @@
+//! Assembles a Windows toolchain from packages of an MSYS2 (pacman) repository: resolves the package
+//! with its dependencies, downloads and extracts them into an output folder or archive and post-processes
+//! the files.
+//!
+//! `ToolchainBuilder` sets up and runs an installation in code. `build` runs a command described by
+//! `config::Config`, as the command line tool does. `Program` gives finer control over a single
+//! installation, and `progress::Progress::with_reporter` receives its progress.
+pub mod archive;
+pub mod builder;
+pub mod bundle;
+pub mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod classify;
+pub mod clean;
+pub mod collision;
+pub mod config;
+mod dashboard;
+pub mod diff;
+pub mod download;
+pub mod error;
+pub mod event;
+mod extract;
+mod filter;
+mod flock;
+pub mod generate;
+mod implib;
+pub mod hooks;
+pub mod imports;
+pub mod installer;
+mod journal;
+pub mod layout;
+pub mod license;
+pub mod logging;
+mod lock;
+pub mod manifest;
+mod metrics;
+pub mod mirror;
+mod notices;
+mod notify;
+pub mod outdated;
+pub mod package;
+pub mod owners;
+pub mod paths;
+mod pe;
+pub mod pick;
+pub mod plugin;
+pub mod progress;
+mod prune;
+pub mod relocate;
+pub mod remove;
+pub mod report;
+pub mod sanitize;
+mod schema;
+pub mod script;
+mod sink;
+pub mod snapshot;
+pub mod source;
+mod space;
+mod staging;
+pub mod state;
+mod stats;
+mod strip;
+pub mod text;
+pub mod tool;
+pub mod urls;
+pub mod verify;
+pub mod wasm;
+
+use archlinux_repo::Package;
+use std::sync::{Arc, Mutex};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use crate::progress::{Progress, PackageDownloadProgress, PackageExtractProgress, ProgressMode};
+use std::path::{Path, PathBuf};
+use futures::StreamExt;
+use tracing::Instrument;
+use crate::config::{Command, Config};
+use crate::cache::CacheAction;
+use crate::flock::{Attempt, FolderLock};
+use crate::bundle::{Bundle, StateAction};
+use compress_tools::list_archive_files;
+use crate::staging::{Staging, TOOL_FOLDER};
+use crate::sink::Sink;
+use crate::archive::{ArchiveSink, ArchiveFormat};
+use crate::extract::{DecodedFile, Entry};
+use crate::sanitize::NamePolicy;
+use crate::collision::{CaseIndex, CollisionPolicy};
+use crate::classify::FileKind;
+use crate::space::{Requirement, SizeReport};
+use crate::download::Downloader;
+use crate::source::PackageSource;
+use crate::source::pacman::PacmanSource;
+use crate::manifest::{Manifest, ManifestEntry, MANIFEST_PATH};
+use crate::owners::OWNERS_PATH;
+use crate::state::{State, STATE_PATH};
+use crate::lock::{LockedPackage, Lockfile};
+use crate::manifest::sha256_hex;
+use crate::notices::{Notice, NOTICES_FILE};
+use crate::license::LicenseAction;
+use crate::relocate::Relocation;
+use crate::imports::ImportCheck;
+use crate::hooks::{Hook, HookAction, HookFailure, Stage};
+use crate::tool::CommandTemplate;
+use crate::generate::{Toolchain, Target, env, cargo, cmake, meson};
+use crate::stats::{PackageStats, Timings};
+use crate::notify::Notification;
+use crate::metrics::RunMetrics;
+use crate::pick::PackageChoice;
+use crate::script::ScriptDecision;
+use crate::plugin::Plugin;
+use crate::report::{ErrorReport, REPORT_FILE};
+use crate::error::{ConfigError, DownloadError, Error, ExtractionError, Phase, ResolutionError, VerificationError};
+use std::time::{Duration, Instant, SystemTime};
+use futures::channel::mpsc::Receiver;
+
+pub use crate::builder::ToolchainBuilder;
+
+/// Write `error-report.json` into the current folder
+async fn write_error_report(config: &Config, error: &Error, progress: &Progress) {
+    let report = ErrorReport::new(config, error);
+    if let Err(e) = tokio::fs::write(REPORT_FILE, report.to_json()).await {
+        progress.warn(&format!("Failed to write {}: {}", REPORT_FILE, e));
+    }
+}
+
+/// Installation of packages into the output of a config
+pub struct Program {
+    config: Config,
+    /// Shared with the command which runs the program, so the whole run has one progress
+    progress: Arc<Progress>,
+    source: Box<dyn PackageSource>,
+    sink: Sink,
+    downloader: Downloader,
+    case_index: CaseIndex,
+    manifest: Mutex<Manifest>,
+    notices: Mutex<Vec<Notice>>,
+    stats: Mutex<Vec<PackageStats>>,
+    /// Paths of PE images passed to `--sign-cmd` and whether signing succeeded
+    signatures: Mutex<Vec<(String, bool)>>,
+    /// All packages of the run, including ones completed by the interrupted run
+    tree: Mutex<Vec<Package>>,
+    /// Files of the previous installation which are removed unless written again by `update`
+    stale: Mutex<Vec<String>>,
+    /// Packages requested explicitly, recorded in the state database
+    explicit: Mutex<Vec<String>>,
+    /// State database of the output which this run changes
+    base: Mutex<Option<State>>,
+    /// SHA-256 of package archives processed by this run by archive name
+    archive_hashes: Mutex<HashMap<String, String>>,
+    /// Lockfile of `--locked` which pins the installed archives
+    locked: Option<Lockfile>,
+    plugin: Option<Plugin>,
+    started: Instant,
+    /// Duration of the run phases for `--timings`
+    timings: Mutex<Timings>,
+}
+
+impl Program {
+    /// Open the output and load the repository of `config`
+    pub async fn new(config: Config) -> Result<Self, Error> {
+        let progress = Progress::new(config.progress_mode, &config.theme);
+        Program::with_progress(config, progress).await
+    }
+
+    /// Like `new`, but progress is reported to `progress`
+    pub async fn with_progress(config: Config, progress: Progress) -> Result<Self, Error> {
+        Program::open(config, Arc::new(progress), None).await
+    }
+
+    /// Like `with_progress`, but packages are installed from `source` instead of the repository of `config`
+    pub async fn with_source(config: Config, progress: Progress, source: Box<dyn PackageSource>) -> Result<Self, Error> {
+        Program::open(config, Arc::new(progress), Some(source)).await
+    }
+
+    async fn open(config: Config, progress: Arc<Progress>, source: Option<Box<dyn PackageSource>>) -> Result<Self, Error> {
+        let started = Instant::now();
+
+        if !config.prune_to.is_empty() && (config.output_to_stdout() || config.archive_output.is_some()) {
+            return Err(ConfigError::PruneArchive.into());
+        }
+        if config.hooks.iter().any(Hook::needs_files) && (config.output_to_stdout() || config.archive_output.is_some()) {
+            return Err(ConfigError::HooksArchive.into());
+        }
+        if config.progress_mode == ProgressMode::Json && config.output_to_stdout() {
+            return Err(ConfigError::JsonStdout.into());
+        }
+        let sink = if config.output_to_stdout() {
+            Sink::Archive(ArchiveSink::stdout(config.archive_options())?)
+        } else if let Some(path) = config.archive_output.as_ref() {
+            let format = ArchiveFormat::from_path(path).ok_or_else(|| ConfigError::UnsupportedArchive(path.clone()))?;
+            Sink::Archive(ArchiveSink::new(path, format, config.archive_options()).await?)
+        } else {
+            tokio::fs::create_dir_all(&config.output_folder).await?;
+            Sink::Directory(Staging::new(&config.output_folder, config.resume).await?)
+        };
+
+        if let Some(dir) = config.keep_archives.as_ref() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::create_dir_all(&config.cache_dir).await?;
+        let downloader = Downloader::new(&config.repository_url(), &config.cache_dir, config.resume);
+
+        let loading = Instant::now();
+        let source = match source {
+            Some(source) => source,
+            None => crate::source::open(&config, &progress).await.map_err(|e| Error::in_phase(Phase::Repository, e))?,
+        };
+        let timings = Timings {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            repository_load_seconds: loading.elapsed().as_secs_f64(),
+            ..Timings::default()
+        };
+        let locked = match config.locked.as_ref() {
+            Some(path) => Some(Lockfile::load(path).await?),
+            None => None,
+        };
+        let plugin = match config.plugin.as_ref() {
+            Some(command) => Some(Plugin::start(command, config.plugin_timeout, &progress).await?),
+            None => None,
+        };
+
+        Ok(Program {
+            config,
+            progress,
+            source,
+            sink,
+            downloader,
+            case_index: CaseIndex::new(),
+            manifest: Mutex::new(Manifest::default()),
+            notices: Mutex::new(Vec::new()),
+            stats: Mutex::new(Vec::new()),
+            signatures: Mutex::new(Vec::new()),
+            tree: Mutex::new(Vec::new()),
+            stale: Mutex::new(Vec::new()),
+            explicit: Mutex::new(Vec::new()),
+            base: Mutex::new(None),
+            archive_hashes: Mutex::new(HashMap::new()),
+            locked,
+            plugin,
+            started,
+            timings: Mutex::new(timings),
+        })
+    }
+
+    /// Install `package` with its dependencies, or packages pinned by `--locked`
+    pub async fn run(self, package: &str) -> Result<(), Error> {
+        let tree = match self.locked.as_ref() {
+            Some(lockfile) => {
+                *self.explicit.lock().unwrap() = lockfile.explicit.clone();
+                let resolving = Instant::now();
+                let tree = self.locked_tree(lockfile)
+                    .map_err(|e| Error::in_phase(Phase::Resolve, e))?;
+                self.timings.lock().unwrap().resolve_seconds += resolving.elapsed().as_secs_f64();
+                tree
+            }
+            None => {
+                let package = find_package(self.source.as_ref(), &self.config, package, &self.progress)
+                    .map_err(|e| Error::in_phase(Phase::Resolve, e))?;
+                *self.explicit.lock().unwrap() = vec![package.name.clone()];
+                self.build_package_tree(package)
+                    .map_err(|e| Error::in_phase(Phase::Resolve, e))?
+            }
+        };
+        let tree = self.plugin_tree(tree).await?;
+        *self.tree.lock().unwrap() = tree.clone();
+        if !self.config.force && self.is_up_to_date(&tree).await {
+            self.progress.info("Output is up to date");
+            if let (Some(path), Some(lockfile)) = (self.config.write_lockfile.as_ref(), self.lockfile().await?) {
+                tokio::fs::write(path, lockfile.to_json()).await?;
+            }
+            // Nothing is staged, commit only removes the staging directory
+            return Ok(self.sink.commit(&[]).await?);
+        }
+        let tree: Vec<Package> = tree.into_iter()
+            .filter(|package| !self.sink.is_done(&package.file_name))
+            .collect();
+        self.manifest.lock().unwrap().files.extend(self.sink.done_files());
+        self.install(tree).await
+    }
+
+    /// `tree` without packages WebAssembly plugins and `--plugin` drop
+    async fn plugin_tree(&self, tree: Vec<Package>) -> Result<Vec<Package>, Error> {
+        let mut dropped = Vec::new();
+        for wasm in self.config.wasm_plugins.iter() {
+            for package in tree.iter() {
+                let keep = wasm.keep_package(&package.name, &package.version).await
+                    .map_err(|message| Error::Plugin { program: wasm.path().display().to_string(), message })
+                    .map_err(|e| Error::in_phase(Phase::Resolve, e))?;
+                if !keep {
+                    dropped.push(package.name.clone());
+                }
+            }
+        }
+        if let Some(plugin) = self.plugin.as_ref() {
+            let kept: Vec<Package> = tree.iter().filter(|package| !dropped.contains(&package.name)).cloned().collect();
+            dropped.extend(plugin.resolved(&kept, &self.progress).await
+                .map_err(|e| Error::in_phase(Phase::Resolve, e))?);
+        }
+        if dropped.is_empty() {
+            return Ok(tree);
+        }
+        self.progress.info(&format!("Plugin dropped packages: {}", dropped.join(", ")));
+        Ok(tree.into_iter().filter(|package| !dropped.contains(&package.name)).collect())
+    }
+
+    /// Check if the output was built from exactly the `tree` packages with the same settings and still
+    /// has all its files. File contents aren't hashed, `verify` does that
+    async fn is_up_to_date(&self, tree: &[Package]) -> bool {
+        if self.config.output_to_stdout() || self.config.archive_output.is_some() || self.config.resume {
+            return false;
+        }
+        let output = self.sink.output();
+        let state = match State::load(output).await {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+        let explicit = self.explicit.lock().unwrap().clone();
+        if state.config_hash != self.config.fingerprint() || state.explicit() != explicit || state.packages.len() != tree.len() {
+            return false;
+        }
+        let installed = tree.iter().all(|package| state.packages.iter()
+            .any(|installed| installed.name == package.name && installed.version == package.version));
+        if !installed {
+            return false;
+        }
+        let manifest = match Manifest::load(output).await {
+            Ok(manifest) => manifest,
+            Err(_) => return false,
+        };
+        for entry in manifest.files.iter() {
+            match tokio::fs::metadata(paths::join(output, &entry.path)).await {
+                Ok(metadata) if metadata.len() == entry.size => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Rebuild output described by `state` of a bundle with packages pinned by `--locked`
+    pub async fn restore(self, mut state: State) -> Result<(), Error> {
+        let package = state.package.clone();
+        state.packages.clear();
+        *self.base.lock().unwrap() = Some(state);
+        self.run(&package).await
+    }
+
+    /// Install packages of the output described by `state`, which are new or have changed versions.
+    /// Files of unchanged packages are kept, files which changed packages no longer have are removed
+    pub async fn update(self, mut state: State, previous: Manifest) -> Result<(), Error> {
+        let explicit = state.explicit();
+        let tree = self.resolve(&explicit).map_err(|e| Error::in_phase(Phase::Resolve, e))?;
+        let tree = self.plugin_tree(tree).await?;
+        *self.tree.lock().unwrap() = tree.clone();
+        *self.explicit.lock().unwrap() = explicit;
+        let installed: HashMap<&str, &str> = state.packages.iter()
+            .map(|package| (package.name.as_str(), package.version.as_str()))
+            .collect();
+        let removed = state.packages.iter()
+            .filter(|installed| !tree.iter().any(|package| package.name == installed.name))
+            .count();
+        let (unchanged, changed): (Vec<Package>, Vec<Package>) = tree.into_iter()
+            .partition(|package| installed.get(package.name.as_str()) == Some(&package.version.as_str()));
+        let kept: HashSet<&str> = unchanged.iter().map(|package| package.name.as_str()).collect();
+        self.progress.info(&format!("{} packages to update, {} unchanged, {} removed", changed.len(), unchanged.len(), removed));
+        let (previous, stale): (Vec<ManifestEntry>, Vec<ManifestEntry>) = previous.files.into_iter()
+            .partition(|entry| kept.contains(entry.package.as_str()));
+        for entry in previous.iter() {
+            self.case_index.claim(&entry.path, &entry.package).ok();
+        }
+        self.manifest.lock().unwrap().files.extend(previous);
+        let stale: Vec<String> = stale.into_iter().map(|entry| entry.path).collect();
+        let snapshot = snapshot::create(self.sink.output(), &stale).await?;
+        self.progress.info(&format!("Saved snapshot {}, restore it with rollback", snapshot));
+        *self.stale.lock().unwrap() = stale;
+        // Every installed package is resolved again, so packages nothing requires any more are dropped
+        state.packages.clear();
+        *self.base.lock().unwrap() = Some(state);
+        self.install(changed).await
+    }
+
+    /// Install `packages` with their dependencies which are not installed yet into the output
+    /// described by `state`
+    pub async fn add(self, state: State, previous: Manifest, packages: &[String]) -> Result<(), Error> {
+        let tree: Vec<Package> = self.resolve(packages).map_err(|e| Error::in_phase(Phase::Resolve, e))?.into_iter()
+            .filter(|package| !state.packages.iter().any(|installed| installed.name == package.name))
+            .collect();
+        *self.tree.lock().unwrap() = tree.clone();
+        let mut explicit = state.explicit();
+        explicit.extend(packages.iter().filter(|package| !explicit.contains(package)).cloned().collect::<Vec<String>>());
+        *self.explicit.lock().unwrap() = explicit;
+        self.progress.info(&format!("{} packages to install", tree.len()));
+        for entry in previous.files.iter() {
+            self.case_index.claim(&entry.path, &entry.package).ok();
+        }
+        self.manifest.lock().unwrap().files.extend(previous.files);
+        *self.base.lock().unwrap() = Some(state);
+        self.install(tree).await
+    }
+
+    /// Packages `names` with all their dependencies
+    fn resolve(&self, names: &[String]) -> Result<Vec<Package>, ResolutionError> {
+        let mut tree: Vec<Package> = Vec::new();
+        for name in names.iter() {
+            let package = self.source.package(name)
+                .ok_or_else(|| ResolutionError::PackageNotFound(name.clone()))?
+                .to_owned();
+            for package in self.build_package_tree(package)? {
+                if !tree.contains(&package) {
+                    tree.push(package);
+                }
+            }
+        }
+        Ok(tree)
+    }
+
+    /// Extract `tree` packages and complete the output
+    async fn install(self, tree: Vec<Package>) -> Result<(), Error> {
+        self.check_disk_space(&tree)?;
+        // Packages extracted by an interrupted run are counted in the progress of the whole build
+        let done: Vec<Package> = self.tree.lock().unwrap().iter()
+            .filter(|package| self.sink.is_done(&package.file_name) && !tree.iter().any(|pending| pending.file_name == package.file_name))
+            .cloned()
+            .collect();
+        self.progress.start_packages(&tree, &done);
+        let mut download_stream = futures::stream::iter(tree.iter().map(|package| self.process_package(package)
+                .instrument(tracing::info_span!("package", name = %package.name, version = %package.version))))
+            .buffer_unordered(self.config.parallelism.max(1) as usize);
+        let cancel = tokio::signal::ctrl_c();
+        tokio::pin!(cancel);
+        let cancel_requested = self.progress.cancelled();
+        tokio::pin!(cancel_requested);
+        let result: Result<(), Error> = loop {
+            tokio::select! {
+                result = download_stream.next() => match result {
+                    Some(Ok(())) => {}
+                    Some(Err(e)) => break Err(e),
+                    None => break Ok(()),
+                },
+                _ = &mut cancel => break Err(Error::Cancelled),
+                _ = &mut cancel_requested => break Err(Error::Cancelled),
+            }
+        };
+        drop(download_stream);
+        let processing = Instant::now();
+        let result = match result {
+            Ok(()) => self.complete().await.map_err(|e| Error::in_phase(Phase::PostProcessing, e)),
+            Err(e) => Err(e),
+        };
+        self.timings.lock().unwrap().post_processing_seconds = processing.elapsed().as_secs_f64();
+        // Sink is consumed by commit, so everything which needs the whole program is prepared before it
+        let lockfile = match result {
+            Ok(()) => self.lockfile().await,
+            Err(e) => Err(e),
+        };
+        let lockfile = match lockfile {
+            Ok(lockfile) => lockfile,
+            Err(e) => {
+                self.sink.abort().await?;
+                return Err(e);
+            }
+        };
+        let stale = self.stale_files();
+        self.write_stats().await?;
+        let output = self.sink.staged_root().map(|_| self.sink.output().to_owned());
+        let built = output.clone()
+            .or_else(|| self.config.archive_output.clone())
+            .unwrap_or_else(|| self.config.output_folder.clone());
+        let root = self.explicit.lock().unwrap().first().cloned().unwrap_or_default();
+        let root_version = self.tree.lock().unwrap().iter()
+            .find(|package| package.name == root)
+            .map(|package| package.version.clone())
+            .unwrap_or_default();
+        let committing = Instant::now();
+        self.sink.commit(&stale).await.map_err(|e| Error::in_phase(Phase::Commit, e))?;
+        self.timings.lock().unwrap().commit_seconds = committing.elapsed().as_secs_f64();
+        if let Some(path) = self.config.timings.as_ref() {
+            let mut timings = self.timings.lock().unwrap().clone();
+            timings.set_packages(&self.stats.lock().unwrap());
+            timings.total_seconds = self.started.elapsed().as_secs_f64();
+            tokio::fs::write(path, timings.to_json()).await?;
+        }
+        if let (Some(path), Some(lockfile)) = (self.config.write_lockfile.as_ref(), lockfile) {
+            tokio::fs::write(path, lockfile.to_json()).await?;
+        }
+        let lockfile = self.config.write_lockfile.as_ref().or_else(|| self.config.locked.as_ref());
+        cache::register(&self.config.cache_dir, output.as_deref(), lockfile.map(PathBuf::as_path)).await?;
+        if let Some(path) = self.config.checksums.as_ref() {
+            let checksums = self.manifest.lock().unwrap().to_sha256sums();
+            tokio::fs::write(path, checksums).await?;
+        }
+        if let Some(count) = self.config.report_size {
+            let report = SizeReport::new(&self.manifest.lock().unwrap(), count);
+            self.progress.info(&report.render());
+        }
+        run_stage_hook(&self.config, &self.progress, Stage::PostBuild, &root, &root_version, &built).await?;
+        let manifest = self.manifest.lock().unwrap();
+        let bytes = manifest.files.iter().map(|entry| entry.size).sum();
+        self.progress.summary(self.tree.lock().unwrap().len(), manifest.files.len(), bytes, &manifest.digest());
+        Ok(())
+    }
+
+    /// Lockfile with every installed package archive, if `--write-lockfile` is set
+    async fn lockfile(&self) -> Result<Option<Lockfile>, Error> {
+        if self.config.write_lockfile.is_none() {
+            return Ok(None);
+        }
+        let mut installed: Vec<(String, String, String)> = self.tree.lock().unwrap().iter()
+            .map(|package| (package.name.clone(), package.version.clone(), package.file_name.clone()))
+            .collect();
+        if let Some(base) = self.base.lock().unwrap().as_ref() {
+            for package in base.packages.iter() {
+                if !installed.iter().any(|(name, _, _)| name == &package.name) {
+                    installed.push((package.name.clone(), package.version.clone(), package.file_name.clone()));
+                }
+            }
+        }
+        let mut packages = Vec::with_capacity(installed.len());
+        for (name, version, file_name) in installed {
+            let url = self.source.archive_url(&file_name);
+            let url = urls::resolve(&self.config, &name, &file_name, &url).unwrap_or(url);
+            let known = self.archive_hashes.lock().unwrap().get(&file_name).cloned();
+            let sha256 = match known {
+                Some(sha256) => sha256,
+                None => self.downloader.archive_sha256(&file_name, &url).await?,
+            };
+            packages.push(LockedPackage { name, version, url, file_name, sha256 });
+        }
+        let explicit = self.explicit.lock().unwrap().clone();
+        Ok(Some(Lockfile::new(&self.config.repository_url(), &self.config.repository_name, &explicit, packages)))
+    }
+
+    /// Files of the previous installation which no package of this run has written
+    fn stale_files(&self) -> Vec<String> {
+        let manifest = self.manifest.lock().unwrap();
+        let current: HashSet<&str> = manifest.files.iter().map(|entry| entry.path.as_str()).collect();
+        self.stale.lock().unwrap().iter()
+            .filter(|path| !current.contains(path.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    fn toolchain(&self) -> Toolchain {
+        Toolchain::detect(&self.manifest.lock().unwrap(), &self.config.repository_name)
+    }
+
+    fn target(&self) -> Target {
+        Target::new(&self.config.repository_name, &self.config.architecture)
+    }
+
+    /// Absolute location of the output after installation, if known
+    fn install_root(&self) -> Option<String> {
+        match self.config.relocation.as_ref() {
+            Some(Relocation::Absolute(root)) => Some(root.clone()),
+            _ if self.sink.output().as_os_str().is_empty() => None,
+            _ => Some(paths::display(self.sink.output())),
+        }
+    }
+
+    /// Post-process the output after all packages are extracted
+    async fn complete(&self) -> Result<(), Error> {
+        self.prune().await?;
+        self.check_imports()?;
+        self.check_size()?;
+        self.check_signatures()?;
+        self.finish().await
+    }
+
+    /// Remove files which aren't required by `--prune-to` executables
+    async fn prune(&self) -> Result<(), Error> {
+        if self.config.prune_to.is_empty() {
+            return Ok(());
+        }
+        let removed: HashSet<String> = {
+            let manifest = self.manifest.lock().unwrap();
+            let kept = prune::closure(&manifest, &self.config.prune_to, &self.config.prune_keep)
+                .map_err(VerificationError::BinaryNotFound)?;
+            manifest.files.iter()
+                .filter(|entry| !kept.contains(entry.path.as_str()))
+                .map(|entry| entry.path.clone())
+                .collect()
+        };
+        for path in removed.iter() {
+            self.sink.remove(path).await?;
+        }
+        self.manifest.lock().unwrap().files.retain(|entry| !removed.contains(&entry.path));
+        Ok(())
+    }
+
+    fn check_imports(&self) -> Result<(), VerificationError> {
+        let action = match self.config.check_imports {
+            Some(action) => action,
+            None => return Ok(()),
+        };
+        let missing = imports::missing(&self.manifest.lock().unwrap(), &self.config.allow_imports);
+        for import in missing.iter() {
+            self.progress.warn(&format!("{} imports {}, which is not in the output", &import.path, &import.dll));
+        }
+        if !missing.is_empty() && action == ImportCheck::Fail {
+            return Err(VerificationError::MissingImports(missing.len()));
+        }
+        Ok(())
+    }
+
+    /// Fail if the output exceeds `--max-output-size`, listing what takes the most space
+    fn check_size(&self) -> Result<(), VerificationError> {
+        let limit = match self.config.max_output_size {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let report = SizeReport::new(&self.manifest.lock().unwrap(), 10);
+        if report.total <= limit {
+            return Ok(());
+        }
+        self.progress.warn(&report.render());
+        Err(VerificationError::OutputTooLarge { size: report.total, limit })
+    }
+
+    /// Report `--sign-cmd` results and fail if any file is left unsigned
+    fn check_signatures(&self) -> Result<(), Error> {
+        if self.config.sign_command.is_none() {
+            return Ok(());
+        }
+        let signatures = self.signatures.lock().unwrap();
+        let failed: Vec<&str> = signatures.iter()
+            .filter(|(_, signed)| !signed)
+            .map(|(path, _)| path.as_str())
+            .collect();
+        self.progress.info(&format!("Signed {} of {} files", signatures.len() - failed.len(), signatures.len()));
+        if !failed.is_empty() {
+            return Err(Error::SigningFailed(failed.iter().map(|path| path.to_string()).collect()));
+        }
+        Ok(())
+    }
+
+    /// Print `--stats` table or write it as JSON
+    async fn write_stats(&self) -> Result<(), Error> {
+        if !self.config.stats {
+            return Ok(());
+        }
+        let stats = self.stats.lock().unwrap().clone();
+        match self.config.stats_file.as_ref() {
+            Some(path) => tokio::fs::write(path, stats::to_json(&stats)).await?,
+            None => self.progress.info(&stats::render(&stats)),
+        }
+        Ok(())
+    }
+
+    /// Write files generated from the whole run
+    async fn finish(&self) -> Result<(), Error> {
+        if self.config.notices_concat {
+            let text = notices::concatenate(&mut self.notices.lock().unwrap());
+            self.write_generated(NOTICES_FILE, text).await?;
+        }
+        if self.config.env_scripts {
+            let toolchain = self.toolchain();
+            self.write_generated(env::ENV_BAT, env::bat(&toolchain).into_bytes()).await?;
+            self.write_generated(env::ENV_PS1, env::ps1(&toolchain).into_bytes()).await?;
+            self.write_generated(env::ENV_SH, env::sh(&toolchain).into_bytes()).await?;
+        }
+        if let Some(path) = self.config.cmake_toolchain.as_ref() {
+            let file = cmake::toolchain_file(&self.toolchain(), &self.target(), self.config.toolchain_mode, path);
+            self.write_generated(path, file.into_bytes()).await?;
+        }
+        if let Some(path) = self.config.meson_file.as_ref() {
+            let file = meson::machine_file(&self.toolchain(), &self.target(), self.config.toolchain_mode, path);
+            self.write_generated(path, file.into_bytes()).await?;
+        }
+        if let Some(path) = self.config.cargo_config.as_ref() {
+            let root = self.install_root();
+            let file = cargo::config_file(&self.toolchain(), &self.target(), self.config.toolchain_mode, path, root.as_deref());
+            self.write_generated(path, file.into_bytes()).await?;
+        }
+        self.run_hooks().await?;
+        if let (Some(plugin), Some(root)) = (self.plugin.as_ref(), self.sink.staged_root()) {
+            // File systems with coarse timestamps may date files written right now in the past
+            let started = SystemTime::now() - Duration::from_secs(2);
+            if plugin.finished(root, &self.progress).await? {
+                self.record_changed_files(root.to_owned(), started).await?;
+            }
+        }
+        let (manifest, owners, state) = {
+            let manifest = self.manifest.lock().unwrap();
+            let state = State::new(&self.config, &self.explicit.lock().unwrap(), &self.tree.lock().unwrap(),
+                                   self.base.lock().unwrap().as_ref(), &manifest);
+            (manifest.to_json(), owners::encode(&manifest), state.to_json())
+        };
+        self.sink.write(OWNERS_PATH, owners).await?;
+        self.sink.write(STATE_PATH, state).await?;
+        self.sink.write(MANIFEST_PATH, manifest).await?;
+        Ok(())
+    }
+
+    /// Run `--hook` commands and `--fixup` built-ins for every extracted package they match
+    async fn run_hooks(&self) -> Result<(), Error> {
+        let packages: BTreeSet<(String, String)> = self.manifest.lock().unwrap().files.iter()
+            .filter(|entry| entry.package != env!("CARGO_PKG_NAME"))
+            .map(|entry| (entry.package.clone(), entry.version.clone()))
+            .collect();
+        for hook in self.config.hooks.iter() {
+            for (package, version) in packages.iter().filter(|(package, _)| hook.matches(package)) {
+                match &hook.action {
+                    HookAction::QtConf => {
+                        let files = hooks::qt_conf(&self.manifest.lock().unwrap(), package);
+                        for (path, data) in files {
+                            self.write_generated(&path, data).await?;
+                        }
+                    }
+                    HookAction::PixbufLoaders => {
+                        let root = self.sink.staged_root().ok_or(ConfigError::HooksArchive)?;
+                        let command = hooks::pixbuf_loaders(&self.manifest.lock().unwrap(), package, root);
+                        if let Some(command) = command {
+                            self.run_hook_command(command, package, version).await?;
+                        }
+                    }
+                    HookAction::Command(command) => self.run_hook_command(command.clone(), package, version).await?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run hook command in the staged output and record files it created or changed
+    async fn run_hook_command(&self, command: CommandTemplate, package: &str, version: &str) -> Result<(), Error> {
+        let root = self.sink.staged_root().ok_or(ConfigError::HooksArchive)?.to_owned();
+        // File systems with coarse timestamps may date files written right now in the past
+        let started = SystemTime::now() - Duration::from_secs(2);
+        let (status, output) = {
+            let (root, package, version) = (root.clone(), package.to_owned(), version.to_owned());
+            tokio::task::spawn_blocking(move || hooks::run(&command, &root, &package, &version)).await??
+        };
+        for line in output.lines() {
+            self.progress.info(&format!("[{} hook] {}", package, line));
+        }
+        if !status.success() {
+            return Err(Error::HookFailed { package: package.to_owned(), status: status.to_string() });
+        }
+        self.record_changed_files(root, started).await
+    }
+
+    /// Add files of staged output `root` modified after `started` to the manifest, or update their entries
+    async fn record_changed_files(&self, root: PathBuf, started: SystemTime) -> Result<(), Error> {
+        let changed = {
+            let root = root.clone();
+            tokio::task::spawn_blocking(move || hooks::changed_files(&root, started)).await??
+        };
+        for path in changed {
+            let data = tokio::fs::read(paths::join(&root, &path)).await?;
+            let mut manifest = self.manifest.lock().unwrap();
+            match manifest.files.iter_mut().find(|entry| entry.path == path) {
+                Some(entry) => {
+                    let updated = ManifestEntry::generated(&path, &data);
+                    entry.size = updated.size;
+                    entry.sha256 = updated.sha256;
+                }
+                None => manifest.files.push(ManifestEntry::generated(&path, &data)),
+            }
+        }
+        Ok(())
+    }
+
+    fn check_disk_space(&self, tree: &[Package]) -> Result<(), Error> {
+        let installed: u64 = tree.iter().map(|package| package.installed_size).sum();
+        let compressed: u64 = tree.iter().map(|package| package.compressed_size).sum();
+        let mut requirements = Vec::new();
+        if let Some(path) = self.config.archive_output.as_ref() {
+            requirements.push(Requirement { path: path.clone(), required: compressed });
+        } else if !self.config.output_to_stdout() {
+            requirements.push(Requirement { path: self.config.output_folder.clone(), required: installed });
+        }
+        let missing: u64 = tree.iter()
+            .filter(|package| !self.downloader.cached_path(package).exists())
+            .map(|package| package.compressed_size)
+            .sum();
+        requirements.push(Requirement { path: self.config.cache_dir.clone(), required: missing });
+        if let Some(dir) = self.config.keep_archives.as_ref() {
+            requirements.push(Requirement { path: dir.clone(), required: compressed });
+        }
+        if self.config.strip_tool.is_some() || self.config.compress_command.is_some() || self.config.sign_command.is_some() {
+            let largest = tree.iter().map(|package| package.installed_size).max().unwrap_or(0);
+            requirements.push(Requirement { path: std::env::temp_dir(), required: largest });
+        }
+        match space::check(&requirements)? {
+            None => Ok(()),
+            Some(shortage) => Err(ExtractionError::InsufficientSpace {
+                path: shortage.path.display().to_string(),
+                required: shortage.required,
+                available: shortage.available,
+            }.into()),
+        }
+    }
+
+    async fn process_package(&self, package: &Package) -> Result<(), Error> {
+        let cached = tokio::fs::metadata(self.downloader.cached_path(package)).await.is_ok();
+        let started = Instant::now();
+        let archive = self.download_package(&package).await
+            .map_err(|e| Error::in_package(Phase::Download, &package.name, e))?;
+        let download_seconds = started.elapsed().as_secs_f64();
+        let download_bytes = archive.len() as u64;
+        self.archive_hashes.lock().unwrap().insert(package.file_name.clone(), sha256_hex(&archive));
+        if let Some(dir) = self.config.keep_archives.as_ref() {
+            tokio::fs::write(dir.join(&package.file_name), &archive).await?;
+        }
+        let archive = self.source.unpack(package, archive).await
+            .map_err(|e| Error::in_package(Phase::Extract, &package.name, e))?;
+        run_stage_hook(&self.config, &self.progress, Stage::PreExtract, &package.name, &package.version, &self.hook_output()).await?;
+        let started = Instant::now();
+        let files = self.extract_package(archive, &package).await
+            .map_err(|e| Error::in_package(Phase::Extract, &package.name, e))?;
+        self.stats.lock().unwrap().push(PackageStats {
+            package: package.name.clone(),
+            version: package.version.clone(),
+            cached,
+            download_bytes,
+            download_seconds,
+            extract_seconds: started.elapsed().as_secs_f64(),
+            files: files.len(),
+            extracted_bytes: files.iter().map(|entry| entry.size).sum(),
+        });
+        self.sink.mark_done(&package.file_name, &files).await?;
+        self.manifest.lock().unwrap().files.extend(files);
+        run_stage_hook(&self.config, &self.progress, Stage::PostExtract, &package.name, &package.version, &self.hook_output()).await?;
+        self.progress.package_done();
+        Ok(())
+    }
+
+    /// Folder or archive files are written into, passed to `[hooks]` commands as `{output}`
+    fn hook_output(&self) -> PathBuf {
+        match (self.sink.staged_root(), self.config.archive_output.as_ref()) {
+            (Some(root), _) => root.to_owned(),
+            (None, Some(path)) => path.clone(),
+            (None, None) => self.config.output_folder.clone(),
+        }
+    }
+
+    async fn extract_package(&self, archive: Vec<u8>, package: &Package) -> Result<Vec<ManifestEntry>, Error> {
+        let progress = self.progress.package_extract(package);
+        let files = list_archive_files(&archive[..])?;
+        progress.set_count(files.len());
+        if files.iter().any(|file| file == ".INSTALL") {
+            progress.warn("Package has install scriptlet, which is not run");
+        }
+        let mut selected = Vec::<Entry>::new();
+        for path in files.into_iter() {
+            let file = match self.source.entry_path(&path) {
+                Some(file) => file,
+                None => {
+                    progress.file(&path);
+                    continue;
+                }
+            };
+            if self.config.notices && notices::is_license(&file) {
+                let target = notices::notice_path(&package.name, &file);
+                selected.push(Entry { path: path.clone(), target, notice: true });
+            }
+            let decision = filter::decide(&file, &self.config.include, &self.config.exclude);
+            if self.config.explain_filters {
+                progress.info(&format!("{}: {}", &file, &decision));
+            }
+            if !decision.is_extracted() {
+                tracing::trace!(file = %file, decision = %decision, "skipped by filters");
+                progress.file(&file);
+                continue;
+            }
+            let target = match self.sanitize_target(&file, &progress) {
+                Some(target) => target,
+                None => {
+                    progress.file(&file);
+                    continue;
+                }
+            };
+            let target = paths::remap(target, &self.config.path_remaps);
+            let target = self.config.layout.target(package, target);
+            let target = self.claim_target(package, target, &progress)?;
+            if paths::display_len(self.sink.output(), &target) > self.config.max_path {
+                progress.warn(&format!("Output path of {} is longer than {} characters: {}", &file, self.config.max_path, &target));
+            }
+            tracing::trace!(file = %file, target = %target, "selected");
+            selected.push(Entry { path, target, notice: false });
+        }
+        tracing::debug!(files = selected.len(), "extracting");
+        let (decoder, receivers) = extract::decode(archive, selected, self.config.extract_threads as usize);
+        let written = Mutex::new(Vec::<ManifestEntry>::new());
+        futures::future::try_join_all(receivers.into_iter().map(|files| self.write_files(package, files, &progress, &written))).await?;
+        decoder.await??;
+        progress.complete();
+        let written = written.into_inner().unwrap();
+        tracing::debug!(files = written.len(), "extracted");
+        Ok(written)
+    }
+
+    /// Output path of `file` fixed by `--name-policy` if its name is invalid on Windows, `None` if the
+    /// file is skipped
+    fn sanitize_target(&self, file: &str, progress: &PackageExtractProgress) -> Option<String> {
+        let fixed = match sanitize::sanitize(file) {
+            Some(fixed) => fixed,
+            None => return Some(file.to_owned()),
+        };
+        match self.config.name_policy {
+            NamePolicy::Keep => Some(file.to_owned()),
+            NamePolicy::Rename => {
+                progress.warn(&format!("Renamed {} to {}: name is invalid on Windows", file, &fixed));
+                Some(fixed)
+            }
+            NamePolicy::Skip => {
+                progress.warn(&format!("Skipped {}: name is invalid on Windows", file));
+                None
+            }
+        }
+    }
+
+    /// Reserve output path `target` for a file of `package`, renaming it if it collides with another path
+    /// by case and the collision policy allows it
+    fn claim_target(&self, package: &Package, target: String, progress: &PackageExtractProgress) -> Result<String, Error> {
+        match self.case_index.claim(&target, &package.name) {
+            Ok(()) => Ok(target),
+            Err(collision) => match self.config.case_collisions {
+                CollisionPolicy::Fail => Err(ExtractionError::CaseCollision {
+                    path: target,
+                    package: package.name.clone(),
+                    existing: collision.existing,
+                    owner: collision.owner,
+                }.into()),
+                CollisionPolicy::Rename => {
+                    let renamed = self.case_index.claim_renamed(&target, &package.name);
+                    progress.warn(&format!("Renamed {} to {}: it differs from {} of package {} only by case", &target, &renamed, &collision.existing, &collision.owner));
+                    Ok(renamed)
+                }
+            }
+        }
+    }
+
+    fn transform(&self, entry: &Entry, data: Vec<u8>) -> Vec<u8> {
+        let data = match self.config.relocation.as_ref() {
+            Some(relocation) => relocate::relocate(&entry.path, &entry.target, &data, relocation).unwrap_or(data),
+            None => data,
+        };
+        let is_text = self.config.text_files.iter().any(|regex| regex.is_match(&entry.path)) && !text::is_binary(&data);
+        if !is_text {
+            return data;
+        }
+        let data = self.config.prefix_rewrites.iter().fold(data, |data, rewrite| rewrite.apply(data));
+        match self.config.line_endings {
+            Some(ending) => text::convert_line_endings(&data, ending),
+            None => data,
+        }
+    }
+
+    async fn strip(&self, kind: FileKind, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        match (self.config.strip_tool.as_ref(), kind) {
+            (Some(tool), FileKind::PeExecutable) | (Some(tool), FileKind::PeLibrary) |
+            (Some(tool), FileKind::StaticLibrary) | (Some(tool), FileKind::ImportLibrary) => {
+                let tool = tool.clone();
+                Ok(tokio::task::spawn_blocking(move || strip::strip_external(&tool, data, kind)).await??)
+            }
+            (None, FileKind::PeExecutable) | (None, FileKind::PeLibrary) => Ok(strip::strip_pe(&data).unwrap_or(data)),
+            _ => Ok(data),
+        }
+    }
+
+    async fn compress(&self, entry: &Entry, kind: FileKind, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let template = match self.config.compress_command.as_ref() {
+            Some(template) if kind.is_pe() => template.clone(),
+            _ => return Ok(data),
+        };
+        if self.config.compress_exclude.iter().any(|regex| regex.is_match(&entry.path)) {
+            return Ok(data);
+        }
+        let name = entry.target.clone();
+        Ok(tokio::task::spawn_blocking(move || tool::process_file(&template, &data, &name)).await??)
+    }
+
+    /// Sign PE image with `--sign-cmd`. Failures are recorded and reported when the run completes, the
+    /// file is written unsigned
+    async fn sign(&self, entry: &Entry, kind: FileKind, data: Vec<u8>, progress: &PackageExtractProgress) -> Result<Vec<u8>, Error> {
+        let template = match self.config.sign_command.as_ref() {
+            Some(template) if kind.is_pe() => template.clone(),
+            _ => return Ok(data),
+        };
+        let name = entry.target.clone();
+        let unsigned = data.clone();
+        let result = tokio::task::spawn_blocking(move || tool::sign_file(&template, &data, &name)).await?;
+        let signed = result.is_ok();
+        self.signatures.lock().unwrap().push((entry.target.clone(), signed));
+        match result {
+            Ok(data) => Ok(data),
+            Err(e) => {
+                progress.warn(&format!("Failed to sign {}: {}", &entry.target, e));
+                Ok(unsigned)
+            }
+        }
+    }
+
+    async fn write_files(&self, package: &Package, mut files: Receiver<DecodedFile>, progress: &PackageExtractProgress,
+                         written: &Mutex<Vec<ManifestEntry>>) -> Result<(), Error> {
+        while let Some(mut file) = files.next().await {
+            progress.decoded(file.data.len() as u64);
+            let data = if file.entry.notice {
+                if self.config.notices_concat {
+                    self.notices.lock().unwrap().push(Notice {
+                        package: package.name.clone(),
+                        path: file.entry.path.clone(),
+                        text: file.data.clone(),
+                    });
+                }
+                file.data
+            } else {
+                let kind = classify::classify(&file.data);
+                let pruned = self.config.no_static_libs && kind == FileKind::StaticLibrary;
+                if pruned || !self.config.content_filters.iter().all(|filter| filter.keeps(kind)) {
+                    if self.config.explain_filters {
+                        let reason = if pruned { "--no-static-libs" } else { "--content-filter" };
+                        progress.info(&format!("{}: dropped by {}", &file.entry.path, reason));
+                    }
+                    progress.file(&file.entry.path);
+                    continue;
+                }
+                match self.custom_filter(package, &file).await? {
+                    (ScriptDecision::Keep, _) => {}
+                    (ScriptDecision::Drop, option) => {
+                        if self.config.explain_filters {
+                            progress.info(&format!("{}: dropped by {}", &file.entry.path, option));
+                        }
+                        progress.file(&file.entry.path);
+                        continue;
+                    }
+                    (ScriptDecision::Remap(target), option) => {
+                        // Scripts and plugins choose any path, it is checked like a path from the archive
+                        let target = paths::check_relative(&target).map_err(|message| ExtractionError::InvalidTarget {
+                            path: file.entry.path.clone(),
+                            target,
+                            option: option.to_owned(),
+                            message,
+                        })?;
+                        let target = match self.sanitize_target(&target, progress) {
+                            Some(target) => target,
+                            None => {
+                                progress.file(&file.entry.path);
+                                continue;
+                            }
+                        };
+                        if self.config.explain_filters {
+                            progress.info(&format!("{}: moved to {} by {}", &file.entry.path, &target, option));
+                        }
+                        file.entry.target = self.claim_target(package, target, progress)?;
+                    }
+                }
+                if self.config.import_libs && kind == FileKind::PeLibrary {
+                    self.write_import_library(package, &file.entry, &file.data, progress, written).await?;
+                }
+                let data = if self.config.strip {
+                    self.strip(kind, file.data).await?
+                } else {
+                    file.data
+                };
+                let data = self.compress(&file.entry, kind, data).await?;
+                let data = self.transform(&file.entry, data);
+                self.sign(&file.entry, kind, data, progress).await?
+            };
+            let entry = ManifestEntry::new(package, &file.entry.target, &data);
+            self.sink.write(&file.entry.target, data).await?;
+            written.lock().unwrap().push(entry);
+            if !file.entry.notice {
+                progress.file(&file.entry.path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Decision of `--filter-script`, then of WebAssembly plugins and `--plugin`, about a file which passed other filters, with
+    /// the option which made it
+    async fn custom_filter(&self, package: &Package, file: &DecodedFile) -> Result<(ScriptDecision, &'static str), Error> {
+        let size = file.data.len() as u64;
+        let binary = text::is_binary(&file.data);
+        if let Some(script) = self.config.filter_script.as_ref() {
+            let decision = script.decide(&package.name, &file.entry.target, size, binary)
+                .map_err(|message| ExtractionError::FilterScript { path: file.entry.target.clone(), message })?;
+            if decision != ScriptDecision::Keep {
+                return Ok((decision, "--filter-script"));
+            }
+        }
+        for wasm in self.config.wasm_plugins.iter() {
+            let decision = wasm.filter(&package.name, &file.entry.target, size, binary).await
+                .map_err(|message| Error::Plugin { program: wasm.path().display().to_string(), message })?;
+            if decision != ScriptDecision::Keep {
+                return Ok((decision, "WebAssembly plugin"));
+            }
+        }
+        if let Some(plugin) = self.plugin.as_ref() {
+            let decision = plugin.file(&package.name, &file.entry.target, size, binary, &self.progress).await?;
+            return Ok((decision, "--plugin"));
+        }
+        Ok((ScriptDecision::Keep, "filters"))
+    }
+
+    /// Generate MSVC import library for DLL and write it into `lib` folder next to `bin` folder of the
+    /// DLL, or next to the DLL itself
+    async fn write_import_library(&self, package: &Package, entry: &Entry, dll: &[u8], progress: &PackageExtractProgress,
+                                  written: &Mutex<Vec<ManifestEntry>>) -> Result<(), Error> {
+        let data = match implib::generate(dll) {
+            Some(data) => data,
+            None => return Ok(()),
+        };
+        let (folder, name) = match entry.target.rfind('/') {
+            Some(index) => (&entry.target[..index], &entry.target[index + 1..]),
+            None => ("", entry.target.as_str()),
+        };
+        let stem = name.rfind('.').map(|index| &name[..index]).unwrap_or(name);
+        let path = if folder == "bin" {
+            format!("lib/{}.lib", stem)
+        } else if let Some(prefix) = folder.strip_suffix("/bin") {
+            format!("{}/lib/{}.lib", prefix, stem)
+        } else if folder.is_empty() {
+            format!("{}.lib", stem)
+        } else {
+            format!("{}/{}.lib", folder, stem)
+        };
+        if let Err(collision) = self.case_index.claim(&path, &package.name) {
+            progress.warn(&format!("Import library {} is not generated, it collides with {} from {}", &path, &collision.existing, &collision.owner));
+            return Ok(());
+        }
+        let manifest_entry = ManifestEntry::new(package, &path, &data);
+        self.sink.write(&path, data).await?;
+        written.lock().unwrap().push(manifest_entry);
+        Ok(())
+    }
+
+    /// Write file generated by this tool into the output
+    async fn write_generated(&self, path: &str, data: Vec<u8>) -> Result<(), Error> {
+        let entry = ManifestEntry::generated(path, &data);
+        self.sink.write(path, data).await?;
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.files.retain(|existing| existing.path != path);
+        manifest.files.push(entry);
+        Ok(())
+    }
+
+    async fn download_package(&self, package: &Package) -> Result<Vec<u8>, Error> {
+        let progress = self.progress.package_download(package);
+        let pin = self.locked.as_ref()
+            .and_then(|lockfile| lockfile.packages.iter().find(|pin| pin.file_name == package.file_name));
+        let archive = match fetch_archive(&self.config, self.source.as_ref(), &self.downloader, package, &progress).await {
+            Ok(archive) => archive,
+            Err(e) => match pin {
+                Some(pin) if download::is_not_found(&e) => {
+                    if !self.config.allow_snapshot_fallback {
+                        return Err(ResolutionError::PinnedArchiveMissing(pin.file_name.clone()).into());
+                    }
+                    self.progress.warn(&format!("Repository no longer has {}, downloading it from {}", &pin.file_name, &pin.url));
+                    self.downloader.download_from(package, &pin.url, &progress).await?
+                }
+                _ => return Err(e),
+            },
+        };
+        if let Some(pin) = pin {
+            let actual = sha256_hex(&archive);
+            if actual != pin.sha256 {
+                // Don't let the next run take the wrong archive from the cache
+                let _ = tokio::fs::remove_file(self.downloader.cached_path(package)).await;
+                return Err(DownloadError::ArchiveHashMismatch { file: pin.file_name.clone(), expected: pin.sha256.clone(), actual }.into());
+            }
+        }
+        progress.complete();
+        Ok(archive)
+    }
+
+    /// Packages pinned by `lockfile` with versions and archives it records. Dependencies are not
+    /// resolved, lockfile already lists every package
+    fn locked_tree(&self, lockfile: &Lockfile) -> Result<Vec<Package>, ResolutionError> {
+        if lockfile.repository != self.config.repository_url() {
+            self.progress.warn(&format!("Lockfile was written for repository {}, using {}", &lockfile.repository, self.config.repository_url()));
+        }
+        let mut tree = Vec::with_capacity(lockfile.packages.len());
+        for pin in lockfile.packages.iter() {
+            let mut package = self.source.package(&pin.name)
+                .ok_or_else(|| ResolutionError::PackageNotFound(pin.name.clone()))?
+                .to_owned();
+            if package.version != pin.version {
+                self.progress.info(&format!("Using locked {} {} instead of {}", &pin.name, &pin.version, &package.version));
+            }
+            package.version = pin.version.clone();
+            package.file_name = pin.file_name.clone();
+            tree.push(package);
+        }
+        Ok(tree)
+    }
+
+    fn build_package_tree(&self, package: Package) -> Result<Vec<Package>, ResolutionError> {
+        let resolving = Instant::now();
+        let tree = resolve_tree(self.source.as_ref(), &self.config, &self.progress, package);
+        self.timings.lock().unwrap().resolve_seconds += resolving.elapsed().as_secs_f64();
+        tree
+    }
+}
+
+/// Archive of `package` from the URL rewrites of `config` give, or from `source`
+async fn fetch_archive(config: &Config, source: &dyn PackageSource, downloader: &Downloader, package: &Package,
+                       progress: &PackageDownloadProgress) -> Result<Vec<u8>, Error> {
+    match urls::resolve(config, &package.name, &package.file_name, &source.archive_url(&package.file_name)) {
+        Some(url) => downloader.download_from(package, &url, progress).await,
+        None => source.fetch(package, downloader, progress).await,
+    }
+}
+
+async fn load_repository(config: &Config, progress: &Progress) -> Result<PacmanSource, Error> {
+    PacmanSource::load(&config.repository_name, &config.repository_url(), progress).await
+}
+
+/// Package `name`. If no package has exactly this name, packages which names start with, contain or
+/// fuzzy match it are offered, as `--first` and `--exact` say
+fn find_package(source: &dyn PackageSource, config: &Config, name: &str, progress: &Progress) -> Result<Package, Error> {
+    if let Some(package) = source.package(name) {
+        return Ok(package.to_owned());
+    }
+    let not_found = || -> Error { ResolutionError::PackageNotFound(name.to_owned()).into() };
+    if config.package_choice == PackageChoice::Exact {
+        return Err(not_found());
+    }
+    let candidates = pick::candidates(source, name);
+    let interactive = config.progress_mode != ProgressMode::Json && console::Term::stderr().is_term();
+    let package = match (candidates.len(), config.package_choice) {
+        (0, _) => return Err(not_found()),
+        // Even a single fuzzy match may be a typo, so it is taken without asking only with --first
+        (_, PackageChoice::First) => candidates[0],
+        _ if interactive => pick::ask(name, &candidates)?.ok_or_else(not_found)?,
+        _ => return Err(ResolutionError::AmbiguousPackage {
+            name: name.to_owned(),
+            candidates: candidates.iter().take(10).map(|package| package.name.clone()).collect(),
+        }.into()),
+    };
+    progress.info(&format!("Package {} not found, using {}", name, &package.name));
+    Ok(package.to_owned())
+}
+
+/// Package with all its dependencies, checked against the license policy
+fn resolve_tree(source: &dyn PackageSource, config: &Config, progress: &Progress, package: Package) -> Result<Vec<Package>, ResolutionError> {
+    let progress = progress.tree();
+    let mut tree = Vec::<Package>::new();
+    tree.push(package);
+    loop {
+        let mut modified = false;
+        let mut patch = Vec::<Package>::new();
+        for item in tree.iter() {
+            progress.index(item);
+            for dependency in source.dependencies(item) {
+                let package = source.package(&dependency)
+                    .ok_or_else(|| ResolutionError::PackageNotFound(dependency.clone()))?;
+                if !tree.contains(package) && !patch.contains(package) {
+                    patch.push(package.to_owned());
+                    modified = true;
+                }
+            }
+        }
+        tree.append(&mut patch);
+        if !modified {
+            break
+        }
+    }
+    if !config.license_policy.is_empty() {
+        let mut denied = Vec::new();
+        for package in tree.iter() {
+            let violations = config.license_policy.violations(package);
+            if !violations.is_empty() {
+                progress.warn(&format!("Package {} has disallowed license {}", &package.name, violations.join(", ")));
+                denied.push(package.name.clone());
+            }
+        }
+        if !denied.is_empty() && config.license_policy.action == LicenseAction::Fail {
+            return Err(ResolutionError::LicenseDenied(denied));
+        }
+    }
+    progress.done(tree.len());
+    Ok(tree)
+}
+
+/// Class of the error which stopped the program, the value is the exit code:
+///
+/// * 1 - other failure
+/// * 2 - invalid arguments or config file, or settings which can't be used together
+/// * 3 - packages can't be resolved: missing package, disallowed license, pinned archive is gone
+/// * 4 - repository database or package archive can't be downloaded or is damaged
+/// * 5 - package can't be extracted into the output
+/// * 6 - output doesn't pass checks: manifest, DLL imports, size limit
+/// * 130 - run is interrupted with Ctrl+C or from the dashboard
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+pub enum Failure {
+    Other = 1,
+    Config = 2,
+    Resolution = 3,
+    Download = 4,
+    Extraction = 5,
+    Verification = 6,
+    Cancelled = 130,
+}
+
+impl Failure {
+    /// Class of `error` by its kind, otherwise by the phase it happened in
+    pub fn classify(error: &Error) -> Self {
+        match error.root() {
+            Error::Cancelled => return Failure::Cancelled,
+            Error::Config(_) => return Failure::Config,
+            Error::Resolution(_) => return Failure::Resolution,
+            Error::Download(_) => return Failure::Download,
+            Error::Extraction(ExtractionError::CaseCollision { .. }) | Error::Extraction(ExtractionError::InsufficientSpace { .. }) |
+            Error::Extraction(ExtractionError::FilterScript { .. }) |
+            Error::Extraction(ExtractionError::InvalidTarget { .. }) => return Failure::Extraction,
+            Error::Verification(_) => return Failure::Verification,
+            Error::SigningFailed(_) | Error::HookFailed { .. } | Error::Locked(_) => return Failure::Other,
+            _ => {}
+        }
+        match error.phase() {
+            Some(Phase::Repository) | Some(Phase::Download) => Failure::Download,
+            Some(Phase::Resolve) => Failure::Resolution,
+            Some(Phase::Extract) => Failure::Extraction,
+            _ => Failure::Other,
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        self as i32
+    }
+}
+
+/// Run `[hooks]` command of `stage` for `package` if the config file sets one. Failures are handled by
+/// `on_failure` of the config file
+async fn run_stage_hook(config: &Config, progress: &Progress, stage: Stage, package: &str, version: &str, output: &Path) -> Result<(), Error> {
+    let command = match config.stage_hooks.command(stage) {
+        Some(command) => command.clone(),
+        None => return Ok(()),
+    };
+    let result = {
+        let (package, version, output) = (package.to_owned(), version.to_owned(), output.to_owned());
+        tokio::task::spawn_blocking(move || hooks::run_stage(&command, &package, &version, &output)).await?
+    };
+    let status = match result {
+        Ok((status, output)) => {
+            for line in output.lines() {
+                progress.info(&format!("[{} {}] {}", package, stage.name(), line));
+            }
+            if status.success() {
+                return Ok(());
+            }
+            status.to_string()
+        }
+        Err(e) => e.to_string(),
+    };
+    match config.stage_hooks.failure {
+        HookFailure::Fail => Err(Error::HookFailed { package: package.to_owned(), status }),
+        HookFailure::Warn => {
+            progress.warn(&format!("{} hook of {} failed: {}", stage.name(), package, status));
+            Ok(())
+        }
+        HookFailure::Ignore => {
+            tracing::debug!(package, stage = stage.name(), status = %status, "hook failed");
+            Ok(())
+        }
+    }
+}
+
+/// Output folder which the command reads or changes
+fn command_output(config: &Config) -> Option<&Path> {
+    match &config.command {
+        Command::Build if config.output_to_stdout() || config.archive_output.is_some() => None,
+        Command::Build | Command::Package(_) | Command::Installer(_) | Command::Remove(_) => Some(config.output_folder.as_path()),
+        Command::Add(options) => Some(options.output.as_path()),
+        Command::Clean(options) => Some(options.output.as_path()),
+        Command::Outdated(options) => Some(options.output.as_path()),
+        Command::Rollback(options) => Some(options.output.as_path()),
+        Command::State(options) => Some(options.output.as_path()),
+        Command::Update(options) => Some(options.output.as_path()),
+        Command::Verify(options) => Some(options.output.as_path()),
+        Command::Cache(_) | Command::Diff(_) | Command::Mirror(_) | Command::Owns(_) => None,
+    }
+}
+
+/// Check if the command downloads into or changes the package cache
+fn uses_cache(config: &Config) -> bool {
+    match &config.command {
+        Command::Build | Command::Add(_) | Command::Cache(_) | Command::Mirror(_) | Command::State(_) | Command::Update(_) => true,
+        _ => false,
+    }
+}
+
+/// Lock the output and the cache folders the command uses. Output folders which don't exist are only
+/// locked by commands which create them
+async fn lock_folders(config: &Config, progress: &Progress) -> Result<Vec<FolderLock>, Error> {
+    let mut folders = Vec::new();
+    if let Some(output) = command_output(config) {
+        let creates = match &config.command {
+            Command::Build | Command::State(_) => true,
+            _ => false,
+        };
+        if creates || tokio::fs::metadata(output).await.is_ok() {
+            folders.push((output.join(TOOL_FOLDER), output));
+        }
+    }
+    if uses_cache(config) {
+        folders.push((config.cache_dir.clone(), config.cache_dir.as_path()));
+    }
+    let mut locks = Vec::with_capacity(folders.len());
+    for (folder, shown) in folders {
+        match FolderLock::acquire(&folder, config.wait_lock, progress).await? {
+            Attempt::Locked(lock) => locks.push(lock),
+            Attempt::Busy => return Err(Error::Locked(shown.display().to_string())),
+        }
+    }
+    Ok(locks)
+}
+
+/// Run the command of `config`, then write metrics, send notifications and write the error report
+/// as settings of `config` say. Everything is reported to one progress
+pub async fn build(config: Config) -> Result<(), Error> {
+    let started = Instant::now();
+    let progress = Arc::new(Progress::new(config.progress_mode, &config.theme));
+    let result = run_command(&config, &progress).await;
+    finished(&config, &result, started.elapsed(), &progress).await;
+    result
+}
+
+async fn run_command(config: &Config, progress: &Arc<Progress>) -> Result<(), Error> {
+    let _locks = lock_folders(config, progress).await?;
+    if let Some(output) = command_output(config) {
+        journal::recover(output, progress).await?;
+    }
+    match &config.command {
+        Command::Build => {
+            let program = Program::open(config.clone(), progress.clone(), None).await?;
+            program.run(&config.package).await
+        }
+        Command::Package(options) => package::package(&config.output_folder, options, progress).await,
+        Command::Installer(options) => {
+            let target = Target::new(&config.repository_name, &config.architecture);
+            installer::installer(&config.output_folder, options, &target, progress).await
+        }
+        Command::Add(options) => {
+            let state = State::load(&options.output).await?;
+            let previous = Manifest::load(&options.output).await?;
+            let (progress_mode, theme) = (config.progress_mode, config.theme.clone());
+            let mut config = config::clap::config_from(&state.args)?;
+            config.output_folder = options.output.clone();
+            config.progress_mode = progress_mode;
+            config.theme = theme;
+            config.archive_output = None;
+            config.resume = false;
+            let program = Program::open(config, progress.clone(), None).await?;
+            program.add(state, previous, &options.packages).await
+        }
+        Command::Cache(options) => match &options.action {
+            CacheAction::Info => cache::info(&config.cache_dir, progress).await,
+            CacheAction::Prune { keep_days, max_size } => cache::prune(&config.cache_dir, *keep_days, *max_size, progress).await,
+            CacheAction::Gc { dry_run } => cache::gc(&config.cache_dir, *dry_run, progress).await,
+            CacheAction::Verify { delete } => match cache::verify(&config.cache_dir, *delete, progress).await? {
+                0 => Ok(()),
+                damaged => Err(DownloadError::DamagedArchives(damaged).into()),
+            },
+        },
+        Command::Clean(options) => clean::clean(options, progress).await,
+        Command::Diff(options) => diff::diff(options, progress).await,
+        Command::Mirror(options) => {
+            let source = load_repository(config, progress).await?;
+            let package = find_package(&source, config, &options.package, progress)?;
+            let tree = resolve_tree(&source, config, progress, package)?;
+            tokio::fs::create_dir_all(&config.cache_dir).await?;
+            let downloader = Downloader::new(&config.repository_url(), &config.cache_dir, config.resume);
+            mirror::mirror(&tree, &downloader, &config.repository_name, config.parallelism.max(1) as usize, options, progress).await
+        }
+        Command::Outdated(options) => {
+            let installed = outdated::Installed::load(options).await?;
+            let source = PacmanSource::load(&installed.repository_name, &installed.repository, progress).await?;
+            let report = outdated::Report::new(&installed, &source);
+            for name in report.missing.iter() {
+                progress.warn(&format!("Package {} is no longer in the repository", name));
+            }
+            let mut text = report.render();
+            for root in installed.explicit.iter() {
+                if report.affected.contains(root) {
+                    text += &format!("{} is affected\n", root);
+                } else {
+                    text += &format!("{} is up to date\n", root);
+                }
+            }
+            progress.report("outdated", &text);
+            Ok(())
+        }
+        Command::Owns(options) => owners::owns(&config.output_folder, options, progress).await,
+        Command::Remove(options) => remove::remove(&config.output_folder, options, progress).await,
+        Command::Rollback(options) => snapshot::rollback(options, progress).await,
+        Command::State(options) => match &options.action {
+            StateAction::Export { bundle: path } => bundle::export(&options.output, &config.cache_dir, path, progress).await,
+            StateAction::Import { bundle: path } => {
+                let imported = Bundle::load(path).await?;
+                if !bundle::is_empty(&options.output).await? {
+                    return bundle::adopt(&options.output, &imported, progress).await;
+                }
+                let lockfile = tempfile::NamedTempFile::new()?;
+                tokio::fs::write(lockfile.path(), imported.lockfile.to_json()).await?;
+                let (progress_mode, theme) = (config.progress_mode, config.theme.clone());
+                let mut config = config::clap::config_from(&imported.state.args)?;
+                config.output_folder = options.output.clone();
+                config.progress_mode = progress_mode;
+                config.theme = theme;
+                config.archive_output = None;
+                config.resume = false;
+                config.write_lockfile = None;
+                config.locked = Some(lockfile.path().to_owned());
+                let program = Program::open(config, progress.clone(), None).await?;
+                program.restore(imported.state).await
+            }
+        },
+        Command::Update(options) => {
+            let state = State::load(&options.output).await?;
+            let previous = Manifest::load(&options.output).await?;
+            let (progress_mode, theme) = (config.progress_mode, config.theme.clone());
+            let mut config = config::clap::config_from(&state.args)?;
+            config.output_folder = options.output.clone();
+            config.progress_mode = progress_mode;
+            config.theme = theme;
+            config.archive_output = None;
+            config.resume = false;
+            let program = Program::open(config, progress.clone(), None).await?;
+            program.update(state, previous).await
+        }
+        Command::Verify(options) => match verify::verify(options, progress).await? {
+            0 => Ok(()),
+            problems => Err(VerificationError::ManifestMismatch(problems).into()),
+        },
+    }
+}
+
+/// Installed packages of the output after a successful run
+async fn installed_packages(config: &Config, result: &Result<(), Error>) -> Option<usize> {
+    match (result, command_output(config)) {
+        (Ok(()), Some(output)) => State::load(output).await.ok().map(|state| state.packages.len()),
+        _ => None,
+    }
+}
+
+/// Write `--metrics` file of the finished run
+async fn write_metrics(config: &Config, result: &Result<(), Error>, duration: Duration, progress: &Progress) {
+    if let Some(path) = config.metrics.as_ref() {
+        let metrics = RunMetrics { success: result.is_ok(), packages: installed_packages(config, result).await, duration };
+        if let Err(e) = metrics.write(path).await {
+            progress.warn(&format!("Failed to write metrics to {}: {}", path.display(), e));
+        }
+    }
+}
+
+/// Send notification about the finished run, if `--notify-cmd`, `--notify-webhook` or `--notify-desktop` is set
+async fn notify_finished(config: &Config, result: &Result<(), Error>, duration: Duration, progress: &Progress) {
+    // Nobody looks at the desktop of a CI job
+    let desktop = config.notify_desktop && console::Term::stderr().is_term();
+    if config.notify_command.is_none() && config.notify_webhook.is_none() && !desktop {
+        return;
+    }
+    let (status, errors) = match result {
+        Ok(()) => ("success", Vec::new()),
+        Err(e) if e.is_cancelled() => ("cancelled", vec![e.to_string()]),
+        Err(e) => ("failure", vec![e.to_string()]),
+    };
+    let packages = installed_packages(config, result).await;
+    let notification = Notification::new(status, config.command.name(), packages, duration, errors);
+    if let Some(template) = config.notify_command.as_ref() {
+        if let Err(e) = notify::run_command(template, &notification).await {
+            progress.warn(&format!("Notification command failed: {}", e));
+        }
+    }
+    if let Some(url) = config.notify_webhook.as_ref() {
+        if let Err(e) = notify::post(url, &notification).await {
+            progress.warn(&format!("Failed to send notification to {}: {}", url, e));
+        }
+    }
+    if desktop {
+        if let Err(e) = notify::desktop(&notification).await {
+            progress.warn(&format!("Failed to show desktop notification: {}", e));
+        }
+    }
+}
+
+/// Write metrics, send notifications and write the error report of the run which finished with `result`
+/// after `duration`, as settings of `config` say
+async fn finished(config: &Config, result: &Result<(), Error>, duration: Duration, progress: &Progress) {
+    write_metrics(config, result, duration, progress).await;
+    notify_finished(config, result, duration, progress).await;
+    if let Err(e) = result {
+        write_error_report(config, e, progress).await;
+    }
+}
@@ -0,0 +1,115 @@
+//! License-based package filtering
+use archlinux_repo::Package;
+use std::str::FromStr;
+
+/// Glob pattern supporting `*` and `?`, matched case-insensitively
+#[derive(Clone, Debug)]
+pub struct Glob {
+    pattern: Vec<char>,
+}
+
+impl FromStr for Glob {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Glob { pattern: s.to_lowercase().chars().collect() })
+    }
+}
+
+impl Glob {
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.to_lowercase().chars().collect();
+        glob_match(&self.pattern, &text)
+    }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((&'*', rest)) => (0..=text.len()).any(|skip| glob_match(rest, &text[skip..])),
+        Some((&'?', rest)) => !text.is_empty() && glob_match(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
+
+/// What to do with packages which licenses are not allowed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LicenseAction {
+    Fail,
+    Warn,
+}
+
+impl FromStr for LicenseAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(LicenseAction::Fail),
+            "warn" => Ok(LicenseAction::Warn),
+            _ => Err(format!("Unknown license action: \"{}\"", s)),
+        }
+    }
+}
+
+/// License rules. Package passes only when every one of its licenses is allowed and none is denied
+#[derive(Clone, Debug)]
+pub struct LicensePolicy {
+    pub allow: Vec<Glob>,
+    pub deny: Vec<Glob>,
+    pub action: LicenseAction,
+}
+
+impl LicensePolicy {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Return licenses of `package` violating the policy. Package without license metadata violates
+    /// policy with allow rules
+    pub fn violations(&self, package: &Package) -> Vec<String> {
+        let licenses = package.license.clone().unwrap_or_default();
+        if licenses.is_empty() && !self.allow.is_empty() {
+            return vec![String::from("unknown")];
+        }
+        licenses.into_iter()
+            .filter(|license| {
+                let denied = self.deny.iter().any(|glob| glob.is_match(license));
+                let allowed = self.allow.is_empty() || self.allow.iter().any(|glob| glob.is_match(license));
+                denied || !allowed
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str) -> Glob {
+        Glob::from_str(pattern).unwrap()
+    }
+
+    #[test]
+    fn star_matches_any_sequence() {
+        assert!(glob("GPL*").is_match("GPL"));
+        assert!(glob("GPL*").is_match("GPL3-or-later"));
+        assert!(glob("*-only").is_match("LGPL2.1-only"));
+        assert!(glob("a*b*c").is_match("aXXbYYc"));
+        assert!(!glob("GPL*").is_match("LGPL"));
+        assert!(!glob("a*b*c").is_match("aXXbYY"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_char() {
+        assert!(glob("GPL?").is_match("GPL3"));
+        assert!(!glob("GPL?").is_match("GPL"));
+        assert!(!glob("GPL?").is_match("GPL30"));
+    }
+
+    #[test]
+    fn match_ignores_case() {
+        assert!(glob("mit").is_match("MIT"));
+        assert!(glob("Apache*").is_match("APACHE-2.0"));
+        assert!(!glob("mit").is_match("MIT-0"));
+    }
+}
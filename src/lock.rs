@@ -0,0 +1,114 @@
+//! Lockfile which pins exact package archives of the output
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+/// Lockfile format version
+pub const LOCK_VERSION: u32 = 1;
+
+/// Package archive pinned by the lockfile
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    pub file_name: String,
+    /// Hex-encoded SHA-256 of the package archive
+    pub sha256: String,
+    /// Location the archive was downloaded from
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub version: u32,
+    /// Repository URL with architecture
+    pub repository: String,
+    pub repository_name: String,
+    /// Packages requested explicitly
+    pub explicit: Vec<String>,
+    /// Packages sorted by name
+    pub packages: Vec<LockedPackage>,
+}
+
+impl Lockfile {
+    pub fn new(repository: &str, repository_name: &str, explicit: &[String], mut packages: Vec<LockedPackage>) -> Self {
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Lockfile {
+            version: LOCK_VERSION,
+            repository: repository.to_owned(),
+            repository_name: repository_name.to_owned(),
+            explicit: explicit.to_vec(),
+            packages,
+        }
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        let mut data = serde_json::to_vec_pretty(self).unwrap();
+        data.push(b'\n');
+        data
+    }
+
+    pub async fn load(path: &Path) -> io::Result<Self> {
+        let data = tokio::fs::read(path).await?;
+        let lockfile: Lockfile = serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if lockfile.version > LOCK_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Lockfile has version {}, but this version of {} supports up to {}",
+                                              lockfile.version, env!("CARGO_PKG_NAME"), LOCK_VERSION)));
+        }
+        Ok(lockfile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str) -> LockedPackage {
+        LockedPackage {
+            name: name.to_owned(),
+            version: "1.0-1".to_owned(),
+            file_name: format!("{}-1.0-1-any.pkg.tar.zst", name),
+            sha256: "00".repeat(32),
+            url: format!("https://mirror.msys2.org/mingw/mingw64/{}-1.0-1-any.pkg.tar.zst", name),
+        }
+    }
+
+    async fn load(data: &[u8]) -> io::Result<Lockfile> {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), data).unwrap();
+        Lockfile::load(file.path()).await
+    }
+
+    #[test]
+    fn packages_are_sorted() {
+        let lockfile = Lockfile::new("https://mirror.msys2.org/mingw/mingw64", "mingw64", &["gcc".to_owned()],
+                                     vec![package("zlib"), package("gcc"), package("binutils")]);
+        let names: Vec<&str> = lockfile.packages.iter().map(|package| package.name.as_str()).collect();
+        assert_eq!(names, vec!["binutils", "gcc", "zlib"]);
+        assert_eq!(lockfile.version, LOCK_VERSION);
+    }
+
+    #[tokio::test]
+    async fn loads_written_lockfile() {
+        let lockfile = Lockfile::new("https://mirror.msys2.org/mingw/mingw64", "mingw64", &["gcc".to_owned()], vec![package("gcc")]);
+        let loaded = load(&lockfile.to_json()).await.unwrap();
+        assert_eq!(loaded.repository_name, "mingw64");
+        assert_eq!(loaded.explicit, vec!["gcc"]);
+        assert_eq!(loaded.packages[0].file_name, "gcc-1.0-1-any.pkg.tar.zst");
+    }
+
+    #[tokio::test]
+    async fn rejects_newer_version() {
+        let mut lockfile = Lockfile::new("https://mirror.msys2.org/mingw/mingw64", "mingw64", &[], Vec::new());
+        lockfile.version = LOCK_VERSION + 1;
+        let error = load(&lockfile.to_json()).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_lockfile() {
+        let error = load(b"{\"version\": 1, \"packages\": []}").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,99 @@
+//! Structured logs of the run. With a log level set, progress messages and debug events of the
+//! download and extraction steps are printed by a `tracing` subscriber instead of progress bars.
+//! Log file receives every event regardless of the console level
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Print events up to `console` level to stderr, colored if `color` is set, and write all events into `file`
+pub fn init(console: Option<Level>, file: Option<&Path>, color: bool) -> io::Result<()> {
+    let console = Filtered {
+        layer: tracing_subscriber::fmt::layer().with_writer(io::stderr).with_target(false).with_ansi(color),
+        level: console,
+    };
+    match file {
+        Some(path) => {
+            let log = Arc::new(File::create(path)?);
+            let file = tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(move || LogFile(log.clone()));
+            tracing_subscriber::registry().with(console).with(file).init();
+        }
+        None => tracing_subscriber::registry().with(console).init(),
+    }
+    Ok(())
+}
+
+/// Level for `-v` flag repeated `count` times
+pub fn verbosity(count: u64) -> Option<Level> {
+    match count {
+        0 => None,
+        1 => Some(Level::DEBUG),
+        _ => Some(Level::TRACE),
+    }
+}
+
+/// Log file shared by all events. Events are formatted before writing, so each is a single write
+struct LogFile(Arc<File>);
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self.0).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self.0).flush()
+    }
+}
+
+/// Layer which receives only events up to `level`, or none if it isn't set. Spans are passed through,
+/// so events keep their context
+struct Filtered<L> {
+    layer: L,
+    level: Option<Level>,
+}
+
+impl<S, L> Layer<S> for Filtered<L>
+    where S: Subscriber + for<'a> LookupSpan<'a>,
+          L: Layer<S> {
+    fn new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        self.layer.new_span(attrs, id, ctx)
+    }
+
+    fn on_record(&self, span: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        self.layer.on_record(span, values, ctx)
+    }
+
+    fn on_follows_from(&self, span: &Id, follows: &Id, ctx: Context<'_, S>) {
+        self.layer.on_follows_from(span, follows, ctx)
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        // More verbose levels compare greater
+        if self.level.map_or(false, |level| *event.metadata().level() <= level) {
+            self.layer.on_event(event, ctx)
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        self.layer.on_enter(id, ctx)
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        self.layer.on_exit(id, ctx)
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        self.layer.on_close(id, ctx)
+    }
+
+    fn on_id_change(&self, old: &Id, new: &Id, ctx: Context<'_, S>) {
+        self.layer.on_id_change(old, new, ctx)
+    }
+}
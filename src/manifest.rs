@@ -0,0 +1,130 @@
+//! Install manifest which lists every output file with its hash and owning package
+use archlinux_repo::Package;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::Path;
+use crate::paths;
+use crate::pe;
+use crate::schema::{self, Migration};
+
+/// Manifest location relative to the output root
+pub const MANIFEST_PATH: &str = ".wtb/manifest.json";
+
+/// Upgrades of manifests written by older versions, current schema version is their number
+const MIGRATIONS: &[Migration] = &[
+    // Schema 1 only adds the version field
+    |_| {},
+];
+
+/// Output file record
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ManifestEntry {
+    /// Path relative to the output root, `/`-separated
+    pub path: String,
+    pub size: u64,
+    /// Hex-encoded SHA-256 of file content
+    pub sha256: String,
+    /// Owning package name
+    pub package: String,
+    /// Owning package version
+    pub version: String,
+    /// DLLs imported by PE image
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub imports: Vec<String>,
+}
+
+impl ManifestEntry {
+    pub fn new(package: &Package, path: &str, data: &[u8]) -> Self {
+        ManifestEntry {
+            path: path.to_owned(),
+            size: data.len() as u64,
+            sha256: sha256_hex(data),
+            package: package.name.clone(),
+            version: package.version.clone(),
+            imports: pe::imports(data),
+        }
+    }
+
+    /// Record of a file generated by this tool
+    pub fn generated(path: &str, data: &[u8]) -> Self {
+        ManifestEntry {
+            path: path.to_owned(),
+            size: data.len() as u64,
+            sha256: sha256_hex(data),
+            package: env!("CARGO_PKG_NAME").to_owned(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            imports: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Serialize manifest with files sorted by path
+    pub fn to_json(&self) -> Vec<u8> {
+        let mut manifest = self.clone();
+        manifest.files.sort_by(|a, b| a.path.cmp(&b.path));
+        schema::to_json(&manifest, MIGRATIONS.len())
+    }
+
+    /// Parse manifest written by this or an older version
+    pub fn from_json(data: &[u8]) -> io::Result<Self> {
+        schema::from_json(data, "Manifest", MIGRATIONS)
+    }
+
+    /// Render `sha256sum`-compatible listing of all files
+    pub fn to_sha256sums(&self) -> String {
+        let mut files: Vec<&ManifestEntry> = self.files.iter().collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files.iter()
+            .map(|entry| format!("{}  {}\n", &entry.sha256, &entry.path))
+            .collect()
+    }
+
+    /// Merkle root of output files. Leaves are hashes of `path\0sha256` sorted by path, odd nodes are
+    /// promoted to the next level unchanged. Doesn't depend on anything but file paths and contents
+    pub fn digest(&self) -> String {
+        let mut files: Vec<&ManifestEntry> = self.files.iter().collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut level: Vec<Vec<u8>> = files.iter()
+            .map(|entry| Sha256::digest(format!("{}\0{}", &entry.path, &entry.sha256).as_bytes()).to_vec())
+            .collect();
+        if level.is_empty() {
+            return sha256_hex(&[]);
+        }
+        while level.len() > 1 {
+            level = level.chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => {
+                        let mut hasher = Sha256::new();
+                        hasher.update(left);
+                        hasher.update(right);
+                        hasher.finalize().to_vec()
+                    }
+                    _ => pair[0].clone(),
+                })
+                .collect();
+        }
+        level[0].iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Load manifest of the output folder
+    pub async fn load(output: &Path) -> io::Result<Self> {
+        Manifest::from_json(&tokio::fs::read(paths::join(output, MANIFEST_PATH)).await?)
+    }
+
+    /// Replace manifest of the output folder
+    pub async fn save(&self, output: &Path) -> io::Result<()> {
+        tokio::fs::write(paths::join(output, MANIFEST_PATH), self.to_json()).await
+    }
+}
+
+/// Hex-encoded SHA-256 of `data`
+pub fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
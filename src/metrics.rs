@@ -0,0 +1,76 @@
+//! Metrics of the run in Prometheus textfile format, so scheduled rebuilds can be monitored by the
+//! node exporter textfile collector. Counters are carried over from the previous file
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const PREFIX: &str = "windows_toolchain_builder";
+
+/// Bytes received from the network by all downloads of the process
+static DOWNLOADED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Count `bytes` received from the network
+pub fn downloaded(bytes: u64) {
+    DOWNLOADED_BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+/// Result of the run
+pub struct RunMetrics {
+    pub success: bool,
+    /// Installed packages of the output after a successful run
+    pub packages: Option<usize>,
+    pub duration: Duration,
+}
+
+/// Value of metric `name` in the metrics file `data`
+fn previous(data: &str, name: &str) -> Option<f64> {
+    data.lines()
+        .filter(|line| !line.starts_with('#'))
+        .find(|line| line.split_whitespace().next() == Some(name))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|value| value.parse().ok())
+}
+
+impl RunMetrics {
+    fn render(&self, previous_file: &str) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as f64;
+        let counter = |name: &str, increment: f64| previous(previous_file, &format!("{}_{}", PREFIX, name)).unwrap_or(0.0) + increment;
+        let last_success = if self.success {
+            Some(now)
+        } else {
+            previous(previous_file, &format!("{}_last_success_timestamp_seconds", PREFIX))
+        };
+        let mut metrics: Vec<(&str, &str, &str, f64)> = vec![
+            ("runs_total", "counter", "Finished runs", counter("runs_total", 1.0)),
+            ("failures_total", "counter", "Runs which failed or were cancelled", counter("failures_total", if self.success { 0.0 } else { 1.0 })),
+            ("downloaded_bytes_total", "counter", "Bytes of package archives received from the network",
+             counter("downloaded_bytes_total", DOWNLOADED_BYTES.load(Ordering::Relaxed) as f64)),
+            ("last_run_success", "gauge", "Whether the last run succeeded", if self.success { 1.0 } else { 0.0 }),
+            ("last_run_duration_seconds", "gauge", "Duration of the last run", self.duration.as_secs_f64()),
+            ("last_run_timestamp_seconds", "gauge", "Time when the last run finished", now),
+        ];
+        if let Some(timestamp) = last_success {
+            metrics.push(("last_success_timestamp_seconds", "gauge", "Time when the last successful run finished", timestamp));
+        }
+        if let Some(packages) = self.packages {
+            metrics.push(("packages", "gauge", "Installed packages of the output", packages as f64));
+        }
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            writeln!(out, "# HELP {}_{} {}", PREFIX, name, help).unwrap();
+            writeln!(out, "# TYPE {}_{} {}", PREFIX, name, kind).unwrap();
+            writeln!(out, "{}_{} {}", PREFIX, name, value).unwrap();
+        }
+        out
+    }
+
+    /// Write metrics into `path`. File is replaced with rename, so the collector never reads a partial file
+    pub async fn write(&self, path: &Path) -> std::io::Result<()> {
+        let previous_file = tokio::fs::read_to_string(path).await.unwrap_or_default();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        tokio::fs::write(&temp, self.render(&previous_file)).await?;
+        tokio::fs::rename(&temp, path).await
+    }
+}
@@ -0,0 +1,94 @@
+//! Local pacman-compatible mirror of the resolved package tree
+use crate::download::Downloader;
+use crate::progress::Progress;
+use archlinux_repo::Package;
+use compress_tools::{list_archive_files, uncompress_archive_file};
+use flate2::write::GzEncoder;
+use futures::StreamExt;
+use std::collections::HashSet;
+use crate::error::Error;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+/// Options of `mirror` command
+#[derive(Clone, Debug)]
+pub struct MirrorOptions {
+    /// Package which is mirrored with its dependencies
+    pub package: String,
+    /// Repository folder to create
+    pub destination: PathBuf,
+    /// Write databases which list only mirrored packages instead of copying upstream ones
+    pub regenerate_db: bool,
+}
+
+/// Download package archives with signatures and repository databases into `options.destination`
+pub async fn mirror(tree: &[Package], downloader: &Downloader, repository_name: &str, parallelism: usize,
+                    options: &MirrorOptions, progress: &Progress) -> Result<(), Error> {
+    tokio::fs::create_dir_all(&options.destination).await?;
+    let mut downloads = futures::stream::iter(tree.iter().map(|package| copy_package(package, downloader, &options.destination, progress)))
+        .buffer_unordered(parallelism);
+    while let Some(result) = downloads.next().await {
+        result?;
+    }
+
+    let names: HashSet<String> = tree.iter()
+        .map(|package| format!("{}-{}", &package.name, &package.version))
+        .collect();
+    for database in ["db", "files"].iter() {
+        let file = format!("{}.{}", repository_name, database);
+        let data = downloader.fetch(&file).await?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Repository has no {}", &file)))?;
+        if options.regenerate_db {
+            let names = names.clone();
+            let data = tokio::task::spawn_blocking(move || filter_database(&data, &names)).await??;
+            write(&options.destination.join(format!("{}.tar.gz", &file)), &data).await?;
+            write(&options.destination.join(&file), &data).await?;
+        } else {
+            write(&options.destination.join(&file), &data).await?;
+        }
+    }
+    progress.info(&format!("Mirrored {} packages into {}", tree.len(), options.destination.display()));
+    Ok(())
+}
+
+async fn copy_package(package: &Package, downloader: &Downloader, destination: &Path, progress: &Progress) -> Result<(), Error> {
+    let download = progress.package_download(package);
+    let archive = downloader.download(package, &download).await?;
+    download.complete();
+    write(&destination.join(&package.file_name), &archive).await?;
+    let signature = format!("{}.sig", &package.file_name);
+    if let Some(data) = downloader.fetch(&signature).await? {
+        write(&destination.join(&signature), &data).await?;
+    }
+    Ok(())
+}
+
+/// Repack database archive with only `<name>-<version>/` entries of `packages`
+fn filter_database(data: &[u8], packages: &HashSet<String>) -> io::Result<Vec<u8>> {
+    let invalid = |e: compress_tools::Error| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::default()));
+    for path in list_archive_files(data).map_err(invalid)? {
+        let folder = path.split('/').next().unwrap_or_default();
+        if path.ends_with('/') || !packages.contains(folder) {
+            continue;
+        }
+        let mut content = Vec::new();
+        uncompress_archive_file(data, Cursor::new(&mut content), &path).map_err(invalid)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_entry_type(tar::EntryType::Regular);
+        builder.append_data(&mut header, &path, &content[..])?;
+    }
+    builder.into_inner()?.finish()
+}
+
+/// Write file through `.part` file, so the mirror never has truncated files
+async fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    tokio::fs::write(&part, data).await?;
+    tokio::fs::rename(&part, path).await
+}
@@ -0,0 +1,58 @@
+//! Third party license notices bundle
+const NOTICES_FOLDER: &str = "THIRD-PARTY-NOTICES";
+/// Concatenated notices file path relative to the output root
+pub const NOTICES_FILE: &str = "THIRD-PARTY-NOTICES.txt";
+const LICENSES_FOLDER: &str = "share/licenses/";
+const LICENSE_NAMES: &[&str] = &["LICENSE", "LICENCE", "COPYING", "COPYRIGHT", "NOTICE"];
+
+/// Check if archive member is a license file
+pub fn is_license(path: &str) -> bool {
+    if path.ends_with('/') {
+        return false;
+    }
+    if path.contains(LICENSES_FOLDER) {
+        return true;
+    }
+    let name = path.rsplit('/').next().unwrap_or(path).to_uppercase();
+    LICENSE_NAMES.iter().any(|license| name.starts_with(license))
+}
+
+/// Check if output `path` is in the notices bundle
+pub fn is_notice(path: &str) -> bool {
+    path.strip_prefix(NOTICES_FOLDER).map_or(false, |rest| rest.starts_with('/'))
+}
+
+/// Path of license file `path` of `package` in the notices bundle
+pub fn notice_path(package: &str, path: &str) -> String {
+    let relative = match path.find(LICENSES_FOLDER) {
+        // Skip package name folder right after share/licenses
+        Some(index) => {
+            let rest = &path[index + LICENSES_FOLDER.len()..];
+            rest.splitn(2, '/').nth(1).unwrap_or(rest)
+        }
+        None => path.rsplit('/').next().unwrap_or(path),
+    };
+    format!("{}/{}/{}", NOTICES_FOLDER, package, relative)
+}
+
+/// License file collected for the concatenated notices file
+pub struct Notice {
+    pub package: String,
+    pub path: String,
+    pub text: Vec<u8>,
+}
+
+/// Concatenate notices sorted by package and path, each one with a header
+pub fn concatenate(notices: &mut Vec<Notice>) -> Vec<u8> {
+    notices.sort_by(|a, b| (&a.package, &a.path).cmp(&(&b.package, &b.path)));
+    let mut result = Vec::new();
+    for notice in notices.iter() {
+        result.extend_from_slice(format!("==== {}: {} ====\n\n", &notice.package, &notice.path).as_bytes());
+        result.extend_from_slice(&notice.text);
+        if !notice.text.ends_with(b"\n") {
+            result.push(b'\n');
+        }
+        result.push(b'\n');
+    }
+    result
+}
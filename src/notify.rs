@@ -0,0 +1,92 @@
+//! Notification about a finished run, so long builds can ping a chat or trigger downstream jobs
+use crate::tool::CommandTemplate;
+use reqwest::header::CONTENT_TYPE;
+use serde::Serialize;
+use crate::error::Error;
+use std::io::{self, Write};
+use std::process::Stdio;
+use std::time::Duration;
+
+/// Payload of the notification, sent as JSON
+#[derive(Clone, Debug, Serialize)]
+pub struct Notification {
+    /// `success`, `failure` or `cancelled`
+    pub status: &'static str,
+    /// Subcommand of the run, `build` for the default one
+    pub command: String,
+    /// Installed packages of the output after a successful run
+    pub packages: Option<usize>,
+    pub duration_seconds: f64,
+    pub errors: Vec<String>,
+}
+
+impl Notification {
+    pub fn new(status: &'static str, command: &str, packages: Option<usize>, duration: Duration, errors: Vec<String>) -> Self {
+        Notification {
+            status,
+            command: command.to_owned(),
+            packages,
+            duration_seconds: duration.as_secs_f64(),
+            errors,
+        }
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap()
+    }
+}
+
+/// Run `template` with the notification on stdin. `{status}` placeholder is replaced with the status
+pub async fn run_command(template: &CommandTemplate, notification: &Notification) -> io::Result<()> {
+    let mut command = template.command(&[("status", notification.status)]);
+    let payload = notification.to_json();
+    let program = template.program().to_owned();
+    tokio::task::spawn_blocking(move || {
+        let mut child = command.stdin(Stdio::piped()).spawn()?;
+        child.stdin.take().unwrap().write_all(&payload)?;
+        let status = child.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::Other, format!("{} exited with {}", program, status)))
+        }
+    }).await?
+}
+
+/// Show the notification on the desktop
+pub async fn desktop(notification: &Notification) -> Result<(), Error> {
+    let summary = match notification.status {
+        "success" => format!("{} finished", &notification.command),
+        "cancelled" => format!("{} cancelled", &notification.command),
+        _ => format!("{} failed", &notification.command),
+    };
+    let mut body = format!("Took {:.0} s", notification.duration_seconds);
+    if let Some(packages) = notification.packages {
+        body.push_str(&format!(", {} packages installed", packages));
+    }
+    for error in notification.errors.iter() {
+        body.push('\n');
+        body.push_str(error);
+    }
+    tokio::task::spawn_blocking(move || {
+        notify_rust::Notification::new()
+            .appname("windows-toolchain-builder")
+            .summary(&summary)
+            .body(&body)
+            .show()
+            .map(|_| ())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+    }).await??;
+    Ok(())
+}
+
+/// POST the notification to `url`
+pub async fn post(url: &str, notification: &Notification) -> Result<(), Error> {
+    reqwest::Client::new()
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(notification.to_json())
+        .send().await?
+        .error_for_status()?;
+    Ok(())
+}
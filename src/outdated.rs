@@ -0,0 +1,229 @@
+//! Comparison of installed packages with the current repository
+use crate::lock::Lockfile;
+use crate::manifest::Manifest;
+use crate::state::State;
+use crate::source::PackageSource;
+use indicatif::HumanBytes;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use crate::error::Error;
+use std::path::PathBuf;
+
+/// Options of `outdated` command
+#[derive(Clone, Debug)]
+pub struct OutdatedOptions {
+    /// Output folder with state database
+    pub output: PathBuf,
+    /// Lockfile to check instead of the output state
+    pub lockfile: Option<PathBuf>,
+}
+
+/// Packages of an output or a lockfile with the repository they were installed from
+#[derive(Clone, Debug)]
+pub struct Installed {
+    /// Repository URL with architecture
+    pub repository: String,
+    pub repository_name: String,
+    pub explicit: Vec<String>,
+    /// Names and versions of installed packages
+    pub packages: Vec<(String, String)>,
+    /// Size of output files of each package. Empty for lockfiles
+    pub sizes: HashMap<String, u64>,
+}
+
+impl Installed {
+    pub async fn load(options: &OutdatedOptions) -> Result<Self, Error> {
+        if let Some(path) = options.lockfile.as_ref() {
+            let lockfile = Lockfile::load(path).await?;
+            return Ok(Installed {
+                repository: lockfile.repository,
+                repository_name: lockfile.repository_name,
+                explicit: lockfile.explicit,
+                packages: lockfile.packages.into_iter().map(|package| (package.name, package.version)).collect(),
+                sizes: HashMap::new(),
+            });
+        }
+        let state = State::load(&options.output).await?;
+        let mut sizes = HashMap::new();
+        if let Ok(manifest) = Manifest::load(&options.output).await {
+            for entry in manifest.files.iter() {
+                *sizes.entry(entry.package.clone()).or_default() += entry.size;
+            }
+        }
+        Ok(Installed {
+            repository: state.repository.clone() + "/" + &state.architecture,
+            repository_name: state.repository_name.clone(),
+            explicit: state.explicit(),
+            packages: state.packages.into_iter().map(|package| (package.name, package.version)).collect(),
+            sizes,
+        })
+    }
+}
+
+/// Installed package which has a newer version in the repository
+#[derive(Clone, Debug)]
+pub struct OutdatedPackage {
+    pub name: String,
+    pub installed: String,
+    pub available: String,
+    /// Archive size of the new version
+    pub download: u64,
+    /// Change of size in the output, if size of installed files is known
+    pub size_delta: Option<i64>,
+}
+
+/// Result of comparing installed packages with the repository
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub packages: Vec<OutdatedPackage>,
+    /// Installed packages the repository no longer has
+    pub missing: Vec<String>,
+    /// Explicitly installed packages with outdated packages among themselves and their dependencies
+    pub affected: Vec<String>,
+}
+
+impl Report {
+    pub fn new(installed: &Installed, source: &dyn PackageSource) -> Self {
+        let mut packages = Vec::new();
+        let mut missing = Vec::new();
+        for (name, version) in installed.packages.iter() {
+            match source.package(name) {
+                Some(package) if compare_versions(&package.version, version) == Ordering::Greater => packages.push(OutdatedPackage {
+                    name: name.clone(),
+                    installed: version.clone(),
+                    available: package.version.clone(),
+                    download: package.compressed_size,
+                    size_delta: installed.sizes.get(name).map(|size| package.installed_size as i64 - *size as i64),
+                }),
+                Some(_) => {}
+                None => missing.push(name.clone()),
+            }
+        }
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        let affected = installed.explicit.iter()
+            .filter(|root| dependencies(source, root).iter().any(|name| packages.iter().any(|package| &package.name == name)))
+            .cloned()
+            .collect();
+        Report { packages, missing, affected }
+    }
+
+    pub fn render(&self) -> String {
+        let width = self.packages.iter().map(|package| package.name.len()).max().unwrap_or(0).max(7);
+        let installed_width = self.packages.iter().map(|package| package.installed.len()).max().unwrap_or(0).max(9);
+        let available_width = self.packages.iter().map(|package| package.available.len()).max().unwrap_or(0).max(9);
+        let mut text = format!("{:<width$}  {:<iw$}  {:<aw$}  {:>10}  {:>11}\n",
+                               "Package", "Installed", "Available", "Download", "Size change",
+                               width = width, iw = installed_width, aw = available_width);
+        for package in self.packages.iter() {
+            let delta = match package.size_delta {
+                Some(delta) if delta < 0 => format!("-{}", HumanBytes(-delta as u64)),
+                Some(delta) => format!("+{}", HumanBytes(delta as u64)),
+                None => String::from("-"),
+            };
+            text += &format!("{:<width$}  {:<iw$}  {:<aw$}  {:>10}  {:>11}\n",
+                             package.name, package.installed, package.available, HumanBytes(package.download).to_string(), delta,
+                             width = width, iw = installed_width, aw = available_width);
+        }
+        let download: u64 = self.packages.iter().map(|package| package.download).sum();
+        text += &format!("{} packages outdated, {} to download\n", self.packages.len(), HumanBytes(download));
+        text
+    }
+}
+
+/// Names of `root` and every package it depends on in the repository
+fn dependencies(source: &dyn PackageSource, root: &str) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    let mut queue = vec![root.to_owned()];
+    while let Some(name) = queue.pop() {
+        if !names.insert(name.clone()) {
+            continue;
+        }
+        if let Some(package) = source.package(&name) {
+            queue.extend(source.dependencies(package));
+        }
+    }
+    names
+}
+
+/// Compare package versions the way pacman does: epoch first, then version, then release if both
+/// versions have it
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a_epoch, a_version, a_release) = split_version(a);
+    let (b_epoch, b_version, b_release) = split_version(b);
+    a_epoch.cmp(&b_epoch)
+        .then_with(|| compare_segments(a_version, b_version))
+        .then_with(|| match (a_release, b_release) {
+            (Some(a), Some(b)) => compare_segments(a, b),
+            _ => Ordering::Equal,
+        })
+}
+
+/// Split `epoch:version-release`
+fn split_version(version: &str) -> (u64, &str, Option<&str>) {
+    let (epoch, rest) = match version.find(':') {
+        Some(index) if version[..index].bytes().all(|c| c.is_ascii_digit()) =>
+            (version[..index].parse().unwrap_or(0), &version[index + 1..]),
+        _ => (0, version),
+    };
+    match rest.rfind('-') {
+        Some(index) => (epoch, &rest[..index], Some(&rest[index + 1..])),
+        None => (epoch, rest, None),
+    }
+}
+
+/// `rpmvercmp` algorithm: versions are compared by alternating runs of digits and letters
+fn compare_segments(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let mut a = a.as_bytes();
+    let mut b = b.as_bytes();
+    while !a.is_empty() && !b.is_empty() {
+        let a_separators = a.iter().take_while(|c| !c.is_ascii_alphanumeric()).count();
+        let b_separators = b.iter().take_while(|c| !c.is_ascii_alphanumeric()).count();
+        a = &a[a_separators..];
+        b = &b[b_separators..];
+        if a.is_empty() || b.is_empty() {
+            break;
+        }
+        if a_separators != b_separators {
+            return a_separators.cmp(&b_separators);
+        }
+        let numeric = a[0].is_ascii_digit();
+        let segment = |s: &[u8]| if numeric {
+            s.iter().take_while(|c| c.is_ascii_digit()).count()
+        } else {
+            s.iter().take_while(|c| c.is_ascii_alphabetic()).count()
+        };
+        let (a_length, b_length) = (segment(a), segment(b));
+        if b_length == 0 {
+            // Numbers are newer than letters
+            return if numeric { Ordering::Greater } else { Ordering::Less };
+        }
+        let (a_segment, b_segment) = (&a[..a_length], &b[..b_length]);
+        let order = if numeric {
+            let a_segment = trim_zeros(a_segment);
+            let b_segment = trim_zeros(b_segment);
+            a_segment.len().cmp(&b_segment.len()).then_with(|| a_segment.cmp(b_segment))
+        } else {
+            a_segment.cmp(b_segment)
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+        a = &a[a_length..];
+        b = &b[b_length..];
+    }
+    match (a.first(), b.first()) {
+        (None, None) => Ordering::Equal,
+        // `1.0alpha` is older than `1.0`, `1.0.1` is newer
+        (None, Some(c)) if !c.is_ascii_alphabetic() => Ordering::Less,
+        (Some(c), _) if c.is_ascii_alphabetic() => Ordering::Less,
+        _ => Ordering::Greater,
+    }
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|c| **c == b'0').count();
+    &digits[zeros..]
+}
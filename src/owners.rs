@@ -0,0 +1,115 @@
+//! File ownership index stored in the output, so users of the toolchain can find out which package
+//! a file came from without the manifest tooling.
+//!
+//! The index is a text file. It starts with `wtb-owners 1`, followed by package lines
+//! `<package>\t<version>` and an empty line, then `<path>\t<package index>` lines sorted by path.
+use crate::manifest::Manifest;
+use crate::paths;
+use crate::progress::Progress;
+use std::collections::BTreeMap;
+use crate::error::Error;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Index location relative to the output root
+pub const OWNERS_PATH: &str = ".wtb/owners.db";
+
+const HEADER: &str = "wtb-owners 1";
+
+/// Options of `owns-local` command
+#[derive(Clone, Debug)]
+pub struct OwnsOptions {
+    /// Files to look up
+    pub paths: Vec<PathBuf>,
+}
+
+/// Package which provided a file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Owner {
+    pub package: String,
+    pub version: String,
+}
+
+/// Build ownership index of all manifest files
+pub fn encode(manifest: &Manifest) -> Vec<u8> {
+    let mut packages: BTreeMap<(&str, &str), usize> = manifest.files.iter()
+        .map(|entry| ((entry.package.as_str(), entry.version.as_str()), 0))
+        .collect();
+    let mut text = format!("{}\n", HEADER);
+    for (index, ((package, version), slot)) in packages.iter_mut().enumerate() {
+        *slot = index;
+        text += &format!("{}\t{}\n", package, version);
+    }
+    text += "\n";
+    let mut files: Vec<(&str, usize)> = manifest.files.iter()
+        .map(|entry| (entry.path.as_str(), packages[&(entry.package.as_str(), entry.version.as_str())]))
+        .collect();
+    files.sort();
+    for (path, index) in files {
+        text += &format!("{}\t{}\n", path, index);
+    }
+    text.into_bytes()
+}
+
+/// Find owner of `path`, relative to the output root. Paths are compared case-insensitively if there
+/// is no exact match, like Windows does
+pub fn lookup(index: &str, path: &str) -> Option<Owner> {
+    let mut lines = index.lines();
+    if lines.next() != Some(HEADER) {
+        return None;
+    }
+    let packages: Vec<Owner> = lines.by_ref()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '\t');
+            Some(Owner { package: parts.next()?.to_owned(), version: parts.next()?.to_owned() })
+        })
+        .collect();
+    let files: Vec<(&str, usize)> = lines
+        .filter_map(|line| {
+            let split = line.rfind('\t')?;
+            Some((&line[..split], line[split + 1..].parse().ok()?))
+        })
+        .collect();
+    files.iter()
+        .find(|(file, _)| *file == path)
+        .or_else(|| files.iter().find(|(file, _)| file.eq_ignore_ascii_case(path)))
+        .and_then(|(_, index)| packages.get(*index).cloned())
+}
+
+/// Print owners of files. Each path is looked up in the output which contains it, or in `output`
+pub async fn owns(output: &Path, options: &OwnsOptions, progress: &Progress) -> Result<(), Error> {
+    for path in options.paths.iter() {
+        let (root, relative) = locate(output, path).await?;
+        let index = tokio::fs::read_to_string(paths::join(&root, OWNERS_PATH)).await?;
+        match lookup(&index, &relative) {
+            Some(owner) => progress.report("owns", &format!("{} is owned by {} {}\n", relative, owner.package, owner.version)),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, format!("No package owns {}", relative)).into()),
+        }
+    }
+    Ok(())
+}
+
+/// Output root and `/`-separated path relative to it. Existing files are resolved against the closest
+/// ancestor with ownership index, anything else is taken relative to `output`
+async fn locate(output: &Path, path: &Path) -> io::Result<(PathBuf, String)> {
+    if let Ok(absolute) = paths::absolute(path).await {
+        for root in absolute.ancestors().skip(1) {
+            if tokio::fs::metadata(paths::join(root, OWNERS_PATH)).await.is_ok() {
+                let relative = absolute.strip_prefix(root).unwrap();
+                return Ok((root.to_owned(), relative_path(relative)));
+            }
+        }
+    }
+    Ok((output.to_owned(), relative_path(path)))
+}
+
+fn relative_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
@@ -0,0 +1,182 @@
+//! Packaging of the output folder into a distributable archive
+use crate::archive::{ArchiveFormat, ArchiveOptions, ArchiveSink};
+use crate::manifest::Manifest;
+use crate::paths;
+use crate::progress::{PackingProgress, Progress};
+use crate::error::Error;
+use std::io;
+use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Distributable archive format
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PackageFormat {
+    Zip,
+    TarZst,
+    /// Packed with external `7z` program
+    SevenZip,
+}
+
+impl FromStr for PackageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zip" => Ok(PackageFormat::Zip),
+            "tar.zst" => Ok(PackageFormat::TarZst),
+            "7z" => Ok(PackageFormat::SevenZip),
+            _ => Err(format!("Unknown package format: \"{}\"", s)),
+        }
+    }
+}
+
+/// Options of `package` command
+#[derive(Clone, Debug)]
+pub struct PackageOptions {
+    pub format: PackageFormat,
+    /// Archive to create
+    pub destination: PathBuf,
+    /// Top-level folder of the archive which holds all files. Files are put into the archive root if empty
+    pub root: String,
+    /// Compression level, 1-21 for tar.zst and 0-9 for 7z. Format default is used if not set
+    pub level: Option<u32>,
+    /// `7z` executable
+    pub seven_zip: String,
+    /// Create self-extracting executable from 7z archive
+    pub self_extract: bool,
+    /// 7-Zip SFX module used as the executable stub. Looked up next to `7z` if not set
+    pub sfx_module: Option<PathBuf>,
+    /// Make archive bit-identical between runs
+    pub reproducible: bool,
+}
+
+/// Pack all files listed in the manifest of `output` folder
+pub async fn package(output: &Path, options: &PackageOptions, progress: &Progress) -> Result<(), Error> {
+    let mut manifest = Manifest::load(output).await?;
+    manifest.files.sort_by(|a, b| a.path.cmp(&b.path));
+    let progress = progress.packing(&options.destination.to_string_lossy(), manifest.files.len());
+    match options.format {
+        PackageFormat::Zip => pack_archive(output, &manifest, ArchiveFormat::Zip, options, &progress).await?,
+        PackageFormat::TarZst => pack_archive(output, &manifest, ArchiveFormat::TarZst, options, &progress).await?,
+        PackageFormat::SevenZip if options.self_extract => pack_self_extracting(output, &manifest, options, &progress).await?,
+        PackageFormat::SevenZip => pack_7z(output, &manifest, options, &options.destination, &progress).await?,
+    }
+    progress.complete();
+    Ok(())
+}
+
+async fn pack_archive(output: &Path, manifest: &Manifest, format: ArchiveFormat, options: &PackageOptions,
+                      progress: &PackingProgress) -> Result<(), Error> {
+    let archive_options = ArchiveOptions { level: options.level, reproducible: options.reproducible };
+    let sink = ArchiveSink::new(&options.destination, format, archive_options).await?;
+    for entry in manifest.files.iter() {
+        let data = match tokio::fs::read(paths::join(output, &entry.path)).await {
+            Ok(data) => data,
+            Err(e) => {
+                sink.discard().await?;
+                return Err(e.into());
+            }
+        };
+        sink.write(&archive_path(options, &entry.path), data).await?;
+        progress.file(&entry.path);
+    }
+    sink.commit().await?;
+    Ok(())
+}
+
+/// Pack 7z archive and prepend SFX module to it
+async fn pack_self_extracting(output: &Path, manifest: &Manifest, options: &PackageOptions,
+                              progress: &PackingProgress) -> Result<(), Error> {
+    let mut executable = match options.sfx_module.clone().or_else(|| find_sfx_module(&options.seven_zip)) {
+        Some(module) => tokio::fs::read(module).await?,
+        None => return Err(io::Error::new(io::ErrorKind::NotFound, "7-Zip SFX module not found, set it with --sfx-module").into()),
+    };
+    let folder = tempfile::tempdir()?;
+    let archive = folder.path().join("archive.7z");
+    pack_7z(output, manifest, options, &archive, progress).await?;
+    executable.extend(tokio::fs::read(&archive).await?);
+    let part = part_path(&options.destination);
+    tokio::fs::write(&part, executable).await?;
+    tokio::fs::rename(&part, &options.destination).await?;
+    Ok(())
+}
+
+/// Copy files into a temporary folder and pack it with `7z`, which can't take file list with
+/// renamed paths
+async fn pack_7z(output: &Path, manifest: &Manifest, options: &PackageOptions, destination: &Path,
+                 progress: &PackingProgress) -> Result<(), Error> {
+    let staging = tempfile::tempdir()?;
+    for entry in manifest.files.iter() {
+        let target = paths::join(staging.path(), &archive_path(options, &entry.path));
+        tokio::fs::create_dir_all(target.parent().unwrap()).await?;
+        tokio::fs::copy(paths::join(output, &entry.path), &target).await?;
+        progress.file(&entry.path);
+    }
+
+    let part = part_path(destination);
+    if tokio::fs::metadata(&part).await.is_ok() {
+        tokio::fs::remove_file(&part).await?;
+    }
+    let mut command = Command::new(&options.seven_zip);
+    command.current_dir(staging.path())
+        .arg("a")
+        .arg("-t7z")
+        .arg("-bd")
+        .arg("-y");
+    if let Some(level) = options.level {
+        command.arg(format!("-mx={}", level));
+    }
+    if options.reproducible {
+        // Don't store timestamps of copied files, LZMA2 output depends on thread count
+        command.args(&["-mtm=off", "-mtc=off", "-mta=off", "-mmt=1"]);
+    }
+    command.arg(std::env::current_dir()?.join(&part));
+    if options.root.is_empty() {
+        command.arg(".");
+    } else {
+        command.arg(&options.root);
+    }
+    let status = tokio::task::spawn_blocking(move || command.status()).await??;
+    if !status.success() {
+        let _ = tokio::fs::remove_file(&part).await;
+        return Err(io::Error::new(io::ErrorKind::Other, format!("{} exited with {}", &options.seven_zip, status)).into());
+    }
+    tokio::fs::rename(&part, destination).await?;
+    Ok(())
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Find `7z.sfx` next to `7z` executable, which is searched in `PATH` if it is a bare name
+fn find_sfx_module(seven_zip: &str) -> Option<PathBuf> {
+    let program = Path::new(seven_zip);
+    let mut folders: Vec<PathBuf> = if program.components().count() > 1 {
+        program.parent().map(Path::to_path_buf).into_iter().collect()
+    } else {
+        std::env::var_os("PATH")
+            .map(|path| std::env::split_paths(&path).collect::<Vec<PathBuf>>())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|folder: &PathBuf| folder.join(seven_zip).exists() || folder.join(format!("{}.exe", seven_zip)).exists())
+            .collect()
+    };
+    // p7zip keeps modules out of PATH
+    folders.push(PathBuf::from("/usr/lib/p7zip"));
+    folders.into_iter()
+        .map(|folder| folder.join("7z.sfx"))
+        .find(|module| module.exists())
+}
+
+/// Path of output file inside the archive
+fn archive_path(options: &PackageOptions, path: &str) -> String {
+    if options.root.is_empty() {
+        path.to_owned()
+    } else {
+        format!("{}/{}", &options.root, path)
+    }
+}
@@ -0,0 +1,195 @@
+//! Long output path detection and shortening
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::io;
+use crate::staging::TOOL_FOLDER;
+
+#[cfg(windows)]
+const VERBATIM_PREFIX: &str = r"\\?\";
+
+/// Output path prefix replacement, parsed from `FROM=TO`
+#[derive(Clone, Debug)]
+pub struct PathRemap {
+    pub from: String,
+    pub to: String,
+}
+
+impl FromStr for PathRemap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(from), Some(to)) if !from.is_empty() => Ok(PathRemap {
+                from: from.trim_end_matches('/').to_owned(),
+                to: to.trim_end_matches('/').to_owned(),
+            }),
+            _ => Err(format!("Path remap must look like FROM=TO, got \"{}\"", s)),
+        }
+    }
+}
+
+impl PathRemap {
+    /// Replace remap prefix in `path`. Prefix must match whole path components
+    pub fn apply(&self, path: &str) -> Option<String> {
+        if path == self.from {
+            return Some(self.to.clone());
+        }
+        let rest = path.strip_prefix(&self.from)?.strip_prefix('/')?;
+        if self.to.is_empty() {
+            Some(rest.to_owned())
+        } else {
+            Some(format!("{}/{}", &self.to, rest))
+        }
+    }
+}
+
+/// Apply first matching remap to `path`
+pub fn remap(path: String, remaps: &[PathRemap]) -> String {
+    remaps.iter()
+        .find_map(|remap| remap.apply(&path))
+        .unwrap_or(path)
+}
+
+/// Check output path chosen by a filter script or plugin. It must be relative and stay inside the
+/// output folder. Returns it with `/` separators and without empty and `.` components
+pub fn check_relative(path: &str) -> Result<String, String> {
+    let path = path.replace('\\', "/");
+    if path.starts_with('/') {
+        return Err(String::from("path is absolute"));
+    }
+    let components: Vec<&str> = path.split('/').filter(|component| !component.is_empty() && *component != ".").collect();
+    match components.first() {
+        None => return Err(String::from("path is empty")),
+        Some(first) if first.contains(':') => return Err(String::from("path has a drive prefix")),
+        Some(_) => {}
+    }
+    if components.contains(&"..") {
+        return Err(String::from("path leaves the output folder"));
+    }
+    Ok(components.join("/"))
+}
+
+/// Make `path` absolute. On Windows the result is a `\\?\` prefixed path, which isn't limited by `MAX_PATH`
+pub async fn absolute(path: &Path) -> io::Result<PathBuf> {
+    let path = tokio::fs::canonicalize(path).await?;
+    #[cfg(windows)]
+    {
+        if !path.to_string_lossy().starts_with(VERBATIM_PREFIX) {
+            return Ok(PathBuf::from(format!("{}{}", VERBATIM_PREFIX, path.display())));
+        }
+    }
+    Ok(path)
+}
+
+/// Join archive path to `base`. Verbatim paths don't accept `/` as a separator, so it is replaced on Windows
+pub fn join(base: &Path, relative: &str) -> PathBuf {
+    #[cfg(windows)]
+    {
+        base.join(relative.replace('/', "\\"))
+    }
+    #[cfg(not(windows))]
+    {
+        base.join(relative)
+    }
+}
+
+/// `/`-separated form of absolute `path` without verbatim prefix
+pub fn display(path: &Path) -> String {
+    #[cfg(windows)]
+    {
+        path.to_string_lossy().trim_start_matches(VERBATIM_PREFIX).replace('\\', "/")
+    }
+    #[cfg(not(windows))]
+    {
+        path.to_string_lossy().into_owned()
+    }
+}
+
+/// Length of `relative` joined to `base` as the user will see it
+pub fn display_len(base: &Path, relative: &str) -> usize {
+    if base.as_os_str().is_empty() {
+        return relative.chars().count();
+    }
+    let base = base.to_string_lossy();
+    #[cfg(windows)]
+    let base = base.trim_start_matches(VERBATIM_PREFIX).to_owned();
+    base.trim_end_matches(|c| c == '/' || c == '\\').chars().count() + 1 + relative.chars().count()
+}
+
+/// `/`-separated paths of all files in `root` except tool data
+pub fn list_files(root: &Path) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut folders = vec![(root.to_owned(), String::new())];
+    while let Some((folder, prefix)) = folders.pop() {
+        for entry in std::fs::read_dir(&folder)? {
+            let entry = entry?;
+            let path = prefix.clone() + &entry.file_name().to_string_lossy();
+            if entry.file_type()?.is_dir() {
+                if path != TOOL_FOLDER {
+                    folders.push((entry.path(), path + "/"));
+                }
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_remap() {
+        let remap = PathRemap::from_str("share/doc/=doc/").unwrap();
+        assert_eq!((remap.from.as_str(), remap.to.as_str()), ("share/doc", "doc"));
+        assert!(PathRemap::from_str("=doc").is_err());
+        assert!(PathRemap::from_str("share").is_err());
+    }
+
+    #[test]
+    fn remap_matches_whole_components() {
+        let remap = PathRemap::from_str("share/doc=doc").unwrap();
+        assert_eq!(remap.apply("share/doc").as_deref(), Some("doc"));
+        assert_eq!(remap.apply("share/doc/gcc/README").as_deref(), Some("doc/gcc/README"));
+        assert_eq!(remap.apply("share/docs/README"), None);
+        assert_eq!(remap.apply("usr/share/doc/README"), None);
+    }
+
+    #[test]
+    fn remap_to_root() {
+        let remap = PathRemap::from_str("mingw64=").unwrap();
+        assert_eq!(remap.apply("mingw64/bin/gcc.exe").as_deref(), Some("bin/gcc.exe"));
+    }
+
+    #[test]
+    fn first_matching_remap_wins() {
+        let remaps = vec![
+            PathRemap::from_str("mingw64/bin=tools").unwrap(),
+            PathRemap::from_str("mingw64=").unwrap(),
+        ];
+        assert_eq!(remap("mingw64/bin/gcc.exe".to_owned(), &remaps), "tools/gcc.exe");
+        assert_eq!(remap("mingw64/lib/libc.a".to_owned(), &remaps), "lib/libc.a");
+        assert_eq!(remap("etc/profile".to_owned(), &remaps), "etc/profile");
+    }
+
+    #[test]
+    fn checks_relative_paths() {
+        assert_eq!(check_relative("bin/./gcc.exe"), Ok("bin/gcc.exe".to_owned()));
+        assert_eq!(check_relative("bin\\\\gcc.exe"), Ok("bin/gcc.exe".to_owned()));
+        assert!(check_relative("/etc/passwd").is_err());
+        assert!(check_relative("\\Windows\\system32").is_err());
+        assert!(check_relative("C:/Windows").is_err());
+        assert!(check_relative("bin/../../outside").is_err());
+        assert!(check_relative("./").is_err());
+    }
+
+    #[test]
+    fn joins_relative_path() {
+        let joined = join(Path::new("out"), "bin/gcc.exe");
+        assert_eq!(joined.components().count(), 3);
+        assert!(joined.ends_with("gcc.exe"));
+    }
+}
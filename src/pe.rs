@@ -0,0 +1,298 @@
+//! PE image import and export tables
+use crate::classify::{read_u16, read_u32};
+use std::convert::TryInto;
+
+const SECTION_HEADER_SIZE: usize = 40;
+const IMPORT_DESCRIPTOR_SIZE: usize = 20;
+const DELAY_IMPORT_DESCRIPTOR_SIZE: usize = 32;
+const EXPORT_DIRECTORY: usize = 0;
+const IMPORT_DIRECTORY: usize = 1;
+const DELAY_IMPORT_DIRECTORY: usize = 13;
+const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+
+struct Section {
+    virtual_address: u32,
+    virtual_size: u32,
+    raw_size: u32,
+    raw_pointer: u32,
+    characteristics: u32,
+}
+
+/// Symbol exported by name
+#[derive(Clone, Debug)]
+pub struct Export {
+    pub name: String,
+    pub ordinal: u16,
+    /// Symbol refers to data instead of code
+    pub data: bool,
+}
+
+/// Parsed PE headers
+pub struct PeImage<'a> {
+    data: &'a [u8],
+    /// COFF machine type
+    pub machine: u16,
+    directories: usize,
+    directory_count: usize,
+    image_base: u64,
+    sections: Vec<Section>,
+}
+
+impl<'a> PeImage<'a> {
+    /// Parse headers of PE image. Returns `None` if `data` isn't a PE image
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        if !data.starts_with(b"MZ") {
+            return None;
+        }
+        let pe = read_u32(data, 0x3c)? as usize;
+        if data.get(pe..pe.checked_add(4)?)? != b"PE\0\0" {
+            return None;
+        }
+        let coff = pe + 4;
+        let machine = read_u16(data, coff)?;
+        let section_count = read_u16(data, coff + 2)? as usize;
+        let optional_size = read_u16(data, coff + 16)? as usize;
+        let optional = coff + 20;
+        let (directories, image_base) = match read_u16(data, optional)? {
+            0x10b => (optional + 96, read_u32(data, optional + 28)? as u64),
+            0x20b => (optional + 112, u64::from_le_bytes(data.get(optional + 24..optional + 32)?.try_into().ok()?)),
+            _ => return None,
+        };
+        let directory_count = read_u32(data, directories - 4)? as usize;
+        let sections = (0..section_count)
+            .map(|index| {
+                let header = optional + optional_size + index * SECTION_HEADER_SIZE;
+                Some(Section {
+                    virtual_size: read_u32(data, header + 8)?,
+                    virtual_address: read_u32(data, header + 12)?,
+                    raw_size: read_u32(data, header + 16)?,
+                    raw_pointer: read_u32(data, header + 20)?,
+                    characteristics: read_u32(data, header + 36)?,
+                })
+            })
+            .collect::<Option<Vec<Section>>>()?;
+        Some(PeImage { data, machine, directories, directory_count, image_base, sections })
+    }
+
+    /// RVA and size of data directory `index`, if present
+    pub(crate) fn directory(&self, index: usize) -> Option<(u32, u32)> {
+        if index >= self.directory_count {
+            return None;
+        }
+        let rva = read_u32(self.data, self.directories + index * 8)?;
+        let size = read_u32(self.data, self.directories + index * 8 + 4)?;
+        if rva == 0 { None } else { Some((rva, size)) }
+    }
+
+    /// File offset of `rva`
+    pub(crate) fn offset(&self, rva: u32) -> Option<usize> {
+        let section = self.section(rva)?;
+        let delta = rva - section.virtual_address;
+        if delta >= section.raw_size {
+            return None;
+        }
+        (section.raw_pointer as usize).checked_add(delta as usize)
+    }
+
+    fn section(&self, rva: u32) -> Option<&Section> {
+        self.sections.iter().find(|section| {
+            let size = section.virtual_size.max(section.raw_size);
+            rva >= section.virtual_address && rva - section.virtual_address < size
+        })
+    }
+
+    pub(crate) fn read_u32(&self, rva: u32) -> Option<u32> {
+        read_u32(self.data, self.offset(rva)?)
+    }
+
+    pub(crate) fn read_u16(&self, rva: u32) -> Option<u16> {
+        read_u16(self.data, self.offset(rva)?)
+    }
+
+    /// NUL-terminated string at `rva`
+    pub(crate) fn string(&self, rva: u32) -> Option<String> {
+        let data = self.data.get(self.offset(rva)?..)?;
+        let end = data.iter().position(|&b| b == 0)?;
+        Some(String::from_utf8_lossy(&data[..end]).into_owned())
+    }
+
+    /// Names of DLLs imported by the image, including delay-loaded ones
+    pub fn imports(&self) -> Vec<String> {
+        let mut imports = Vec::new();
+        if let Some((rva, _)) = self.directory(IMPORT_DIRECTORY) {
+            let mut descriptor = Some(rva);
+            while let Some(name) = descriptor.and_then(|descriptor| self.read_u32(descriptor.checked_add(12)?)) {
+                if name == 0 {
+                    break;
+                }
+                imports.extend(self.string(name));
+                descriptor = descriptor.and_then(|descriptor| descriptor.checked_add(IMPORT_DESCRIPTOR_SIZE as u32));
+            }
+        }
+        if let Some((rva, _)) = self.directory(DELAY_IMPORT_DIRECTORY) {
+            let mut descriptor = Some(rva);
+            while let Some((attributes, name)) = descriptor.and_then(|descriptor| {
+                Some((self.read_u32(descriptor)?, self.read_u32(descriptor.checked_add(4)?)?))
+            }) {
+                if name == 0 {
+                    break;
+                }
+                // Old descriptors use virtual addresses instead of RVAs
+                let name = if attributes & 1 == 0 {
+                    (name as u64).checked_sub(self.image_base).map(|name| name as u32)
+                } else {
+                    Some(name)
+                };
+                imports.extend(name.and_then(|name| self.string(name)));
+                descriptor = descriptor.and_then(|descriptor| descriptor.checked_add(DELAY_IMPORT_DESCRIPTOR_SIZE as u32));
+            }
+        }
+        imports
+    }
+
+    /// Name of the DLL from export directory and symbols exported by name. Symbols outside of
+    /// executable sections are treated as data, forwarded exports as code. Returns `None` if the
+    /// directory points outside of the image
+    pub fn exports(&self) -> Option<(String, Vec<Export>)> {
+        let (directory, size) = self.directory(EXPORT_DIRECTORY)?;
+        let field = |offset: u32| self.read_u32(directory.checked_add(offset)?);
+        let name = self.string(field(12)?)?;
+        let ordinal_base = field(16)?;
+        let address_count = field(20)?;
+        let name_count = field(24)?;
+        let addresses = field(28)?;
+        let names = field(32)?;
+        let ordinals = field(36)?;
+        // Count comes from the file, so it doesn't size the allocation
+        let mut exports = Vec::new();
+        for index in 0..name_count {
+            let symbol = self.string(self.read_u32(element(names, index, 4)?)?)?;
+            let address_index = self.read_u16(element(ordinals, index, 2)?)? as u32;
+            if address_index >= address_count {
+                continue;
+            }
+            let address = self.read_u32(element(addresses, address_index, 4)?)?;
+            let forwarded = address >= directory && address - directory < size;
+            let data = !forwarded && self.section(address)
+                .map_or(false, |section| section.characteristics & IMAGE_SCN_MEM_EXECUTE == 0);
+            exports.push(Export { name: symbol, ordinal: ordinal_base.wrapping_add(address_index) as u16, data });
+        }
+        Some((name, exports))
+    }
+}
+
+/// RVA of element `index` of array at `array` with elements of `size` bytes, `None` on overflow
+fn element(array: u32, index: u32, size: u32) -> Option<u32> {
+    index.checked_mul(size)?.checked_add(array)
+}
+
+/// Names of DLLs imported by PE image `data`. Empty for anything else
+pub fn imports(data: &[u8]) -> Vec<String> {
+    PeImage::parse(data).map(|image| image.imports()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PE: usize = 0x40;
+    const OPTIONAL: usize = PE + 24;
+    const OPTIONAL_SIZE: usize = 240;
+    const SECTIONS: usize = OPTIONAL + OPTIONAL_SIZE;
+    /// Single executable section mapped from file offset 0x200 to RVA 0x1000
+    const RAW: usize = 0x200;
+    const RVA: u32 = 0x1000;
+
+    fn put_u16(data: &mut [u8], offset: usize, value: u16) {
+        data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_str(data: &mut [u8], rva: u32, value: &str) {
+        let offset = RAW + (rva - RVA) as usize;
+        data[offset..offset + value.len()].copy_from_slice(value.as_bytes());
+    }
+
+    /// PE32+ image with export directory at `exports` and import directory at `imports`
+    fn image(exports: u32, imports: u32) -> Vec<u8> {
+        let mut data = vec![0u8; 0x400];
+        data[0..2].copy_from_slice(b"MZ");
+        put_u32(&mut data, 0x3c, PE as u32);
+        data[PE..PE + 4].copy_from_slice(b"PE\0\0");
+        put_u16(&mut data, PE + 4, 0x8664);
+        put_u16(&mut data, PE + 6, 1);
+        put_u16(&mut data, PE + 20, OPTIONAL_SIZE as u16);
+        put_u16(&mut data, OPTIONAL, 0x20b);
+        put_u32(&mut data, OPTIONAL + 108, 16);
+        put_u32(&mut data, OPTIONAL + 112 + EXPORT_DIRECTORY * 8, exports);
+        put_u32(&mut data, OPTIONAL + 112 + EXPORT_DIRECTORY * 8 + 4, 0x40);
+        put_u32(&mut data, OPTIONAL + 112 + IMPORT_DIRECTORY * 8, imports);
+        put_u32(&mut data, SECTIONS + 8, 0x200);
+        put_u32(&mut data, SECTIONS + 12, RVA);
+        put_u32(&mut data, SECTIONS + 16, 0x200);
+        put_u32(&mut data, SECTIONS + 20, RAW as u32);
+        put_u32(&mut data, SECTIONS + 36, IMAGE_SCN_MEM_EXECUTE);
+        data
+    }
+
+    #[test]
+    fn reads_exports_and_imports() {
+        let mut data = image(RVA, RVA + 0xa0);
+        let directory = RAW;
+        put_u32(&mut data, directory + 12, RVA + 0x80);
+        put_u32(&mut data, directory + 16, 1);
+        put_u32(&mut data, directory + 20, 1);
+        put_u32(&mut data, directory + 24, 1);
+        put_u32(&mut data, directory + 28, RVA + 0x40);
+        put_u32(&mut data, directory + 32, RVA + 0x44);
+        put_u32(&mut data, directory + 36, RVA + 0x48);
+        put_u32(&mut data, RAW + 0x40, RVA + 0x100);
+        put_u32(&mut data, RAW + 0x44, RVA + 0x90);
+        put_str(&mut data, RVA + 0x80, "test.dll");
+        put_str(&mut data, RVA + 0x90, "foo");
+        put_u32(&mut data, RAW + 0xa0 + 12, RVA + 0xe0);
+        put_str(&mut data, RVA + 0xe0, "KERNEL32.dll");
+
+        let image = PeImage::parse(&data).unwrap();
+        let (name, exports) = image.exports().unwrap();
+        assert_eq!(name, "test.dll");
+        assert_eq!(exports.len(), 1);
+        assert_eq!((exports[0].name.as_str(), exports[0].ordinal, exports[0].data), ("foo", 1, false));
+        assert_eq!(image.imports(), vec!["KERNEL32.dll"]);
+    }
+
+    #[test]
+    fn directories_at_end_of_address_space() {
+        let data = image(u32::MAX - 4, u32::MAX - 4);
+        let image = PeImage::parse(&data).unwrap();
+        assert!(image.exports().is_none());
+        assert!(image.imports().is_empty());
+    }
+
+    #[test]
+    fn export_arrays_past_end_of_address_space() {
+        let mut data = image(RVA, 0);
+        put_u32(&mut data, RAW + 12, RVA + 0x80);
+        put_u32(&mut data, RAW + 20, u32::MAX);
+        put_u32(&mut data, RAW + 24, 4);
+        put_u32(&mut data, RAW + 28, u32::MAX - 2);
+        put_u32(&mut data, RAW + 32, RVA + 0x44);
+        put_u32(&mut data, RAW + 36, RVA + 0x48);
+        put_u32(&mut data, RAW + 0x44, RVA + 0x90);
+        put_u16(&mut data, RAW + 0x48, u16::MAX);
+        put_str(&mut data, RVA + 0x80, "test.dll");
+        put_str(&mut data, RVA + 0x90, "foo");
+        assert!(PeImage::parse(&data).unwrap().exports().is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        let mut data = image(0, 0);
+        put_u32(&mut data, 0x3c, u32::MAX);
+        assert!(PeImage::parse(&data).is_none());
+        assert!(imports(&data[..0x100]).is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+//! Choice of the root package when the given name doesn't match a package exactly
+use crate::source::PackageSource;
+use archlinux_repo::Package;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+/// Candidates shown by the picker
+const MAX_CANDIDATES: usize = 20;
+
+/// What to do when the name matches several packages
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PackageChoice {
+    /// Ask in the terminal, fail if it isn't interactive
+    Ask,
+    /// Take the best match
+    First,
+    /// Only exact name is accepted
+    Exact,
+}
+
+impl Default for PackageChoice {
+    fn default() -> Self {
+        PackageChoice::Ask
+    }
+}
+
+/// How well a package name matches the query, lower is better
+fn rank(name: &str, query: &str) -> Option<u8> {
+    if name.starts_with(query) {
+        Some(0)
+    } else if name.contains(query) {
+        Some(1)
+    } else {
+        // Letters of the query in order, like `mgcc` for `mingw-w64-x86_64-gcc`
+        let mut letters = name.chars();
+        if query.chars().all(|c| letters.any(|letter| letter == c)) {
+            Some(2)
+        } else {
+            None
+        }
+    }
+}
+
+/// Packages which name starts with, contains or fuzzy matches `query`, best matches first
+pub fn candidates<'a>(source: &'a dyn PackageSource, query: &str) -> Vec<&'a Package> {
+    let query = query.to_lowercase();
+    let mut matches: Vec<(u8, &Package)> = source.packages()
+        .filter_map(|package| rank(&package.name.to_lowercase(), &query).map(|rank| (rank, package)))
+        .collect();
+    matches.sort_by(|(a_rank, a), (b_rank, b)| a_rank.cmp(b_rank)
+        .then(a.name.len().cmp(&b.name.len()))
+        .then(a.name.cmp(&b.name)));
+    matches.into_iter().map(|(_, package)| package).collect()
+}
+
+/// Ask which of `candidates` to use. Returns `None` if nothing is chosen
+pub fn ask<'a>(query: &str, candidates: &[&'a Package]) -> io::Result<Option<&'a Package>> {
+    let shown = &candidates[..candidates.len().min(MAX_CANDIDATES)];
+    let mut stderr = io::stderr();
+    writeln!(stderr, "Package {} not found, did you mean:", query)?;
+    for (i, package) in shown.iter().enumerate() {
+        writeln!(stderr, "  {:>2}) {} {}", i + 1, &package.name, &package.version)?;
+    }
+    if candidates.len() > shown.len() {
+        writeln!(stderr, "  ... and {} more", candidates.len() - shown.len())?;
+    }
+    loop {
+        write!(stderr, "Choose package [1-{}], empty to cancel: ", shown.len())?;
+        stderr.flush()?;
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 || line.trim().is_empty() {
+            return Ok(None);
+        }
+        match usize::from_str(line.trim()) {
+            Ok(i) if i >= 1 && i <= shown.len() => return Ok(Some(shown[i - 1])),
+            _ => writeln!(stderr, "Enter number from 1 to {}", shown.len())?,
+        }
+    }
+}
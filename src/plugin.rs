@@ -0,0 +1,206 @@
+//! External plugins which take part in a run through JSON messages over stdin and stdout.
+//!
+//! Plugin is started once per run. Every request is a JSON object on a single line of its stdin, and
+//! the plugin answers every request with a JSON object on a single line of its stdout. Requests:
+//!
+//! - `{"event": "hello", "protocol": 1}` is sent first. Reply lists events the plugin wants to
+//!   receive, other events are not sent: `{"events": ["resolved", "file", "finished"]}`
+//! - `{"event": "resolved", "packages": [{"name": "...", "version": "..."}]}` is sent when the package
+//!   tree is resolved. Reply can drop packages from it: `{"drop": ["..."]}`
+//! - `{"event": "file", "package": "...", "path": "...", "size": 0, "binary": false}` is sent for
+//!   every file which passed other filters, with its output path. Reply is `{"action": "keep"}`,
+//!   `{"action": "drop"}` or `{"action": "move", "path": "..."}`
+//! - `{"event": "finished", "output": "..."}` is sent when all packages are written into the output
+//!   folder, before it is committed. Files the plugin creates or changes there are added to the manifest
+//!
+//! Any reply can also have `messages` and `warnings` lists, which are shown to the user. Plugin which
+//! doesn't reply within `--plugin-timeout` is killed and the run fails. Stdin is closed when the run ends
+use crate::error::{Error, Result};
+use crate::progress::Progress;
+use crate::script::ScriptDecision;
+use crate::tool::CommandTemplate;
+use archlinux_repo::Package;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Version of the protocol sent in `hello`
+const PROTOCOL: u32 = 1;
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Request<'a> {
+    Hello { protocol: u32 },
+    Resolved { packages: Vec<PackageRef<'a>> },
+    File { package: &'a str, path: &'a str, size: u64, binary: bool },
+    Finished { output: &'a Path },
+}
+
+#[derive(Serialize)]
+struct PackageRef<'a> {
+    name: &'a str,
+    version: &'a str,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FileAction {
+    Keep,
+    Drop,
+    Move,
+}
+
+impl Default for FileAction {
+    fn default() -> Self {
+        FileAction::Keep
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Reply {
+    events: Vec<String>,
+    drop: Vec<String>,
+    action: FileAction,
+    path: Option<String>,
+    messages: Vec<String>,
+    warnings: Vec<String>,
+}
+
+/// Pipes of the running plugin
+struct Connection {
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Connection {
+    fn request(&mut self, line: &str) -> io::Result<Reply> {
+        let stdin = self.stdin.as_mut().ok_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        stdin.write_all(line.as_bytes())?;
+        stdin.write_all(b"\n")?;
+        stdin.flush()?;
+        let mut reply = String::new();
+        if self.stdout.read_line(&mut reply)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "plugin exited without a reply"));
+        }
+        serde_json::from_str(&reply).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("invalid reply: {}", e)))
+    }
+}
+
+/// Plugin process. It is kept apart from its pipes, so it can be killed while a request waits for
+/// the reply
+struct Process(Mutex<Child>);
+
+impl Process {
+    fn kill(&self) {
+        let mut child = self.0.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        // Every request is answered, so the plugin has nothing left to do
+        self.kill();
+    }
+}
+
+/// Running plugin of `--plugin`
+pub struct Plugin {
+    program: String,
+    // Pipes are dropped first, which closes stdin before the process is killed
+    connection: Arc<Mutex<Connection>>,
+    process: Process,
+    timeout: Duration,
+    /// Events the plugin asked for in reply to `hello`
+    events: HashSet<String>,
+}
+
+impl Plugin {
+    /// Start plugin `command` and ask which events it wants. Plugin has `timeout` to reply to every request
+    pub async fn start(command: &CommandTemplate, timeout: Duration, progress: &Progress) -> Result<Self> {
+        let mut child = command.command(&[])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let connection = Connection {
+            stdin: child.stdin.take(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+        };
+        let mut plugin = Plugin {
+            program: command.program().to_owned(),
+            connection: Arc::new(Mutex::new(connection)),
+            process: Process(Mutex::new(child)),
+            timeout,
+            events: HashSet::new(),
+        };
+        let reply = plugin.request(&Request::Hello { protocol: PROTOCOL }, progress).await?;
+        plugin.events = reply.events.into_iter().collect();
+        tracing::debug!(plugin = %plugin.program, events = ?plugin.events, "plugin started");
+        Ok(plugin)
+    }
+
+    fn wants(&self, event: &str) -> bool {
+        self.events.contains(event)
+    }
+
+    async fn request(&self, request: &Request<'_>, progress: &Progress) -> Result<Reply> {
+        let line = serde_json::to_string(request).unwrap();
+        let connection = self.connection.clone();
+        let request = tokio::task::spawn_blocking(move || connection.lock().unwrap().request(&line));
+        let reply = match tokio::time::timeout(self.timeout, request).await {
+            Ok(reply) => reply?,
+            Err(_) => {
+                // Closing the pipes wakes up the blocked request
+                self.process.kill();
+                let message = format!("no reply in {} seconds", self.timeout.as_secs());
+                return Err(Error::Plugin { program: self.program.clone(), message });
+            }
+        };
+        let reply = reply.map_err(|e| Error::Plugin { program: self.program.clone(), message: e.to_string() })?;
+        for message in reply.messages.iter() {
+            progress.info(&format!("[{}] {}", &self.program, message));
+        }
+        for warning in reply.warnings.iter() {
+            progress.warn(&format!("[{}] {}", &self.program, warning));
+        }
+        Ok(reply)
+    }
+
+    /// Names of `tree` packages the plugin drops
+    pub async fn resolved(&self, tree: &[Package], progress: &Progress) -> Result<Vec<String>> {
+        if !self.wants("resolved") {
+            return Ok(Vec::new());
+        }
+        let packages = tree.iter().map(|package| PackageRef { name: &package.name, version: &package.version }).collect();
+        Ok(self.request(&Request::Resolved { packages }, progress).await?.drop)
+    }
+
+    /// What to do with file of `package` written to output `path`
+    pub async fn file(&self, package: &str, path: &str, size: u64, binary: bool, progress: &Progress) -> Result<ScriptDecision> {
+        if !self.wants("file") {
+            return Ok(ScriptDecision::Keep);
+        }
+        let reply = self.request(&Request::File { package, path, size, binary }, progress).await?;
+        match (reply.action, reply.path) {
+            (FileAction::Keep, _) => Ok(ScriptDecision::Keep),
+            (FileAction::Drop, _) => Ok(ScriptDecision::Drop),
+            (FileAction::Move, Some(path)) if !path.is_empty() => Ok(ScriptDecision::Remap(path)),
+            (FileAction::Move, _) => Err(Error::Plugin { program: self.program.clone(), message: format!("move of {} has no path", path) }),
+        }
+    }
+
+    /// Let the plugin process the output folder before it is committed. Returns whether it was asked to
+    pub async fn finished(&self, output: &Path, progress: &Progress) -> Result<bool> {
+        if !self.wants("finished") {
+            return Ok(false);
+        }
+        self.request(&Request::Finished { output }, progress).await?;
+        Ok(true)
+    }
+}
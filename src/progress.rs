@@ -1,93 +1,748 @@
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::sync::Arc;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use tokio::time::Duration;
 use archlinux_repo::Package;
+use crate::dashboard::Dashboard;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::str::FromStr;
+use std::io::{self, Write};
 
+/// How progress is reported
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProgressMode {
+    /// Animated progress bars
+    Bars,
+    /// Timestamped line per finished step, without progress bars
+    Plain,
+    /// Only warnings
+    Quiet,
+    /// Newline-delimited JSON events on stdout
+    Json,
+    /// Messages are `tracing` events, printed by the subscriber set up with the log level
+    Log,
+    /// Plain lines with collapsible groups per phase and annotations for warnings and errors of
+    /// GitHub Actions
+    Github,
+    /// Interactive dashboard, see `dashboard` module
+    Tui,
+}
+
+impl FromStr for ProgressMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ProgressMode::detect()),
+            "bars" => Ok(ProgressMode::Bars),
+            "plain" => Ok(ProgressMode::Plain),
+            "quiet" => Ok(ProgressMode::Quiet),
+            "json" => Ok(ProgressMode::Json),
+            "github" => Ok(ProgressMode::Github),
+            _ => Err(format!("Unknown progress format: \"{}\"", s)),
+        }
+    }
+}
+
+impl ProgressMode {
+    /// GitHub Actions output when running there, otherwise progress bars if stderr is a terminal and
+    /// plain lines if it is redirected to a file or a pipe
+    pub fn detect() -> Self {
+        if std::env::var_os("GITHUB_ACTIONS").map_or(false, |value| value == "true") {
+            ProgressMode::Github
+        } else if ProgressDrawTarget::stderr().is_hidden() {
+            ProgressMode::Plain
+        } else {
+            ProgressMode::Bars
+        }
+    }
+}
+
+/// Receiver of progress events. Methods do nothing by default, so a reporter implements only events
+/// it needs. Dashboard of Tui mode is one, embedders can pass their own to `Progress::with_reporter`
+pub trait ProgressReporter: Send + Sync {
+    /// Message shown to the user
+    fn message(&self, _msg: &str, _warning: bool) {}
+
+    /// Step of repository database loading
+    fn repo_load(&self, _progress: &archlinux_repo::Progress) {}
+
+    /// Dependencies of `package` are being resolved
+    fn tree_index(&self, _package: &Package) {}
+
+    /// Dependency tree of `packages` is resolved
+    fn tree_done(&self, _packages: usize) {}
+
+    /// Installation of `tree` packages starts
+    fn packages(&self, _tree: &[Package]) {}
+
+    /// `pos` of `len` bytes of package archive are downloaded
+    fn download(&self, _package: &str, _pos: u64, _len: u64) {}
+
+    /// `bytes` are received from the network
+    fn received(&self, _bytes: u64) {}
+
+    /// `pos` of `len` package archive entries are processed, the last one is `file`
+    fn extract(&self, _package: &str, _file: Option<&str>, _pos: u64, _len: u64) {}
+
+    /// Package is installed
+    fn done(&self, _package: &str) {}
+}
+
+/// Print message above progress bars, or as a plain line when bars are hidden
+fn print(bar: &ProgressBar, mode: ProgressMode, reporter: Option<&dyn ProgressReporter>, msg: &str, warning: bool) {
+    record(reporter, msg, warning);
+    show(bar, mode, reporter.is_some(), &[(msg.to_owned(), warning)]);
+}
+
+/// Send message to the log and the reporter
+fn record(reporter: Option<&dyn ProgressReporter>, msg: &str, warning: bool) {
+    // Messages always go to the log, which is printed in Log mode and written into the log file
+    if warning {
+        tracing::warn!("{}", msg);
+    } else {
+        tracing::info!("{}", msg);
+    }
+    if let Some(reporter) = reporter {
+        reporter.message(msg, warning);
+    }
+}
+
+/// Write `messages` as one block, so lines of other packages and redraws of bars don't get between them
+fn show(bar: &ProgressBar, mode: ProgressMode, reported: bool, messages: &[(String, bool)]) {
+    if mode == ProgressMode::Bars {
+        let lines: Vec<&str> = messages.iter().map(|(msg, _)| msg.as_str()).collect();
+        bar.println(lines.join("\n"));
+        return;
+    }
+    let stderr = io::stderr();
+    let mut stderr = stderr.lock();
+    for (msg, warning) in messages {
+        let _ = match mode {
+            ProgressMode::Plain => writeln!(stderr, "{} {}", timestamp(), msg),
+            ProgressMode::Quiet if *warning => writeln!(stderr, "{}", msg),
+            ProgressMode::Json => {
+                emit(json!({ "event": if *warning { "warning" } else { "info" }, "message": msg }));
+                Ok(())
+            }
+            ProgressMode::Github if *warning => writeln!(stderr, "::warning::{}", escape_command(msg)),
+            ProgressMode::Github => writeln!(stderr, "{}", msg),
+            // Dashboard shows messages in its log
+            ProgressMode::Tui if !reported => writeln!(stderr, "{}", msg),
+            _ => Ok(()),
+        };
+    }
+}
+
+/// Messages of a package, shown together when the package is complete instead of one by one between
+/// messages of other packages. Log and reporter get them immediately
+#[derive(Default)]
+struct Deferred(Mutex<Vec<(String, bool)>>);
+
+impl Deferred {
+    fn push(&self, reporter: Option<&dyn ProgressReporter>, msg: String, warning: bool) {
+        record(reporter, &msg, warning);
+        self.0.lock().unwrap().push((msg, warning));
+    }
+
+    fn flush(&self, bar: &ProgressBar, mode: ProgressMode, reported: bool) {
+        let messages = std::mem::take(&mut *self.0.lock().unwrap());
+        if !messages.is_empty() {
+            show(bar, mode, reported, &messages);
+        }
+    }
+}
+
+/// Write JSON event line to stdout
+fn emit(event: Value) {
+    println!("{}", event);
+}
+
+/// Report error which stopped the program
+pub fn error(mode: ProgressMode, msg: &str) {
+    tracing::error!("{}", msg);
+    match mode {
+        ProgressMode::Json => emit(json!({ "event": "error", "message": msg })),
+        ProgressMode::Log => {}
+        ProgressMode::Github => eprintln!("::error::{}", escape_command(msg)),
+        _ => eprintln!("{}", msg),
+    }
+}
+
+/// Look of progress bars, `[progress]` section of the config file. Templates use `indicatif` syntax
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// Template of package download bars
+    pub download: String,
+    /// Template of package extraction bars
+    pub extract: String,
+    /// Template of download and extraction bars of the whole run
+    pub overall: String,
+    /// Characters of the filled part, the current position and the empty part of bars
+    pub chars: String,
+    /// Colored bars and logs. `--no-color` and `NO_COLOR` environment variable turn it off
+    pub color: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            download: "{spinner:.green} Downloading {wide_msg}: [{elapsed_precise}] [{bar:80.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} ({eta})".to_owned(),
+            extract: "{spinner:.green} Extracting {wide_msg}: [{elapsed_precise}] [{bar:80.cyan/blue}] {pos}/{len} ({eta})".to_owned(),
+            overall: "{wide_msg}: [{elapsed_precise}] [{bar:80.green/white}] {bytes}/{total_bytes} {bytes_per_sec} ({eta})".to_owned(),
+            chars: "#>-".to_owned(),
+            color: true,
+        }
+    }
+}
+
+impl Theme {
+    fn style(&self, template: &str) -> ProgressStyle {
+        ProgressStyle::default_spinner()
+            .template(template)
+            .progress_chars(&self.chars)
+    }
+}
+
+/// Escape message of a GitHub Actions workflow command, so multiline messages stay in one annotation
+fn escape_command(msg: &str) -> String {
+    msg.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Average transfer speed of `bytes` received in `elapsed` time
+fn speed(bytes: u64, elapsed: Duration) -> String {
+    format!("{}/s", HumanBytes((bytes as f64 / elapsed.as_secs_f64().max(0.001)) as u64))
+}
+
+/// Current UTC time in RFC 3339 format
+fn timestamp() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, time) = ((seconds / 86400) as i64, seconds % 86400);
+    // Civil date from days since epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time % 3600 / 60, time % 60)
+}
+
+/// Warnings of a run, deduplicated and printed as a block when the progress is dropped, so they
+/// don't scroll away under progress bars
+#[derive(Clone, Default)]
+struct Warnings(Arc<Mutex<Vec<(String, usize)>>>);
+
+impl Warnings {
+    fn add(&self, msg: &str) {
+        let mut warnings = self.0.lock().unwrap();
+        match warnings.iter_mut().find(|(warning, _)| warning == msg) {
+            Some((_, count)) => *count += 1,
+            None => warnings.push((msg.to_owned(), 1)),
+        }
+    }
+
+    fn print(&self, mode: ProgressMode) {
+        let warnings = self.0.lock().unwrap();
+        if warnings.is_empty() {
+            return;
+        }
+        match mode {
+            ProgressMode::Json => {
+                let warnings: Vec<Value> = warnings.iter().map(|(msg, count)| json!({ "message": msg, "count": count })).collect();
+                emit(json!({ "event": "warnings", "warnings": warnings }));
+            }
+            // Quiet output has nothing but warnings, and log has them already
+            ProgressMode::Quiet | ProgressMode::Log => {}
+            _ => {
+                let count: usize = warnings.iter().map(|(_, count)| count).sum();
+                eprintln!("{} {}:", count, if count == 1 { "warning" } else { "warnings" });
+                for (msg, count) in warnings.iter() {
+                    if *count > 1 {
+                        eprintln!("  {} (x{})", msg, count);
+                    } else {
+                        eprintln!("  {}", msg);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Time to wait for the driver thread to draw the last state of bars when progress is dropped
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Progress reporting of a command. Bars are drawn by a driver thread which joins the `MultiProgress`
+/// once: an unfinished keeper bar, which is never drawn, holds the join until the progress is dropped
 pub struct Progress {
-    progress: Arc<MultiProgress>
+    progress: Arc<MultiProgress>,
+    keeper: ProgressBar,
+    /// Receives when the driver thread stops
+    stopped: Mutex<mpsc::Receiver<()>>,
+    mode: ProgressMode,
+    overall: Mutex<Option<OverallProgress>>,
+    /// GitHub Actions log group is open
+    group: Mutex<bool>,
+    /// Dashboard of Tui mode, started with the first phase
+    dashboard: Mutex<Option<Arc<Dashboard>>>,
+    /// Receiver of events in addition to the output of the mode, the dashboard in Tui mode
+    reporter: Mutex<Option<Arc<dyn ProgressReporter>>>,
+    theme: Theme,
+    warnings: Warnings,
+    /// Packages were installed, so collected warnings are printed at the end
+    installing: Mutex<bool>,
+}
+
+/// Bars of the whole run above bars of single packages. Their totals are sizes from the repository
+/// database, so ETA covers all packages
+struct OverallProgress {
+    download: Arc<OverallDownload>,
+    /// Extracted bytes of installed sizes
+    extract: ProgressBar,
+    packages: usize,
+    done: usize,
+}
+
+/// Downloaded bytes of compressed sizes
+struct OverallDownload {
+    bar: ProgressBar,
+    started: Instant,
+    /// Bytes received from the network, archives taken from cache aren't counted
+    received: AtomicU64,
+}
+
+impl OverallDownload {
+    /// Count downloaded `bytes`, `received` ones are counted in the average speed
+    fn inc(&self, bytes: u64, received: bool) {
+        self.bar.inc(bytes);
+        if received {
+            let received = self.received.fetch_add(bytes, Ordering::Relaxed) + bytes;
+            self.bar.set_message(&format!("downloaded, avg {}", speed(received, self.started.elapsed())));
+        }
+    }
 }
 
 impl Progress {
-    pub fn new() -> Self {
-        let progress = Arc::new(MultiProgress::new());
-        let progress_exec = progress.clone();
+    pub fn new(mode: ProgressMode, theme: &Theme) -> Self {
+        let progress = Arc::new(match mode {
+            ProgressMode::Bars => MultiProgress::new(),
+            _ => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+        });
+        let keeper = progress.add(ProgressBar::new(0));
+        let (done, stopped) = mpsc::channel();
+        let driven = progress.clone();
         std::thread::spawn(move || {
-            loop {
-                progress_exec.join().unwrap();
-                std::thread::sleep(Duration::from_millis(100));
+            if let Err(e) = driven.join() {
+                eprintln!("Failed to draw progress: {}", e);
             }
+            let _ = done.send(());
         });
-        Progress { progress }
+        Progress {
+            progress,
+            keeper,
+            stopped: Mutex::new(stopped),
+            mode,
+            overall: Mutex::new(None),
+            group: Mutex::new(false),
+            dashboard: Mutex::new(None),
+            reporter: Mutex::new(None),
+            theme: theme.clone(),
+            warnings: Warnings::default(),
+            installing: Mutex::new(false),
+        }
+    }
+
+    /// Start log group of the next phase in GitHub Actions output, closing group of the previous one.
+    /// Dashboard is started here, so commands without phases don't switch the screen
+    fn phase(&self, title: &str) {
+        match self.mode {
+            ProgressMode::Github => {
+                let mut group = self.group.lock().unwrap();
+                if *group {
+                    eprintln!("::endgroup::");
+                }
+                eprintln!("::group::{}", title);
+                *group = true;
+            }
+            ProgressMode::Tui => {
+                let mut dashboard = self.dashboard.lock().unwrap();
+                if dashboard.is_none() {
+                    match Dashboard::start(self.theme.color) {
+                        Ok(started) => {
+                            *self.reporter.lock().unwrap() = Some(started.clone());
+                            *dashboard = Some(started);
+                        }
+                        Err(e) => eprintln!("Failed to start dashboard: {}", e),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Progress which sends events only to `reporter` and log events to `tracing`
+    pub fn with_reporter(reporter: Arc<dyn ProgressReporter>) -> Self {
+        let progress = Progress::new(ProgressMode::Log, &Theme::default());
+        *progress.reporter.lock().unwrap() = Some(reporter);
+        progress
+    }
+
+    fn dashboard(&self) -> Option<Arc<Dashboard>> {
+        self.dashboard.lock().unwrap().clone()
+    }
+
+    fn reporter(&self) -> Option<Arc<dyn ProgressReporter>> {
+        self.reporter.lock().unwrap().clone()
+    }
+
+    /// Wait until user asks to cancel the run from the dashboard
+    pub async fn cancelled(&self) {
+        match self.dashboard() {
+            Some(dashboard) => dashboard.cancelled().await,
+            None => futures::future::pending().await,
+        }
     }
 
     pub fn repo(&self) -> RepoLoadProgress {
+        self.phase("Loading repository");
         RepoLoadProgress {
             progress: self.progress.clone(),
+            mode: self.mode,
+            reporter: self.reporter(),
+            theme: self.theme.clone(),
             repo_load_progress: None
         }
     }
 
     pub fn tree(&self) -> TreeBuildProgress {
-        TreeBuildProgress::new(self.progress.clone())
+        self.phase("Resolving dependencies");
+        TreeBuildProgress::new(self.progress.clone(), self.mode, self.reporter(), &self.theme, self.warnings.clone())
+    }
+
+    pub fn package_download(&self, package: &Package) -> PackageDownloadProgress {
+        let overall = self.overall.lock().unwrap().as_ref().map(|overall| overall.download.clone());
+        PackageDownloadProgress::new(self.progress.as_ref(), self.mode, self.reporter(), &self.theme, package, overall)
+    }
+
+    pub fn package_extract(&self, package: &Package) -> PackageExtractProgress {
+        let overall = self.overall.lock().unwrap().as_ref().map(|overall| overall.extract.clone());
+        PackageExtractProgress::new(self.progress.as_ref(), self.mode, self.reporter(), &self.theme, package, overall, self.warnings.clone())
     }
 
-    pub fn package_download(&self, name: &str) -> PackageDownloadProgress {
-        PackageDownloadProgress::new(self.progress.as_ref(), name)
+    pub fn packing(&self, archive: &str, count: usize) -> PackingProgress {
+        PackingProgress::new(self.progress.as_ref(), self.mode, self.reporter(), &self.theme, archive, count)
     }
 
-    pub fn package_extract(&self, name: &str) -> PackageExtractProgress {
-        PackageExtractProgress::new(self.progress.as_ref(), name)
+    /// Show overall progress of installing `tree`: downloaded part of compressed sizes and extracted
+    /// part of installed sizes of its packages. Packages `done` by an interrupted run are counted as
+    /// complete, so totals cover the whole build
+    pub fn start_packages(&self, tree: &[Package], done: &[Package]) {
+        self.phase(&format!("Installing {} packages", tree.len()));
+        *self.installing.lock().unwrap() = true;
+        if !done.is_empty() {
+            self.info(&format!("{} packages were installed by the interrupted run", done.len()));
+        }
+        if let Some(reporter) = self.reporter() {
+            let all: Vec<Package> = done.iter().chain(tree.iter()).cloned().collect();
+            reporter.packages(&all);
+            for package in done {
+                reporter.done(&package.name);
+            }
+        }
+        let packages = tree.len() + done.len();
+        let overall_bar = |total: u64, msg: &str| {
+            let bar = self.progress.add(ProgressBar::new(total));
+            bar.set_style(self.theme.style(&self.theme.overall));
+            bar.set_message(msg);
+            bar
+        };
+        let compressed = |packages: &[Package]| packages.iter().map(|package| package.compressed_size).sum::<u64>();
+        let installed = |packages: &[Package]| packages.iter().map(|package| package.installed_size).sum::<u64>();
+        let download = Arc::new(OverallDownload {
+            bar: overall_bar(compressed(tree) + compressed(done), "downloaded"),
+            started: Instant::now(),
+            received: AtomicU64::new(0),
+        });
+        download.bar.set_position(compressed(done));
+        let extract = overall_bar(installed(tree) + installed(done), &format!("packages {}/{}", done.len(), packages));
+        extract.set_position(installed(done));
+        *self.overall.lock().unwrap() = Some(OverallProgress { download, extract, packages, done: done.len() });
+    }
+
+    /// Count package as installed in the overall progress
+    pub fn package_done(&self) {
+        let mut overall = self.overall.lock().unwrap();
+        if let Some(progress) = overall.as_mut() {
+            progress.done += 1;
+            progress.extract.set_message(&format!("packages {}/{}", progress.done, progress.packages));
+            if progress.done == progress.packages {
+                let received = progress.download.received.load(Ordering::Relaxed);
+                if received > 0 {
+                    let msg = format!("Downloaded {} at {} average", HumanBytes(received), speed(received, progress.download.started.elapsed()));
+                    print(&progress.download.bar, self.mode, self.reporter().as_deref(), &msg, false);
+                }
+                progress.download.bar.finish_and_clear();
+                progress.extract.finish_and_clear();
+                *overall = None;
+            }
+        }
+    }
+
+    pub fn info(&self, msg: &str) {
+        self.print(msg, false);
+    }
+
+    pub fn warn(&self, msg: &str) {
+        self.warnings.add(msg);
+        self.print(msg, true);
+    }
+
+    /// Whether questions can be asked in the terminal
+    pub fn interactive(&self) -> bool {
+        self.mode != ProgressMode::Json && console::Term::stderr().is_term()
+    }
+
+    /// Print `text`, the result of `command` which only reports something, to stdout. JSON mode emits
+    /// it as a `report` event instead, so stdout has nothing but events
+    pub fn report(&self, command: &str, text: &str) {
+        match self.mode {
+            ProgressMode::Json => emit(json!({ "event": "report", "command": command, "lines": text.lines().collect::<Vec<&str>>() })),
+            _ => print!("{}", text),
+        }
+    }
+
+    /// Report result of the whole run
+    pub fn summary(&self, packages: usize, files: usize, bytes: u64, digest: &str) {
+        match self.mode {
+            ProgressMode::Json => emit(json!({ "event": "summary", "packages": packages, "files": files, "bytes": bytes, "digest": digest })),
+            _ => self.info(&format!("Output digest: {}", digest)),
+        }
+    }
+
+    fn print(&self, msg: &str, warning: bool) {
+        let bar = self.progress.add(ProgressBar::hidden());
+        print(&bar, self.mode, self.reporter().as_deref(), msg, warning);
+        bar.finish_and_clear();
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(overall) = self.overall.get_mut().unwrap().take() {
+            overall.download.bar.finish_and_clear();
+            overall.extract.finish_and_clear();
+        }
+        self.keeper.finish_and_clear();
+        // Bars still held elsewhere would block the driver, so it isn't waited for indefinitely
+        let _ = self.stopped.get_mut().unwrap().recv_timeout(SHUTDOWN_TIMEOUT);
+        if *self.group.get_mut().unwrap() {
+            eprintln!("::endgroup::");
+        }
+        if let Some(dashboard) = self.dashboard.get_mut().unwrap().take() {
+            dashboard.stop();
+        }
+        if *self.installing.get_mut().unwrap() {
+            self.warnings.print(self.mode);
+        }
     }
 }
 
 pub struct PackageDownloadProgress {
     progress: ProgressBar,
-    name: String
+    mode: ProgressMode,
+    reporter: Option<Arc<dyn ProgressReporter>>,
+    name: String,
+    /// Download bar of the whole run
+    overall: Option<Arc<OverallDownload>>,
+    /// Archive size counted in the overall total
+    expected: AtomicU64,
+    started: Instant,
+    /// Bytes received from the network, without resumed part
+    received: AtomicU64,
 }
 
 impl PackageDownloadProgress {
-    fn new(progress: &MultiProgress, package: &str) -> Self {
-        let bar = progress.add(ProgressBar::new(1));
-        bar.set_style(
-            ProgressStyle::default_spinner()
-                .template("{spinner:.green} Downloading {wide_msg}: [{elapsed_precise}] [{bar:80.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-                .progress_chars("#>-")
-        );
-        bar.set_message(package);
-        PackageDownloadProgress { progress: bar, name: package.to_owned() }
+    fn new(progress: &MultiProgress, mode: ProgressMode, reporter: Option<Arc<dyn ProgressReporter>>, theme: &Theme, package: &Package,
+           overall: Option<Arc<OverallDownload>>) -> Self {
+        let bar = progress.add(ProgressBar::new(package.compressed_size));
+        bar.set_style(theme.style(&theme.download));
+        bar.set_message(&package.name);
+        PackageDownloadProgress {
+            progress: bar,
+            mode,
+            reporter,
+            name: package.name.clone(),
+            overall,
+            expected: AtomicU64::new(package.compressed_size),
+            started: Instant::now(),
+            received: AtomicU64::new(0),
+        }
     }
 
+    /// Report downloaded part of the archive
     pub fn chunk(&self, pos: u64, max: u64) {
+        let bytes = pos.saturating_sub(self.progress.position());
+        let received = self.received.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.progress.set_message(&format!("{} (avg {})", &self.name, speed(received, self.started.elapsed())));
+        if let Some(reporter) = self.reporter.as_ref() {
+            reporter.received(bytes);
+        }
+        self.advance(pos, max, true);
+    }
+
+    /// Report part of the archive downloaded by an interrupted run
+    pub fn resumed(&self, offset: u64, max: u64) {
+        self.advance(offset, max, false);
+    }
+
+    /// Report archive of `size` taken from cache
+    pub fn cached(&self, size: u64) {
+        self.advance(size, size, false);
+    }
+
+    fn advance(&self, pos: u64, max: u64, received: bool) {
+        if let Some(overall) = self.overall.as_ref() {
+            // Server may report a size other than the database one
+            let expected = self.expected.swap(max, Ordering::Relaxed);
+            if expected != max {
+                overall.bar.set_length(overall.bar.length().saturating_sub(expected) + max);
+            }
+            overall.inc(pos.saturating_sub(self.progress.position()), received);
+        }
         self.progress.set_length(max);
         self.progress.set_position(pos);
+        if let Some(reporter) = self.reporter.as_ref() {
+            reporter.download(&self.name, pos, max);
+        }
     }
 
     pub fn complete(self) {
-        let msg = format!("Package {} downloaded", &self.name);
-        self.progress.println(msg);
+        let received = self.received.load(Ordering::Relaxed);
+        let seconds = self.started.elapsed().as_secs_f64();
+        if self.mode == ProgressMode::Json {
+            emit(json!({ "event": "downloaded", "package": &self.name, "bytes": self.progress.position(), "received": received, "seconds": seconds }));
+        } else if received > 0 {
+            let msg = format!("Package {} downloaded, {} at {}", &self.name, HumanBytes(received), speed(received, self.started.elapsed()));
+            print(&self.progress, self.mode, self.reporter.as_deref(), &msg, false);
+        } else {
+            print(&self.progress, self.mode, self.reporter.as_deref(), &format!("Package {} downloaded", &self.name), false);
+        }
         self.progress.finish_and_clear();
     }
 }
 
 pub struct PackageExtractProgress {
     progress: ProgressBar,
-    name: String
+    mode: ProgressMode,
+    reporter: Option<Arc<dyn ProgressReporter>>,
+    name: String,
+    /// Extraction bar of the whole run
+    overall: Option<ProgressBar>,
+    /// Installed size of the package
+    size: u64,
+    /// Decoded bytes counted in the overall bar
+    decoded: AtomicU64,
+    warnings: Warnings,
+    messages: Deferred,
 }
 
 impl PackageExtractProgress {
-    fn new(progress: &MultiProgress, package: &str) -> Self {
+    fn new(progress: &MultiProgress, mode: ProgressMode, reporter: Option<Arc<dyn ProgressReporter>>, theme: &Theme, package: &Package,
+           overall: Option<ProgressBar>, warnings: Warnings) -> Self {
         let bar = progress.add(ProgressBar::new(1));
-        bar.set_style(
-            ProgressStyle::default_spinner()
-                .template("{spinner:.green} Extracting {wide_msg}: [{elapsed_precise}] [{bar:80.cyan/blue}] {pos}/{len} ({eta})")
-                .progress_chars("#>-")
-        );
-        bar.set_message(package);
-        PackageExtractProgress { progress: bar, name: package.to_owned() }
+        bar.set_style(theme.style(&theme.extract));
+        bar.set_message(&package.name);
+        PackageExtractProgress {
+            progress: bar,
+            mode,
+            reporter,
+            name: package.name.clone(),
+            overall,
+            size: package.installed_size,
+            decoded: AtomicU64::new(0),
+            warnings,
+            messages: Deferred::default(),
+        }
+    }
+
+    /// Count decoded file of `bytes` size. Overall progress doesn't go over the installed size
+    pub fn decoded(&self, bytes: u64) {
+        let before = self.decoded.fetch_add(bytes, Ordering::Relaxed);
+        if let Some(overall) = self.overall.as_ref() {
+            overall.inc(bytes.min(self.size.saturating_sub(before)));
+        }
     }
 
     pub fn set_count(&self, count: usize) {
         self.progress.set_length(count as u64);
+        if let Some(reporter) = self.reporter.as_ref() {
+            reporter.extract(&self.name, None, 0, count as u64);
+        }
+    }
+
+    pub fn file(&self, file: &str) {
+        self.progress.set_message(file);
+        self.progress.inc(1);
+        if let Some(reporter) = self.reporter.as_ref() {
+            reporter.extract(&self.name, Some(file), self.progress.position(), self.progress.length());
+        }
+    }
+
+    pub fn info(&self, msg: &str) {
+        self.messages.push(self.reporter.as_deref(), format!("[{}] {}", &self.name, msg), false);
+    }
+
+    pub fn warn(&self, msg: &str) {
+        let msg = format!("[{}] {}", &self.name, msg);
+        self.warnings.add(&msg);
+        self.messages.push(self.reporter.as_deref(), msg, true);
+    }
+
+    pub fn complete(self) {
+        if self.mode == ProgressMode::Json {
+            self.messages.flush(&self.progress, self.mode, self.reporter.is_some());
+            emit(json!({ "event": "extracted", "package": &self.name, "files": self.progress.position() }));
+        } else {
+            self.messages.push(self.reporter.as_deref(), format!("Package {} extracted", &self.name), false);
+            self.messages.flush(&self.progress, self.mode, self.reporter.is_some());
+        }
+        // Files skipped by filters are part of the installed size too
+        if let Some(overall) = self.overall.as_ref() {
+            overall.inc(self.size.saturating_sub(self.decoded.load(Ordering::Relaxed)));
+        }
+        if let Some(reporter) = self.reporter.as_ref() {
+            reporter.done(&self.name);
+        }
+        self.progress.finish_and_clear();
+    }
+}
+
+impl Drop for PackageExtractProgress {
+    /// Show messages of a package which failed to extract
+    fn drop(&mut self) {
+        self.messages.flush(&self.progress, self.mode, self.reporter.is_some());
+    }
+}
+
+pub struct PackingProgress {
+    progress: ProgressBar,
+    mode: ProgressMode,
+    reporter: Option<Arc<dyn ProgressReporter>>,
+    archive: String
+}
+
+impl PackingProgress {
+    fn new(progress: &MultiProgress, mode: ProgressMode, reporter: Option<Arc<dyn ProgressReporter>>, theme: &Theme, archive: &str, count: usize) -> Self {
+        let bar = progress.add(ProgressBar::new(count as u64));
+        bar.set_style(theme.style("{spinner:.green} Packing {wide_msg}: [{elapsed_precise}] [{bar:80.cyan/blue}] {pos}/{len} ({eta})"));
+        bar.set_message(archive);
+        PackingProgress { progress: bar, mode, reporter, archive: archive.to_owned() }
     }
 
     pub fn file(&self, file: &str) {
@@ -96,20 +751,27 @@ impl PackageExtractProgress {
     }
 
     pub fn complete(self) {
-        let msg = format!("Package {} extracted", &self.name);
-        self.progress.println(msg);
+        let msg = format!("Archive {} packed", &self.archive);
+        print(&self.progress, self.mode, self.reporter.as_deref(), &msg, false);
         self.progress.finish_and_clear();
     }
 }
 
 pub struct RepoLoadProgress {
     progress: Arc<MultiProgress>,
+    mode: ProgressMode,
+    reporter: Option<Arc<dyn ProgressReporter>>,
+    theme: Theme,
     repo_load_progress: Option<ProgressBar>
 }
 
 impl RepoLoadProgress {
     pub fn report(&mut self, progress: archlinux_repo::Progress) {
+        if let Some(reporter) = self.reporter.as_ref() {
+            reporter.repo_load(&progress);
+        }
         let multi_progress = self.progress.as_ref();
+        let theme = &self.theme;
         match progress {
             archlinux_repo::Progress::LoadingDb => {}
             archlinux_repo::Progress::LoadingFilesMetadata => {}
@@ -121,18 +783,14 @@ impl RepoLoadProgress {
                         } else {
                             ProgressBar::new_spinner()
                         });
-                        p.set_style(
-                            ProgressStyle::default_spinner()
-                                .template("{spinner:.green} {wide_msg}: [{elapsed_precise}] [{bar:80.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-                                .progress_chars("#>-")
-                        );
+                        p.set_style(theme.style("{spinner:.green} {wide_msg}: [{elapsed_precise}] [{bar:80.cyan/blue}] {bytes}/{total_bytes} ({eta})"));
                         p.set_message("Loading repository");
                         p
                     });
                 progress.set_position(current);
                 if let Some(s) = size  {
                     if s == current {
-                        progress.println("Repository loaded");
+                        print(progress, self.mode, self.reporter.as_deref(), "Repository loaded", false);
                         progress.finish_and_clear();
                         self.repo_load_progress = None
                     }
@@ -142,11 +800,7 @@ impl RepoLoadProgress {
                 let progress = self.repo_load_progress
                     .get_or_insert_with(|| {
                         let p = multi_progress.add(ProgressBar::new_spinner());
-                        p.set_style(
-                            ProgressStyle::default_spinner()
-                                .template("{spinner:.green} {wide_msg}: [{bar:80.cyan/blue}]")
-                                .progress_chars("#>-")
-                        );
+                        p.set_style(theme.style("{spinner:.green} {wide_msg}: [{bar:80.cyan/blue}]"));
                         p
                     });
                 let msg = format!("Reading file {}", file);
@@ -154,7 +808,7 @@ impl RepoLoadProgress {
             }
             archlinux_repo::Progress::ReadingDbDone => {
                 if let Some(progress) = self.repo_load_progress.as_ref() {
-                    progress.println("Repository reading complete");
+                    print(progress, self.mode, self.reporter.as_deref(), "Repository reading complete", false);
                     progress.finish_and_clear();
                 }
                 self.repo_load_progress = None
@@ -167,18 +821,14 @@ impl RepoLoadProgress {
                         } else {
                             ProgressBar::new_spinner()
                         });
-                        p.set_style(
-                            ProgressStyle::default_spinner()
-                                .template("{spinner:.green} {wide_msg}: [{elapsed_precise}] [{bar:80.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-                                .progress_chars("#>-")
-                        );
+                        p.set_style(theme.style("{spinner:.green} {wide_msg}: [{elapsed_precise}] [{bar:80.cyan/blue}] {bytes}/{total_bytes} ({eta})"));
                         p.set_message("Loading files metadata");
                         p
                     });
                 progress.set_length(current);
                 if let Some(s) = size  {
                     if s == current {
-                        progress.println("Files metadata loaded");
+                        print(progress, self.mode, self.reporter.as_deref(), "Files metadata loaded", false);
                         progress.finish_and_clear();
                         self.repo_load_progress = None
                     }
@@ -188,11 +838,7 @@ impl RepoLoadProgress {
                 let progress = self.repo_load_progress
                     .get_or_insert_with(|| {
                         let p = multi_progress.add(ProgressBar::new_spinner());
-                        p.set_style(
-                            ProgressStyle::default_spinner()
-                                .template("{spinner:.green} {wide_msg}: [{bar:80.cyan/blue}]")
-                                .progress_chars("#>-")
-                        );
+                        p.set_style(theme.style("{spinner:.green} {wide_msg}: [{bar:80.cyan/blue}]"));
                         p
                     });
                 let msg = format!("Reading file {}", file);
@@ -200,7 +846,7 @@ impl RepoLoadProgress {
             }
             archlinux_repo::Progress::ReadingFilesDone => {
                 if let Some(progress) = self.repo_load_progress.as_ref() {
-                    progress.println("Repository files metadata reading complete");
+                    print(progress, self.mode, self.reporter.as_deref(), "Repository files metadata reading complete", false);
                     progress.finish_and_clear();
                 }
                 self.repo_load_progress = None
@@ -210,28 +856,45 @@ impl RepoLoadProgress {
 }
 
 pub struct TreeBuildProgress {
-    progress_bar: ProgressBar
+    progress_bar: ProgressBar,
+    mode: ProgressMode,
+    reporter: Option<Arc<dyn ProgressReporter>>,
+    warnings: Warnings,
 }
 
 impl TreeBuildProgress {
-    fn new(progress: Arc<MultiProgress>) -> Self {
+    fn new(progress: Arc<MultiProgress>, mode: ProgressMode, reporter: Option<Arc<dyn ProgressReporter>>, theme: &Theme, warnings: Warnings) -> Self {
         let progress_bar = progress.add(ProgressBar::new_spinner());
-        progress_bar.set_style(
-            ProgressStyle::default_spinner()
-                .template("{spinner:.green} {wide_msg}: [{elapsed_precise}] [{bar:80.cyan/blue}]")
-                .progress_chars("#>-")
-        );
+        progress_bar.set_style(theme.style("{spinner:.green} {wide_msg}: [{elapsed_precise}] [{bar:80.cyan/blue}]"));
         progress_bar.set_message("Building tree");
-        TreeBuildProgress { progress_bar }
+        if mode == ProgressMode::Json {
+            emit(json!({ "event": "resolve_started" }));
+        }
+        TreeBuildProgress { progress_bar, mode, reporter, warnings }
     }
 
     pub fn index(&self, package: &Package) {
         let msg = format!("Indexing {}", package.name);
         self.progress_bar.set_message(&msg);
+        if let Some(reporter) = self.reporter.as_ref() {
+            reporter.tree_index(package);
+        }
+    }
+
+    pub fn warn(&self, msg: &str) {
+        self.warnings.add(msg);
+        print(&self.progress_bar, self.mode, self.reporter.as_deref(), msg, true);
     }
 
-    pub fn done(self) {
-        self.progress_bar.println("Tree built");
+    pub fn done(self, packages: usize) {
+        if let Some(reporter) = self.reporter.as_ref() {
+            reporter.tree_done(packages);
+        }
+        if self.mode == ProgressMode::Json {
+            emit(json!({ "event": "resolved", "packages": packages }));
+        } else {
+            print(&self.progress_bar, self.mode, self.reporter.as_deref(), "Tree built", false);
+        }
         self.progress_bar.finish_and_clear();
     }
 }
\ No newline at end of file
@@ -0,0 +1,55 @@
+//! Pruning of the output to files required by selected executables
+use crate::imports::Resolver;
+use crate::manifest::Manifest;
+use crate::notices;
+use std::collections::{HashMap, HashSet};
+
+/// Find output files required by `binaries`: the binaries themselves, their DLL import closure,
+/// files under `keep` folders and license notices. Binaries are matched by path or file name, `.exe`
+/// may be omitted. Returns the binary which isn't in the output as an error
+pub fn closure<'a>(manifest: &'a Manifest, binaries: &[String], keep: &[String]) -> Result<HashSet<&'a str>, String> {
+    let imports: HashMap<&str, &[String]> = manifest.files.iter()
+        .map(|entry| (entry.path.as_str(), entry.imports.as_slice()))
+        .collect();
+    let resolver = Resolver::new(manifest);
+
+    let mut queue = Vec::new();
+    for binary in binaries.iter() {
+        let found: Vec<&str> = manifest.files.iter()
+            .map(|entry| entry.path.as_str())
+            .filter(|path| matches_binary(path, binary))
+            .collect();
+        if found.is_empty() {
+            return Err(binary.clone());
+        }
+        queue.extend(found);
+    }
+
+    let mut kept: HashSet<&str> = HashSet::new();
+    while let Some(path) = queue.pop() {
+        if !kept.insert(path) {
+            continue;
+        }
+        if let Some(dlls) = imports.get(path) {
+            for dll in dlls.iter() {
+                queue.extend(resolver.resolve(path, dll));
+            }
+        }
+    }
+    kept.extend(manifest.files.iter()
+        .map(|entry| entry.path.as_str())
+        .filter(|path| notices::is_notice(path) || keep.iter().any(|folder| is_inside(path, folder))));
+    Ok(kept)
+}
+
+fn matches_binary(path: &str, binary: &str) -> bool {
+    let path = path.to_lowercase();
+    let binary = binary.trim_matches('/').to_lowercase();
+    let exe = format!("{}.exe", &binary);
+    [&binary, &exe].iter().any(|name| path == **name || path.ends_with(&format!("/{}", name)))
+}
+
+fn is_inside(path: &str, folder: &str) -> bool {
+    let folder = folder.trim_matches('/');
+    folder.is_empty() || path == folder || path.strip_prefix(folder).map_or(false, |rest| rest.starts_with('/'))
+}
@@ -0,0 +1,130 @@
+//! Relocation of build prefix in pkg-config `.pc` files and CMake package config files
+use std::str::FromStr;
+
+/// Where relocated files point the build prefix to
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Relocation {
+    /// Path relative to the relocated file, valid wherever the output is moved to
+    Relative,
+    /// Absolute path under the final install location of the output
+    Absolute(String),
+}
+
+impl FromStr for Relocation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err("Relocation target must not be empty".to_owned()),
+            "relative" => Ok(Relocation::Relative),
+            _ => Ok(Relocation::Absolute(s.replace('\\', "/").trim_end_matches('/').to_owned())),
+        }
+    }
+}
+
+const CMAKE_FOLDER: &str = "lib/cmake/";
+
+/// Check if `path` is a pkg-config file
+pub fn is_pc_file(path: &str) -> bool {
+    path.ends_with(".pc")
+}
+
+/// Check if `path` is a CMake package config file
+pub fn is_cmake_file(path: &str) -> bool {
+    path.ends_with(".cmake") && path.contains(CMAKE_FOLDER)
+}
+
+/// Relocate `.pc` or CMake file with `path` inside the package and `target` path inside the output.
+/// Returns `None` if the file doesn't need relocation
+pub fn relocate(path: &str, target: &str, data: &[u8], relocation: &Relocation) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(data).ok()?;
+    if is_pc_file(path) {
+        relocate_pc(path, target, text, relocation)
+    } else if is_cmake_file(path) {
+        relocate_cmake(path, target, text, relocation)
+    } else {
+        None
+    }
+}
+
+/// Rewrite `prefix=` of `.pc` file and replace build prefix in all other lines with `${prefix}`
+fn relocate_pc(path: &str, target: &str, text: &str, relocation: &Relocation) -> Option<Vec<u8>> {
+    let build_prefix = text.lines()
+        .find_map(|line| line.trim_start().strip_prefix("prefix="))?
+        .trim()
+        .trim_end_matches('/');
+    if !build_prefix.starts_with('/') || build_prefix.len() < 2 {
+        return None;
+    }
+    // Number of folders between the prefix and the file, usually `lib/pkgconfig` or `share/pkgconfig`
+    let depth = path.strip_prefix(&build_prefix[1..])
+        .and_then(|rest| rest.strip_prefix('/'))
+        .map(|rest| rest.matches('/').count())
+        .unwrap_or(2);
+    let prefix = new_prefix("${pcfiledir}", target, depth, relocation)?;
+
+    let mut result = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(|c| c == '\r' || c == '\n');
+        if content.trim_start().starts_with("prefix=") {
+            result += "prefix=";
+            result += &prefix;
+            result += &line[content.len()..];
+        } else {
+            result += &replace_prefix(line, build_prefix, "${prefix}");
+        }
+    }
+    Some(result.into_bytes())
+}
+
+/// Replace build prefix in CMake file with path relative to `${CMAKE_CURRENT_LIST_DIR}`. Build prefix
+/// is the folder containing `lib/cmake` in the package
+fn relocate_cmake(path: &str, target: &str, text: &str, relocation: &Relocation) -> Option<Vec<u8>> {
+    let index = path.find(CMAKE_FOLDER)?;
+    let build_prefix = format!("/{}", path[..index].trim_end_matches('/'));
+    if build_prefix.len() < 2 || !text.contains(&build_prefix) {
+        return None;
+    }
+    let depth = path[index..].matches('/').count();
+    let prefix = new_prefix("${CMAKE_CURRENT_LIST_DIR}", target, depth, relocation)?;
+    Some(replace_prefix(text, &build_prefix, &prefix).into_bytes())
+}
+
+/// Prefix `depth` folders above relocated file
+fn new_prefix(file_dir: &str, target: &str, depth: usize, relocation: &Relocation) -> Option<String> {
+    match relocation {
+        Relocation::Relative => {
+            let mut prefix = file_dir.to_owned();
+            for _ in 0..depth {
+                prefix += "/..";
+            }
+            Some(prefix)
+        }
+        Relocation::Absolute(root) => {
+            let mut folders: Vec<&str> = target.split('/').collect();
+            if folders.len() < depth + 1 {
+                return None;
+            }
+            folders.truncate(folders.len() - depth - 1);
+            folders.insert(0, root.as_str());
+            Some(folders.join("/"))
+        }
+    }
+}
+
+/// Replace occurrences of `prefix` which are whole path components
+fn replace_prefix(text: &str, prefix: &str, replacement: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find(prefix) {
+        let after = &rest[index + prefix.len()..];
+        let before = rest[..index].chars().next_back();
+        let starts_component = before.map_or(true, |c| !c.is_alphanumeric() && !"_-.:".contains(c));
+        let ends_component = after.chars().next().map_or(true, |c| !c.is_alphanumeric() && !"_-.".contains(c));
+        result += &rest[..index];
+        result += if starts_component && ends_component { replacement } else { prefix };
+        rest = after;
+    }
+    result += rest;
+    result
+}
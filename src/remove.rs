@@ -0,0 +1,115 @@
+//! Removal of packages from the output folder using its install manifest
+use crate::flock::LOCK_FILE;
+use crate::manifest::Manifest;
+use crate::owners::{self, OWNERS_PATH};
+use crate::paths;
+use crate::progress::Progress;
+use crate::staging::TOOL_FOLDER;
+use crate::state::{State, STATE_PATH};
+use std::collections::{BTreeSet, HashMap};
+use crate::error::Error;
+use std::io;
+use std::path::Path;
+
+/// Options of `remove` command
+#[derive(Clone, Debug)]
+pub struct RemoveOptions {
+    /// Packages to remove
+    pub packages: Vec<String>,
+    /// Remove every file listed in the manifest and tool data
+    pub all: bool,
+}
+
+/// Delete files contributed by packages together with dependencies nothing else requires. Files which
+/// are also listed for packages that stay are kept
+pub async fn remove(output: &Path, options: &RemoveOptions, progress: &Progress) -> Result<(), Error> {
+    let mut manifest = Manifest::load(output).await?;
+    if options.all {
+        for entry in manifest.files.iter() {
+            remove_file(output, &entry.path).await?;
+        }
+        // Lock file is held by this run, so it is left in place
+        let tool_folder = output.join(TOOL_FOLDER);
+        if let Ok(mut entries) = tokio::fs::read_dir(&tool_folder).await {
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_name() == LOCK_FILE {
+                    continue;
+                }
+                if entry.file_type().await?.is_dir() {
+                    tokio::fs::remove_dir_all(entry.path()).await?;
+                } else {
+                    tokio::fs::remove_file(entry.path()).await?;
+                }
+            }
+        }
+        progress.info(&format!("Removed {} files", manifest.files.len()));
+        return Ok(());
+    }
+
+    let mut state = State::load(output).await.ok();
+    let mut removed: BTreeSet<String> = options.packages.iter().cloned().collect();
+    for package in removed.iter() {
+        let installed = state.iter().any(|state| state.packages.iter().any(|installed| &installed.name == package));
+        if !installed && !manifest.files.iter().any(|entry| &entry.package == package) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("Package {} is not in the output", package)).into());
+        }
+    }
+    if let Some(state) = state.as_ref() {
+        let roots: Vec<String> = state.explicit().into_iter().filter(|name| !removed.contains(name)).collect();
+        if let Some(orphans) = state.orphans(&roots) {
+            for package in removed.iter() {
+                if state.packages.iter().any(|installed| &installed.name == package) && !orphans.contains(package) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Package {} is required by other installed packages", package)).into());
+                }
+            }
+            for orphan in orphans {
+                if removed.insert(orphan.clone()) {
+                    progress.info(&format!("Removing {}, which is no longer required", orphan));
+                }
+            }
+        }
+    }
+    let mut owners: HashMap<&str, &str> = HashMap::new();
+    for entry in manifest.files.iter().filter(|entry| !removed.contains(entry.package.as_str())) {
+        owners.insert(&entry.path, &entry.package);
+    }
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for entry in manifest.files.iter().filter(|entry| removed.contains(entry.package.as_str())) {
+        match owners.get(entry.path.as_str()) {
+            Some(owner) => progress.warn(&format!("Kept {} of package {}: it is also owned by package {}", &entry.path, &entry.package, owner)),
+            None => {
+                remove_file(output, &entry.path).await?;
+                *counts.entry(&entry.package).or_default() += 1;
+            }
+        }
+    }
+    for package in removed.iter() {
+        progress.info(&format!("Removed {} files of package {}", counts.get(package.as_str()).unwrap_or(&0), package));
+    }
+    manifest.files.retain(|entry| !removed.contains(entry.package.as_str()));
+    manifest.save(output).await?;
+    tokio::fs::write(paths::join(output, OWNERS_PATH), owners::encode(&manifest)).await?;
+    if let Some(state) = state.as_mut() {
+        state.packages.retain(|package| !removed.contains(package.name.as_str()));
+        state.explicit.retain(|package| !removed.contains(package.as_str()));
+        tokio::fs::write(paths::join(output, STATE_PATH), state.to_json()).await?;
+    }
+    Ok(())
+}
+
+/// Remove file with `path` relative to `output` and its parent folders left empty
+pub async fn remove_file(output: &Path, path: &str) -> io::Result<()> {
+    let path = paths::join(output, path);
+    match tokio::fs::remove_file(&path).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut folder = path.parent();
+    while let Some(dir) = folder {
+        if dir == output || tokio::fs::remove_dir(dir).await.is_err() {
+            break;
+        }
+        folder = dir.parent();
+    }
+    Ok(())
+}
@@ -0,0 +1,83 @@
+//! Error report written when a run fails, so CI can triage failures without parsing the log
+use crate::config::Config;
+use crate::error::{Error, Phase};
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// File the report is written into, in the current folder
+pub const REPORT_FILE: &str = "error-report.json";
+
+/// IO or HTTP error which caused the failure
+#[derive(Debug, Serialize)]
+pub struct Cause {
+    /// `io` or `http`
+    pub kind: &'static str,
+    pub message: String,
+    /// Kind of IO error, like `NotFound` or `PermissionDenied`
+    pub io_kind: Option<String>,
+    pub http_status: Option<u16>,
+    pub url: Option<String>,
+}
+
+/// What was being installed
+#[derive(Debug, Serialize)]
+pub struct Context {
+    pub command: &'static str,
+    pub package: String,
+    pub repository: String,
+    pub architecture: String,
+    pub output: PathBuf,
+    pub locked: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    /// Stable identifier of the error, like `package_not_found`, `io` or `http`
+    pub code: String,
+    pub message: String,
+    pub phase: Option<Phase>,
+    pub package: Option<String>,
+    pub cause: Option<Cause>,
+    pub context: Context,
+}
+
+impl ErrorReport {
+    pub fn new(config: &Config, error: &Error) -> Self {
+        let cause = match error.root() {
+            Error::Filesystem(error) => Some(Cause {
+                kind: "io",
+                message: error.to_string(),
+                io_kind: Some(format!("{:?}", error.kind())),
+                http_status: None,
+                url: None,
+            }),
+            Error::Network(error) => Some(Cause {
+                kind: "http",
+                message: error.to_string(),
+                io_kind: None,
+                http_status: error.status().map(|status| status.as_u16()),
+                url: error.url().map(|url| url.to_string()),
+            }),
+            _ => None,
+        };
+        ErrorReport {
+            code: error.code().to_owned(),
+            message: error.to_string(),
+            phase: error.phase(),
+            package: error.package().map(String::from),
+            cause,
+            context: Context {
+                command: config.command.name(),
+                package: config.package.clone(),
+                repository: config.repository_url(),
+                architecture: config.architecture.clone(),
+                output: config.output_folder.clone(),
+                locked: config.locked.clone(),
+            },
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
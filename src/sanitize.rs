@@ -0,0 +1,109 @@
+//! Detection of file names which can't be created on Windows
+use std::str::FromStr;
+
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+const INVALID_CHARS: &[char] = &[':', '<', '>', '|', '"', '?', '*', '\\'];
+
+/// What to do with files which have names invalid on Windows
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NamePolicy {
+    /// Write file as-is
+    Keep,
+    /// Replace invalid characters and suffix reserved names with `_`
+    Rename,
+    /// Do not extract the file
+    Skip,
+}
+
+impl FromStr for NamePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(NamePolicy::Keep),
+            "rename" => Ok(NamePolicy::Rename),
+            "skip" => Ok(NamePolicy::Skip),
+            _ => Err(format!("Unknown name policy: \"{}\"", s)),
+        }
+    }
+}
+
+/// Check `path` for names invalid on Windows and return the fixed path, or `None` if path is valid
+pub fn sanitize(path: &str) -> Option<String> {
+    let mut changed = false;
+    let components: Vec<String> = path.split('/')
+        .map(|component| {
+            let fixed = sanitize_component(component);
+            changed |= fixed != component;
+            fixed
+        })
+        .collect();
+    if changed {
+        Some(components.join("/"))
+    } else {
+        None
+    }
+}
+
+fn sanitize_component(component: &str) -> String {
+    if component.is_empty() || component == "." || component == ".." {
+        return component.to_owned();
+    }
+    let mut fixed: String = component.chars()
+        .map(|c| if INVALID_CHARS.contains(&c) || c.is_control() { '_' } else { c })
+        .collect();
+    if fixed.ends_with('.') || fixed.ends_with(' ') {
+        fixed = fixed.trim_end_matches(|c| c == '.' || c == ' ').to_owned() + "_";
+    }
+    let stem_end = fixed.find('.').unwrap_or_else(|| fixed.len());
+    let stem = fixed[..stem_end].trim_end();
+    if RESERVED_NAMES.iter().any(|name| name.eq_ignore_ascii_case(stem)) {
+        fixed.insert(stem_end, '_');
+    }
+    fixed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_path_is_unchanged() {
+        assert_eq!(sanitize("bin/gcc.exe"), None);
+        assert_eq!(sanitize("share/doc/./../README"), None);
+    }
+
+    #[test]
+    fn invalid_chars_are_replaced() {
+        assert_eq!(sanitize("share/man/man3/std::vector.3").as_deref(), Some("share/man/man3/std__vector.3"));
+        assert_eq!(sanitize("a<b>c|d\"e?f*g\\h").as_deref(), Some("a_b_c_d_e_f_g_h"));
+        assert_eq!(sanitize("tab\tname").as_deref(), Some("tab_name"));
+    }
+
+    #[test]
+    fn trailing_dots_and_spaces_are_replaced() {
+        assert_eq!(sanitize("lib/name.").as_deref(), Some("lib/name_"));
+        assert_eq!(sanitize("lib/name. .").as_deref(), Some("lib/name_"));
+        assert_eq!(sanitize("dir /file").as_deref(), Some("dir_/file"));
+    }
+
+    #[test]
+    fn reserved_names_are_suffixed() {
+        assert_eq!(sanitize("include/aux.h").as_deref(), Some("include/aux_.h"));
+        assert_eq!(sanitize("Con").as_deref(), Some("Con_"));
+        assert_eq!(sanitize("nul/file").as_deref(), Some("nul_/file"));
+        assert_eq!(sanitize("lpt1.tar.gz").as_deref(), Some("lpt1_.tar.gz"));
+        assert_eq!(sanitize("console.h"), None);
+        assert_eq!(sanitize("com10"), None);
+    }
+
+    #[test]
+    fn parses_policy() {
+        assert_eq!(NamePolicy::from_str("rename"), Ok(NamePolicy::Rename));
+        assert!(NamePolicy::from_str("drop").is_err());
+    }
+}
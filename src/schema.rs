@@ -0,0 +1,38 @@
+//! Versioning of data files kept in the output folder. Files written by older versions of the tool
+//! are migrated to the current schema when loaded and saved in it when written again
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::io;
+
+/// Field which holds schema version of a data file. Files without it have version 0
+const SCHEMA_FIELD: &str = "schema";
+
+/// Upgrade of a data file from schema version equal to its index in the migration list to the next one
+pub type Migration = fn(&mut Value);
+
+/// Parse data file `name`, applying migrations from its schema version. Current version is the number
+/// of migrations
+pub fn from_json<T: DeserializeOwned>(data: &[u8], name: &str, migrations: &[Migration]) -> io::Result<T> {
+    let invalid = |e: serde_json::Error| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", name, e));
+    let mut value: Value = serde_json::from_slice(data).map_err(invalid)?;
+    let version = value.get(SCHEMA_FIELD).and_then(Value::as_u64).unwrap_or(0) as usize;
+    if version > migrations.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("{} has schema version {}, but this version of {} supports up to {}. Upgrade {}",
+                                          name, version, env!("CARGO_PKG_NAME"), migrations.len(), env!("CARGO_PKG_NAME"))));
+    }
+    for migration in migrations[version..].iter() {
+        migration(&mut value);
+    }
+    serde_json::from_value(value).map_err(invalid)
+}
+
+/// Serialize data file with schema `version`
+pub fn to_json<T: Serialize>(data: &T, version: usize) -> Vec<u8> {
+    let mut value = serde_json::to_value(data).unwrap();
+    if let Value::Object(object) = &mut value {
+        object.insert(SCHEMA_FIELD.to_owned(), Value::from(version));
+    }
+    serde_json::to_vec_pretty(&value).unwrap()
+}
@@ -0,0 +1,64 @@
+//! File filters written in Rhai, for trimming policies which are hard to express with regexes
+use rhai::{Dynamic, Engine, Scope, AST, INT};
+use std::fmt;
+use std::sync::Arc;
+
+/// Name of the function the script defines
+const FILTER_FUNCTION: &str = "filter";
+/// Operations a single `filter` call may run, so a looping script fails instead of hanging extraction
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 64 * 1024;
+const MAX_ARRAY_SIZE: usize = 10_000;
+const MAX_MAP_SIZE: usize = 10_000;
+
+/// What the script decided about a file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ScriptDecision {
+    Keep,
+    Drop,
+    /// Write the file to this output path instead
+    Remap(String),
+}
+
+/// Compiled script with `filter(package, path, size, is_binary)` function. It receives package name,
+/// output path of the file, its size in bytes and whether it is binary, and returns `true` to keep the
+/// file, `false` to drop it or a string with another output path
+#[derive(Clone)]
+pub struct ScriptFilter {
+    source: String,
+    engine: Arc<Engine>,
+    ast: AST,
+}
+
+impl fmt::Debug for ScriptFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptFilter").field("source", &self.source).finish()
+    }
+}
+
+impl ScriptFilter {
+    pub fn new(source: &str) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(MAX_CALL_LEVELS);
+        engine.set_max_string_size(MAX_STRING_SIZE);
+        engine.set_max_array_size(MAX_ARRAY_SIZE);
+        engine.set_max_map_size(MAX_MAP_SIZE);
+        let ast = engine.compile(source).map_err(|e| format!("Invalid filter script: {}", e))?;
+        Ok(ScriptFilter { source: source.to_owned(), engine: Arc::new(engine), ast })
+    }
+
+    pub fn decide(&self, package: &str, path: &str, size: u64, is_binary: bool) -> Result<ScriptDecision, String> {
+        let args = (package.to_owned(), path.to_owned(), size as INT, is_binary);
+        let result: Dynamic = self.engine.call_fn(&mut Scope::new(), &self.ast, FILTER_FUNCTION, args)
+            .map_err(|e| e.to_string())?;
+        if let Ok(keep) = result.as_bool() {
+            return Ok(if keep { ScriptDecision::Keep } else { ScriptDecision::Drop });
+        }
+        match result.take_string() {
+            Ok(path) if !path.is_empty() => Ok(ScriptDecision::Remap(path)),
+            _ => Err(String::from("filter must return true, false or an output path")),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Destination of extracted files
+use std::io;
+use std::path::Path;
+use crate::staging::Staging;
+use crate::archive::ArchiveSink;
+use crate::manifest::ManifestEntry;
+
+pub enum Sink {
+    /// Files are staged and merged into the output folder
+    Directory(Staging),
+    /// Files are streamed into an archive
+    Archive(ArchiveSink),
+}
+
+impl Sink {
+    /// Output location. Archive paths are relative to the archive root, so it is empty for them
+    pub fn output(&self) -> &Path {
+        match self {
+            Sink::Directory(staging) => staging.output(),
+            Sink::Archive(_) => Path::new(""),
+        }
+    }
+
+    /// Folder where written files can be accessed before commit. Archives have none
+    pub fn staged_root(&self) -> Option<&Path> {
+        match self {
+            Sink::Directory(staging) => Some(staging.path()),
+            Sink::Archive(_) => None,
+        }
+    }
+
+    /// Write file with `path` relative to the output root
+    pub async fn write(&self, path: &str, data: Vec<u8>) -> io::Result<()> {
+        match self {
+            Sink::Directory(staging) => staging.write(path, data).await,
+            Sink::Archive(archive) => archive.write(path, data).await,
+        }
+    }
+
+    /// Remove written file with `path` relative to the output root. Archives can't be modified
+    pub async fn remove(&self, path: &str) -> io::Result<()> {
+        match self {
+            Sink::Directory(staging) => staging.remove(path).await,
+            Sink::Archive(_) => Err(io::Error::new(io::ErrorKind::Other, "Files can't be removed from archive output")),
+        }
+    }
+
+    /// Check if package archive `file_name` was written completely by the interrupted run
+    pub fn is_done(&self, file_name: &str) -> bool {
+        match self {
+            Sink::Directory(staging) => staging.is_done(file_name),
+            Sink::Archive(_) => false,
+        }
+    }
+
+    /// Files written by packages completed by the interrupted run
+    pub fn done_files(&self) -> Vec<ManifestEntry> {
+        match self {
+            Sink::Directory(staging) => staging.done_files(),
+            Sink::Archive(_) => Vec::new(),
+        }
+    }
+
+    /// Record that package archive `file_name` is written completely
+    pub async fn mark_done(&self, file_name: &str, files: &[ManifestEntry]) -> io::Result<()> {
+        match self {
+            Sink::Directory(staging) => staging.mark_done(file_name, files).await,
+            Sink::Archive(_) => Ok(()),
+        }
+    }
+
+    /// Make all written files visible in the output and delete `removed` files of the output folder
+    pub async fn commit(self, removed: &[String]) -> io::Result<()> {
+        match self {
+            Sink::Directory(staging) => staging.commit(removed).await,
+            Sink::Archive(archive) => archive.commit().await,
+        }
+    }
+
+    /// Stop writing after a failure. Staged files of completed packages are kept to resume the run
+    /// later, partially written ones and archives are removed
+    pub async fn abort(self) -> io::Result<()> {
+        match self {
+            Sink::Directory(staging) => staging.abort().await,
+            Sink::Archive(archive) => archive.discard().await,
+        }
+    }
+}
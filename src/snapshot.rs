@@ -0,0 +1,133 @@
+//! Snapshots of the output taken before `update`, which `rollback` restores.
+//!
+//! Snapshot is a folder `<output>/.wtb/snapshots/<unix time>` with tool data files of the output
+//! and a `files` folder with files which the update may replace or remove. Files are hard-linked
+//! when possible, as the update replaces files instead of writing into them.
+use crate::manifest::{Manifest, MANIFEST_PATH};
+use crate::owners::OWNERS_PATH;
+use crate::paths;
+use crate::progress::Progress;
+use crate::remove::remove_file;
+use crate::staging::TOOL_FOLDER;
+use crate::state::STATE_PATH;
+use std::collections::HashSet;
+use crate::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of snapshots kept in the output, older ones are removed by `create`
+const KEEP_SNAPSHOTS: usize = 3;
+const FILES_FOLDER: &str = "files";
+
+/// Options of `rollback` command
+#[derive(Clone, Debug)]
+pub struct RollbackOptions {
+    /// Output folder to restore
+    pub output: PathBuf,
+    /// Snapshot to restore. The latest one is used if not set
+    pub snapshot: Option<String>,
+    /// List snapshots instead of restoring
+    pub list: bool,
+}
+
+fn snapshots_folder(output: &Path) -> PathBuf {
+    output.join(TOOL_FOLDER).join("snapshots")
+}
+
+/// Snapshot names, oldest first
+async fn list(output: &Path) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    if let Ok(mut entries) = tokio::fs::read_dir(snapshots_folder(output)).await {
+        while let Some(entry) = entries.next_entry().await? {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort_by_key(|name| name.parse::<u64>().unwrap_or(0));
+    Ok(names)
+}
+
+/// Save tool data and `files` of the output into a new snapshot
+pub async fn create(output: &Path, files: &[String]) -> io::Result<String> {
+    let name = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0).to_string();
+    let folder = snapshots_folder(output).join(&name);
+    tokio::fs::create_dir_all(&folder).await?;
+    for data in [MANIFEST_PATH, STATE_PATH, OWNERS_PATH].iter() {
+        let source = paths::join(output, data);
+        if tokio::fs::metadata(&source).await.is_ok() {
+            tokio::fs::copy(&source, folder.join(file_name(data))).await?;
+        }
+    }
+    let (output_path, files) = (output.to_owned(), files.to_vec());
+    let files_folder = folder.join(FILES_FOLDER);
+    tokio::task::spawn_blocking(move || -> io::Result<()> {
+        for path in files.iter() {
+            let source = paths::join(&output_path, path);
+            if !source.is_file() {
+                continue;
+            }
+            let target = paths::join(&files_folder, path);
+            std::fs::create_dir_all(target.parent().unwrap())?;
+            if std::fs::hard_link(&source, &target).is_err() {
+                std::fs::copy(&source, &target)?;
+            }
+        }
+        Ok(())
+    }).await??;
+
+    let names = list(output).await?;
+    for old in names.iter().take(names.len().saturating_sub(KEEP_SNAPSHOTS)) {
+        tokio::fs::remove_dir_all(snapshots_folder(output).join(old)).await?;
+    }
+    Ok(name)
+}
+
+/// Restore the output from a snapshot and remove the snapshot
+pub async fn rollback(options: &RollbackOptions, progress: &Progress) -> Result<(), Error> {
+    let output = &options.output;
+    let names = list(output).await?;
+    if options.list {
+        for name in names.iter() {
+            progress.info(name);
+        }
+        return Ok(());
+    }
+    let name = match options.snapshot.as_ref().or_else(|| names.last()) {
+        Some(name) if names.contains(name) => name.clone(),
+        Some(name) => return Err(io::Error::new(io::ErrorKind::NotFound, format!("Snapshot {} not found", name)).into()),
+        None => return Err(io::Error::new(io::ErrorKind::NotFound, "Output has no snapshots").into()),
+    };
+    let folder = snapshots_folder(output).join(&name);
+    let current = Manifest::load(output).await?;
+    let previous = Manifest::from_json(&tokio::fs::read(folder.join(file_name(MANIFEST_PATH))).await?)?;
+
+    let kept: HashSet<&str> = previous.files.iter().map(|entry| entry.path.as_str()).collect();
+    for entry in current.files.iter().filter(|entry| !kept.contains(entry.path.as_str())) {
+        remove_file(output, &entry.path).await?;
+    }
+    let files_folder = folder.join(FILES_FOLDER);
+    let files = if tokio::fs::metadata(&files_folder).await.is_ok() {
+        let files_folder = files_folder.clone();
+        tokio::task::spawn_blocking(move || paths::list_files(&files_folder)).await??
+    } else {
+        Vec::new()
+    };
+    for path in files.iter() {
+        let target = paths::join(output, path);
+        tokio::fs::create_dir_all(target.parent().unwrap()).await?;
+        tokio::fs::rename(paths::join(&files_folder, path), &target).await?;
+    }
+    for data in [MANIFEST_PATH, STATE_PATH, OWNERS_PATH].iter() {
+        let source = folder.join(file_name(data));
+        if tokio::fs::metadata(&source).await.is_ok() {
+            tokio::fs::rename(&source, paths::join(output, data)).await?;
+        }
+    }
+    tokio::fs::remove_dir_all(&folder).await?;
+    progress.info(&format!("Restored snapshot {}: {} files", name, files.len()));
+    Ok(())
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
@@ -0,0 +1,181 @@
+//! Conda channels, read from `repodata.json`. conda-forge and msys2 channels carry MinGW toolchains
+//! as `m2w64-*` packages, which install into `Library/mingw-w64`
+use crate::error::{Error, Result};
+use crate::outdated::compare_versions;
+use crate::progress::Progress;
+use crate::source::{download_index, resolve_requirements, PackageIndex, PackageInfo, PackageSource};
+use archlinux_repo::Package;
+use compress_tools::{list_archive_files, uncompress_archive_file};
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+struct RepoData {
+    /// `.tar.bz2` packages by archive file name
+    #[serde(default)]
+    packages: HashMap<String, Record>,
+    /// `.conda` packages by archive file name
+    #[serde(default, rename = "packages.conda")]
+    conda_packages: HashMap<String, Record>,
+}
+
+#[derive(Deserialize)]
+struct Record {
+    name: String,
+    version: String,
+    #[serde(default)]
+    build_number: u64,
+    /// Requirements with version constraints, like `python >=3.8,<3.9.0a0`
+    #[serde(default)]
+    depends: Vec<String>,
+    license: Option<String>,
+    sha256: Option<String>,
+    #[serde(default)]
+    size: u64,
+}
+
+/// Package of the channel before its requirements are resolved to packages
+struct CondaPackage {
+    info: PackageInfo,
+    /// Archive path relative to the channel
+    location: String,
+    /// Version without the build number
+    version: String,
+    build_number: u64,
+    requires: Vec<String>,
+}
+
+/// Conda channel
+pub struct CondaSource {
+    url: String,
+    /// Folder MinGW prefix is placed into, named like the root folder of MSYS2 packages
+    prefix: String,
+    packages: PackageIndex,
+}
+
+impl CondaSource {
+    /// Load channel at `url` with packages for `architecture` and packages for any architecture.
+    /// MinGW prefix of packages is placed into `prefix` folder
+    pub async fn load(url: &str, architecture: &str, prefix: &str, progress: &Progress) -> Result<Self> {
+        let url = url.trim_end_matches('/').to_owned();
+        let subdir = match architecture {
+            "i686" | "i386" | "x86" => "win-32",
+            "aarch64" => "win-arm64",
+            _ => "win-64",
+        };
+        let client = reqwest::Client::new();
+        let mut repo_progress = progress.repo();
+        let mut packages: HashMap<String, CondaPackage> = HashMap::new();
+        for subdir in [subdir, "noarch"].iter() {
+            let data = download_index(&client, &format!("{}/{}/repodata.json", &url, subdir), &mut repo_progress).await?;
+            let repodata: RepoData = serde_json::from_slice(&data)
+                .map_err(|e| Error::Repository(format!("Invalid repodata of {}: {}", subdir, e)))?;
+            let records = repodata.packages.into_iter().chain(repodata.conda_packages);
+            for (file_name, record) in records {
+                let package = CondaPackage {
+                    info: PackageInfo {
+                        name: record.name,
+                        // Build number is the release of the package
+                        version: format!("{}-{}", record.version, record.build_number),
+                        file_name: file_name.clone(),
+                        compressed_size: record.size,
+                        sha256: record.sha256.unwrap_or_default(),
+                        architecture: subdir.to_string(),
+                        licenses: record.license.into_iter().collect(),
+                        ..PackageInfo::default()
+                    },
+                    location: format!("{}/{}", subdir, file_name),
+                    version: record.version,
+                    build_number: record.build_number,
+                    requires: record.depends,
+                };
+                // Channels keep every version of a package, the latest one is used
+                match packages.get(&package.info.name) {
+                    Some(existing) if is_older(&package, existing) => {}
+                    _ => { packages.insert(package.info.name.clone(), package); }
+                }
+            }
+        }
+        let mut packages: Vec<CondaPackage> = packages.into_iter().map(|(_, package)| package).collect();
+        packages.sort_by(|a, b| a.info.name.cmp(&b.info.name));
+        let depends = resolve_requires(&packages);
+
+        let mut source = CondaSource { url, prefix: prefix.to_owned(), packages: PackageIndex::default() };
+        for (package, depends) in packages.into_iter().zip(depends) {
+            let mut info = package.info;
+            info.depends = depends;
+            source.packages.add(&info, package.location)?;
+        }
+        progress.info(&format!("Loaded {} packages from {}", source.packages.len(), &source.url));
+        Ok(source)
+    }
+}
+
+impl PackageSource for CondaSource {
+    fn package(&self, name: &str) -> Option<&Package> {
+        self.packages.get(name)
+    }
+
+    fn packages(&self) -> Box<dyn Iterator<Item = &Package> + '_> {
+        Box::new(self.packages.packages.iter())
+    }
+
+    fn archive_url(&self, file_name: &str) -> String {
+        self.packages.archive_url(&self.url, file_name)
+    }
+
+    fn entry_path(&self, path: &str) -> Option<String> {
+        let path = path.trim_start_matches("./").trim_start_matches('/');
+        // Package metadata
+        if path == "info" || path.starts_with("info/") {
+            return None;
+        }
+        match path.strip_prefix("Library/mingw-w64") {
+            Some("") => Some(self.prefix.clone()),
+            Some(rest) if rest.starts_with('/') => Some(format!("{}{}", &self.prefix, rest)),
+            _ => Some(path.to_owned()),
+        }
+    }
+
+    fn unpack<'a>(&'a self, package: &'a Package, archive: Vec<u8>) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move {
+            if !package.file_name.ends_with(".conda") {
+                return Ok(archive);
+            }
+            // .conda package is a zip with metadata and files in separate tarballs
+            let file_name = package.file_name.clone();
+            tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+                let inner = list_archive_files(&archive[..])?
+                    .into_iter()
+                    .find(|path| path.starts_with("pkg-"))
+                    .ok_or_else(|| Error::Repository(format!("{} has no package tarball", file_name)))?;
+                let mut tarball = Vec::new();
+                uncompress_archive_file(&archive[..], &mut tarball, &inner)?;
+                Ok(tarball)
+            }).await?
+        })
+    }
+}
+
+/// Whether `package` is an older build than `other`
+fn is_older(package: &CondaPackage, other: &CondaPackage) -> bool {
+    compare_versions(&package.version, &other.version).then(package.build_number.cmp(&other.build_number)) == Ordering::Less
+}
+
+/// Names of packages which provide requirements of every package
+fn resolve_requires(packages: &[CondaPackage]) -> Vec<Vec<String>> {
+    let providers: HashMap<&str, &str> = packages.iter()
+        .map(|package| (package.info.name.as_str(), package.info.name.as_str()))
+        .collect();
+    packages.iter()
+        .map(|package| {
+            let requires: Vec<String> = package.requires.iter()
+                .filter_map(|requirement| requirement.split_whitespace().next())
+                .map(String::from)
+                .collect();
+            resolve_requirements(&package.info.name, &requires, &providers)
+        })
+        .collect()
+}
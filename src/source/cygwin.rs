@@ -0,0 +1,169 @@
+//! Cygwin mirrors, read from `setup.ini`. Package archives are tarballs of the Cygwin root
+use crate::error::Result;
+use crate::progress::Progress;
+use crate::source::{decompress, download_index, resolve_requirements, PackageIndex, PackageInfo, PackageSource};
+use archlinux_repo::Package;
+use std::collections::HashMap;
+
+/// Package of `setup.ini` before its requirements are resolved to packages
+#[derive(Default)]
+struct CygwinPackage {
+    info: PackageInfo,
+    /// Archive path relative to the mirror
+    location: String,
+    provides: Vec<String>,
+    requires: Vec<String>,
+}
+
+/// Cygwin mirror
+pub struct CygwinSource {
+    url: String,
+    /// Folder Cygwin root is placed into
+    prefix: String,
+    packages: PackageIndex,
+}
+
+impl CygwinSource {
+    /// Load mirror at `url` for `architecture`. Cygwin root of packages is placed into `prefix` folder
+    pub async fn load(url: &str, architecture: &str, prefix: &str, progress: &Progress) -> Result<Self> {
+        let url = url.trim_end_matches('/').to_owned();
+        // Cygwin names 32-bit architecture x86
+        let architecture = match architecture {
+            "i686" | "i386" => "x86",
+            architecture => architecture,
+        };
+        let client = reqwest::Client::new();
+        let mut repo_progress = progress.repo();
+        let data = download_index(&client, &format!("{}/{}/setup.xz", &url, architecture), &mut repo_progress).await?;
+        let data = decompress(data).await?;
+        let packages = tokio::task::spawn_blocking(move || parse_setup(&String::from_utf8_lossy(&data))).await?;
+        let depends = resolve_requires(&packages);
+
+        let mut source = CygwinSource { url, prefix: prefix.to_owned(), packages: PackageIndex::default() };
+        for (package, depends) in packages.into_iter().zip(depends) {
+            let mut info = package.info;
+            info.architecture = architecture.to_owned();
+            info.depends = depends;
+            source.packages.add(&info, package.location)?;
+        }
+        progress.info(&format!("Loaded {} packages from {}", source.packages.len(), &source.url));
+        Ok(source)
+    }
+}
+
+impl PackageSource for CygwinSource {
+    fn package(&self, name: &str) -> Option<&Package> {
+        self.packages.get(name)
+    }
+
+    fn packages(&self) -> Box<dyn Iterator<Item = &Package> + '_> {
+        Box::new(self.packages.packages.iter())
+    }
+
+    fn archive_url(&self, file_name: &str) -> String {
+        self.packages.archive_url(&self.url, file_name)
+    }
+
+    fn entry_path(&self, path: &str) -> Option<String> {
+        // Archives hold paths relative to the Cygwin root, like usr/bin/gcc.exe
+        let path = path.trim_start_matches("./").trim_start_matches('/');
+        if path.is_empty() {
+            Some(self.prefix.clone())
+        } else {
+            Some(format!("{}/{}", &self.prefix, path))
+        }
+    }
+}
+
+/// Text of a quoted value
+fn unquote(value: &str) -> String {
+    value.trim_matches('"').to_owned()
+}
+
+/// Packages of `setup.ini` which have an archive. Only the current version of every package is
+/// used, `[prev]` and `[test]` ones are skipped
+fn parse_setup(setup: &str) -> Vec<CygwinPackage> {
+    let mut packages = Vec::new();
+    let mut package: Option<CygwinPackage> = None;
+    let mut current = true;
+    let mut lines = setup.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim_end();
+        if let Some(name) = line.strip_prefix("@ ") {
+            packages.extend(package.take().filter(|package| !package.location.is_empty()));
+            package = Some(CygwinPackage {
+                info: PackageInfo { name: name.trim().to_owned(), ..PackageInfo::default() },
+                ..CygwinPackage::default()
+            });
+            current = true;
+            continue;
+        }
+        if line.starts_with('[') {
+            current = line == "[curr]";
+            continue;
+        }
+        let (key, value) = match line.find(':') {
+            Some(index) => (&line[..index], line[index + 1..].trim()),
+            None => continue,
+        };
+        // Quoted values, like long descriptions, can span several lines
+        if value.starts_with('"') && (value.len() == 1 || !value.ends_with('"')) {
+            for line in &mut lines {
+                if line.trim_end().ends_with('"') {
+                    break;
+                }
+            }
+        }
+        // Fields before the first package describe the mirror
+        let package = match package.as_mut() {
+            Some(package) if current => package,
+            _ => continue,
+        };
+        match key {
+            "sdesc" => package.info.description = unquote(value),
+            "version" => package.info.version = value.to_owned(),
+            "license" => package.info.licenses = vec![value.to_owned()],
+            "install" => {
+                let mut parts = value.split_whitespace();
+                package.location = parts.next().unwrap_or_default().to_owned();
+                package.info.file_name = package.location.rsplit('/').next().unwrap_or_default().to_owned();
+                package.info.compressed_size = parts.next().and_then(|size| size.parse().ok()).unwrap_or(0);
+            }
+            // Older mirrors list requirements as names separated by spaces
+            "requires" => if package.requires.is_empty() {
+                package.requires = value.split_whitespace().map(String::from).collect();
+            },
+            // Requirements with version constraints, like `libgcc1 (>= 11.4.0), cygwin`
+            "depends2" => {
+                package.requires = value.split(',')
+                    .filter_map(|requirement| requirement.split(|c: char| c.is_whitespace() || c == '(').find(|name| !name.is_empty()))
+                    .map(String::from)
+                    .collect();
+            }
+            "provides" => {
+                package.provides = value.split(',')
+                    .filter_map(|capability| capability.split_whitespace().next())
+                    .map(String::from)
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+    packages.extend(package.filter(|package| !package.location.is_empty()));
+    packages
+}
+
+/// Names of packages which provide requirements of every package
+fn resolve_requires(packages: &[CygwinPackage]) -> Vec<Vec<String>> {
+    let mut providers: HashMap<&str, &str> = packages.iter()
+        .map(|package| (package.info.name.as_str(), package.info.name.as_str()))
+        .collect();
+    for package in packages.iter() {
+        for capability in package.provides.iter() {
+            providers.entry(capability.as_str()).or_insert_with(|| package.info.name.as_str());
+        }
+    }
+    packages.iter()
+        .map(|package| resolve_requirements(&package.info.name, &package.requires, &providers))
+        .collect()
+}
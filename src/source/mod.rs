@@ -0,0 +1,218 @@
+//! Repositories packages are installed from. Package metadata of every source uses the pacman form,
+//! which sources of other formats convert their metadata into
+use crate::config::Config;
+use crate::download::Downloader;
+use crate::error::{Error, Result};
+use crate::metrics;
+use crate::progress::{PackageDownloadProgress, Progress, RepoLoadProgress};
+use archlinux_repo::Package;
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::str::FromStr;
+
+pub mod conda;
+pub mod cygwin;
+pub mod pacman;
+pub mod rpm;
+
+/// Format of the repository
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SourceKind {
+    /// pacman repository, like MSYS2 ones
+    Pacman,
+    /// RPM repository with `repodata`, like Fedora ones carrying `mingw64-*` packages
+    Rpm,
+    /// Cygwin mirror with `setup.ini`
+    Cygwin,
+    /// Conda channel with `repodata.json`, like conda-forge or msys2 ones carrying `m2w64-*` packages.
+    /// Experimental
+    Conda,
+}
+
+impl FromStr for SourceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "pacman" => Ok(SourceKind::Pacman),
+            "rpm" => Ok(SourceKind::Rpm),
+            "cygwin" => Ok(SourceKind::Cygwin),
+            "conda" => Ok(SourceKind::Conda),
+            _ => Err(format!("Unknown package source: \"{}\"", s)),
+        }
+    }
+}
+
+/// Repository which packages are resolved from and downloaded from
+pub trait PackageSource: Send + Sync {
+    /// Package called `name`
+    fn package(&self, name: &str) -> Option<&Package>;
+
+    /// Every package of the repository
+    fn packages(&self) -> Box<dyn Iterator<Item = &Package> + '_>;
+
+    /// Names of packages `package` depends on
+    fn dependencies(&self, package: &Package) -> Vec<String> {
+        package.depends.iter().flatten().map(|dependency| dependency.name.clone()).collect()
+    }
+
+    /// URL of package archive `file_name`
+    fn archive_url(&self, file_name: &str) -> String;
+
+    /// Output path of archive entry `path` of a package, `None` to skip the entry. Paths are relative
+    /// and start with the repository name, as in pacman packages
+    fn entry_path(&self, path: &str) -> Option<String> {
+        Some(path.to_owned())
+    }
+
+    /// Archive of `package`, taken from the cache of `downloader` or downloaded into it
+    fn fetch<'a>(&'a self, package: &'a Package, downloader: &'a Downloader, progress: &'a PackageDownloadProgress) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move { downloader.download_from(package, &self.archive_url(&package.file_name), progress).await })
+    }
+
+    /// Archive of `package` which can be extracted, made from the downloaded one. Downloaded archive
+    /// is the one hashes of the repository and lockfiles are checked against
+    fn unpack<'a>(&'a self, _package: &'a Package, archive: Vec<u8>) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move { Ok(archive) })
+    }
+}
+
+/// Load the repository of `config`
+pub async fn open(config: &Config, progress: &Progress) -> Result<Box<dyn PackageSource>> {
+    Ok(match config.source {
+        SourceKind::Pacman => Box::new(pacman::PacmanSource::load(&config.repository_name, &config.repository_url(), progress).await?),
+        SourceKind::Rpm => Box::new(rpm::RpmSource::load(&config.repository, &config.architecture, &config.repository_name, progress).await?),
+        SourceKind::Cygwin => Box::new(cygwin::CygwinSource::load(&config.repository, &config.architecture, &config.repository_name, progress).await?),
+        SourceKind::Conda => Box::new(conda::CondaSource::load(&config.repository, &config.architecture, &config.repository_name, progress).await?),
+    })
+}
+
+/// Metadata of a package from a repository which isn't a pacman one
+#[derive(Clone, Debug, Default)]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    /// Name of the package archive in the cache
+    pub file_name: String,
+    pub compressed_size: u64,
+    pub installed_size: u64,
+    pub sha256: String,
+    pub architecture: String,
+    pub licenses: Vec<String>,
+    /// Names of packages this one depends on
+    pub depends: Vec<String>,
+}
+
+impl PackageInfo {
+    /// Package in pacman form. `archlinux_repo` builds packages only from `desc` records of pacman
+    /// databases, so the metadata is written as one
+    pub fn to_package(&self) -> Result<Package> {
+        let mut desc = String::new();
+        let size = [self.compressed_size.to_string(), self.installed_size.to_string()];
+        let fields: [(&str, Vec<&str>); 12] = [
+            ("FILENAME", vec![self.file_name.as_str()]),
+            ("NAME", vec![self.name.as_str()]),
+            ("VERSION", vec![self.version.as_str()]),
+            ("DESC", vec![self.description.as_str()]),
+            ("CSIZE", vec![size[0].as_str()]),
+            ("ISIZE", vec![size[1].as_str()]),
+            ("SHA256SUM", vec![self.sha256.as_str()]),
+            ("LICENSE", self.licenses.iter().map(String::as_str).collect()),
+            ("ARCH", vec![self.architecture.as_str()]),
+            ("BUILDDATE", vec!["0"]),
+            ("PACKAGER", vec!["Unknown Packager"]),
+            ("DEPENDS", self.depends.iter().map(String::as_str).collect()),
+        ];
+        for (key, values) in fields.iter() {
+            let values: Vec<&str> = values.iter().copied().filter(|value| !value.is_empty()).collect();
+            if !values.is_empty() {
+                desc += &format!("%{}%\n{}\n\n", key, values.join("\n"));
+            }
+        }
+        archlinux_repo_parser::from_str(&desc)
+            .map_err(|e| Error::Repository(format!("Package {} has invalid metadata: {}", &self.name, e)))
+    }
+}
+
+/// Packages of a source which isn't a pacman repository, with locations of their archives
+#[derive(Default)]
+struct PackageIndex {
+    packages: Vec<Package>,
+    names: HashMap<String, usize>,
+    /// Archive path relative to the repository by archive file name
+    locations: HashMap<String, String>,
+}
+
+impl PackageIndex {
+    /// Add package which archive is at `location` in the repository
+    fn add(&mut self, info: &PackageInfo, location: String) -> Result<()> {
+        self.names.insert(info.name.clone(), self.packages.len());
+        self.locations.insert(info.file_name.clone(), location);
+        self.packages.push(info.to_package()?);
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> Option<&Package> {
+        self.names.get(name).map(|index| &self.packages[*index])
+    }
+
+    fn len(&self) -> usize {
+        self.packages.len()
+    }
+
+    /// URL of archive `file_name` in repository at `url`
+    fn archive_url(&self, url: &str, file_name: &str) -> String {
+        let location = self.locations.get(file_name).map(String::as_str).unwrap_or(file_name);
+        format!("{}/{}", url, location)
+    }
+}
+
+/// Names of packages which provide `requirements`. Requirements are resolved by package names first,
+/// then by `provides` of packages. Requirements nothing provides are skipped
+fn resolve_requirements<'a>(package: &str, requirements: &[String], providers: &HashMap<&'a str, &'a str>) -> Vec<String> {
+    let mut depends: Vec<String> = requirements.iter()
+        .filter_map(|requirement| match providers.get(requirement.as_str()) {
+            Some(name) => Some(name),
+            None => {
+                tracing::debug!(package, requirement = %requirement, "requirement isn't provided by the repository");
+                None
+            }
+        })
+        .filter(|name| **name != package)
+        .map(|name| name.to_string())
+        .collect();
+    depends.sort();
+    depends.dedup();
+    depends
+}
+
+/// Download repository index at `url`, showing its progress as the repository load
+async fn download_index(client: &reqwest::Client, url: &str, progress: &mut RepoLoadProgress) -> Result<Vec<u8>> {
+    tracing::debug!(url, "downloading index");
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    let size = response.content_length();
+    let mut data = Vec::with_capacity(size.unwrap_or(0) as usize);
+    while let Some(chunk) = response.chunk().await? {
+        data.extend_from_slice(&chunk[..]);
+        metrics::downloaded(chunk.len() as u64);
+        progress.report(archlinux_repo::Progress::LoadingDbChunk(data.len() as u64, size));
+    }
+    progress.report(archlinux_repo::Progress::ReadingDbDone);
+    Ok(data)
+}
+
+/// Decompress index compressed with gzip, xz, bzip2 or zstd. Uncompressed data is returned as-is
+async fn decompress(data: Vec<u8>) -> Result<Vec<u8>> {
+    let is_compressed = data.starts_with(&[0x1f, 0x8b]) || data.starts_with(&[0xfd, b'7', b'z', b'X', b'Z'])
+        || data.starts_with(b"BZh") || data.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]);
+    if !is_compressed {
+        return Ok(data);
+    }
+    let data = tokio::task::spawn_blocking(move || {
+        let mut uncompressed = Vec::new();
+        compress_tools::uncompress_data(Cursor::new(data), &mut uncompressed).map(|_| uncompressed)
+    }).await??;
+    Ok(data)
+}
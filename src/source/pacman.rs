@@ -0,0 +1,39 @@
+//! MSYS2 and other pacman repositories
+use crate::error::{Error, Result};
+use crate::progress::Progress;
+use crate::source::PackageSource;
+use archlinux_repo::{Package, Repository, RepositoryBuilder};
+use std::sync::RwLock;
+
+/// Repository described by `<name>.db` and `<name>.files` databases
+pub struct PacmanSource {
+    repository: Repository,
+    url: String,
+}
+
+impl PacmanSource {
+    /// Load database of repository `name` served at `url`, which includes architecture
+    pub async fn load(name: &str, url: &str, progress: &Progress) -> Result<Self> {
+        let repo_progress = RwLock::new(progress.repo());
+        let repository = RepositoryBuilder::new(name, url)
+            .progress_listener(Box::new(move |p| repo_progress.write().unwrap().report(p)))
+            .load()
+            .await
+            .map_err(|e| Error::Repository(e.to_string()))?;
+        Ok(PacmanSource { repository, url: url.trim_end_matches('/').to_owned() })
+    }
+}
+
+impl PackageSource for PacmanSource {
+    fn package(&self, name: &str) -> Option<&Package> {
+        self.repository.get_package_by_name(name)
+    }
+
+    fn packages(&self) -> Box<dyn Iterator<Item = &Package> + '_> {
+        Box::new(self.repository.into_iter())
+    }
+
+    fn archive_url(&self, file_name: &str) -> String {
+        format!("{}/{}", &self.url, file_name)
+    }
+}
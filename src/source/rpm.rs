@@ -0,0 +1,245 @@
+//! Fedora and other RPM repositories, read from `repodata/primary.xml`. Fedora carries MinGW
+//! toolchains as `mingw64-*` and `mingw32-*` packages
+use crate::error::{Error, Result};
+use crate::progress::Progress;
+use crate::source::{decompress, download_index, resolve_requirements, PackageIndex, PackageInfo, PackageSource};
+use archlinux_repo::Package;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::{HashMap, HashSet};
+
+/// Package of `primary.xml` before its requirements are resolved to packages
+#[derive(Default)]
+struct RpmPackage {
+    info: PackageInfo,
+    /// Archive path relative to the repository
+    location: String,
+    provides: Vec<String>,
+    requires: Vec<String>,
+    files: Vec<String>,
+}
+
+/// Element which text is read
+#[derive(Clone, Copy)]
+enum Field {
+    Name,
+    Arch,
+    Summary,
+    License,
+    Checksum,
+    File,
+}
+
+/// Capability list which `rpm:entry` elements belong to
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum List {
+    None,
+    Provides,
+    Requires,
+}
+
+/// RPM repository with `repodata` folder
+pub struct RpmSource {
+    url: String,
+    /// Folder MinGW sysroot is placed into, named like the root folder of MSYS2 packages
+    prefix: String,
+    packages: PackageIndex,
+}
+
+impl RpmSource {
+    /// Load repository at `url`. Only packages built for `architecture` or for any architecture are
+    /// used. MinGW sysroot of packages is placed into `prefix` folder
+    pub async fn load(url: &str, architecture: &str, prefix: &str, progress: &Progress) -> Result<Self> {
+        let url = url.trim_end_matches('/').to_owned();
+        let client = reqwest::Client::new();
+        let mut repo_progress = progress.repo();
+        let repomd = client.get(&format!("{}/repodata/repomd.xml", &url)).send().await?
+            .error_for_status()?
+            .text().await?;
+        let primary = primary_location(&repomd)?;
+        let data = download_index(&client, &format!("{}/{}", &url, &primary), &mut repo_progress).await?;
+        let data = decompress(data).await?;
+        let architecture = architecture.to_owned();
+        let packages = tokio::task::spawn_blocking(move || parse_primary(&data, &architecture)).await??;
+        let depends = resolve_requires(&packages);
+
+        let mut source = RpmSource { url, prefix: prefix.to_owned(), packages: PackageIndex::default() };
+        for (package, depends) in packages.into_iter().zip(depends) {
+            let mut info = package.info;
+            info.depends = depends;
+            source.packages.add(&info, package.location)?;
+        }
+        progress.info(&format!("Loaded {} packages from {}", source.packages.len(), &source.url));
+        Ok(source)
+    }
+}
+
+impl PackageSource for RpmSource {
+    fn package(&self, name: &str) -> Option<&Package> {
+        self.packages.get(name)
+    }
+
+    fn packages(&self) -> Box<dyn Iterator<Item = &Package> + '_> {
+        Box::new(self.packages.packages.iter())
+    }
+
+    fn archive_url(&self, file_name: &str) -> String {
+        self.packages.archive_url(&self.url, file_name)
+    }
+
+    fn entry_path(&self, path: &str) -> Option<String> {
+        let path = path.trim_start_matches("./").trim_start_matches('/');
+        // MinGW packages install into /usr/<target>/sys-root/mingw, which is the root of the toolchain.
+        // Tools which run on the build machine are kept where they are
+        let parts: Vec<&str> = path.splitn(5, '/').collect();
+        match parts.as_slice() {
+            ["usr", _, "sys-root", "mingw"] => Some(self.prefix.clone()),
+            ["usr", _, "sys-root", "mingw", rest] => Some(format!("{}/{}", &self.prefix, rest)),
+            _ => Some(path.to_owned()),
+        }
+    }
+}
+
+fn xml_error(error: quick_xml::Error) -> Error {
+    Error::Repository(format!("Invalid repository metadata: {}", error))
+}
+
+/// Value of attribute `name` of `element`
+fn attribute(element: &BytesStart, name: &[u8]) -> Result<Option<String>> {
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(xml_error)?;
+        if attribute.key == name {
+            let value = attribute.unescaped_value().map_err(xml_error)?;
+            return Ok(Some(String::from_utf8_lossy(&value).into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+/// Path of `primary.xml` listed by `repomd.xml`
+fn primary_location(repomd: &str) -> Result<String> {
+    let mut reader = Reader::from_str(repomd);
+    let mut buf = Vec::new();
+    let mut primary = false;
+    loop {
+        match reader.read_event(&mut buf).map_err(xml_error)? {
+            Event::Start(element) if element.name() == b"data" => {
+                primary = attribute(&element, b"type")?.as_deref() == Some("primary");
+            }
+            Event::Empty(element) if primary && element.name() == b"location" => {
+                if let Some(href) = attribute(&element, b"href")? {
+                    return Ok(href);
+                }
+            }
+            Event::Eof => return Err(Error::Repository(String::from("Repository metadata doesn't list primary.xml"))),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// Packages of `primary.xml` built for `architecture` or for any architecture. When several packages
+/// have the same name, the first one is used
+fn parse_primary(data: &[u8], architecture: &str) -> Result<Vec<RpmPackage>> {
+    let mut reader = Reader::from_reader(data);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut packages = Vec::new();
+    let mut names = HashSet::new();
+    let mut package: Option<RpmPackage> = None;
+    let mut field: Option<Field> = None;
+    let mut list = List::None;
+    loop {
+        let event = reader.read_event(&mut buf).map_err(xml_error)?;
+        match &event {
+            Event::Start(element) | Event::Empty(element) if element.name() == b"package" => {
+                package = Some(RpmPackage::default());
+            }
+            Event::Start(element) | Event::Empty(element) => if let Some(package) = package.as_mut() {
+                match element.name() {
+                    b"name" => field = Some(Field::Name),
+                    b"arch" => field = Some(Field::Arch),
+                    b"summary" => field = Some(Field::Summary),
+                    b"rpm:license" => field = Some(Field::License),
+                    b"file" => field = Some(Field::File),
+                    b"checksum" if attribute(element, b"type")?.as_deref() == Some("sha256") => field = Some(Field::Checksum),
+                    b"version" => {
+                        let epoch = attribute(element, b"epoch")?.unwrap_or_default();
+                        let version = attribute(element, b"ver")?.unwrap_or_default();
+                        let release = attribute(element, b"rel")?.unwrap_or_default();
+                        package.info.version = match epoch.as_str() {
+                            "" | "0" => format!("{}-{}", version, release),
+                            epoch => format!("{}:{}-{}", epoch, version, release),
+                        };
+                    }
+                    b"size" => {
+                        package.info.compressed_size = attribute(element, b"package")?.and_then(|size| size.parse().ok()).unwrap_or(0);
+                        package.info.installed_size = attribute(element, b"installed")?.and_then(|size| size.parse().ok()).unwrap_or(0);
+                    }
+                    b"location" => {
+                        let location = attribute(element, b"href")?.unwrap_or_default();
+                        package.info.file_name = location.rsplit('/').next().unwrap_or_default().to_owned();
+                        package.location = location;
+                    }
+                    b"rpm:provides" => list = List::Provides,
+                    b"rpm:requires" => list = List::Requires,
+                    b"rpm:entry" if list != List::None => {
+                        if let Some(name) = attribute(element, b"name")? {
+                            // Features of rpm itself
+                            if !name.starts_with("rpmlib(") {
+                                match list {
+                                    List::Provides => package.provides.push(name),
+                                    _ => package.requires.push(name),
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            },
+            Event::Text(text) => {
+                if let (Some(package), Some(field)) = (package.as_mut(), field) {
+                    let text = text.unescape_and_decode(&reader).map_err(xml_error)?;
+                    match field {
+                        Field::Name => package.info.name = text,
+                        Field::Arch => package.info.architecture = text,
+                        Field::Summary => package.info.description = text,
+                        Field::License => package.info.licenses = vec![text],
+                        Field::Checksum => package.info.sha256 = text,
+                        Field::File => package.files.push(text),
+                    }
+                }
+            }
+            Event::End(element) => match element.name() {
+                b"package" => if let Some(package) = package.take() {
+                    let arch = package.info.architecture.as_str();
+                    if (arch == architecture || arch == "noarch") && names.insert(package.info.name.clone()) {
+                        packages.push(package);
+                    }
+                },
+                b"rpm:provides" | b"rpm:requires" => list = List::None,
+                _ => field = None,
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(packages)
+}
+
+/// Names of packages which provide requirements of every package. Requirements nothing in the
+/// repository provides, like ones of install scriptlets, are skipped
+fn resolve_requires(packages: &[RpmPackage]) -> Vec<Vec<String>> {
+    let mut providers: HashMap<&str, &str> = packages.iter()
+        .map(|package| (package.info.name.as_str(), package.info.name.as_str()))
+        .collect();
+    for package in packages.iter() {
+        for capability in package.provides.iter().chain(package.files.iter()) {
+            providers.entry(capability.as_str()).or_insert_with(|| package.info.name.as_str());
+        }
+    }
+    packages.iter()
+        .map(|package| resolve_requirements(&package.info.name, &package.requires, &providers))
+        .collect()
+}
@@ -0,0 +1,113 @@
+//! Free disk space checks
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::manifest::Manifest;
+use indicatif::HumanBytes;
+
+/// Space required on the volume of `path`
+pub struct Requirement {
+    pub path: PathBuf,
+    pub required: u64,
+}
+
+/// Shortage found by `check`
+pub struct Shortage {
+    pub path: PathBuf,
+    pub required: u64,
+    pub available: u64,
+}
+
+/// Check all requirements and return the first which can't be satisfied
+pub fn check(requirements: &[Requirement]) -> io::Result<Option<Shortage>> {
+    for requirement in requirements.iter().filter(|requirement| requirement.required > 0) {
+        let available = fs2::available_space(existing_ancestor(&requirement.path))?;
+        if available < requirement.required {
+            return Ok(Some(Shortage {
+                path: requirement.path.clone(),
+                required: requirement.required,
+                available,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Parse size like `800MiB`, `1.5G` or `4096`. Binary and decimal units are both accepted, bare
+/// `K`, `M` and `G` are binary
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = f64::from_str(number).map_err(|_| format!("Invalid size: \"{}\"", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        _ => return Err(format!("Unknown size unit: \"{}\"", unit)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Breakdown of output size
+pub struct SizeReport {
+    pub total: u64,
+    /// Largest packages with their total size
+    pub packages: Vec<(String, u64)>,
+    /// Largest top-level folders with their total size, `.` for files in the root
+    pub folders: Vec<(String, u64)>,
+    /// Largest files
+    pub files: Vec<(String, u64)>,
+}
+
+impl SizeReport {
+    /// Sum sizes of manifest files and keep the `count` largest items of every breakdown
+    pub fn new(manifest: &Manifest, count: usize) -> Self {
+        let mut packages: HashMap<&str, u64> = HashMap::new();
+        let mut folders: HashMap<&str, u64> = HashMap::new();
+        for entry in manifest.files.iter() {
+            *packages.entry(entry.package.as_str()).or_default() += entry.size;
+            let folder = entry.path.find('/').map_or(".", |index| &entry.path[..index]);
+            *folders.entry(folder).or_default() += entry.size;
+        }
+        SizeReport {
+            total: manifest.files.iter().map(|entry| entry.size).sum(),
+            packages: top(packages.into_iter().map(|(package, size)| (package.to_owned(), size)), count),
+            folders: top(folders.into_iter().map(|(folder, size)| (folder.to_owned(), size)), count),
+            files: top(manifest.files.iter().map(|entry| (entry.path.clone(), entry.size)), count),
+        }
+    }
+
+    /// Human-readable tables of every breakdown
+    pub fn render(&self) -> String {
+        let mut text = format!("Total size: {}\n", HumanBytes(self.total));
+        for (title, items) in [("package", &self.packages), ("folder", &self.folders), ("file", &self.files)].iter() {
+            text += &format!("\nLargest by {}:\n", title);
+            for (name, size) in items.iter() {
+                let share = if self.total == 0 { 0.0 } else { *size as f64 * 100.0 / self.total as f64 };
+                text += &format!("  {:>10}  {:>5.1}%  {}\n", HumanBytes(*size).to_string(), share, name);
+            }
+        }
+        text
+    }
+}
+
+fn top(items: impl Iterator<Item = (String, u64)>, count: usize) -> Vec<(String, u64)> {
+    let mut items: Vec<(String, u64)> = items.collect();
+    items.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    items.truncate(count);
+    items
+}
+
+fn existing_ancestor(path: &Path) -> &Path {
+    path.ancestors()
+        .find(|ancestor| !ancestor.as_os_str().is_empty() && ancestor.exists())
+        .unwrap_or_else(|| Path::new("."))
+}
@@ -0,0 +1,185 @@
+//! Staging directory used to assemble the output before moving it into place.
+//!
+//! All packages are extracted into `<output>/.wtb/staging` first. Only when the whole run succeeded
+//! the staged tree is merged into the output folder with `rename`, so an interrupted run can never
+//! leave a half-populated toolchain behind. The merge itself is journaled, see [`crate::journal`].
+//! Packages extracted completely are listed in `<output>/.wtb/staging.done`, so an interrupted run can
+//! be resumed. Files of packages which weren't completed are removed when the run is aborted.
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::io;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use crate::journal;
+use crate::paths;
+use crate::manifest::ManifestEntry;
+use tokio::fs::OpenOptions;
+
+/// Folder inside the output which holds tool-private data
+pub const TOOL_FOLDER: &str = ".wtb";
+
+pub struct Staging {
+    path: PathBuf,
+    output: PathBuf,
+    done_path: PathBuf,
+    done: HashMap<String, Vec<ManifestEntry>>,
+    /// Paths of staged files which belong to completed packages
+    kept: Mutex<HashSet<String>>,
+}
+
+/// Line of `staging.done` file
+#[derive(Serialize, Deserialize)]
+struct DoneRecord {
+    file_name: String,
+    files: Vec<ManifestEntry>,
+}
+
+impl Staging {
+    /// Create staging directory for `output`. Leftovers of previous interrupted run are reused with
+    /// `resume`, or removed otherwise
+    pub async fn new(output: &Path, resume: bool) -> io::Result<Self> {
+        let output = paths::absolute(output).await?;
+        let path = output.join(TOOL_FOLDER).join("staging");
+        let done_path = output.join(TOOL_FOLDER).join("staging.done");
+        let mut done = HashMap::new();
+        if resume && tokio::fs::metadata(&path).await.is_ok() {
+            if let Ok(list) = tokio::fs::read_to_string(&done_path).await {
+                done = list.lines()
+                    .filter_map(|line| serde_json::from_str::<DoneRecord>(line).ok())
+                    .map(|record| (record.file_name, record.files))
+                    .collect();
+            }
+        } else {
+            if tokio::fs::metadata(&path).await.is_ok() {
+                tokio::fs::remove_dir_all(&path).await?;
+            }
+            if tokio::fs::metadata(&done_path).await.is_ok() {
+                tokio::fs::remove_file(&done_path).await?;
+            }
+        }
+        tokio::fs::create_dir_all(&path).await?;
+        let kept = Mutex::new(done.values().flatten().map(|file| file.path.clone()).collect());
+        Ok(Staging { path, output, done_path, done, kept })
+    }
+
+    /// Check if package archive `file_name` was extracted completely by the interrupted run
+    pub fn is_done(&self, file_name: &str) -> bool {
+        self.done.contains_key(file_name)
+    }
+
+    /// Files written by packages extracted completely by the interrupted run
+    pub fn done_files(&self) -> Vec<ManifestEntry> {
+        self.done.values().flatten().cloned().collect()
+    }
+
+    /// Record that package archive `file_name` is extracted completely
+    pub async fn mark_done(&self, file_name: &str, files: &[ManifestEntry]) -> io::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        let record = DoneRecord { file_name: file_name.to_owned(), files: files.to_vec() };
+        let mut line = serde_json::to_vec(&record).unwrap();
+        line.push(b'\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.done_path).await?;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        self.kept.lock().unwrap().extend(files.iter().map(|file| file.path.clone()));
+        Ok(())
+    }
+
+    /// Absolute path to the output folder
+    pub fn output(&self) -> &Path {
+        &self.output
+    }
+
+    /// Staging directory, which has the same layout as the output folder
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write file with `path` relative to the output folder into the staging directory
+    pub async fn write(&self, path: &str, data: Vec<u8>) -> io::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        let path = paths::join(&self.path, path);
+        tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path).await?;
+        file.write_all(&data[..]).await?;
+        file.flush().await
+    }
+
+    /// Remove staged file with `path` relative to the output folder and its parent folders left empty
+    pub async fn remove(&self, path: &str) -> io::Result<()> {
+        let path = paths::join(&self.path, path);
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let mut folder = path.parent();
+        while let Some(dir) = folder {
+            if dir == self.path || tokio::fs::remove_dir(dir).await.is_err() {
+                break;
+            }
+            folder = dir.parent();
+        }
+        Ok(())
+    }
+
+    /// Remove staged files of packages which weren't completed, keeping the rest for `--resume`
+    pub async fn abort(self) -> io::Result<()> {
+        let root = self.path.clone();
+        let staged = tokio::task::spawn_blocking(move || paths::list_files(&root)).await??;
+        let kept = std::mem::take(&mut *self.kept.lock().unwrap());
+        for path in staged.iter().filter(|path| !kept.contains(*path)) {
+            self.remove(path).await?;
+        }
+        Ok(())
+    }
+
+    /// Move all staged files into the output folder, replacing existing ones, and delete `removed`
+    /// output files. Interrupted commit is rolled back by the next run
+    pub async fn commit(self, removed: &[String]) -> io::Result<()> {
+        let Staging { path, output, done_path, .. } = self;
+        let removed = removed.to_vec();
+        tokio::task::spawn_blocking(move || journal::commit(&path, &done_path, &output, &removed)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str) -> ManifestEntry {
+        ManifestEntry {
+            path: path.to_owned(),
+            size: 1,
+            sha256: String::new(),
+            package: "done".to_owned(),
+            version: "1.0-1".to_owned(),
+            imports: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn abort_keeps_only_completed_packages() {
+        let output = tempfile::tempdir().unwrap();
+        let staging = Staging::new(output.path(), false).await.unwrap();
+        staging.write("bin/done.exe", vec![1]).await.unwrap();
+        staging.write("bin/partial.exe", vec![1]).await.unwrap();
+        staging.write("share/partial/data.txt", vec![1]).await.unwrap();
+        staging.mark_done("done-1.0-1-any.pkg.tar.zst", &[entry("bin/done.exe")]).await.unwrap();
+        let root = staging.path().to_owned();
+        staging.abort().await.unwrap();
+        assert_eq!(paths::list_files(&root).unwrap(), vec!["bin/done.exe"]);
+
+        let staging = Staging::new(output.path(), true).await.unwrap();
+        assert!(staging.is_done("done-1.0-1-any.pkg.tar.zst"));
+        assert_eq!(staging.done_files().len(), 1);
+    }
+}
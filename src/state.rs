@@ -0,0 +1,173 @@
+//! State database of the output folder. Lists installed packages with their files and settings the
+//! output was built with, so the output can be updated and cleaned later
+use crate::config::Config;
+use crate::manifest::Manifest;
+use crate::paths;
+use crate::schema::{self, Migration};
+use archlinux_repo::Package;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// State location relative to the output root
+pub const STATE_PATH: &str = ".wtb/state.json";
+
+/// Upgrades of states written by older versions, current schema version is their number
+const MIGRATIONS: &[Migration] = &[
+    migrate_explicit,
+];
+
+/// Schema 1: explicitly installed packages and package dependencies are recorded. Only the root
+/// package is known to be explicit, dependencies are unknown
+fn migrate_explicit(state: &mut Value) {
+    let package = state.get("package").cloned().unwrap_or(Value::Null);
+    if let Value::Object(state) = state {
+        state.entry("explicit").or_insert_with(|| Value::Array(vec![package]));
+        if let Some(Value::Array(packages)) = state.get_mut("packages") {
+            for package in packages.iter_mut().filter_map(Value::as_object_mut) {
+                package.entry("depends").or_insert(Value::Null);
+            }
+        }
+    }
+}
+
+/// Options of `add` command
+#[derive(Clone, Debug)]
+pub struct AddOptions {
+    /// Output folder with state database
+    pub output: PathBuf,
+    /// Packages to install
+    pub packages: Vec<String>,
+}
+
+/// Options of `update` command
+#[derive(Clone, Debug)]
+pub struct UpdateOptions {
+    /// Output folder with state database
+    pub output: PathBuf,
+}
+
+/// Package installed into the output
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub version: String,
+    /// Package archive name in the repository
+    pub file_name: String,
+    /// Output files of the package, `/`-separated and relative to the output root
+    pub files: Vec<String>,
+    /// Names of packages this one depends on. Not recorded by older versions
+    pub depends: Option<Vec<String>>,
+}
+
+impl InstalledPackage {
+    pub fn new(package: &Package) -> Self {
+        InstalledPackage {
+            name: package.name.clone(),
+            version: package.version.clone(),
+            file_name: package.file_name.clone(),
+            files: Vec::new(),
+            depends: Some(package.depends.iter().flatten().map(|dependency| dependency.name.clone()).collect()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct State {
+    /// Root package of the installation
+    pub package: String,
+    /// Packages installed on request, the root package and ones installed with `add`. Everything else
+    /// is installed as their dependency
+    pub explicit: Vec<String>,
+    pub repository: String,
+    pub repository_name: String,
+    pub architecture: String,
+    /// Hash of settings which affect output content
+    pub config_hash: String,
+    /// Command line arguments of the build, without program name
+    pub args: Vec<String>,
+    /// Unix time of the build
+    pub installed: u64,
+    pub packages: Vec<InstalledPackage>,
+}
+
+impl State {
+    /// State of the output with `tree` packages extracted into files of `manifest`. Packages of `base`
+    /// state which are not in `tree` are kept, as are its build arguments
+    pub fn new(config: &Config, explicit: &[String], tree: &[Package], base: Option<&State>, manifest: &Manifest) -> Self {
+        let mut files: HashMap<&str, Vec<String>> = HashMap::new();
+        for entry in manifest.files.iter() {
+            files.entry(entry.package.as_str()).or_default().push(entry.path.clone());
+        }
+        let kept = base.iter()
+            .flat_map(|base| base.packages.iter())
+            .filter(|installed| !tree.iter().any(|package| package.name == installed.name))
+            .cloned();
+        let mut packages: Vec<InstalledPackage> = tree.iter()
+            .map(InstalledPackage::new)
+            .chain(kept)
+            .map(|mut package| {
+                package.files = files.remove(package.name.as_str()).unwrap_or_default();
+                package.files.sort();
+                package
+            })
+            .collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        State {
+            package: base.map_or_else(|| config.package.clone(), |base| base.package.clone()),
+            explicit: explicit.to_vec(),
+            repository: config.repository.clone(),
+            repository_name: config.repository_name.clone(),
+            architecture: config.architecture.clone(),
+            config_hash: config.fingerprint(),
+            args: base.map_or_else(|| std::env::args().skip(1).collect(), |base| base.args.clone()),
+            installed: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            packages,
+        }
+    }
+
+    /// Explicitly installed packages. States of older versions only know the root package
+    pub fn explicit(&self) -> Vec<String> {
+        if self.explicit.is_empty() {
+            vec![self.package.clone()]
+        } else {
+            self.explicit.clone()
+        }
+    }
+
+    /// Installed packages which are neither in `roots` nor their dependencies. `None` if the state
+    /// doesn't record dependencies
+    pub fn orphans(&self, roots: &[String]) -> Option<Vec<String>> {
+        let depends: HashMap<&str, &Vec<String>> = self.packages.iter()
+            .map(|package| Some((package.name.as_str(), package.depends.as_ref()?)))
+            .collect::<Option<_>>()?;
+        let mut required: HashSet<&str> = HashSet::new();
+        let mut queue: Vec<&str> = roots.iter().map(String::as_str).collect();
+        while let Some(name) = queue.pop() {
+            if required.insert(name) {
+                queue.extend(depends.get(name).into_iter().flat_map(|deps| deps.iter().map(String::as_str)));
+            }
+        }
+        Some(self.packages.iter()
+            .filter(|package| !required.contains(package.name.as_str()))
+            .map(|package| package.name.clone())
+            .collect())
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        schema::to_json(self, MIGRATIONS.len())
+    }
+
+    /// Parse state written by this or an older version
+    pub fn from_json(data: &[u8]) -> io::Result<Self> {
+        schema::from_json(data, "State database", MIGRATIONS)
+    }
+
+    /// Load state of the output folder
+    pub async fn load(output: &Path) -> io::Result<Self> {
+        State::from_json(&tokio::fs::read(paths::join(output, STATE_PATH)).await?)
+    }
+}
@@ -0,0 +1,104 @@
+//! Per-package timing and throughput statistics
+use indicatif::HumanBytes;
+use serde::Serialize;
+
+/// Statistics of one processed package
+#[derive(Clone, Debug, Serialize)]
+pub struct PackageStats {
+    pub package: String,
+    pub version: String,
+    /// Archive was taken from the download cache
+    pub cached: bool,
+    /// Size of package archive
+    pub download_bytes: u64,
+    pub download_seconds: f64,
+    pub extract_seconds: f64,
+    /// Number of files written to the output
+    pub files: usize,
+    /// Total size of files written to the output
+    pub extracted_bytes: u64,
+}
+
+/// Duration of the phases of a run, written by `--timings` to track performance between versions of the tool
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Timings {
+    /// Version of the tool
+    pub version: String,
+    pub repository_load_seconds: f64,
+    /// Dependency resolution, or reading packages of the lockfile
+    pub resolve_seconds: f64,
+    pub packages: Vec<PackageTimings>,
+    /// Checks and generated files after all packages are extracted
+    pub post_processing_seconds: f64,
+    /// Moving the staged files into the output
+    pub commit_seconds: f64,
+    pub total_seconds: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PackageTimings {
+    pub package: String,
+    pub cached: bool,
+    pub download_seconds: f64,
+    pub extract_seconds: f64,
+}
+
+impl Timings {
+    /// Fill package timings from their statistics
+    pub fn set_packages(&mut self, stats: &[PackageStats]) {
+        self.packages = stats.iter()
+            .map(|stats| PackageTimings {
+                package: stats.package.clone(),
+                cached: stats.cached,
+                download_seconds: stats.download_seconds,
+                extract_seconds: stats.extract_seconds,
+            })
+            .collect();
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).unwrap()
+    }
+}
+
+/// Table of package statistics sorted by total time, slowest first, with a total row
+pub fn render(stats: &[PackageStats]) -> String {
+    let mut stats: Vec<&PackageStats> = stats.iter().collect();
+    stats.sort_by(|a, b| {
+        let a = a.download_seconds + a.extract_seconds;
+        let b = b.download_seconds + b.extract_seconds;
+        b.partial_cmp(&a).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let width = stats.iter().map(|stats| stats.package.len()).max().unwrap_or(0).max(7);
+    let mut text = format!("{:<width$}  {:>10}  {:>9}  {:>12}  {:>9}  {:>7}  {:>10}\n",
+                           "Package", "Download", "Time", "Speed", "Extract", "Files", "Size", width = width);
+    for stats in stats.iter() {
+        let speed = if stats.cached {
+            String::from("cached")
+        } else {
+            format!("{}/s", HumanBytes(per_second(stats.download_bytes, stats.download_seconds)))
+        };
+        text += &format!("{:<width$}  {:>10}  {:>8.2}s  {:>12}  {:>8.2}s  {:>7}  {:>10}\n",
+                         stats.package, HumanBytes(stats.download_bytes).to_string(), stats.download_seconds, speed,
+                         stats.extract_seconds, stats.files, HumanBytes(stats.extracted_bytes).to_string(), width = width);
+    }
+    let download_bytes: u64 = stats.iter().map(|stats| stats.download_bytes).sum();
+    let download_seconds: f64 = stats.iter().map(|stats| stats.download_seconds).sum();
+    let extract_seconds: f64 = stats.iter().map(|stats| stats.extract_seconds).sum();
+    let files: usize = stats.iter().map(|stats| stats.files).sum();
+    let extracted_bytes: u64 = stats.iter().map(|stats| stats.extracted_bytes).sum();
+    text += &format!("{:<width$}  {:>10}  {:>8.2}s  {:>12}  {:>8.2}s  {:>7}  {:>10}\n",
+                     "Total", HumanBytes(download_bytes).to_string(), download_seconds,
+                     format!("{}/s", HumanBytes(per_second(download_bytes, download_seconds))),
+                     extract_seconds, files, HumanBytes(extracted_bytes).to_string(), width = width);
+    text
+}
+
+/// Package statistics as JSON array
+pub fn to_json(stats: &[PackageStats]) -> Vec<u8> {
+    serde_json::to_vec_pretty(stats).unwrap()
+}
+
+fn per_second(bytes: u64, seconds: f64) -> u64 {
+    if seconds > 0.0 { (bytes as f64 / seconds) as u64 } else { 0 }
+}
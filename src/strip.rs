@@ -0,0 +1,218 @@
+//! Debug information stripping
+use crate::classify::{read_u16, read_u32, FileKind};
+use crate::tool::{self, CommandTemplate};
+use std::io;
+
+const SECTION_HEADER_SIZE: usize = 40;
+const SYMBOL_SIZE: usize = 18;
+const SECURITY_DIRECTORY: usize = 4;
+
+struct Section {
+    header: usize,
+    name: String,
+    long_name: bool,
+    virtual_address: u32,
+    virtual_size: u32,
+    raw_size: u32,
+    raw_pointer: u32,
+}
+
+impl Section {
+    fn is_debug(&self) -> bool {
+        self.name.starts_with(".debug") || self.name.starts_with(".zdebug")
+    }
+}
+
+/// Remove `.debug*` sections and COFF symbol table from PE image. MinGW linkers place them at the
+/// end of the file, so the image is simply truncated. Returns `None` if image has no debug
+/// sections, its layout doesn't allow stripping it this way or it is malformed
+pub fn strip_pe(data: &[u8]) -> Option<Vec<u8>> {
+    let pe = read_u32(data, 0x3c)? as usize;
+    let coff = pe + 4;
+    let section_count = read_u16(data, coff + 2)? as usize;
+    let symbol_table = read_u32(data, coff + 8)? as usize;
+    let symbol_count = read_u32(data, coff + 12)? as usize;
+    let optional_size = read_u16(data, coff + 16)? as usize;
+    let optional = coff + 20;
+    let directories = match read_u16(data, optional)? {
+        0x10b => optional + 96,
+        0x20b => optional + 112,
+        _ => return None,
+    };
+    // Certificate table is addressed by file offset and is located at the end of signed files
+    if read_u32(data, directories + SECURITY_DIRECTORY * 8 + 4)? != 0 {
+        return None;
+    }
+    let section_alignment = read_u32(data, optional + 32)?;
+    let string_table = symbol_count.checked_mul(SYMBOL_SIZE)?.checked_add(symbol_table)?;
+
+    let mut sections = Vec::with_capacity(section_count);
+    for index in 0..section_count {
+        let header = optional + optional_size + index * SECTION_HEADER_SIZE;
+        let raw_name = data.get(header..header + 8)?;
+        let raw_name = &raw_name[..raw_name.iter().position(|&b| b == 0).unwrap_or(8)];
+        let mut name = String::from_utf8_lossy(raw_name).into_owned();
+        let long_name = name.starts_with('/');
+        if long_name && symbol_table != 0 {
+            let offset: usize = name[1..].parse().ok()?;
+            let long_name = data.get(string_table.checked_add(offset)?..)?;
+            let end = long_name.iter().position(|&b| b == 0)?;
+            name = String::from_utf8_lossy(&long_name[..end]).into_owned();
+        }
+        sections.push(Section {
+            header,
+            name,
+            long_name,
+            virtual_size: read_u32(data, header + 8)?,
+            virtual_address: read_u32(data, header + 12)?,
+            raw_size: read_u32(data, header + 16)?,
+            raw_pointer: read_u32(data, header + 20)?,
+        });
+    }
+
+    let kept = sections.iter().position(Section::is_debug)?;
+    let (kept_sections, debug_sections) = sections.split_at(kept);
+    if !debug_sections.iter().all(Section::is_debug) {
+        return None;
+    }
+    // Remaining sections must not refer to the string table which is going to be removed
+    if kept_sections.iter().any(|section| section.long_name) {
+        return None;
+    }
+    let kept_end = kept_sections.iter()
+        .try_fold(0usize, |end, section| Some(end.max((section.raw_pointer as usize).checked_add(section.raw_size as usize)?)))?;
+    let truncate_at = debug_sections.iter()
+        .filter(|section| section.raw_size != 0)
+        .map(|section| section.raw_pointer as usize)
+        .min()
+        .unwrap_or_else(|| data.len());
+    let truncate_at = if symbol_table != 0 { truncate_at.min(symbol_table) } else { truncate_at };
+    if truncate_at < kept_end || truncate_at > data.len() {
+        return None;
+    }
+
+    let image_end = kept_sections.iter()
+        .try_fold(0u32, |end, section| Some(end.max(section.virtual_address.checked_add(section.virtual_size)?)))?;
+    let alignment = section_alignment.max(1);
+    let image_size = image_end.checked_add(alignment - 1)? / alignment * alignment;
+
+    // Headers of malformed images may lie in the truncated part, so every write is checked
+    let mut result = data[..truncate_at].to_vec();
+    write_u16(&mut result, coff + 2, kept as u16)?;
+    write_u32(&mut result, coff + 8, 0)?;
+    write_u32(&mut result, coff + 12, 0)?;
+    for section in debug_sections {
+        result.get_mut(section.header..section.header + SECTION_HEADER_SIZE)?.iter_mut().for_each(|byte| *byte = 0);
+    }
+    write_u32(&mut result, optional + 56, image_size)?;
+    // Checksum is not verified for user mode images, zero means it isn't set
+    write_u32(&mut result, optional + 64, 0)?;
+    Some(result)
+}
+
+fn write_u16(data: &mut [u8], offset: usize, value: u16) -> Option<()> {
+    data.get_mut(offset..offset + 2)?.copy_from_slice(&value.to_le_bytes());
+    Some(())
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) -> Option<()> {
+    data.get_mut(offset..offset + 4)?.copy_from_slice(&value.to_le_bytes());
+    Some(())
+}
+
+/// Strip file with external `strip`-compatible tool. Archives are stripped only from debug
+/// information to keep their symbol index usable for linking
+pub fn strip_external(tool: &str, data: Vec<u8>, kind: FileKind) -> io::Result<Vec<u8>> {
+    let flag = if kind.is_library() { "--strip-debug" } else { "--strip-all" };
+    tool::process_file(&CommandTemplate::new(tool, vec![flag.to_owned()]), &data, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PE: usize = 0x40;
+    const OPTIONAL: usize = PE + 24;
+    const OPTIONAL_SIZE: usize = 240;
+    const SECTIONS: usize = OPTIONAL + OPTIONAL_SIZE;
+
+    fn put_u16(data: &mut [u8], offset: usize, value: u16) {
+        data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// PE32+ image with `sections` of (name, virtual address, virtual size, raw pointer, raw size)
+    /// and COFF symbol table at `symbol_table`
+    fn image(sections: &[(&str, u32, u32, u32, u32)], symbol_table: u32, len: usize) -> Vec<u8> {
+        let mut data = vec![0u8; len];
+        data[0..2].copy_from_slice(b"MZ");
+        put_u32(&mut data, 0x3c, PE as u32);
+        data[PE..PE + 4].copy_from_slice(b"PE\0\0");
+        put_u16(&mut data, PE + 4, 0x8664);
+        put_u16(&mut data, PE + 6, sections.len() as u16);
+        put_u32(&mut data, PE + 12, symbol_table);
+        put_u32(&mut data, PE + 16, if symbol_table == 0 { 0 } else { 1 });
+        put_u16(&mut data, PE + 20, OPTIONAL_SIZE as u16);
+        put_u16(&mut data, OPTIONAL, 0x20b);
+        put_u32(&mut data, OPTIONAL + 32, 0x1000);
+        put_u32(&mut data, OPTIONAL + 56, 0x10000);
+        for (index, (name, virtual_address, virtual_size, raw_pointer, raw_size)) in sections.iter().enumerate() {
+            let header = SECTIONS + index * SECTION_HEADER_SIZE;
+            data[header..header + name.len()].copy_from_slice(name.as_bytes());
+            put_u32(&mut data, header + 8, *virtual_size);
+            put_u32(&mut data, header + 12, *virtual_address);
+            put_u32(&mut data, header + 16, *raw_size);
+            put_u32(&mut data, header + 20, *raw_pointer);
+        }
+        data
+    }
+
+    #[test]
+    fn strips_trailing_debug_sections() {
+        let data = image(&[(".text", 0x1000, 0x180, 0x400, 0x200), (".debug", 0x2000, 0x100, 0x600, 0x200)], 0x800, 0x900);
+        let stripped = strip_pe(&data).unwrap();
+        assert_eq!(stripped.len(), 0x600);
+        assert_eq!(read_u16(&stripped, PE + 6), Some(1));
+        assert_eq!(read_u32(&stripped, PE + 12), Some(0));
+        assert_eq!(read_u32(&stripped, PE + 16), Some(0));
+        assert!(stripped[SECTIONS + SECTION_HEADER_SIZE..SECTIONS + 2 * SECTION_HEADER_SIZE].iter().all(|&b| b == 0));
+        assert_eq!(read_u32(&stripped, OPTIONAL + 56), Some(0x2000));
+    }
+
+    #[test]
+    fn keeps_image_without_debug_sections() {
+        let data = image(&[(".text", 0x1000, 0x180, 0x400, 0x200)], 0, 0x600);
+        assert!(strip_pe(&data).is_none());
+    }
+
+    #[test]
+    fn keeps_image_with_debug_section_in_the_middle() {
+        let data = image(&[(".debug", 0x1000, 0x100, 0x400, 0x200), (".text", 0x2000, 0x180, 0x600, 0x200)], 0, 0x800);
+        assert!(strip_pe(&data).is_none());
+    }
+
+    #[test]
+    fn rejects_symbol_table_inside_headers() {
+        let data = image(&[(".debug", 0x1000, 0x100, 0x400, 0x200)], 0x10, 0x600);
+        assert!(strip_pe(&data).is_none());
+    }
+
+    #[test]
+    fn rejects_overflowing_section_addresses() {
+        let data = image(&[(".text", 0xffff_f000, 0x2000, 0x400, 0x200), (".debug", 0x1000, 0x100, 0x600, 0x200)], 0, 0x800);
+        assert!(strip_pe(&data).is_none());
+        let data = image(&[(".text", 0x1000, 0x180, 0xffff_ff00, 0x200), (".debug", 0x2000, 0x100, 0x600, 0x200)], 0, 0x800);
+        assert!(strip_pe(&data).is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        let data = image(&[(".text", 0x1000, 0x180, 0x400, 0x200), (".debug", 0x2000, 0x100, 0x600, 0x200)], 0, 0x800);
+        for len in [0, 0x3c, PE + 10, OPTIONAL + 40, SECTIONS + 20].iter() {
+            assert!(strip_pe(&data[..*len]).is_none());
+        }
+    }
+}
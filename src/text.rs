@@ -0,0 +1,141 @@
+//! Text file transformations
+use std::str::FromStr;
+
+/// Line ending style
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl FromStr for LineEnding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            _ => Err(format!("Unknown line ending: \"{}\"", s)),
+        }
+    }
+}
+
+/// Check for NUL bytes, which never appear in text files
+pub fn is_binary(data: &[u8]) -> bool {
+    data.contains(&0)
+}
+
+/// Convert all line endings in `data` to `ending`
+pub fn convert_line_endings(data: &[u8], ending: LineEnding) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len() + data.len() / 32);
+    let mut iter = data.iter().peekable();
+    while let Some(&byte) = iter.next() {
+        match byte {
+            b'\r' if iter.peek() == Some(&&b'\n') => {}
+            b'\n' => {
+                if ending == LineEnding::Crlf {
+                    result.push(b'\r');
+                }
+                result.push(b'\n');
+            }
+            _ => result.push(byte),
+        }
+    }
+    result
+}
+
+/// Replacement of hardcoded build prefix inside text files, parsed from `FROM=TO`
+#[derive(Clone, Debug)]
+pub struct PrefixRewrite {
+    pub from: String,
+    pub to: String,
+}
+
+impl FromStr for PrefixRewrite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(from), Some(to)) if !from.is_empty() => Ok(PrefixRewrite { from: from.to_owned(), to: to.to_owned() }),
+            _ => Err(format!("Prefix rewrite must look like FROM=TO, got \"{}\"", s)),
+        }
+    }
+}
+
+impl PrefixRewrite {
+    /// Replace all occurrences of the prefix in `data`
+    pub fn apply(&self, data: Vec<u8>) -> Vec<u8> {
+        replace_all(data, self.from.as_bytes(), self.to.as_bytes())
+    }
+}
+
+fn replace_all(data: Vec<u8>, from: &[u8], to: &[u8]) -> Vec<u8> {
+    if data.len() < from.len() || !data.windows(from.len()).any(|window| window == from) {
+        return data;
+    }
+    let mut result = Vec::with_capacity(data.len());
+    let mut pos = 0;
+    while pos < data.len() {
+        if data[pos..].starts_with(from) {
+            result.extend_from_slice(to);
+            pos += from.len();
+        } else {
+            result.push(data[pos]);
+            pos += 1;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_crlf() {
+        assert_eq!(convert_line_endings(b"a\nb\r\nc", LineEnding::Crlf), b"a\r\nb\r\nc");
+    }
+
+    #[test]
+    fn converts_to_lf() {
+        assert_eq!(convert_line_endings(b"a\r\nb\nc\r\n", LineEnding::Lf), b"a\nb\nc\n");
+    }
+
+    #[test]
+    fn keeps_lone_carriage_return() {
+        assert_eq!(convert_line_endings(b"a\rb\r", LineEnding::Crlf), b"a\rb\r");
+    }
+
+    #[test]
+    fn detects_binary() {
+        assert!(is_binary(b"MZ\0\0"));
+        assert!(!is_binary(b"prefix=/mingw64\n"));
+    }
+
+    #[test]
+    fn parses_line_ending() {
+        assert_eq!(LineEnding::from_str("crlf"), Ok(LineEnding::Crlf));
+        assert!(LineEnding::from_str("cr").is_err());
+    }
+
+    #[test]
+    fn rewrites_every_prefix() {
+        let rewrite = PrefixRewrite::from_str("/mingw64=C:/toolchain").unwrap();
+        let data = b"prefix=/mingw64\nlibdir=/mingw64/lib\n".to_vec();
+        assert_eq!(rewrite.apply(data), b"prefix=C:/toolchain\nlibdir=C:/toolchain/lib\n".to_vec());
+    }
+
+    #[test]
+    fn rewrite_without_match_keeps_data() {
+        let rewrite = PrefixRewrite::from_str("/mingw64=").unwrap();
+        assert_eq!(rewrite.apply(b"/ming".to_vec()), b"/ming".to_vec());
+        assert_eq!(rewrite.apply(b"/mingw64/bin".to_vec()), b"/bin".to_vec());
+    }
+
+    #[test]
+    fn parses_rewrite() {
+        assert!(PrefixRewrite::from_str("=/usr").is_err());
+        assert!(PrefixRewrite::from_str("/usr").is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! External command templates
+use std::io::{self, Read, Write};
+use std::process::Command;
+use std::str::FromStr;
+
+/// Command line with `{name}` placeholders in arguments
+#[derive(Clone, Debug)]
+pub struct CommandTemplate {
+    program: String,
+    args: Vec<String>,
+}
+
+impl FromStr for CommandTemplate {
+    type Err = String;
+
+    /// Parse command line split by whitespace. Arguments with spaces are quoted, see `split`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = split(s)?.into_iter();
+        match parts.next() {
+            Some(program) => Ok(CommandTemplate { program, args: parts.collect() }),
+            None => Err(String::from("Command must not be empty")),
+        }
+    }
+}
+
+/// Split command line into arguments like a shell does, keeping backslashes of Windows paths. Text in
+/// single quotes is taken as is, text in double quotes as is except for `\"`. Outside of quotes, a
+/// backslash escapes only a quote or whitespace which follows it
+fn split(s: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some('\\') if c == '"' && chars.peek() == Some(&'"') => arg.push(chars.next().unwrap()),
+                        Some(other) => arg.push(other),
+                        None => return Err(format!("Unterminated {} quote in command: {}", c, s)),
+                    }
+                }
+            }
+            '\\' if chars.peek().map_or(false, |next| *next == '\'' || *next == '"' || next.is_whitespace()) => {
+                arg.get_or_insert_with(String::new).push(chars.next().unwrap());
+            }
+            c if c.is_whitespace() => args.extend(arg.take()),
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+impl CommandTemplate {
+    pub fn new(program: &str, args: Vec<String>) -> Self {
+        CommandTemplate { program: program.to_owned(), args }
+    }
+
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    /// Check if any argument uses `{placeholder}`
+    pub fn uses(&self, placeholder: &str) -> bool {
+        let placeholder = format!("{{{}}}", placeholder);
+        self.args.iter().any(|arg| arg.contains(&placeholder))
+    }
+
+    /// Build command, replacing `{name}` placeholders in arguments with values
+    pub fn command(&self, values: &[(&str, &str)]) -> Command {
+        let mut command = Command::new(&self.program);
+        for arg in self.args.iter() {
+            let arg = values.iter().fold(arg.clone(), |arg, (name, value)| arg.replace(&format!("{{{}}}", name), value));
+            command.arg(arg);
+        }
+        command
+    }
+}
+
+/// Run `template` over `data` stored in a temporary file and read the file back. File path is passed
+/// as `{file}` placeholder, or appended to arguments when template doesn't use it. Temporary file
+/// gets the same extension as `name`, as some tools decide by it
+pub fn process_file(template: &CommandTemplate, data: &[u8], name: &str) -> io::Result<Vec<u8>> {
+    let extension = name.rfind('.').map(|index| &name[index..]).unwrap_or("");
+    let mut file = tempfile::Builder::new().suffix(extension).tempfile()?;
+    file.write_all(data)?;
+    file.flush()?;
+    let path = file.path().to_string_lossy().into_owned();
+    let mut command = template.command(&[("file", &path)]);
+    if !template.uses("file") {
+        command.arg(&path);
+    }
+    let status = command.status()?;
+    if !status.success() {
+        return Err(io::Error::new(io::ErrorKind::Other, format!("{} exited with {}", template.program(), status)));
+    }
+    let mut result = Vec::with_capacity(data.len());
+    std::fs::File::open(file.path())?.read_to_end(&mut result)?;
+    Ok(result)
+}
+
+/// Run signing `template` over `data` stored in a temporary file and return the signed file. Tools
+/// which sign in place get the path as `{file}`, tools which write a new file get `{in}` and `{out}`.
+/// File name is available as `{name}`. Path is appended to arguments when no path placeholder is used
+pub fn sign_file(template: &CommandTemplate, data: &[u8], name: &str) -> io::Result<Vec<u8>> {
+    let name = name.rsplit('/').next().unwrap_or(name);
+    let folder = tempfile::tempdir()?;
+    let input = folder.path().join(name);
+    let output = folder.path().join("signed").join(name);
+    std::fs::create_dir(output.parent().unwrap())?;
+    std::fs::write(&input, data)?;
+    let input_path = input.to_string_lossy().into_owned();
+    let output_path = output.to_string_lossy().into_owned();
+    let mut command = template.command(&[("file", &input_path), ("in", &input_path), ("out", &output_path), ("name", name)]);
+    if !template.uses("file") && !template.uses("in") {
+        command.arg(&input_path);
+    }
+    let status = command.status()?;
+    if !status.success() {
+        return Err(io::Error::new(io::ErrorKind::Other, format!("{} exited with {}", template.program(), status)));
+    }
+    std::fs::read(if template.uses("out") { &output } else { &input })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        split(s).unwrap()
+    }
+
+    #[test]
+    fn splits_by_whitespace() {
+        assert_eq!(args("  upx  --best\t{file} "), ["upx", "--best", "{file}"]);
+    }
+
+    #[test]
+    fn keeps_quoted_spaces() {
+        assert_eq!(args(r#""C:\Program Files\Windows Kits\signtool.exe" sign /a {file}"#),
+                   [r"C:\Program Files\Windows Kits\signtool.exe", "sign", "/a", "{file}"]);
+        assert_eq!(args("tool 'two words' x'y z'"), ["tool", "two words", "xy z"]);
+    }
+
+    #[test]
+    fn keeps_backslashes_of_paths() {
+        assert_eq!(args(r"C:\tools\upx.exe -o {out}"), [r"C:\tools\upx.exe", "-o", "{out}"]);
+    }
+
+    #[test]
+    fn unescapes_quotes_and_spaces() {
+        assert_eq!(args(r#"echo "say \"hi\"" a\ b \'"#), ["echo", r#"say "hi""#, "a b", "'"]);
+    }
+
+    #[test]
+    fn keeps_empty_quoted_argument() {
+        assert_eq!(args(r#"tool "" x"#), ["tool", "", "x"]);
+    }
+
+    #[test]
+    fn rejects_unterminated_quote() {
+        assert!(split("tool \"x").is_err());
+        assert!(CommandTemplate::from_str("  ").is_err());
+    }
+}
@@ -0,0 +1,70 @@
+//! Package archive URLs changed by the user, e.g. to download some packages from an internal
+//! artifact store instead of the repository
+use crate::config::Config;
+use crate::license::Glob;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Archive URL of packages matching `package` glob. `{url}`, `{file_name}` and `{name}` placeholders
+/// of the template are replaced with repository URL of the archive, archive file name and package name
+#[derive(Clone, Debug)]
+pub struct UrlRewrite {
+    pub package: Glob,
+    pub template: String,
+}
+
+impl FromStr for UrlRewrite {
+    type Err = String;
+
+    /// Parse `PACKAGE=URL`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let index = s.find('=').ok_or_else(|| format!("URL rewrite must be PACKAGE=URL: \"{}\"", s))?;
+        UrlRewrite::new(&s[..index], &s[index + 1..])
+    }
+}
+
+impl UrlRewrite {
+    pub fn new(package: &str, template: &str) -> Result<Self, String> {
+        if template.is_empty() {
+            return Err(format!("URL rewrite of {} has no URL", package));
+        }
+        Ok(UrlRewrite { package: Glob::from_str(package)?, template: template.to_owned() })
+    }
+
+    /// URL of archive `file_name` of package `name`, `None` if the rewrite is for other packages
+    pub fn apply(&self, name: &str, file_name: &str, url: &str) -> Option<String> {
+        if !self.package.is_match(name) {
+            return None;
+        }
+        Some(self.template.replace("{url}", url).replace("{file_name}", file_name).replace("{name}", name))
+    }
+}
+
+/// Callback of the library API which receives package name and repository URL of its archive, and
+/// returns URL to download the archive from instead, or `None` to keep it
+#[derive(Clone)]
+pub struct UrlResolver(Arc<dyn Fn(&str, &str) -> Option<String> + Send + Sync>);
+
+impl UrlResolver {
+    pub fn new(resolve: impl Fn(&str, &str) -> Option<String> + Send + Sync + 'static) -> Self {
+        UrlResolver(Arc::new(resolve))
+    }
+}
+
+impl fmt::Debug for UrlResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UrlResolver")
+    }
+}
+
+/// URL to download archive `file_name` of package `name` from instead of repository `url`, `None` to
+/// use the repository. Resolver of `config` goes first, then the first matching rewrite
+pub fn resolve(config: &Config, name: &str, file_name: &str, url: &str) -> Option<String> {
+    let resolved = config.url_resolver.as_ref().and_then(|resolver| (resolver.0)(name, url))
+        .or_else(|| config.url_rewrites.iter().find_map(|rewrite| rewrite.apply(name, file_name, url)));
+    if let Some(resolved) = resolved.as_ref() {
+        tracing::debug!(package = name, url = %resolved, "archive URL rewritten");
+    }
+    resolved
+}
@@ -0,0 +1,44 @@
+//! Verification of an output folder against its install manifest
+use crate::manifest::{sha256_hex, Manifest};
+use crate::paths;
+use crate::progress::Progress;
+use std::collections::HashSet;
+use crate::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+/// Options of `verify` command
+#[derive(Clone, Debug)]
+pub struct VerifyOptions {
+    /// Output folder to check
+    pub output: PathBuf,
+}
+
+/// Re-hash every manifest file and look for files missing from the manifest. Returns number of problems
+pub async fn verify(options: &VerifyOptions, progress: &Progress) -> Result<usize, Error> {
+    let manifest = Manifest::load(&options.output).await?;
+    let mut problems = 0;
+    for entry in manifest.files.iter() {
+        match tokio::fs::read(paths::join(&options.output, &entry.path)).await {
+            Ok(data) => if data.len() as u64 != entry.size || sha256_hex(&data) != entry.sha256 {
+                progress.warn(&format!("Modified: {} of package {}", &entry.path, &entry.package));
+                problems += 1;
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                progress.warn(&format!("Deleted: {} of package {}", &entry.path, &entry.package));
+                problems += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let known: HashSet<&str> = manifest.files.iter().map(|entry| entry.path.as_str()).collect();
+    let output = options.output.clone();
+    let files = tokio::task::spawn_blocking(move || paths::list_files(&output)).await??;
+    for path in files.iter().filter(|path| !known.contains(path.as_str())) {
+        progress.warn(&format!("Unexpected: {}", path));
+        problems += 1;
+    }
+    progress.info(&format!("Verified {} files, {} problems found", manifest.files.len(), problems));
+    Ok(problems)
+}
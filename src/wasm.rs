@@ -0,0 +1,150 @@
+//! WebAssembly plugins, a sandboxed alternative to `--plugin`. Modules get no imports, so they can't
+//! reach files, network or clock, and every call runs in a fresh instance with a fixed fuel budget, so
+//! results depend only on arguments and a looping module fails instead of hanging the run.
+//!
+//! Module exports `memory` and `alloc(len: i32) -> i32`, which returns address of `len` bytes where
+//! strings are passed as UTF-8, and any of these functions:
+//!
+//! - `keep_package(name, name_len, version, version_len) -> i32` is called for every package of the
+//!   resolved tree, package is dropped if it returns 0
+//! - `filter(package, package_len, path, path_len, size: i64, binary: i32) -> i64` is called for
+//!   every file which passed other filters, with its output path. It returns 0 to keep the file, -1 to
+//!   drop it, or address and length of another output path as `address << 32 | length` to move it
+use crate::manifest::sha256_hex;
+use crate::script::ScriptDecision;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use wasmi::{Engine, Linker, Memory, Module, Store, WasmParams, WasmResults};
+
+/// Instructions a single call may run, roughly
+const FUEL_PER_CALL: u64 = 100_000_000;
+
+/// Compiled module
+#[derive(Clone)]
+pub struct WasmPlugin {
+    path: PathBuf,
+    sha256: String,
+    engine: Engine,
+    module: Arc<Module>,
+    /// Whether the module exports `keep_package`
+    keeps_packages: bool,
+    /// Whether the module exports `filter`
+    filters: bool,
+}
+
+impl fmt::Debug for WasmPlugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WasmPlugin").field("path", &self.path).field("sha256", &self.sha256).finish()
+    }
+}
+
+impl WasmPlugin {
+    /// Load module at `path`
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut config = wasmi::Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &data[..]).map_err(|e| format!("Invalid WebAssembly module {}: {}", path.display(), e))?;
+        let mut plugin = WasmPlugin {
+            path: path.to_owned(),
+            sha256: sha256_hex(&data),
+            engine,
+            module: Arc::new(module),
+            keeps_packages: false,
+            filters: false,
+        };
+        // Modules which need imports or have no memory are rejected before the run starts
+        let instance = plugin.instantiate().map_err(|e| format!("Invalid WebAssembly module {}: {}", path.display(), e))?;
+        plugin.keeps_packages = instance.instance.get_func(&instance.store, "keep_package").is_some();
+        plugin.filters = instance.instance.get_func(&instance.store, "filter").is_some();
+        Ok(plugin)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn instantiate(&self) -> Result<Instance, String> {
+        let mut store = Store::new(&self.engine, ());
+        store.add_fuel(FUEL_PER_CALL).map_err(|e| e.to_string())?;
+        let instance = Linker::<()>::new(&self.engine)
+            .instantiate(&mut store, &self.module)
+            .map_err(|e| e.to_string())?
+            .start(&mut store)
+            .map_err(|e| e.to_string())?;
+        let memory = instance.get_memory(&store, "memory")
+            .ok_or_else(|| String::from("module doesn't export memory"))?;
+        Ok(Instance { store, instance, memory })
+    }
+
+    /// Run `call` with a fresh instance on a blocking thread, so the module doesn't stall the executor
+    async fn run<T: Send + 'static>(&self, call: impl FnOnce(&mut Instance) -> Result<T, String> + Send + 'static) -> Result<T, String> {
+        let plugin = self.clone();
+        tokio::task::spawn_blocking(move || call(&mut plugin.instantiate()?)).await
+            .map_err(|e| e.to_string())?
+    }
+
+    /// Whether package `name` stays in the tree
+    pub async fn keep_package(&self, name: &str, version: &str) -> Result<bool, String> {
+        if !self.keeps_packages {
+            return Ok(true);
+        }
+        let (name, version) = (name.to_owned(), version.to_owned());
+        self.run(move |instance| {
+            let (name, name_len) = instance.write(&name)?;
+            let (version, version_len) = instance.write(&version)?;
+            let result: i32 = instance.call("keep_package", (name, name_len, version, version_len))?;
+            Ok(result != 0)
+        }).await
+    }
+
+    /// What to do with file of `package` written to output `path`
+    pub async fn filter(&self, package: &str, path: &str, size: u64, binary: bool) -> Result<ScriptDecision, String> {
+        if !self.filters {
+            return Ok(ScriptDecision::Keep);
+        }
+        let (package, path) = (package.to_owned(), path.to_owned());
+        self.run(move |instance| {
+            let (package, package_len) = instance.write(&package)?;
+            let (path, path_len) = instance.write(&path)?;
+            let args = (package, package_len, path, path_len, size as i64, binary as i32);
+            match instance.call::<_, i64>("filter", args)? {
+                0 => Ok(ScriptDecision::Keep),
+                -1 => Ok(ScriptDecision::Drop),
+                result => {
+                    let (address, len) = ((result as u64 >> 32) as usize, (result as u64 & 0xffff_ffff) as usize);
+                    let mut data = vec![0; len];
+                    instance.memory.read(&instance.store, address, &mut data).map_err(|e| e.to_string())?;
+                    let path = String::from_utf8(data).map_err(|_| String::from("filter returned path which isn't UTF-8"))?;
+                    Ok(ScriptDecision::Remap(path))
+                }
+            }
+        }).await
+    }
+}
+
+struct Instance {
+    store: Store<()>,
+    instance: wasmi::Instance,
+    memory: Memory,
+}
+
+impl Instance {
+    /// Call exported function `name` with signature `Params -> Results`
+    fn call<Params: WasmParams, Results: WasmResults>(&mut self, name: &str, args: Params) -> Result<Results, String> {
+        self.instance.get_typed_func::<Params, Results>(&self.store, name)
+            .map_err(|e| format!("{}: {}", name, e))?
+            .call(&mut self.store, args)
+            .map_err(|e| format!("{}: {}", name, e))
+    }
+
+    /// Copy `text` into memory allocated by the module. Returns its address and length
+    fn write(&mut self, text: &str) -> Result<(i32, i32), String> {
+        let len = text.len() as i32;
+        let address: i32 = self.call("alloc", len)?;
+        self.memory.write(&mut self.store, address as u32 as usize, text.as_bytes()).map_err(|e| e.to_string())?;
+        Ok((address, len))
+    }
+}